@@ -0,0 +1,29 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+use cosmic_ray_detector::detector::Detector;
+
+const SIZES: [usize; 3] = [1 << 20, 16 << 20, 128 << 20];
+
+fn write(c: &mut Criterion) {
+    let mut group = c.benchmark_group("write");
+    for size in SIZES {
+        let mut detector = Detector::new(0, size);
+        group.throughput(Throughput::Bytes(size as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(size), &size, |b, _| b.iter(|| detector.reset()));
+    }
+    group.finish();
+}
+
+fn check(c: &mut Criterion) {
+    let mut group = c.benchmark_group("check");
+    for size in SIZES {
+        let mut detector = Detector::new(0, size);
+        detector.reset();
+        group.throughput(Throughput::Bytes(size as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(size), &size, |b, _| b.iter(|| detector.is_intact()));
+    }
+    group.finish();
+}
+
+criterion_group!(benches, write, check);
+criterion_main!(benches);
@@ -0,0 +1,77 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+fn main() {
+    // Embed the git commit the binary was built from, if there is one, so that logs can be traced back to the exact source.
+    if let Ok(output) = Command::new("git").args(["rev-parse", "--short", "HEAD"]).output() {
+        if output.status.success() {
+            let hash = String::from_utf8_lossy(&output.stdout);
+            println!("cargo:rustc-env=GIT_HASH={}", hash.trim());
+        }
+    }
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/refs");
+
+    // Collect the translatable messages, for `cosmic_ray_detector messages`.
+    let mut messages = Vec::new();
+    let mut sources: Vec<PathBuf> = fs::read_dir("src").into_iter().flatten().flatten().map(|entry| entry.path()).collect();
+    sources.sort();
+    for source in sources.iter().filter(|path| path.extension().is_some_and(|extension| extension == "rs")) {
+        if let Ok(code) = fs::read_to_string(source) {
+            for message in tr_messages(&code) {
+                if !messages.contains(&message) {
+                    messages.push(message);
+                }
+            }
+        }
+    }
+    let list: String = messages.iter().map(|message| format!("    {:?},\n", message)).collect();
+    let out = Path::new(&env::var("OUT_DIR").unwrap()).join("messages.rs");
+    fs::write(out, format!("pub const MESSAGES: &[&str] = &[\n{}];\n", list)).unwrap();
+    println!("cargo:rerun-if-changed=src");
+}
+
+/// The string literals passed to `tr!` in the code.
+fn tr_messages(code: &str) -> Vec<String> {
+    let mut messages = Vec::new();
+    let mut rest = code;
+    while let Some(position) = rest.find("tr!(") {
+        // Not the end of a longer macro name such as include_str!.
+        let part_of_name = rest[..position].ends_with(|character: char| character.is_alphanumeric() || character == '_');
+        rest = rest[position + 4..].trim_start();
+        if part_of_name {
+            continue;
+        }
+        let Some(literal) = rest.strip_prefix('"') else {
+            continue;
+        };
+        let mut message = String::new();
+        let mut characters = literal.char_indices();
+        while let Some((index, character)) = characters.next() {
+            match character {
+                '"' => {
+                    rest = &literal[index + 1..];
+                    break;
+                }
+                '\\' => match characters.next() {
+                    Some((_, 'n')) => message.push('\n'),
+                    Some((_, 't')) => message.push('\t'),
+                    // A line continuation skips the line break and the indentation after it.
+                    Some((_, '\n')) => {
+                        let skipped = literal[index + 2..].len() - literal[index + 2..].trim_start().len();
+                        for _ in 0..skipped {
+                            characters.next();
+                        }
+                    }
+                    Some((_, other)) => message.push(other),
+                    None => {}
+                },
+                other => message.push(other),
+            }
+        }
+        messages.push(message);
+    }
+    messages
+}
@@ -0,0 +1,51 @@
+/* C bindings of the cosmic ray detector, implemented in src/ffi.rs and built as libcosmic_ray_detector. */
+#ifndef COSMIC_RAY_DETECTOR_H
+#define COSMIC_RAY_DETECTOR_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* A detector and its checking thread. Every function but crd_free may be called from any thread. */
+typedef struct crd_detector crd_detector;
+
+/* A change found in the detector memory. */
+typedef struct {
+    /* When the check that found the change finished, in milliseconds since 1970. */
+    uint64_t detected_ms;
+    /* The index of the changed byte, -1 if it had changed back by the time it was looked for. */
+    int64_t index;
+    /* The value the changed byte held, -1 if it had changed back. */
+    int32_t value;
+    /* The number of checks that passed since the previous event. */
+    uint64_t checks;
+    /* The change happened at most this many milliseconds before it was detected. */
+    uint64_t window_ms;
+} crd_event;
+
+/* Allocates a detector of `bytes` bytes, NULL if `bytes` is zero. Nothing is checked until crd_start or crd_check. */
+crd_detector *crd_new(size_t bytes);
+
+/* Checks once on the calling thread: 1 if something changed and an event was queued, 0 if intact, -1 if NULL. */
+int32_t crd_check(crd_detector *detector);
+
+/* Starts checking every `delay_ms` milliseconds on a thread of its own. -1 if NULL, 0 otherwise. */
+int32_t crd_start(crd_detector *detector, uint64_t delay_ms);
+
+/* Stops the checking thread, returning once its current check is done. -1 if NULL, 0 otherwise. */
+int32_t crd_stop(crd_detector *detector);
+
+/* Moves up to `capacity` queued events into `events`, oldest first, returning how many it moved. */
+size_t crd_events(crd_detector *detector, crd_event *events, size_t capacity);
+
+/* Stops the detector and frees it. */
+void crd_free(crd_detector *detector);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,411 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::{
+    coincidence::{coincidences, offset_histogram, read_event_times, OFFSET_BINS},
+    community::CommunityDataset,
+    config::{AnalyzeArgs, GlobalArgs, TimeZone},
+    cross_section::cross_section,
+    exposure::{gb_hours, local_utc_offset, Ledger},
+    health::poisson_tail,
+    leaderboard::altitude_band,
+    record::{read_log, Record},
+};
+
+/// The confidence levels upper limits on the flip rate are given for.
+const CONFIDENCE_LEVELS: [f64; 2] = [0.90, 0.95];
+/// The confidence level of the interval of the cross-section.
+const CROSS_SECTION_CONFIDENCE: f64 = 0.95;
+/// The length of the longest bar of the histograms.
+const HISTOGRAM_WIDTH: u64 = 40;
+const WEEKDAYS: [&str; 7] = ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"];
+
+/// Prints a summary of the sessions and bitflips in a log file.
+pub fn analyze(_global: &GlobalArgs, args: AnalyzeArgs) -> Result<(), Box<dyn Error>> {
+    let records = read_log(&args.file_path)?;
+
+    let mut sessions: u64 = 0;
+    let mut flips: u64 = 0;
+    let mut vanished_flips: u64 = 0;
+    let mut warmup_flips: u64 = 0;
+    let mut checks: u64 = 0;
+    let mut dropped: u64 = 0;
+    let mut disagreements: u64 = 0;
+    let mut panics: u64 = 0;
+    let mut shutdowns: u64 = 0;
+    let mut sequences: Vec<u64> = Vec::new();
+    let mut swapping_heartbeats: u64 = 0;
+    let mut paging_heartbeats: u64 = 0;
+    let mut reduced_checks: Option<u64> = None;
+    let mut reduced_flips: u64 = 0;
+    // Late checks, missed deadlines and the largest 99th percentile of how late checks started, with --fixed-cadence.
+    let mut cadence: Option<(u64, u64, f64)> = None;
+    // Checks during heavy SMI activity, the SMIs counted and the slow checks, from the heartbeats.
+    let mut smi: Option<(u64, Option<u64>, u64)> = None;
+    let mut heavy_smi_flips: u64 = 0;
+    // Sessions and flips on memory running faster than it is rated for, and whether the current session's is.
+    let (mut xmp_sessions, mut xmp_flips, mut xmp) = (0u64, 0u64, false);
+    // Where the latest session ran and on which memory, for comparing with the community.
+    let (mut altitude_m, mut memory_type) = (None, None);
+    // Flips with --inverted-halves that changed bytes in the normal half only, the inverted half only, and both.
+    let mut halves: Option<(u64, u64, u64)> = None;
+    let mut memory_errors: Option<(u64, u64)> = None;
+    let mut last_memory_errors: Option<(u64, u64)> = None;
+    let mut by_hour = [0u64; 24];
+    let mut by_weekday = [0u64; 7];
+    // Flips by the NUMA node their bytes are on, on machines with more than one.
+    let mut by_numa_node: BTreeMap<u32, u64> = BTreeMap::new();
+    // When each flip may have happened, from the earliest to the latest time in milliseconds.
+    let mut flip_times: Vec<(f64, f64)> = Vec::new();
+    for record in &records {
+        sequences.extend(record.sequence());
+        match record {
+            Record::Session(session) => {
+                xmp = session.hardware.memory_xmp == Some(true);
+                xmp_sessions += xmp as u64;
+                altitude_m = session.altitude_m;
+                memory_type = session.hardware.memory_type.clone();
+            }
+            Record::Heartbeat { paging, residency, cadence: heartbeat_cadence, smi: heartbeat_smi, .. } => {
+                if let Some(paging) = paging {
+                    paging_heartbeats += 1;
+                    swapping_heartbeats += paging.swapped() as u64;
+                }
+                if let Some(residency) = residency {
+                    *reduced_checks.get_or_insert(0) += residency.reduced_checks;
+                }
+                if let Some(heartbeat_cadence) = heartbeat_cadence {
+                    let (late, missed, late_p99_ms) = cadence.get_or_insert((0, 0, 0.0));
+                    *late += heartbeat_cadence.late_checks;
+                    *missed += heartbeat_cadence.missed_deadlines;
+                    *late_p99_ms = late_p99_ms.max(heartbeat_cadence.late_p99_ms);
+                }
+                if let Some(heartbeat_smi) = heartbeat_smi {
+                    let (heavy, count, slow) = smi.get_or_insert((0, None, 0));
+                    *heavy += heartbeat_smi.heavy_checks;
+                    if let Some(smi_count) = heartbeat_smi.smi_count {
+                        *count.get_or_insert(0) += smi_count;
+                    }
+                    *slow += heartbeat_smi.slow_checks;
+                }
+            }
+            Record::Dropped { records, .. } => dropped += records,
+            Record::Disagreement { .. } => disagreements += 1,
+            Record::Panic { .. } => panics += 1,
+            Record::Shutdown { .. } => shutdowns += 1,
+            Record::Start { .. } => {
+                sessions += 1;
+                // The counts start over when the machine reboots, which can happen between sessions.
+                last_memory_errors = None;
+            }
+            Record::MemoryErrors { corrected, uncorrected, .. } => {
+                let (total_corrected, total_uncorrected) = memory_errors.get_or_insert((0, 0));
+                if let Some((last_corrected, last_uncorrected)) = last_memory_errors {
+                    *total_corrected += corrected.saturating_sub(last_corrected);
+                    *total_uncorrected += uncorrected.saturating_sub(last_uncorrected);
+                }
+                last_memory_errors = Some((*corrected, *uncorrected));
+            }
+            // Flips found while the machine was still settling would skew the rates.
+            Record::Flip { warmup: true, .. } => warmup_flips += 1,
+            Record::Flip { checks: flip_checks, vanished, detected_ms, uncertainty, diffs, .. } => {
+                flips += 1;
+                let inverted: Vec<bool> = diffs.iter().filter_map(|diff| diff.inverted).collect();
+                if !inverted.is_empty() {
+                    let (normal, inverted_only, both) = halves.get_or_insert((0, 0, 0));
+                    match (inverted.contains(&false), inverted.contains(&true)) {
+                        (true, true) => *both += 1,
+                        (false, _) => *inverted_only += 1,
+                        (true, false) => *normal += 1,
+                    }
+                }
+                let mut nodes: Vec<u32> = diffs.iter().filter_map(|diff| diff.numa_node).collect();
+                nodes.sort();
+                nodes.dedup();
+                for node in nodes {
+                    *by_numa_node.entry(node).or_default() += 1;
+                }
+                if uncertainty.as_ref().is_some_and(|uncertainty| uncertainty.resident_fraction.is_some()) {
+                    reduced_flips += 1;
+                }
+                if uncertainty.as_ref().is_some_and(|uncertainty| uncertainty.heavy_smi) {
+                    heavy_smi_flips += 1;
+                }
+                xmp_flips += xmp as u64;
+                checks += flip_checks;
+                if *vanished {
+                    vanished_flips += 1;
+                }
+                let latest = match uncertainty.as_ref().and_then(|uncertainty| uncertainty.detected_ns) {
+                    Some(detected_ns) => detected_ns as f64 / 1e6,
+                    None => *detected_ms as f64,
+                };
+                let window_ms = uncertainty.as_ref().map_or(0, |uncertainty| uncertainty.window_ms);
+                flip_times.push((latest - window_ms as f64, latest));
+                let (hour, weekday) = hour_and_weekday(*detected_ms, args.time_zone);
+                by_hour[hour] += 1;
+                by_weekday[weekday] += 1;
+            }
+        }
+    }
+
+    println!("Sessions: {}", sessions);
+    // Older versions didn't log their shutdowns, so without any there is nothing to tell.
+    if shutdowns > 0 {
+        println!("Sessions that stopped cleanly: {} of {}", shutdowns, sessions);
+    }
+    println!("Bitflips: {} ({} of them could not be located)", flips, vanished_flips);
+    println!("Integrity checks leading up to bitflips: {}", checks);
+    if warmup_flips > 0 {
+        println!("Bitflips during the warm-up, left out of the rates: {}", warmup_flips);
+    }
+    if dropped > 0 {
+        println!("Heartbeats and status updates dropped because logging couldn't keep up: {}", dropped);
+    }
+    if !sequences.is_empty() {
+        // Records that went missing leave gaps in the numbers, records that were merged in twice repeat them.
+        sequences.sort_unstable();
+        let duplicated = sequences.windows(2).filter(|pair| pair[0] == pair[1]).count();
+        let missing: u64 = sequences.windows(2).map(|pair| (pair[1] - pair[0]).saturating_sub(1)).sum();
+        println!("Records missing by their sequence numbers: {}, duplicated: {}", missing, duplicated);
+    }
+    if disagreements > 0 {
+        println!("Checks where the checksums and the full scan disagreed: {}", disagreements);
+    }
+    if panics > 0 {
+        println!("Checks that panicked and were started over: {}", panics);
+    }
+    if paging_heartbeats > 0 {
+        // While the machine swaps some of the detector may have been on disk rather than exposed in memory.
+        println!("Heartbeat intervals with swapping: {} of {}", swapping_heartbeats, paging_heartbeats);
+    }
+    if let Some(reduced_checks) = reduced_checks {
+        println!("Checks with part of the detector swapped out: {}", reduced_checks);
+    }
+    if reduced_flips > 0 {
+        println!("Bitflips found with part of the detector swapped out: {}", reduced_flips);
+    }
+    if let Some((late, missed, late_p99_ms)) = cadence {
+        println!(
+            "Checks that started late: {}, deadlines missed: {}, 99% of the checks started within {:.1} ms of their deadline",
+            late, missed, late_p99_ms
+        );
+    }
+    if let Some((normal, inverted, both)) = halves {
+        // A particle only hits one half, changes in both at once look like a bug in the checks rather than a flip.
+        println!(
+            "Bitflips in the normal half: {}, in the inverted half: {}, in both halves at once: {}",
+            normal, inverted, both
+        );
+    }
+    if xmp_sessions > 0 {
+        // Overclocked memory has far more errors of its own, they don't compare with other stations' flips.
+        println!(
+            "Sessions on memory running faster than it is rated for: {}, with {} bitflips",
+            xmp_sessions, xmp_flips
+        );
+    }
+    if let Some((heavy, count, slow)) = smi {
+        let counted = count.map_or("not counted".to_string(), |count| count.to_string());
+        println!("Checks during heavy SMI activity: {} (SMIs: {}, slow scans: {})", heavy, counted, slow);
+    }
+    if heavy_smi_flips > 0 {
+        // Firmware can touch memory while it runs, these are worth a second look before counting them as cosmic rays.
+        println!("Bitflips found during heavy SMI activity: {}", heavy_smi_flips);
+    }
+    if let Some((corrected, uncorrected)) = memory_errors {
+        println!(
+            "Memory errors in the rest of the machine while running: {} corrected, {} uncorrected",
+            corrected, uncorrected
+        );
+    }
+
+    if flips > 0 {
+        let zone = match args.time_zone {
+            TimeZone::Local => "local time".to_string(),
+            TimeZone::Fixed(0) => "UTC".to_string(),
+            TimeZone::Fixed(offset) => {
+                format!("UTC{}{:02}:{:02}", if offset < 0 { '-' } else { '+' }, offset.abs() / 3600, offset.abs() % 3600 / 60)
+            }
+        };
+        // Cosmic rays don't keep office hours, bitflips that bunch up at some times point at something on site.
+        println!("\nBitflips by hour of the day ({}):", zone);
+        let hours: Vec<String> = (0..24).map(|hour| format!("{:02}", hour)).collect();
+        print_histogram(&hours, &by_hour);
+        println!("\nBitflips by day of the week ({}):", zone);
+        print_histogram(&WEEKDAYS.map(String::from), &by_weekday);
+        if !by_numa_node.is_empty() {
+            // Every node holds the same share of the detector with --numa, a node with far more flips has worse memory.
+            println!("\nBitflips by NUMA node:");
+            let nodes: Vec<String> = by_numa_node.keys().map(|node| format!("node {}", node)).collect();
+            print_histogram(&nodes, &by_numa_node.values().copied().collect::<Vec<u64>>());
+        }
+        println!();
+    }
+
+    let (exposure, source) = exposure(&args.file_path, args.ledger_path.clone(), &records)?;
+    println!("Exposure: {:.3} GB hours, from {}", exposure, source);
+    if flips == 0 {
+        if exposure > 0.0 {
+            // Seeing nothing still bounds the rate: with Poisson statistics the chance of no events at all
+            // drops below 1 - CL once the expected number of events exceeds -ln(1 - CL).
+            for confidence in CONFIDENCE_LEVELS {
+                println!(
+                    "Upper limit on the bitflip rate at {:.0}% confidence: {:.3e} per GB hour",
+                    confidence * 100.0,
+                    -(1.0 - confidence).ln() / exposure
+                );
+            }
+        } else {
+            println!("No exposure to put an upper limit on the bitflip rate, run with --heartbeat-interval or keep the ledger");
+        }
+    }
+    if let Some(section) =
+        cross_section(flips, exposure, args.reference_flux, args.reference_flux_uncertainty, CROSS_SECTION_CONFIDENCE)
+    {
+        println!(
+            "Cross-section per bit at a flux of {} per cm² per hour: {:.3e} cm², {:.0}% interval {:.3e} to {:.3e} cm² \
+             ({:.3e} to {:.3e} from counting alone)",
+            args.reference_flux,
+            section.per_bit_cm2,
+            CROSS_SECTION_CONFIDENCE * 100.0,
+            section.lower_cm2,
+            section.upper_cm2,
+            section.statistical_cm2.0,
+            section.statistical_cm2.1
+        );
+        println!("Bitflip rate: {:.1} FIT per Mbit", section.fit_per_mbit);
+    }
+
+    if let Some(path) = &args.community {
+        let dataset = CommunityDataset::load(path)?;
+        let band = altitude_band(altitude_m);
+        let memory_type = memory_type.unwrap_or_else(|| "unknown".into());
+        match dataset.comparable(&band, &memory_type) {
+            Some(community) => {
+                println!(
+                    "\nCommunity rate at {} with {} memory: {} from {} stations over {:.1} GB hours",
+                    community.band,
+                    community.memory_type,
+                    community.flips_per_gb_hour.map_or("no exposure yet".into(), |rate| format!("{:.3e} per GB hour", rate)),
+                    community.stations,
+                    community.gb_hours
+                );
+                if let Some(community_rate) = community.flips_per_gb_hour.filter(|_| exposure > 0.0) {
+                    let expected = community_rate * exposure;
+                    println!(
+                        "This station: {:.3e} per GB hour, {} bitflips where the community rate expects {:.2}",
+                        flips as f64 / exposure,
+                        flips,
+                        expected
+                    );
+                    // A rate far off in either direction is worth a look at the station before comparing further.
+                    let (chance, direction) = match flips as f64 >= expected {
+                        true => (poisson_tail(flips, expected), "many or more"),
+                        false => (1.0 - poisson_tail(flips + 1, expected), "few or fewer"),
+                    };
+                    println!("Chance of this {} at the community rate: {:.1e}", direction, chance);
+                }
+            }
+            None => println!("\nNo station of the community is in the {} altitude band", band),
+        }
+    }
+
+    if let Some(path) = &args.coincidences {
+        let events = read_event_times(path)?;
+        println!("\nCoincidences with the {} events in {}:", events.len(), path.display());
+        for &window_ms in &args.coincidence_window {
+            let found = coincidences(&flip_times, &events, window_ms);
+            println!(
+                "  Within {} ms of {} bitflips: {} events, {:.2} expected by chance, {:.1e} likely by chance",
+                found.window_ms, found.flips, found.observed, found.expected, found.p
+            );
+        }
+        // Events caused by the same shower as a flip pile up at the offset of the check that found it.
+        let range_ms = args.coincidence_window.iter().copied().fold(0.0, f64::max);
+        if range_ms > 0.0 && !flip_times.is_empty() {
+            println!("\nEvents by offset from the detection of bitflips (ms):");
+            let bin_width = 2.0 * range_ms / OFFSET_BINS as f64;
+            let labels: Vec<String> =
+                (0..OFFSET_BINS).map(|bin| format!("{:>+9.0}", bin as f64 * bin_width - range_ms)).collect();
+            print_histogram(&labels, &offset_histogram(&flip_times, &events, range_ms));
+        }
+    }
+
+    Ok(())
+}
+
+/// The exposure of a log in GB hours, and where it comes from. The ledger keeps track of the exposure all the
+/// time, the log only as often as something is written to it, so the ledger is used when there is one.
+pub fn exposure(file_path: &Path, ledger_path: Option<PathBuf>, records: &[Record]) -> Result<(f64, String), String> {
+    let ledger_path = ledger_path.unwrap_or_else(|| PathBuf::from(format!("{}.ledger", file_path.display())));
+    Ok(match ledger_path.exists() {
+        true => (Ledger::open(ledger_path.clone())?.total_gb_hours(), ledger_path.display().to_string()),
+        false => (log_gb_hours(records), "the log".to_string()),
+    })
+}
+
+/// The exposure of the sessions in a log, from the detector size and the time between the first and the last
+/// record of each, less the warm-up.
+pub fn log_gb_hours(records: &[Record]) -> f64 {
+    let mut gb_hours: f64 = 0.0;
+    let mut detector_bytes: Option<u64> = None;
+    let mut warmup_ms: u64 = 0;
+    let mut session_span: Option<(u128, u128)> = None;
+    for record in records {
+        let timestamp = match record {
+            Record::Session(session) => {
+                gb_hours += session_gb_hours(detector_bytes, warmup_ms, session_span.take());
+                detector_bytes = session.detector_bytes;
+                warmup_ms = session.warmup_ms.unwrap_or(0);
+                continue;
+            }
+            Record::Heartbeat { timestamp_ms, .. }
+            | Record::Dropped { timestamp_ms, .. }
+            | Record::Disagreement { timestamp_ms, .. }
+            | Record::Panic { timestamp_ms, .. }
+            | Record::Shutdown { timestamp_ms, .. }
+            | Record::MemoryErrors { timestamp_ms, .. } => *timestamp_ms,
+            Record::Start { started_ms, .. } => *started_ms,
+            Record::Flip { detected_ms, .. } => *detected_ms,
+        };
+        let (first, last) = session_span.get_or_insert((timestamp, timestamp));
+        *first = (*first).min(timestamp);
+        *last = (*last).max(timestamp);
+    }
+    gb_hours + session_gb_hours(detector_bytes, warmup_ms, session_span)
+}
+
+/// Sessions written by older versions don't say how large their detector was, they count as no exposure.
+fn session_gb_hours(detector_bytes: Option<u64>, warmup_ms: u64, span: Option<(u128, u128)>) -> f64 {
+    match (detector_bytes, span) {
+        (Some(bytes), Some((first, last))) => {
+            gb_hours(bytes as usize, Duration::from_millis(((last - first) as u64).saturating_sub(warmup_ms)))
+        }
+        _ => 0.0,
+    }
+}
+
+/// The hour of the day, and the day of the week counted from Monday, of a timestamp in the given time zone.
+fn hour_and_weekday(timestamp_ms: u128, time_zone: TimeZone) -> (usize, usize) {
+    let seconds = (timestamp_ms / 1000) as i64;
+    let offset = match time_zone {
+        TimeZone::Local => local_utc_offset(seconds.max(0) as u64),
+        TimeZone::Fixed(offset) => offset,
+    };
+    let local = seconds + offset;
+    let hour = local.rem_euclid(86400) / 3600;
+    // 1970-01-01 was a Thursday.
+    let weekday = (local.div_euclid(86400) + 3).rem_euclid(7);
+    (hour as usize, weekday as usize)
+}
+
+fn print_histogram(labels: &[String], counts: &[u64]) {
+    let max = counts.iter().copied().max().unwrap_or_default().max(1);
+    for (label, count) in labels.iter().zip(counts) {
+        let bar = (count * HISTOGRAM_WIDTH).div_ceil(max);
+        println!("  {:>3} {:>6} {}", label, count, "#".repeat(bar as usize));
+    }
+}
@@ -0,0 +1,106 @@
+use std::time::Duration;
+
+use serde_json::json;
+
+use crate::rate::RateEstimate;
+
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+/// However unlikely they are, fewer events than this in the recent window are no burst. A station that expects one
+/// flip a year would otherwise raise an alarm over the very first one.
+const MIN_BURST_EVENTS: f64 = 3.0;
+
+/// A recent bitflip rate far above what the station usually sees. Cosmic rays don't come in bursts,
+/// failing memory, overheating and electrical problems do.
+#[derive(Clone, Debug)]
+pub struct Anomaly {
+    pub recent_per_gb_day: f64,
+    pub baseline_per_gb_day: f64,
+    /// How many standard deviations the recent events are above the ones the baseline rate expects.
+    pub sigma: f64,
+}
+
+/// Compares the recent rate with a baseline over a much longer window, which starts out at the expected rate
+/// so that a station without any history yet has something to compare with.
+pub struct AnomalyDetector {
+    baseline: RateEstimate,
+    expected_per_gb_day: f64,
+    threshold: f64,
+    alerted: bool,
+}
+
+impl AnomalyDetector {
+    /// `threshold` is in standard deviations of the number of events.
+    pub fn new(baseline_window: Duration, expected_per_gb_day: f64, threshold: f64) -> Self {
+        AnomalyDetector { baseline: RateEstimate::new(baseline_window), expected_per_gb_day, threshold, alerted: false }
+    }
+
+    pub fn add_exposure(&mut self, detector_bytes: usize, duration: Duration) {
+        self.baseline.add_exposure(detector_bytes, duration);
+    }
+
+    pub fn add_event(&mut self) {
+        self.baseline.add_event();
+    }
+
+    /// Returns the anomaly when the recent events exceed the ones expected from the baseline by more than the
+    /// threshold, once until they fall below it again.
+    pub fn check(&mut self, recent: &RateEstimate) -> Option<Anomaly> {
+        let baseline_per_gb_day = self.baseline.per_gb_day().unwrap_or_default().max(self.expected_per_gb_day);
+        let expected = baseline_per_gb_day * recent.gb_days();
+        if expected <= 0.0 {
+            return None;
+        }
+        // The events are Poisson distributed, their standard deviation is the square root of the expected number.
+        let sigma = (recent.events() - expected) / expected.sqrt();
+        let anomalous = sigma > self.threshold && recent.events() >= MIN_BURST_EVENTS;
+        let first = anomalous && !self.alerted;
+        self.alerted = anomalous;
+        first.then(|| Anomaly {
+            recent_per_gb_day: recent.per_gb_day().unwrap_or_default(),
+            baseline_per_gb_day,
+            sigma,
+        })
+    }
+}
+
+/// Posts anomalies as JSON to a webhook. The message is in `text`, which chat services such as Slack and
+/// Mattermost show as it is.
+pub struct AlertWebhook {
+    url: String,
+    station_id: String,
+}
+
+impl AlertWebhook {
+    pub fn new(url: String, station_id: String) -> Self {
+        AlertWebhook { url, station_id }
+    }
+
+    pub fn send(&self, anomaly: &Anomaly, text: &str) -> Result<(), String> {
+        let body = json!({
+            "text": text,
+            "station_id": self.station_id,
+            "recent_per_gb_day": anomaly.recent_per_gb_day,
+            "baseline_per_gb_day": anomaly.baseline_per_gb_day,
+            "sigma": anomaly.sigma,
+        });
+        self.post(body)
+    }
+
+    /// Posts a change in the free disk space of the log.
+    pub fn send_disk_space(&self, free_bytes: u64, text: &str) -> Result<(), String> {
+        let body = json!({
+            "text": text,
+            "station_id": self.station_id,
+            "free_bytes": free_bytes,
+        });
+        self.post(body)
+    }
+
+    fn post(&self, body: serde_json::Value) -> Result<(), String> {
+        ureq::post(&self.url)
+            .timeout(WEBHOOK_TIMEOUT)
+            .send_json(body)
+            .map_err(|e| format!("Unable to send the alert to the webhook: {}", e))?;
+        Ok(())
+    }
+}
@@ -0,0 +1,73 @@
+use std::error::Error;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use sha2::{Digest, Sha256};
+
+use crate::{
+    config::{BackupArgs, GlobalArgs},
+    exposure::civil_from_days,
+};
+
+/// Copies a file next to itself with the current UTC time in its name, e.g. `results.txt.backup-20240131T120000Z`,
+/// makes sure the copy reads back identical, and notes it in `<file>.backups`. Returns the path of the copy.
+/// Anything that rewrites months of data in place makes one of these first.
+pub fn backup(path: &Path) -> Result<PathBuf, String> {
+    let backup_path = PathBuf::from(format!("{}.backup-{}", path.display(), utc_timestamp()));
+    if backup_path.exists() {
+        return Err(format!("{} already exists", backup_path.display()));
+    }
+    fs::copy(path, &backup_path)
+        .and_then(|_| File::open(&backup_path)?.sync_all())
+        .map_err(|e| format!("Unable to copy {} to {}: {}", path.display(), backup_path.display(), e))?;
+
+    let checksum = sha256_file(path)?;
+    if sha256_file(&backup_path)? != checksum {
+        return Err(format!("The backup {} does not match {}", backup_path.display(), path.display()));
+    }
+
+    let list_path = PathBuf::from(format!("{}.backups", path.display()));
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&list_path)
+        .and_then(|mut list| writeln!(list, "{},{}", backup_path.display(), checksum))
+        .map_err(|e| format!("Unable to record the backup in {}: {}", list_path.display(), e))?;
+    Ok(backup_path)
+}
+
+/// Makes a verified backup of a log, ledger or collector database by hand.
+pub fn backup_command(_global: &GlobalArgs, args: BackupArgs) -> Result<(), Box<dyn Error>> {
+    let backup_path = backup(&args.file_path)?;
+    println!("Backed up {} to {}", args.file_path.display(), backup_path.display());
+    Ok(())
+}
+
+fn sha256_file(path: &Path) -> Result<String, String> {
+    let mut hasher = Sha256::new();
+    File::open(path)
+        .and_then(|mut file| io::copy(&mut file, &mut hasher))
+        .map_err(|e| format!("Unable to read {}: {}", path.display(), e))?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// The current time as e.g. `20240131T120000Z`.
+pub fn utc_timestamp() -> String {
+    let seconds = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Time went backwards")
+        .as_secs();
+    let (year, month, day) = civil_from_days((seconds / 86400) as i64);
+    let seconds_of_day = seconds % 86400;
+    format!(
+        "{:04}{:02}{:02}T{:02}{:02}{:02}Z",
+        year,
+        month,
+        day,
+        seconds_of_day / 3600,
+        seconds_of_day % 3600 / 60,
+        seconds_of_day % 60
+    )
+}
@@ -0,0 +1,146 @@
+use std::error::Error;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+use crate::{
+    config::{BenchArgs, GlobalArgs},
+    detector::{Detector, ScanMode},
+    mem_size,
+    rapl::{Rapl, Reading},
+};
+
+/// The checks timed, one for every scan mode. The default mode keeps the name it had before there were others, so
+/// that reports still compare.
+const CHECK_STRATEGIES: [(&str, ScanMode); 3] =
+    [("check", ScanMode::Words), ("check_bytes", ScanMode::Bytes), ("check_simd", ScanMode::Simd)];
+
+/// One timed strategy on one detector size.
+#[derive(Serialize)]
+struct Measurement {
+    strategy: &'static str,
+    size_bytes: usize,
+    iterations: u32,
+    seconds_per_pass: f64,
+    bytes_per_second: f64,
+    /// The energy the processors and memory used, where their RAPL counters can be read.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    joules_per_pass: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    joules_per_gb: Option<f64>,
+}
+
+impl Measurement {
+    fn new(strategy: &'static str, size: usize, iterations: u32, total: Duration, joules: Option<f64>) -> Self {
+        let seconds_per_pass = (total / iterations).as_secs_f64();
+        let joules_per_pass = joules.map(|joules| joules / iterations as f64);
+        Measurement {
+            strategy,
+            size_bytes: size,
+            iterations,
+            seconds_per_pass,
+            bytes_per_second: size as f64 / seconds_per_pass.max(f64::EPSILON),
+            joules_per_pass,
+            joules_per_gb: joules_per_pass.map(|joules| joules / (size as f64 / 1e9)),
+        }
+    }
+}
+
+/// Runs `pass` the given number of times, returning how long it took and how much energy it used.
+fn time_passes(iterations: u32, rapl: &Option<Rapl>, mut pass: impl FnMut()) -> (Duration, Option<f64>) {
+    let read = || rapl.as_ref().and_then(|rapl| rapl.read().ok());
+    let energy_start: Option<Reading> = read();
+    let start = Instant::now();
+    for _ in 0..iterations {
+        pass();
+    }
+    let elapsed = start.elapsed();
+    let joules = match (rapl, energy_start, read()) {
+        (Some(rapl), Some(energy_start), Some(energy_end)) => Some(rapl.joules_between(&energy_start, &energy_end)),
+        _ => None,
+    };
+    (elapsed, joules)
+}
+
+/// The results of a whole benchmark run, in the shape written by `--json` so that releases can be compared.
+#[derive(Serialize)]
+struct Report {
+    version: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    git_hash: Option<&'static str>,
+    measurements: Vec<Measurement>,
+}
+
+/// Times how long it takes to write and to check detectors of the given sizes.
+pub fn bench(_global: &GlobalArgs, args: BenchArgs) -> Result<(), Box<dyn Error>> {
+    let iterations = args.iterations.max(1);
+    let rapl = match Rapl::open() {
+        Ok(rapl) => Some(rapl),
+        Err(err) => {
+            if !args.json {
+                println!("Not measuring energy: {}", err);
+            }
+            None
+        }
+    };
+
+    let mut measurements = Vec::new();
+    for &size in &args.memory_to_occupy {
+        if !args.json {
+            println!("Benchmarking a detector of {} over {} iterations", mem_size(size as u64), iterations);
+        }
+        let mut detector = Detector::new(0, size);
+        detector.write(42);
+        let first = measurements.len();
+
+        let (elapsed, joules) = time_passes(iterations, &rapl, || detector.reset());
+        measurements.push(Measurement::new("write", size, iterations, elapsed, joules));
+
+        for (strategy, scan_mode) in CHECK_STRATEGIES {
+            detector.set_scan_mode(scan_mode);
+            let (elapsed, joules) = time_passes(iterations, &rapl, || {
+                detector.is_intact();
+            });
+            measurements.push(Measurement::new(strategy, size, iterations, elapsed, joules));
+        }
+
+        detector.set_scan_mode(ScanMode::Words);
+        detector.set_paranoid_reads(true);
+        let (elapsed, joules) = time_passes(iterations, &rapl, || {
+            detector.is_intact();
+        });
+        measurements.push(Measurement::new("check_paranoid", size, iterations, elapsed, joules));
+
+        if !args.json {
+            for measurement in &measurements[first..] {
+                print_throughput(measurement);
+            }
+        }
+    }
+
+    if args.json {
+        let report = Report {
+            version: env!("CARGO_PKG_VERSION"),
+            git_hash: option_env!("GIT_HASH"),
+            measurements,
+        };
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    }
+    Ok(())
+}
+
+fn print_throughput(measurement: &Measurement) {
+    let energy = match (measurement.joules_per_pass, measurement.joules_per_gb) {
+        (Some(joules_per_pass), Some(joules_per_gb)) => {
+            format!(", {:.3} J per pass, {:.3} J/GB", joules_per_pass, joules_per_gb)
+        }
+        _ => String::new(),
+    };
+    println!(
+        "{}: {:?} per pass, {}/s{}",
+        measurement.strategy,
+        Duration::from_secs_f64(measurement.seconds_per_pass),
+        mem_size(measurement.bytes_per_second as u64),
+        energy
+    );
+}
@@ -0,0 +1,520 @@
+use crate::record::{FlipFields, HeartbeatFields, Record, SessionInfo, Tags, Uncertainty};
+use crate::station::Location;
+
+/// Every binary log starts with these bytes, followed by the format version.
+pub const MAGIC: &[u8; 4] = b"CRDB";
+const FORMAT_VERSION: u8 = 1;
+
+const TAG_SESSION: u8 = 0;
+const TAG_START: u8 = 1;
+const TAG_FLIP: u8 = 2;
+const TAG_HEARTBEAT: u8 = 3;
+const TAG_MEMORY_ERRORS: u8 = 4;
+/// A flip followed by its uncertainty as key value pairs.
+const TAG_FLIP_WITH_UNCERTAINTY: u8 = 5;
+const TAG_DROPPED: u8 = 6;
+/// A heartbeat followed by the energy of the checks as key value pairs.
+const TAG_HEARTBEAT_WITH_ENERGY: u8 = 7;
+/// A heartbeat followed by any of its energy, paging, residency, cadence and SMIs as key value pairs.
+const TAG_HEARTBEAT_WITH_FIELDS: u8 = 8;
+const TAG_DISAGREEMENT: u8 = 9;
+/// The sequence number of the next record plus one, or zero for none, written only where it doesn't follow from the
+/// previous record.
+const TAG_SEQUENCE: u8 = 10;
+/// The tags of the following records as key value pairs, written only where they change.
+const TAG_TAGS: u8 = 11;
+/// A flip followed by any of its uncertainty, number of changed bytes and diffs as key value pairs.
+const TAG_FLIP_WITH_FIELDS: u8 = 12;
+const TAG_PANIC: u8 = 13;
+const TAG_SHUTDOWN: u8 = 14;
+
+/// Returns the header a new binary log file has to start with.
+pub fn header() -> Vec<u8> {
+    let mut bytes = MAGIC.to_vec();
+    bytes.push(FORMAT_VERSION);
+    bytes
+}
+
+/// Encodes records compactly by storing timestamps and check counters as varint deltas from the previous record.
+/// A heartbeat a few seconds after the previous one usually takes five bytes instead of a forty byte CSV line.
+/// The deltas start over at every session record, so sessions can be appended to the same file by different processes.
+#[derive(Default)]
+pub struct BinaryEncoder {
+    deltas: DeltaState,
+}
+
+#[derive(Default, Clone)]
+struct DeltaState {
+    last_timestamp: u64,
+    last_checks: u64,
+    /// The sequence number the next record has unless the log says otherwise.
+    next_sequence: Option<u64>,
+    /// The tags of the next record unless the log says otherwise.
+    tags: Tags,
+}
+
+impl DeltaState {
+    fn timestamp_delta(&mut self, timestamp: u128) -> i64 {
+        let timestamp = timestamp as u64;
+        let delta = timestamp.wrapping_sub(self.last_timestamp) as i64;
+        self.last_timestamp = timestamp;
+        delta
+    }
+
+    fn apply_timestamp_delta(&mut self, delta: i64) -> u128 {
+        self.last_timestamp = self.last_timestamp.wrapping_add(delta as u64);
+        self.last_timestamp as u128
+    }
+
+    fn checks_delta(&mut self, checks: u64) -> i64 {
+        let delta = checks.wrapping_sub(self.last_checks) as i64;
+        self.last_checks = checks;
+        delta
+    }
+
+    fn apply_checks_delta(&mut self, delta: i64) -> u64 {
+        self.last_checks = self.last_checks.wrapping_add(delta as u64);
+        self.last_checks
+    }
+}
+
+impl BinaryEncoder {
+    pub fn encode(&mut self, record: &Record) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        if !matches!(record, Record::Session(_)) {
+            if record.sequence() != self.deltas.next_sequence {
+                bytes.push(TAG_SEQUENCE);
+                write_varint(&mut bytes, record.sequence().map_or(0, |sequence| sequence + 1));
+            }
+            self.deltas.next_sequence = record.sequence().map(|sequence| sequence + 1);
+        }
+        if let Some(tags) = record.tags().filter(|tags| **tags != self.deltas.tags) {
+            bytes.push(TAG_TAGS);
+            write_fields(&mut bytes, tags.iter().map(|(key, value)| (key.as_str(), value.clone())).collect());
+            self.deltas.tags = tags.clone();
+        }
+        match record {
+            Record::Session(session) => {
+                self.deltas = DeltaState::default();
+                bytes.push(TAG_SESSION);
+                write_fields(&mut bytes, session.fields());
+            }
+            Record::Start { started_ms, delay_ms, pid, location, .. } => {
+                bytes.push(TAG_START);
+                write_signed(&mut bytes, self.deltas.timestamp_delta(*started_ms));
+                write_varint(&mut bytes, *delay_ms);
+                // Zero means no PID, which no real process has.
+                write_varint(&mut bytes, pid.map(|pid| pid as u64 + 1).unwrap_or(0));
+                write_location(&mut bytes, location);
+            }
+            Record::Flip { started_ms, delay_ms, checks, vanished, detected_ms, location, .. } => {
+                let extra = record.flip_fields().unwrap_or_default();
+                // Flips with nothing but their uncertainty stay readable by older versions.
+                let only_uncertainty = extra.changed_bytes.is_none() && extra.diffs.is_empty() && !extra.warmup;
+                bytes.push(match (&extra.uncertainty, only_uncertainty) {
+                    (None, true) => TAG_FLIP,
+                    (Some(_), true) => TAG_FLIP_WITH_UNCERTAINTY,
+                    _ => TAG_FLIP_WITH_FIELDS,
+                });
+                write_signed(&mut bytes, self.deltas.timestamp_delta(*started_ms));
+                write_varint(&mut bytes, *delay_ms);
+                write_varint(&mut bytes, *checks);
+                bytes.push(*vanished as u8);
+                write_signed(&mut bytes, self.deltas.timestamp_delta(*detected_ms));
+                write_location(&mut bytes, location);
+                if !extra.is_empty() {
+                    write_fields(&mut bytes, extra.fields());
+                }
+            }
+            Record::Heartbeat { timestamp_ms, total_checks, .. } => {
+                let extra = record.heartbeat_fields().unwrap_or_default();
+                // Heartbeats with nothing but their energy stay readable by older versions.
+                let only_energy = extra.paging.is_none()
+                    && extra.residency.is_none()
+                    && extra.cadence.is_none()
+                    && extra.smi.is_none();
+                bytes.push(match (&extra.energy, only_energy) {
+                    (None, true) => TAG_HEARTBEAT,
+                    (Some(_), true) => TAG_HEARTBEAT_WITH_ENERGY,
+                    _ => TAG_HEARTBEAT_WITH_FIELDS,
+                });
+                write_signed(&mut bytes, self.deltas.timestamp_delta(*timestamp_ms));
+                write_signed(&mut bytes, self.deltas.checks_delta(*total_checks));
+                if !extra.is_empty() {
+                    write_fields(&mut bytes, extra.fields());
+                }
+            }
+            Record::MemoryErrors { timestamp_ms, corrected, uncorrected, .. } => {
+                bytes.push(TAG_MEMORY_ERRORS);
+                write_signed(&mut bytes, self.deltas.timestamp_delta(*timestamp_ms));
+                write_varint(&mut bytes, *corrected);
+                write_varint(&mut bytes, *uncorrected);
+            }
+            Record::Dropped { timestamp_ms, records, .. } => {
+                bytes.push(TAG_DROPPED);
+                write_signed(&mut bytes, self.deltas.timestamp_delta(*timestamp_ms));
+                write_varint(&mut bytes, *records);
+            }
+            Record::Disagreement { timestamp_ms, total_checks, crc_intact, scan_intact, .. } => {
+                bytes.push(TAG_DISAGREEMENT);
+                write_signed(&mut bytes, self.deltas.timestamp_delta(*timestamp_ms));
+                write_signed(&mut bytes, self.deltas.checks_delta(*total_checks));
+                bytes.push(*crc_intact as u8);
+                bytes.push(*scan_intact as u8);
+            }
+            Record::Panic { timestamp_ms, total_checks, message, .. } => {
+                bytes.push(TAG_PANIC);
+                write_signed(&mut bytes, self.deltas.timestamp_delta(*timestamp_ms));
+                write_signed(&mut bytes, self.deltas.checks_delta(*total_checks));
+                write_str(&mut bytes, message);
+            }
+            Record::Shutdown { timestamp_ms, total_checks, flips, uptime_ms, .. } => {
+                bytes.push(TAG_SHUTDOWN);
+                write_signed(&mut bytes, self.deltas.timestamp_delta(*timestamp_ms));
+                write_signed(&mut bytes, self.deltas.checks_delta(*total_checks));
+                write_varint(&mut bytes, *flips);
+                write_varint(&mut bytes, *uptime_ms);
+            }
+        }
+        bytes
+    }
+}
+
+/// Decodes a whole binary log, header included.
+pub fn decode_log(bytes: &[u8]) -> Result<Vec<Record>, String> {
+    let header = header();
+    if !bytes.starts_with(&header) {
+        return Err("Not a binary log file of a supported version".into());
+    }
+    let (records, consumed) = BinaryDecoder::default().decode(&bytes[header.len()..])?;
+    if header.len() + consumed < bytes.len() {
+        return Err("The binary log ends in the middle of a record".into());
+    }
+    Ok(records)
+}
+
+/// Decodes records as they are appended to a binary log, keeping the deltas between calls.
+#[derive(Default)]
+pub struct BinaryDecoder {
+    deltas: DeltaState,
+}
+
+impl BinaryDecoder {
+    /// Decodes the complete records at the start of `bytes`, which follow the ones decoded before, and returns
+    /// them together with the number of bytes they took. A record that isn't completely written yet is left over.
+    pub fn decode(&mut self, bytes: &[u8]) -> Result<(Vec<Record>, usize), String> {
+        let mut reader = Reader { bytes, position: 0, truncated: false };
+        let mut records = Vec::new();
+        let mut consumed = 0;
+        while reader.position < bytes.len() {
+            let deltas = self.deltas.clone();
+            match decode_record(&mut reader, &mut self.deltas) {
+                Ok(record) => records.push(record),
+                Err(_) if reader.truncated => {
+                    self.deltas = deltas;
+                    break;
+                }
+                Err(err) => return Err(err),
+            }
+            consumed = reader.position;
+        }
+        Ok((records, consumed))
+    }
+}
+
+fn decode_record(reader: &mut Reader, deltas: &mut DeltaState) -> Result<Record, String> {
+    let mut record = match reader.byte()? {
+        TAG_SEQUENCE => {
+            deltas.next_sequence = reader.varint()?.checked_sub(1);
+            return decode_record(reader, deltas);
+        }
+        TAG_TAGS => {
+            deltas.tags = reader.fields()?.into_iter().collect();
+            return decode_record(reader, deltas);
+        }
+        TAG_SESSION => {
+            *deltas = DeltaState::default();
+            let fields = reader.fields()?;
+            Record::Session(SessionInfo::from_fields(
+                fields.iter().map(|(key, value)| (key.as_str(), value.as_str())),
+            )?)
+        }
+        TAG_START => Record::Start {
+            started_ms: deltas.apply_timestamp_delta(reader.signed()?),
+            delay_ms: reader.varint()?,
+            pid: match reader.varint()? {
+                0 => None,
+                pid => Some((pid - 1) as u32),
+            },
+            location: reader.location()?,
+            tags: Tags::new(),
+            sequence: None,
+        },
+        tag @ (TAG_FLIP | TAG_FLIP_WITH_UNCERTAINTY | TAG_FLIP_WITH_FIELDS) => {
+            let started_ms = deltas.apply_timestamp_delta(reader.signed()?);
+            let delay_ms = reader.varint()?;
+            let checks = reader.varint()?;
+            let vanished = reader.byte()? != 0;
+            let detected_ms = deltas.apply_timestamp_delta(reader.signed()?);
+            let location = reader.location()?;
+            let extra = match tag {
+                TAG_FLIP => FlipFields::default(),
+                TAG_FLIP_WITH_UNCERTAINTY => {
+                    let fields = reader.fields()?;
+                    FlipFields {
+                        uncertainty: Some(Uncertainty::from_fields(
+                            fields.iter().map(|(key, value)| (key.as_str(), value.as_str())),
+                        )?),
+                        ..Default::default()
+                    }
+                }
+                _ => {
+                    let fields = reader.fields()?;
+                    FlipFields::from_fields(fields.iter().map(|(key, value)| (key.as_str(), value.as_str())))?
+                }
+            };
+            Record::Flip {
+                started_ms,
+                delay_ms,
+                checks,
+                vanished,
+                detected_ms,
+                location,
+                uncertainty: extra.uncertainty,
+                changed_bytes: extra.changed_bytes,
+                diffs: extra.diffs,
+                warmup: extra.warmup,
+                tags: Tags::new(),
+                sequence: None,
+            }
+        }
+        tag @ (TAG_HEARTBEAT | TAG_HEARTBEAT_WITH_ENERGY | TAG_HEARTBEAT_WITH_FIELDS) => {
+            let timestamp_ms = deltas.apply_timestamp_delta(reader.signed()?);
+            let total_checks = deltas.apply_checks_delta(reader.signed()?);
+            let extra = match tag {
+                TAG_HEARTBEAT => HeartbeatFields::default(),
+                _ => {
+                    let fields = reader.fields()?;
+                    HeartbeatFields::from_fields(fields.iter().map(|(key, value)| (key.as_str(), value.as_str())))?
+                }
+            };
+            Record::Heartbeat {
+                timestamp_ms,
+                total_checks,
+                energy: extra.energy,
+                paging: extra.paging,
+                residency: extra.residency,
+                cadence: extra.cadence,
+                smi: extra.smi,
+                tags: Tags::new(),
+                sequence: None,
+            }
+        }
+        TAG_MEMORY_ERRORS => Record::MemoryErrors {
+            timestamp_ms: deltas.apply_timestamp_delta(reader.signed()?),
+            corrected: reader.varint()?,
+            uncorrected: reader.varint()?,
+            tags: Tags::new(),
+            sequence: None,
+        },
+        TAG_DROPPED => Record::Dropped {
+            timestamp_ms: deltas.apply_timestamp_delta(reader.signed()?),
+            records: reader.varint()?,
+            tags: Tags::new(),
+            sequence: None,
+        },
+        TAG_DISAGREEMENT => Record::Disagreement {
+            timestamp_ms: deltas.apply_timestamp_delta(reader.signed()?),
+            total_checks: deltas.apply_checks_delta(reader.signed()?),
+            crc_intact: reader.byte()? != 0,
+            scan_intact: reader.byte()? != 0,
+            tags: Tags::new(),
+            sequence: None,
+        },
+        TAG_PANIC => Record::Panic {
+            timestamp_ms: deltas.apply_timestamp_delta(reader.signed()?),
+            total_checks: deltas.apply_checks_delta(reader.signed()?),
+            message: reader.string()?,
+            tags: Tags::new(),
+            sequence: None,
+        },
+        TAG_SHUTDOWN => Record::Shutdown {
+            timestamp_ms: deltas.apply_timestamp_delta(reader.signed()?),
+            total_checks: deltas.apply_checks_delta(reader.signed()?),
+            flips: reader.varint()?,
+            uptime_ms: reader.varint()?,
+            tags: Tags::new(),
+            sequence: None,
+        },
+        tag => return Err(format!("Unknown record type {} at byte {}", tag, reader.position - 1)),
+    };
+    if !matches!(record, Record::Session(_)) {
+        record.set_sequence(deltas.next_sequence);
+        record.set_tags(deltas.tags.clone());
+        deltas.next_sequence = deltas.next_sequence.map(|sequence| sequence + 1);
+    }
+    Ok(record)
+}
+
+fn write_varint(bytes: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        bytes.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    bytes.push(value as u8);
+}
+
+/// Zigzag encodes the value so that small negative deltas stay small.
+fn write_signed(bytes: &mut Vec<u8>, value: i64) {
+    write_varint(bytes, ((value << 1) ^ (value >> 63)) as u64);
+}
+
+fn write_str(bytes: &mut Vec<u8>, value: &str) {
+    write_varint(bytes, value.len() as u64);
+    bytes.extend_from_slice(value.as_bytes());
+}
+
+fn write_fields(bytes: &mut Vec<u8>, fields: Vec<(&str, String)>) {
+    write_varint(bytes, fields.len() as u64);
+    for (key, value) in fields {
+        write_str(bytes, key);
+        write_str(bytes, &value);
+    }
+}
+
+fn write_location(bytes: &mut Vec<u8>, location: &Option<Location>) {
+    match location {
+        Some(location) => {
+            bytes.push(1);
+            bytes.extend_from_slice(&location.latitude.to_le_bytes());
+            bytes.extend_from_slice(&location.longitude.to_le_bytes());
+        }
+        None => bytes.push(0),
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+    /// Whether reading ran into the end of the bytes.
+    truncated: bool,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, count: usize) -> Result<&'a [u8], String> {
+        let end = self.position + count;
+        if end > self.bytes.len() {
+            self.truncated = true;
+            return Err("The binary log ends in the middle of a record".into());
+        }
+        let taken = &self.bytes[self.position..end];
+        self.position = end;
+        Ok(taken)
+    }
+
+    fn byte(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn varint(&mut self) -> Result<u64, String> {
+        let mut value: u64 = 0;
+        for shift in (0..64).step_by(7) {
+            let byte = self.byte()?;
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err("Malformed varint in binary log".into())
+    }
+
+    fn signed(&mut self) -> Result<i64, String> {
+        let value = self.varint()?;
+        Ok(((value >> 1) as i64) ^ -((value & 1) as i64))
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        let length = self.varint()? as usize;
+        String::from_utf8(self.take(length)?.to_vec()).map_err(|e| e.to_string())
+    }
+
+    fn f64(&mut self) -> Result<f64, String> {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(self.take(8)?);
+        Ok(f64::from_le_bytes(bytes))
+    }
+
+    fn fields(&mut self) -> Result<Vec<(String, String)>, String> {
+        let count = self.varint()?;
+        let mut fields = Vec::new();
+        for _ in 0..count {
+            fields.push((self.string()?, self.string()?));
+        }
+        Ok(fields)
+    }
+
+    fn location(&mut self) -> Result<Option<Location>, String> {
+        match self.byte()? {
+            0 => Ok(None),
+            _ => Ok(Some(Location {
+                latitude: self.f64()?,
+                longitude: self.f64()?,
+            })),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A session of every kind of record, with sequence numbers and tags that change along the way.
+    const LOG: &str = r#"{"type":"session","session_id":"0b5e","version":"1.2.0","schema":6,"seed":1234}
+{"type":"start","started_ms":1700000000000,"delay_ms":30000,"pid":4321,"location":{"latitude":10.5,"longitude":-11.25},"sequence":1}
+{"type":"heartbeat","timestamp_ms":1700000005000,"total_checks":12,"sequence":2}
+{"type":"flip","started_ms":1700000006000,"delay_ms":30000,"checks":3,"vanished":false,"detected_ms":1700000006100,"location":null,"uncertainty":{"window_ms":5100,"scan_ms":100,"clock":"synchronized","pattern_coverage":0.5},"changed_bytes":1,"diffs":[{"index":4096,"expected":0,"observed":4,"bits":[2],"chunk":0,"offset":4096}],"sequence":3}
+{"type":"dropped","timestamp_ms":1700000007000,"records":2,"tags":{"room":"basement"},"sequence":6}
+{"type":"memory_errors","timestamp_ms":1700000008000,"corrected":1,"uncorrected":0,"tags":{"room":"basement"},"sequence":7}
+{"type":"shutdown","timestamp_ms":1700000009000,"total_checks":20,"flips":1,"uptime_ms":9000,"sequence":8}"#;
+
+    fn records() -> Vec<Record> {
+        LOG.lines().map(|line| serde_json::from_str(line).unwrap()).collect()
+    }
+
+    fn encode(records: &[Record]) -> Vec<u8> {
+        let mut encoder = BinaryEncoder::default();
+        let mut bytes = header();
+        for record in records {
+            bytes.extend(encoder.encode(record));
+        }
+        bytes
+    }
+
+    fn json(records: &[Record]) -> Vec<serde_json::Value> {
+        records.iter().map(|record| serde_json::to_value(record).unwrap()).collect()
+    }
+
+    #[test]
+    fn records_read_back_the_same_as_they_were_written() {
+        let records = records();
+        assert_eq!(json(&decode_log(&encode(&records)).unwrap()), json(&records));
+    }
+
+    #[test]
+    fn a_truncated_last_record_is_left_for_later() {
+        let records = records();
+        let bytes = encode(&records);
+        let complete = encode(&records[..records.len() - 1]).len();
+        let truncated = &bytes[..bytes.len() - 1];
+
+        assert!(decode_log(truncated).is_err());
+        let mut decoder = BinaryDecoder::default();
+        let (decoded, consumed) = decoder.decode(&truncated[header().len()..]).unwrap();
+        assert_eq!(json(&decoded), json(&records[..records.len() - 1]));
+        assert_eq!(header().len() + consumed, complete);
+
+        // Once the rest of it is written it decodes with the deltas of the records before it.
+        let (rest, _) = decoder.decode(&bytes[complete..]).unwrap();
+        assert_eq!(json(&rest), json(&records[records.len() - 1..]));
+    }
+}
@@ -0,0 +1,110 @@
+use std::time::{Duration, Instant};
+
+/// How much a new measurement moves the averages.
+const SMOOTHING: f64 = 0.2;
+
+/// Keeps the detector's average CPU use within a fraction of the machine, by choosing how many threads check the
+/// memory and how long to wait between checks. The CPU time and duration of every check are measured, so when the
+/// machine gets busier and checks get slower the wait grows with them, and the threads are cut back to the cores
+/// that are idle. The scan bandwidth follows from the number of threads, checks are never throttled otherwise.
+pub struct CpuBudget {
+    /// The fraction of all cores together, e.g. 0.05.
+    fraction: f64,
+    cores: usize,
+    threads: usize,
+    /// Averages over the recent checks.
+    cpu_per_check: Option<Duration>,
+    time_per_check: Option<Duration>,
+}
+
+impl CpuBudget {
+    pub fn new(fraction: f64) -> Self {
+        let cores = std::thread::available_parallelism().map_or(1, |cores| cores.get());
+        let mut budget = CpuBudget { fraction, cores, threads: 1, cpu_per_check: None, time_per_check: None };
+        budget.threads = budget.wanted_threads(cores as f64);
+        budget
+    }
+
+    pub fn threads(&self) -> usize {
+        self.threads
+    }
+
+    pub fn fraction(&self) -> f64 {
+        self.fraction
+    }
+
+    pub fn cores(&self) -> usize {
+        self.cores
+    }
+
+    /// One thread per core the budget covers, but no more than there are idle cores, and at least one.
+    fn wanted_threads(&self, idle_cores: f64) -> usize {
+        let budget_cores = (self.fraction * self.cores as f64).ceil() as usize;
+        budget_cores.min(idle_cores.floor() as usize).clamp(1, self.cores)
+    }
+
+    /// Picks the number of threads again for the current load average, returning it if it changed.
+    pub fn retune_threads(&mut self, load_average: f64) -> Option<usize> {
+        let threads = self.wanted_threads(self.cores as f64 - load_average);
+        if threads == self.threads {
+            return None;
+        }
+        self.threads = threads;
+        // Checks with a different number of threads cost differently, start measuring them afresh.
+        self.cpu_per_check = None;
+        self.time_per_check = None;
+        Some(threads)
+    }
+
+    /// Adds the CPU time the whole process used during a check and how long the check took.
+    pub fn record_check(&mut self, cpu: Duration, time: Duration) {
+        let average = |previous: Option<Duration>, current: Duration| match previous {
+            Some(previous) => previous.mul_f64(1.0 - SMOOTHING) + current.mul_f64(SMOOTHING),
+            None => current,
+        };
+        self.cpu_per_check = Some(average(self.cpu_per_check, cpu));
+        self.time_per_check = Some(average(self.time_per_check, time));
+    }
+
+    /// The shortest wait between checks that keeps the average CPU use within the budget.
+    pub fn delay(&self) -> Duration {
+        match (self.cpu_per_check, self.time_per_check) {
+            (Some(cpu), Some(time)) => {
+                let cycle = cpu.div_f64(self.fraction * self.cores as f64);
+                cycle.saturating_sub(time)
+            }
+            _ => Duration::ZERO,
+        }
+    }
+}
+
+/// The CPU time used by all threads of the process so far.
+#[cfg(target_os = "linux")]
+pub fn process_cpu_time() -> Option<Duration> {
+    // getrusage only writes to the struct it is given.
+    let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+    if unsafe { libc::getrusage(libc::RUSAGE_SELF, &mut usage) } != 0 {
+        return None;
+    }
+    let time = |time: libc::timeval| Duration::new(time.tv_sec as u64, time.tv_usec as u32 * 1000);
+    Some(time(usage.ru_utime) + time(usage.ru_stime))
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn process_cpu_time() -> Option<Duration> {
+    None
+}
+
+/// Runs `f` on the given number of threads and returns its result together with the CPU time the process used
+/// meanwhile and how long it took. Where the CPU time is unknown, every thread is taken to be busy all along.
+pub fn measure<R>(threads: usize, f: impl FnOnce() -> R) -> (R, Duration, Duration) {
+    let cpu_start = process_cpu_time();
+    let start = Instant::now();
+    let result = f();
+    let time = start.elapsed();
+    let cpu = match (cpu_start, process_cpu_time()) {
+        (Some(cpu_start), Some(cpu_end)) => cpu_end.saturating_sub(cpu_start),
+        _ => time * threads as u32,
+    };
+    (result, cpu, time)
+}
@@ -0,0 +1,147 @@
+use std::io;
+use std::time::Duration;
+
+use sysinfo::{RefreshKind, System, SystemExt};
+
+use crate::{
+    config::{Pattern, SizeSpec},
+    detector::{DetectorArray, ScanMode},
+    fill::{fill_memory, Thresholds},
+    mem_size,
+    rng::{random_seed, Rng},
+    runner::{Runner, Sink},
+};
+
+const INTERVAL_DEFAULT: Duration = Duration::from_secs(30);
+
+/// A builder that wasn't told the detector's size yet, which it can't build without.
+pub struct NoSize;
+
+/// Puts together a runner for applications that embed the detector, with the settings the program takes on its
+/// command line as types rather than strings. Only a builder that was given a size can be built:
+///
+/// ```no_run
+/// # use std::time::Duration;
+/// # use cosmic_ray_detector::{builder::RunnerBuilder, config::{Pattern, SizeSpec}};
+/// let runner = RunnerBuilder::new()
+///     .size(SizeSpec::Percent(75.0))
+///     .pattern(Pattern::Checkerboard)
+///     .interval(Duration::from_secs(30))
+///     .sink(|event: &_| println!("{:?}", event))
+///     .build()?;
+/// let handle = runner.spawn()?;
+/// # Ok::<(), String>(())
+/// ```
+pub struct RunnerBuilder<S = NoSize> {
+    size: S,
+    pattern: Pattern,
+    seed: Option<u64>,
+    interval: Duration,
+    heartbeat_interval: Option<Duration>,
+    scan_mode: ScanMode,
+    sinks: Vec<Box<dyn Sink>>,
+}
+
+impl RunnerBuilder<NoSize> {
+    /// A builder for a detector of zeros checked every 30 seconds, as the program does by default.
+    pub fn new() -> Self {
+        RunnerBuilder {
+            size: NoSize,
+            pattern: Pattern::Fixed(0),
+            seed: None,
+            interval: INTERVAL_DEFAULT,
+            heartbeat_interval: None,
+            scan_mode: ScanMode::Words,
+            sinks: Vec::new(),
+        }
+    }
+}
+
+impl Default for RunnerBuilder<NoSize> {
+    fn default() -> Self {
+        RunnerBuilder::new()
+    }
+}
+
+impl<S> RunnerBuilder<S> {
+    /// How large the detector is.
+    pub fn size(self, size: SizeSpec) -> RunnerBuilder<SizeSpec> {
+        RunnerBuilder {
+            size,
+            pattern: self.pattern,
+            seed: self.seed,
+            interval: self.interval,
+            heartbeat_interval: self.heartbeat_interval,
+            scan_mode: self.scan_mode,
+            sinks: self.sinks,
+        }
+    }
+
+    /// What the detector is written with, at the start and after every flip.
+    pub fn pattern(mut self, pattern: Pattern) -> Self {
+        self.pattern = pattern;
+        self
+    }
+
+    /// The seed random patterns are drawn from, a random one by default.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// How long to wait between two checks.
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Sends heartbeats to the sinks every `interval` while nothing changes, none by default.
+    pub fn heartbeats(mut self, interval: Duration) -> Self {
+        self.heartbeat_interval = Some(interval);
+        self
+    }
+
+    /// How the detector is read when checking it.
+    pub fn scan_mode(mut self, scan_mode: ScanMode) -> Self {
+        self.scan_mode = scan_mode;
+        self
+    }
+
+    /// Hands every flip, and every heartbeat when they are on, to `sink`. Sinks get the events in the order they were
+    /// added.
+    pub fn sink(mut self, sink: impl Sink + 'static) -> Self {
+        self.sinks.push(Box::new(sink));
+        self
+    }
+}
+
+impl RunnerBuilder<SizeSpec> {
+    /// Allocates the detector and sets up the runner, failing when the size makes no sense on this machine or not all
+    /// of it could be allocated. An automatic size fills the memory first to find out how large the detector can be.
+    pub fn build(self) -> Result<Runner<DetectorArray>, String> {
+        let mut sys_info = System::new_with_specifics(RefreshKind::new().with_memory());
+        let size = match self.size {
+            SizeSpec::Auto { min_free } => {
+                let defaults = Thresholds::default();
+                let thresholds = Thresholds { free_headroom: min_free.unwrap_or(defaults.free_headroom), ..defaults };
+                fill_memory(&mut io::sink(), &mut sys_info, thresholds, false).map_err(|e| e.to_string())?
+            }
+            size => size.bytes(sys_info.total_memory())?,
+        };
+        let mut detector = DetectorArray::new(0, size);
+        if detector.size() < size {
+            return Err(format!(
+                "Only {} of the {} asked for could be allocated",
+                mem_size(detector.size() as u64),
+                mem_size(size as u64)
+            ));
+        }
+        detector.set_scan_mode(self.scan_mode);
+        let rng = Rng::new(self.seed.unwrap_or_else(random_seed));
+        let mut runner = Runner::new(detector, self.interval).pattern(self.pattern, rng);
+        if let Some(interval) = self.heartbeat_interval {
+            runner = runner.heartbeats(interval);
+        }
+        Ok(self.sinks.into_iter().fold(runner, Runner::sink))
+    }
+}
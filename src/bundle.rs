@@ -0,0 +1,191 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    config::{ExportBundleArgs, GlobalArgs, ImportBundleArgs},
+    station::StationConfig,
+};
+
+const MANIFEST_NAME: &str = "manifest.json";
+
+/// Describes the contents of a bundle, so the collector can check that nothing was lost or damaged on the way.
+#[derive(Serialize, Deserialize, Debug)]
+struct Manifest {
+    station_id: String,
+    created_ms: u64,
+    version: String,
+    files: Vec<ManifestFile>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct ManifestFile {
+    name: String,
+    size: u64,
+    sha256: String,
+}
+
+/// Packs the log, the exposure ledger, the station file and any extra files such as quarantine lists
+/// into a single gzipped tar archive with checksums, for carrying away from a station without a network.
+pub fn export_bundle(global: &GlobalArgs, args: ExportBundleArgs) -> Result<(), Box<dyn Error>> {
+    let station = StationConfig::load(&global.station_config)?;
+    let station_id = station.station_id(global.station_id.as_deref())?;
+
+    let ledger_path = args
+        .ledger_path
+        .clone()
+        .unwrap_or_else(|| PathBuf::from(format!("{}.ledger", args.file_path.display())));
+    let mut paths = vec![args.file_path.clone()];
+    for optional in [ledger_path, global.station_config.clone()] {
+        if optional.exists() {
+            paths.push(optional);
+        }
+    }
+    paths.extend(args.include.iter().cloned());
+
+    let mut files: BTreeMap<String, Vec<u8>> = BTreeMap::new();
+    for path in &paths {
+        let name = path
+            .file_name()
+            .ok_or_else(|| format!("{} is not a file", path.display()))?
+            .to_string_lossy()
+            .into_owned();
+        if name == MANIFEST_NAME || files.contains_key(&name) {
+            return Err(format!("More than one file in the bundle would be called {}", name).into());
+        }
+        let contents = fs::read(path).map_err(|e| format!("Unable to read {}: {}", path.display(), e))?;
+        files.insert(name, contents);
+    }
+
+    let manifest = Manifest {
+        station_id,
+        created_ms: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("Time went backwards")
+            .as_millis() as u64,
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        files: files
+            .iter()
+            .map(|(name, contents)| ManifestFile {
+                name: name.clone(),
+                size: contents.len() as u64,
+                sha256: sha256_hex(contents),
+            })
+            .collect(),
+    };
+
+    let encoder = GzEncoder::new(File::create(&args.output)?, Compression::best());
+    let mut archive = tar::Builder::new(encoder);
+    append_file(&mut archive, MANIFEST_NAME, &serde_json::to_vec_pretty(&manifest)?, manifest.created_ms / 1000)?;
+    for (name, contents) in &files {
+        append_file(&mut archive, name, contents, manifest.created_ms / 1000)?;
+    }
+    archive.into_inner()?.finish()?;
+
+    // The checksum of the whole bundle goes next to it, in the format sha256sum understands.
+    let checksum = sha256_hex(&fs::read(&args.output)?);
+    fs::write(checksum_path(&args.output), format!("{}  {}\n", checksum, file_name(&args.output)))?;
+
+    if global.verbose {
+        println!("Wrote {} files to {} ({})", files.len(), args.output.display(), checksum);
+    }
+    Ok(())
+}
+
+/// Verifies a bundle made by `export-bundle` and unpacks it into a directory named after the station.
+pub fn import_bundle(global: &GlobalArgs, args: ImportBundleArgs) -> Result<(), Box<dyn Error>> {
+    let bundle = fs::read(&args.bundle)?;
+    match fs::read_to_string(checksum_path(&args.bundle)) {
+        Ok(expected) => {
+            let expected = expected.split_whitespace().next().unwrap_or_default();
+            if expected != sha256_hex(&bundle) {
+                return Err(format!("{} does not match its checksum, it was damaged on the way", args.bundle.display()).into());
+            }
+        }
+        Err(_) => eprintln!("There is no checksum file next to {}, only checking the files inside it", args.bundle.display()),
+    }
+
+    let mut files: BTreeMap<String, Vec<u8>> = BTreeMap::new();
+    let mut archive = tar::Archive::new(GzDecoder::new(&bundle[..]));
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let name = entry.path()?.to_string_lossy().into_owned();
+        let mut contents = Vec::new();
+        entry.read_to_end(&mut contents)?;
+        files.insert(name, contents);
+    }
+
+    let manifest: Manifest = serde_json::from_slice(
+        files
+            .get(MANIFEST_NAME)
+            .ok_or("The bundle has no manifest")?,
+    )?;
+    for file in &manifest.files {
+        let contents = files
+            .get(&file.name)
+            .ok_or_else(|| format!("{} is missing from the bundle", file.name))?;
+        if contents.len() as u64 != file.size || sha256_hex(contents) != file.sha256 {
+            return Err(format!("{} in the bundle does not match its checksum", file.name).into());
+        }
+        // Names come from an untrusted archive, they must not be able to point outside the destination.
+        if Path::new(&file.name).components().count() != 1 || file.name == ".." {
+            return Err(format!("Refusing to unpack {}", file.name).into());
+        }
+    }
+
+    // The station ID ends up in the path too, it gets the same check as one given on the command line.
+    StationConfig::default().station_id(Some(&manifest.station_id))?;
+    let destination = args.into.join(&manifest.station_id).join(manifest.created_ms.to_string());
+    fs::create_dir_all(&destination)?;
+    for file in &manifest.files {
+        fs::write(destination.join(&file.name), &files[&file.name])?;
+    }
+    fs::write(destination.join(MANIFEST_NAME), &files[MANIFEST_NAME])?;
+
+    if global.verbose {
+        println!(
+            "Imported {} files from station {} into {}",
+            manifest.files.len(),
+            manifest.station_id,
+            destination.display()
+        );
+    }
+    Ok(())
+}
+
+fn append_file<W: std::io::Write>(
+    archive: &mut tar::Builder<W>,
+    name: &str,
+    contents: &[u8],
+    mtime: u64,
+) -> std::io::Result<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(contents.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(mtime);
+    header.set_cksum();
+    archive.append_data(&mut header, name, contents)
+}
+
+fn sha256_hex(contents: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(contents))
+}
+
+fn checksum_path(bundle: &Path) -> PathBuf {
+    PathBuf::from(format!("{}.sha256", bundle.display()))
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
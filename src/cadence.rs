@@ -0,0 +1,83 @@
+use std::time::{Duration, Instant};
+
+use crate::record::Cadence;
+
+/// A check that starts later than this after its deadline counts as late, waking up from a sleep takes a little.
+const LATE_TOLERANCE: Duration = Duration::from_millis(10);
+/// The most start times kept for the percentiles, every other one is let go when there are more. Without
+/// heartbeats to take them a fast cadence would pile up samples for as long as the detector runs.
+const MAX_SAMPLES: usize = 100_000;
+
+/// Schedules checks at fixed times, every period from the start, instead of waiting the same time after every check.
+/// The times the detector is looked at then stay evenly spread however long the checks take, which keeps the
+/// exposure timeline uniform.
+pub struct CadenceScheduler {
+    period: Duration,
+    next: Instant,
+    /// How late the checks since the last `take` started, in milliseconds.
+    late_ms: Vec<f64>,
+    late_max_ms: f64,
+    late_checks: u64,
+    missed_deadlines: u64,
+}
+
+impl CadenceScheduler {
+    pub fn new(period: Duration) -> Result<Self, String> {
+        if period.is_zero() {
+            return Err("--fixed-cadence needs a delay between checks to schedule them by, pass -d".into());
+        }
+        Ok(CadenceScheduler {
+            period,
+            next: Instant::now() + period,
+            late_ms: Vec::new(),
+            late_max_ms: 0.0,
+            late_checks: 0,
+            missed_deadlines: 0,
+        })
+    }
+
+    /// Starts the schedule over from now, after time the detector wasn't being checked anyway, such as a reset.
+    pub fn restart(&mut self) {
+        self.next = Instant::now() + self.period;
+    }
+
+    /// How long to wait for the next deadline, nothing when it has passed.
+    pub fn until_next(&self) -> Duration {
+        self.next.saturating_duration_since(Instant::now())
+    }
+
+    /// Records that a check is starting now, for the latest deadline that passed, and moves on to the next one.
+    /// Deadlines that passed while the check before was still running are missed, rather than caught up on with
+    /// checks in a row.
+    pub fn check_started(&mut self) {
+        let late = Instant::now().saturating_duration_since(self.next);
+        let missed = (late.as_nanos() / self.period.as_nanos()) as u32;
+        let late = late - self.period * missed;
+        self.missed_deadlines += missed as u64;
+        self.late_checks += (late > LATE_TOLERANCE) as u64;
+        let late_ms = late.as_secs_f64() * 1000.0;
+        self.late_max_ms = self.late_max_ms.max(late_ms);
+        if self.late_ms.len() >= MAX_SAMPLES {
+            self.late_ms = self.late_ms.iter().copied().step_by(2).collect();
+        }
+        self.late_ms.push(late_ms);
+        self.next += self.period * (missed + 1);
+    }
+
+    /// How well the checks since the last time kept to their deadlines, starting over afterwards.
+    pub fn take(&mut self) -> Option<Cadence> {
+        let mut late_ms = std::mem::take(&mut self.late_ms);
+        if late_ms.is_empty() {
+            return None;
+        }
+        late_ms.sort_unstable_by(f64::total_cmp);
+        let percentile = |fraction: f64| late_ms[((late_ms.len() - 1) as f64 * fraction).round() as usize];
+        Some(Cadence {
+            late_checks: std::mem::take(&mut self.late_checks),
+            missed_deadlines: std::mem::take(&mut self.missed_deadlines),
+            late_p50_ms: percentile(0.5),
+            late_p99_ms: percentile(0.99),
+            late_max_ms: std::mem::take(&mut self.late_max_ms),
+        })
+    }
+}
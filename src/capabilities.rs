@@ -0,0 +1,209 @@
+use std::error::Error;
+use std::fs;
+use std::io::{self, Write};
+
+use crate::{cgroup, config::GlobalArgs, edac, mem_size, rapl::Rapl, softdirty::SoftDirty};
+
+const STATUS_PATH: &str = "/proc/self/status";
+const LIMITS_PATH: &str = "/proc/self/limits";
+const TRANSPARENT_HUGEPAGE_PATH: &str = "/sys/kernel/mm/transparent_hugepage/enabled";
+const MEMINFO_PATH: &str = "/proc/meminfo";
+const CAP_IPC_LOCK: u32 = 14;
+const CAP_SYS_ADMIN: u32 = 21;
+
+/// An optional feature that depends on what the system and our privileges allow.
+pub struct Capability {
+    pub name: &'static str,
+    pub available: bool,
+    /// What was found, or what is missing and how to get it.
+    pub detail: String,
+}
+
+/// Finds out which optional features can be used, so that missing privileges are reported up front
+/// instead of causing cryptic errors in the middle of a run. Nothing here requires privileges itself.
+pub fn detect() -> Vec<Capability> {
+    let mut capabilities = Vec::new();
+
+    capabilities.push(memory_locking());
+
+    capabilities.push(match SoftDirty::open() {
+        Ok(_) => Capability {
+            name: "Soft-dirty tracking (--soft-dirty)",
+            available: true,
+            detail: "supported".into(),
+        },
+        Err(err) => Capability {
+            name: "Soft-dirty tracking (--soft-dirty)",
+            available: false,
+            detail: err,
+        },
+    });
+
+    capabilities.push(physical_addresses());
+
+    capabilities.push(match fs::read_to_string(TRANSPARENT_HUGEPAGE_PATH) {
+        // The active mode is the one in brackets, e.g. "always [madvise] never".
+        Ok(modes) => {
+            let mode = modes
+                .split_whitespace()
+                .find_map(|mode| mode.strip_prefix('[')?.strip_suffix(']'))
+                .unwrap_or("unknown")
+                .to_string();
+            Capability {
+                name: "Transparent huge pages",
+                available: mode == "always" || mode == "madvise",
+                detail: format!("mode {}", mode),
+            }
+        }
+        Err(_) => Capability {
+            name: "Transparent huge pages",
+            available: false,
+            detail: "not supported by this system".into(),
+        },
+    });
+
+    let free_huge_pages = meminfo_value("HugePages_Free").unwrap_or(0);
+    capabilities.push(Capability {
+        name: "Reserved huge pages",
+        available: free_huge_pages > 0,
+        detail: match free_huge_pages {
+            0 => "none free, reserve some through /proc/sys/vm/nr_hugepages".into(),
+            free => format!("{} free", free),
+        },
+    });
+
+    capabilities.push(match edac::read_counts() {
+        Ok(Some(_)) => Capability {
+            name: "EDAC memory error counts (--edac-interval)",
+            available: true,
+            detail: "available".into(),
+        },
+        Ok(None) => Capability {
+            name: "EDAC memory error counts (--edac-interval)",
+            available: false,
+            detail: "no EDAC memory controllers, the machine probably has no ECC memory".into(),
+        },
+        Err(err) => Capability {
+            name: "EDAC memory error counts (--edac-interval)",
+            available: false,
+            detail: err,
+        },
+    });
+
+    capabilities.push(match cgroup::check_memory_controller() {
+        Ok(()) => Capability {
+            name: "Own memory cgroup (--self-cgroup)",
+            available: true,
+            detail: "cgroup v2 with the memory controller, creating one needs write access to /sys/fs/cgroup".into(),
+        },
+        Err(err) => Capability {
+            name: "Own memory cgroup (--self-cgroup)",
+            available: false,
+            detail: err,
+        },
+    });
+
+    capabilities.push(match Rapl::open() {
+        Ok(_) => Capability {
+            name: "RAPL energy counters",
+            available: true,
+            detail: "readable, heartbeats and bench report the energy of checks".into(),
+        },
+        Err(err) => Capability {
+            name: "RAPL energy counters",
+            available: false,
+            detail: err,
+        },
+    });
+
+    capabilities
+}
+
+/// How much memory may be locked into RAM.
+pub fn memory_locking() -> Capability {
+    match locked_memory_limit() {
+        _ if has(CAP_IPC_LOCK) => Capability {
+            name: "Locking memory",
+            available: true,
+            detail: "unlimited, CAP_IPC_LOCK is held".into(),
+        },
+        Some(None) => Capability {
+            name: "Locking memory",
+            available: true,
+            detail: "unlimited".into(),
+        },
+        Some(Some(limit)) => Capability {
+            name: "Locking memory",
+            available: limit > 0,
+            detail: format!("up to {}, raise RLIMIT_MEMLOCK or grant CAP_IPC_LOCK for more", mem_size(limit)),
+        },
+        None => Capability {
+            name: "Locking memory",
+            available: false,
+            detail: format!("unknown, {} is unreadable", LIMITS_PATH),
+        },
+    }
+}
+
+/// Whether pagemap tells physical addresses, which the kernel only does for CAP_SYS_ADMIN.
+pub fn physical_addresses() -> Capability {
+    Capability {
+        name: "Physical addresses in pagemap",
+        available: has(CAP_SYS_ADMIN),
+        detail: match has(CAP_SYS_ADMIN) {
+            true => "CAP_SYS_ADMIN is held".into(),
+            false => "needs CAP_SYS_ADMIN, the kernel hides them from everyone else".into(),
+        },
+    }
+}
+
+/// Prints the capabilities as a table.
+pub fn print(out: &mut dyn Write, capabilities: &[Capability]) -> io::Result<()> {
+    let width = capabilities.iter().map(|capability| capability.name.len()).max().unwrap_or(0);
+    for capability in capabilities {
+        writeln!(
+            out,
+            "{:width$}  {:3}  {}",
+            capability.name,
+            if capability.available { "yes" } else { "no" },
+            capability.detail,
+            width = width
+        )?;
+    }
+    Ok(())
+}
+
+/// The `capabilities` subcommand.
+pub fn capabilities(_global: &GlobalArgs) -> Result<(), Box<dyn Error>> {
+    print(&mut io::stdout(), &detect())?;
+    Ok(())
+}
+
+fn has(capability: u32) -> bool {
+    effective_capabilities().is_some_and(|effective| effective & (1 << capability) != 0)
+}
+
+/// The effective capability set of the process, on Linux.
+fn effective_capabilities() -> Option<u64> {
+    let status = fs::read_to_string(STATUS_PATH).ok()?;
+    let mask = status.lines().find_map(|line| line.strip_prefix("CapEff:"))?;
+    u64::from_str_radix(mask.trim(), 16).ok()
+}
+
+/// The soft limit on locked memory in bytes, `Some(None)` if there is none.
+fn locked_memory_limit() -> Option<Option<u64>> {
+    let limits = fs::read_to_string(LIMITS_PATH).ok()?;
+    let line = limits.lines().find(|line| line.starts_with("Max locked memory"))?;
+    match line.trim_start_matches("Max locked memory").split_whitespace().next()? {
+        "unlimited" => Some(None),
+        limit => limit.parse().ok().map(Some),
+    }
+}
+
+fn meminfo_value(key: &str) -> Option<u64> {
+    let meminfo = fs::read_to_string(MEMINFO_PATH).ok()?;
+    meminfo
+        .lines()
+        .find_map(|line| line.strip_prefix(key)?.strip_prefix(':'))
+        .and_then(|value| value.split_whitespace().next()?.parse().ok())
+}
@@ -0,0 +1,84 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{ErrorKind, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+
+/// Where the unified cgroup v2 hierarchy is mounted.
+const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+/// Memory the process needs besides the detector: threads, buffers, the event queue and the HTTP clients.
+const CGROUP_HEADROOM: u64 = 128 * 1024 * 1024;
+
+/// A cgroup of the detector's own that limits its memory to little more than the detector, so that a bug
+/// inflating its memory use runs into the limit instead of pushing the rest of the machine into swap, and the
+/// OOM killer only ever has the detector to pick from.
+pub struct SelfCgroup {
+    path: PathBuf,
+    /// Kept open so that the limit can still be changed once the sandbox forbids opening it.
+    memory_max: File,
+}
+
+impl SelfCgroup {
+    /// Creates the station's cgroup below the root of the hierarchy, or reuses the one an earlier run left,
+    /// and moves the process into it without a limit yet. Needs write access to the root of the hierarchy,
+    /// which usually means root.
+    pub fn enter(station_id: &str) -> Result<Self, String> {
+        check_memory_controller()?;
+        let root = Path::new(CGROUP_ROOT);
+        let subtree_control = root.join("cgroup.subtree_control");
+        let enabled = fs::read_to_string(&subtree_control).unwrap_or_default();
+        if !enabled.split_whitespace().any(|controller| controller == "memory") {
+            fs::write(&subtree_control, "+memory")
+                .map_err(|e| format!("Unable to enable the memory controller in {}: {}", subtree_control.display(), e))?;
+        }
+
+        // Station ids are free text, the cgroup's name can't have slashes in it.
+        let name: String = station_id
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+            .collect();
+        let path = root.join(format!("cosmic_ray_detector-{}", name));
+        match fs::create_dir(&path) {
+            Ok(()) => {}
+            Err(err) if err.kind() == ErrorKind::AlreadyExists => {}
+            Err(err) => return Err(format!("Unable to create the cgroup {}: {}", path.display(), err)),
+        }
+        let memory_max_path = path.join("memory.max");
+        let memory_max = OpenOptions::new()
+            .write(true)
+            .open(&memory_max_path)
+            .map_err(|e| format!("Unable to open {}: {}", memory_max_path.display(), e))?;
+        let mut cgroup = SelfCgroup { path, memory_max };
+        // An earlier run leaves its limit behind, finding the detector size needs the memory to be unlimited.
+        cgroup.write_memory_max("max")?;
+        let procs = cgroup.path.join("cgroup.procs");
+        fs::write(&procs, process::id().to_string())
+            .map_err(|e| format!("Unable to move the process into {}: {}", procs.display(), e))?;
+        Ok(cgroup)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Limits the memory of the process to a detector of the given size and a little more for everything else.
+    pub fn limit(&mut self, detector_bytes: usize) -> Result<(), String> {
+        self.write_memory_max(&(detector_bytes as u64 + CGROUP_HEADROOM).to_string())
+    }
+
+    fn write_memory_max(&mut self, value: &str) -> Result<(), String> {
+        self.memory_max
+            .seek(SeekFrom::Start(0))
+            .and_then(|_| self.memory_max.write_all(value.as_bytes()))
+            .map_err(|e| format!("Unable to set the memory limit of {}: {}", self.path.display(), e))
+    }
+}
+
+/// Whether cgroup v2 is there with its memory controller, which doesn't tell whether we may create cgroups.
+pub fn check_memory_controller() -> Result<(), String> {
+    let controllers = fs::read_to_string(Path::new(CGROUP_ROOT).join("cgroup.controllers"))
+        .map_err(|_| format!("There is no cgroup v2 hierarchy at {}", CGROUP_ROOT))?;
+    match controllers.split_whitespace().any(|controller| controller == "memory") {
+        true => Ok(()),
+        false => Err("The memory controller of cgroup v2 isn't available".into()),
+    }
+}
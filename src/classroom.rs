@@ -0,0 +1,87 @@
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    exposure::{local_utc_offset, utc_date},
+    i18n::tr,
+};
+
+const BANNER: &str = r"
+  ####   #  #####  #####  #      #  ####    #
+  #   #  #    #    #      #      #  #   #   #
+  ####   #    #    ####   #      #  ####    #
+  #   #  #    #    #      #      #  #
+  ####   #    #    #      #####  #  #       #
+";
+
+/// The bitflips a station has seen, for demonstrations in a classroom. They are kept in a small file next to the
+/// log so that the count carries on where it was when the detector is started again the next lesson.
+pub struct Classroom {
+    path: PathBuf,
+    counts: Counts,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct Counts {
+    total: u64,
+    /// The local date of `today`.
+    day: String,
+    today: u64,
+}
+
+impl Classroom {
+    pub fn open(path: PathBuf) -> Result<Self, String> {
+        let counts = match fs::read_to_string(&path) {
+            Ok(contents) => serde_json::from_str(&contents)
+                .map_err(|e| format!("Unable to read the classroom counter {}: {}", path.display(), e))?,
+            Err(err) if err.kind() == ErrorKind::NotFound => Counts::default(),
+            Err(err) => return Err(format!("Unable to read the classroom counter {}: {}", path.display(), err)),
+        };
+        Ok(Classroom { path, counts })
+    }
+
+    /// Counts a bitflip and saves the counts.
+    pub fn add_event(&mut self) -> io::Result<()> {
+        self.today();
+        self.counts.today += 1;
+        self.counts.total += 1;
+        let temporary_path = self.path.with_extension("classroom.tmp");
+        fs::write(&temporary_path, serde_json::to_string(&self.counts)?)?;
+        fs::rename(&temporary_path, &self.path)
+    }
+
+    /// The bitflips seen since midnight, local time.
+    pub fn today(&mut self) -> u64 {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+        let day = utc_date(now.saturating_add_signed(local_utc_offset(now)));
+        if day != self.counts.day {
+            self.counts.day = day;
+            self.counts.today = 0;
+        }
+        self.counts.today
+    }
+
+    pub fn total(&self) -> u64 {
+        self.counts.total
+    }
+
+    pub fn status(&mut self) -> String {
+        let today = self.today();
+        tr!("Bitflips today: {}   Total: {}", format!("{:>4}", today), format!("{:>6}", self.total()))
+    }
+
+    /// What to show when the detector finds a bitflip.
+    pub fn banner(&mut self) -> String {
+        let today = self.today();
+        format!(
+            "{}\n{}\n{}\n",
+            BANNER,
+            tr!("A cosmic ray may just have flipped a bit in the memory of this computer!"),
+            tr!("Bitflips today: {}   Total: {}", today, self.total())
+        )
+    }
+}
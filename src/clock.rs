@@ -0,0 +1,87 @@
+use serde::{Deserialize, Serialize};
+
+/// Whether the system clock is kept in sync by NTP or a similar service, as far as the kernel knows.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ClockSync {
+    Synchronized,
+    Unsynchronized,
+    /// The system doesn't say.
+    Unknown,
+}
+
+impl ClockSync {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ClockSync::Synchronized => "synchronized",
+            ClockSync::Unsynchronized => "unsynchronized",
+            ClockSync::Unknown => "unknown",
+        }
+    }
+
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "synchronized" => Ok(ClockSync::Synchronized),
+            "unsynchronized" => Ok(ClockSync::Unsynchronized),
+            "unknown" => Ok(ClockSync::Unknown),
+            other => Err(format!("Unknown clock sync status '{}'", other)),
+        }
+    }
+}
+
+/// Where the time of a bitflip came from.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum TimeSource {
+    #[default]
+    System,
+    /// The system clock corrected by the pulses of a PPS device such as /dev/pps0.
+    Pps,
+    /// The system clock corrected by the PPS pulses gpsd reports.
+    Gpsd,
+}
+
+impl TimeSource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TimeSource::System => "system",
+            TimeSource::Pps => "pps",
+            TimeSource::Gpsd => "gpsd",
+        }
+    }
+
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "system" => Ok(TimeSource::System),
+            "pps" => Ok(TimeSource::Pps),
+            "gpsd" => Ok(TimeSource::Gpsd),
+            other => Err(format!("Unknown time source '{}'", other)),
+        }
+    }
+
+    pub fn is_system(&self) -> bool {
+        *self == TimeSource::System
+    }
+}
+
+/// Asks the kernel whether the clock is synchronized, and if so how far off it may be at most in milliseconds.
+/// Only reads the kernel's clock state, nothing is adjusted.
+#[cfg(target_os = "linux")]
+pub fn sync_status() -> (ClockSync, Option<u64>) {
+    // adjtimex only writes to the struct it is given, and with modes 0 it changes nothing.
+    let mut timex: libc::timex = unsafe { std::mem::zeroed() };
+    let state = unsafe { libc::adjtimex(&mut timex) };
+    if state < 0 {
+        return (ClockSync::Unknown, None);
+    }
+    if state == libc::TIME_ERROR || timex.status & libc::STA_UNSYNC != 0 {
+        return (ClockSync::Unsynchronized, None);
+    }
+    // The maximum error is in microseconds.
+    (ClockSync::Synchronized, Some(timex.maxerror as u64 / 1000))
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn sync_status() -> (ClockSync, Option<u64>) {
+    (ClockSync::Unknown, None)
+}
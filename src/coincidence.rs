@@ -0,0 +1,81 @@
+use std::fs;
+use std::path::Path;
+
+use crate::health::poisson_tail;
+
+/// The number of bins of the cross-correlation histogram.
+pub const OFFSET_BINS: usize = 20;
+
+/// How many events of another detector, such as a muon scintillator, fell close to bitflips, against how many
+/// would by chance if the two were unrelated.
+pub struct Coincidences {
+    pub window_ms: f64,
+    /// The flips that happened while the other detector was running.
+    pub flips: usize,
+    /// Pairs of a flip and an event within the window of each other.
+    pub observed: u64,
+    /// The pairs expected by chance at the other detector's average rate.
+    pub expected: f64,
+    /// The chance of at least as many pairs by chance.
+    pub p: f64,
+}
+
+/// Reads the event times of another detector, one per line as Unix time in seconds with any fraction.
+/// Only the first comma or whitespace separated column counts, empty lines and lines starting with # are skipped.
+/// Returns the times in milliseconds, sorted.
+pub fn read_event_times(path: &Path) -> Result<Vec<f64>, String> {
+    let contents = fs::read_to_string(path).map_err(|e| format!("Unable to read {}: {}", path.display(), e))?;
+    let mut times = Vec::new();
+    for line in contents.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#')) {
+        let column = line.split(|c: char| c == ',' || c.is_whitespace()).next().unwrap_or(line);
+        let seconds: f64 = column
+            .parse()
+            .map_err(|_| format!("Malformed event time '{}' in {}", column, path.display()))?;
+        times.push(seconds * 1000.0);
+    }
+    times.sort_unstable_by(f64::total_cmp);
+    Ok(times)
+}
+
+/// Counts the pairs of a flip and an event at most `window_ms` apart. A flip is known to have happened somewhere
+/// between its `(earliest, latest)` times in milliseconds, so the window is added on both sides of that.
+/// Only flips while the events were being recorded count.
+pub fn coincidences(flips: &[(f64, f64)], events: &[f64], window_ms: f64) -> Coincidences {
+    let (Some(&first), Some(&last)) = (events.first(), events.last()) else {
+        return Coincidences { window_ms, flips: 0, observed: 0, expected: 0.0, p: 1.0 };
+    };
+    let rate = match last > first {
+        true => events.len() as f64 / (last - first),
+        false => 0.0,
+    };
+    let mut counted = 0;
+    let mut observed = 0;
+    let mut expected = 0.0;
+    for &(earliest, latest) in flips.iter().filter(|(earliest, latest)| *latest >= first && *earliest <= last) {
+        counted += 1;
+        observed += events_between(events, earliest - window_ms, latest + window_ms) as u64;
+        expected += rate * (latest - earliest + 2.0 * window_ms);
+    }
+    Coincidences { window_ms, flips: counted, observed, expected, p: poisson_tail(observed, expected) }
+}
+
+/// The cross-correlation of flips and events: how many events were found at each offset from the latest time
+/// of a flip, in `OFFSET_BINS` bins from `-range_ms` to `range_ms`.
+pub fn offset_histogram(flips: &[(f64, f64)], events: &[f64], range_ms: f64) -> Vec<u64> {
+    let mut bins = vec![0; OFFSET_BINS];
+    if range_ms <= 0.0 {
+        return bins;
+    }
+    let bin_width = 2.0 * range_ms / OFFSET_BINS as f64;
+    for &(_, latest) in flips {
+        let start = events.partition_point(|&event| event < latest - range_ms);
+        for &event in events[start..].iter().take_while(|&&event| event < latest + range_ms) {
+            bins[(((event - latest + range_ms) / bin_width) as usize).min(OFFSET_BINS - 1)] += 1;
+        }
+    }
+    bins
+}
+
+fn events_between(events: &[f64], from: f64, to: f64) -> usize {
+    events.partition_point(|&event| event <= to) - events.partition_point(|&event| event < from)
+}
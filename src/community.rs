@@ -0,0 +1,95 @@
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::{FetchCommunityArgs, GlobalArgs},
+    leaderboard::CommunityRate,
+    serve::COMMUNITY_PATH,
+    share::ShareConsent,
+};
+
+/// The aggregate rates of a community collector, as `fetch-community` saves them for `analyze --community`.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CommunityDataset {
+    /// Where the rates were downloaded from.
+    #[serde(default)]
+    pub source: String,
+    /// When the collector worked the rates out, in milliseconds since the Unix epoch.
+    pub generated_ms: u64,
+    pub rates: Vec<CommunityRate>,
+}
+
+impl CommunityDataset {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| format!("Unable to read the community rates in {}: {}", path.display(), e))?;
+        serde_json::from_str(&contents)
+            .map_err(|e| format!("Unable to parse the community rates in {}: {}", path.display(), e))
+    }
+
+    /// The rate of the stations most like one in the given altitude band with the given memory generation: those
+    /// with the same memory in the same band, or every station in the band when none of them has the same memory.
+    /// None when no station is in the band.
+    pub fn comparable(&self, band: &str, memory_type: &str) -> Option<CommunityRate> {
+        let in_band: Vec<&CommunityRate> = self.rates.iter().filter(|rate| rate.band == band).collect();
+        if let Some(rate) = in_band.iter().find(|rate| rate.memory_type == memory_type) {
+            return Some((*rate).clone());
+        }
+        if in_band.is_empty() {
+            return None;
+        }
+        let flips = in_band.iter().map(|rate| rate.flips).sum();
+        let gb_hours = in_band.iter().map(|rate| rate.gb_hours).sum();
+        Some(CommunityRate {
+            band: band.to_string(),
+            memory_type: "any".into(),
+            // A station that changed its memory is in more than one of them, so this can count it twice.
+            stations: in_band.iter().map(|rate| rate.stations).sum(),
+            flips,
+            gb_hours,
+            flips_per_gb_hour: (gb_hours > 0.0).then(|| flips as f64 / gb_hours),
+        })
+    }
+}
+
+/// Where a collector serves its community rates, from the URL stations upload to or the collector's own.
+pub fn community_url(collector_url: &str) -> String {
+    let base = collector_url.trim_end_matches('/');
+    let base = base.strip_suffix("/upload").unwrap_or(base);
+    format!("{}{}", base, COMMUNITY_PATH)
+}
+
+/// Downloads the aggregate rates of a community collector, by default the one consent to sharing was given to.
+pub fn fetch_community(global: &GlobalArgs, args: FetchCommunityArgs) -> Result<(), Box<dyn Error>> {
+    let collector_url = match args.url {
+        Some(url) => url,
+        None => match ShareConsent::load(&args.consent)? {
+            Some(consent) => consent.url,
+            None => return Err("No collector to fetch from, give one with --url".into()),
+        },
+    };
+    let url = community_url(&collector_url);
+    if global.verbose {
+        println!("Fetching the community rates from {}", url);
+    }
+    let mut dataset: CommunityDataset = ureq::get(&url)
+        .call()
+        .map_err(|e| format!("Unable to fetch the community rates from {}: {}", url, e))?
+        .into_json()
+        .map_err(|e| format!("Unable to parse the community rates from {}: {}", url, e))?;
+    dataset.source = url;
+    fs::write(&args.output, serde_json::to_string_pretty(&dataset)? + "\n")
+        .map_err(|e| format!("Unable to save the community rates in {}: {}", args.output.display(), e))?;
+    let stations: u64 = dataset.rates.iter().map(|rate| rate.stations).sum();
+    println!(
+        "Saved {} community rates from {} stations in {}, compare with them with analyze --community {}",
+        dataset.rates.len(),
+        stations,
+        args.output.display(),
+        args.output.display()
+    );
+    Ok(())
+}
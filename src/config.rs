@@ -1,98 +1,1179 @@
 use std::fs::File;
-use std::num::ParseIntError;
-use clap::Parser;
+use std::num::ParseFloatError;
+use std::path::PathBuf;
+use std::time::Duration;
+use clap::{Args, Parser, Subcommand, ValueEnum};
 use std::usize;
 
+use crate::detector::ScanMode;
+use crate::rng::Rng;
+
 const DELAY_DEFAULT: u64 = 30000;
+/// The often quoted one flip per gigabyte per month, real rates vary a lot between memory generations.
+const EXPECTED_RATE_DEFAULT: f64 = 0.0014;
+const INTERVAL_FRACTION_DEFAULT: f64 = 0.01;
+const STALL_FACTOR_DEFAULT: f64 = 10.0;
+const MIN_RESIDENCY_DEFAULT: f64 = 1.0;
+const RATE_WINDOW_DEFAULT: f64 = 24.0;
+const RESIZE_GROW_AFTER_DEFAULT: f64 = 10.0;
+const RESIZE_COOLDOWN_DEFAULT: f64 = 5.0;
+const ANOMALY_SIGMA_DEFAULT: f64 = 5.0;
+const ANOMALY_BASELINE_WINDOW_DEFAULT: f64 = 720.0;
+const STATION_CONFIG_DEFAULT: &str = "station.toml";
+const INFLUX_INTERVAL_DEFAULT: u64 = 60000;
+const LOG_SYNC_INTERVAL_DEFAULT: u64 = 60000;
+const S3_REGION_DEFAULT: &str = "us-east-1";
+const S3_INTERVAL_DEFAULT: u64 = 3600000;
+const SD_CARD_FLUSH_HOURS_DEFAULT: u64 = 6;
+const DISK_WARNING_DEFAULT: &str = "100MB";
+const GPIO_PULSE_WIDTH_DEFAULT: u64 = 10000;
+const SERIAL_BAUD_RATE_DEFAULT: u32 = 115200;
+const BENCH_SIZE_DEFAULT: &str = "100MB";
+const BENCH_ITERATIONS_DEFAULT: u32 = 10;
+const SELFTEST_SIZE_DEFAULT: &str = "1MB";
+const SIMULATE_SIZE_DEFAULT: &str = "1GB";
+const SIMULATE_EVENTS_DEFAULT: u64 = 100000;
+const SIMULATE_RATE_DEFAULT: f64 = 1.0;
+const SYNC_OUTPUT_DEFAULT: &str = "lan_sync.jsonl";
+const SYNC_INTERVAL_DEFAULT: u64 = 300000;
+const SYNC_DISCOVERY_TIME_DEFAULT: u64 = 5000;
+const STATION_KEY_DEFAULT: &str = "station.key";
+const SHARE_CONSENT_DEFAULT: &str = "share.json";
+const COMMUNITY_DEFAULT: &str = "community.json";
+const STORAGE_DEFAULT: &str = "files";
+const DATA_DIR_DEFAULT: &str = "collector";
+const IMPORT_DIRECTORY_DEFAULT: &str = "imported";
+const LISTEN_DEFAULT: &str = "127.0.0.1:8080";
+const TAIL_LINES_DEFAULT: usize = 10;
+const TAIL_INTERVAL_DEFAULT: u64 = 1000;
+const DOI_LICENSE_DEFAULT: &str = "cc-by-4.0";
+const COINCIDENCE_WINDOWS_DEFAULT: [f64; 3] = [0.0, 1000.0, 60000.0];
+/// The flux of neutrons above 10 MeV at sea level in New York City in particles per cm² per hour, the reference of
+/// JEDEC JESD89A that most SEU measurements are scaled to.
+const REFERENCE_FLUX_DEFAULT: f64 = 13.0;
+const PATTERN_DEFAULT: &str = "0x00";
+const SCRUB_INTERVAL_DEFAULT: &str = "1h";
+const SCAN_MODE_DEFAULT: &str = "words";
+const HEAVY_SMI_RATE_DEFAULT: f64 = 1.0;
 
 /// Monitors memory for bit-flips (won't work on ECC memory).
 /// The chance of detection scales with the physical size of your DRAM modules
 /// and the percentage of them you allocate to this program.
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
-pub struct Args {
-    #[arg(short, required = false, value_parser(parse_size_string), default_value_t = 0)]
-    /// The size of the memory to monitor for bitflips, understands e.g. 200, 5kB, 2GB and 3Mb. If this is specified or set to a non-zero value, the program will not automatically fill all available memory
-    pub memory_to_occupy: usize,
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Command,
+
+    #[command(flatten)]
+    pub global: GlobalArgs,
+}
+
+/// Flags shared by every subcommand.
+#[derive(Args, Debug)]
+pub struct GlobalArgs {
+    #[arg(long, global = true, required = false, default_value = STATION_CONFIG_DEFAULT)]
+    /// Path to a TOML file with station settings such as latitude and longitude. It is fine if it does not exist
+    pub station_config: PathBuf,
+
+    #[arg(long, global = true, required = false)]
+    /// Identifies the station when several share a machine, only one instance can run per station. Overrides the station file
+    pub station_id: Option<String>,
+
+    #[arg(short, long, global = true, required = false, default_value_t = true)]
+    /// Whether to print extra information
+    pub verbose: bool,
+
+    #[arg(long, global = true, required = false, default_value_t = false)]
+    /// Print plain lines of text without colors or a status line updated in place, e.g. when a supervisor captures
+    /// the output. This is the default when not writing to a terminal, colors are also off when NO_COLOR is set
+    pub plain: bool,
+
+    #[arg(long, global = true, required = false)]
+    /// The language of the messages, e.g. de or es. Defaults to the one LC_ALL, LC_MESSAGES or LANG asks for
+    pub lang: Option<String>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Monitor memory for bitflips and log them
+    Run(Box<RunArgs>),
+    /// Summarize the contents of a log file
+    Analyze(AnalyzeArgs),
+    /// Look for signs of failing hardware in a log and say whether its bitflips look like cosmic rays
+    Health(HealthArgs),
+    /// Follow a log file or the collector's storage and print new records as they arrive
+    Tail(TailArgs),
+    /// Measure how fast detector memory can be written and checked
+    Bench(BenchArgs),
+    /// Flip a bit in a small detector on purpose and make sure it is found
+    Selftest(SelftestArgs),
+    /// Estimate which fraction of events a detector configuration would log, and how late
+    Simulate(SimulateArgs),
+    /// Convert a log file to another format
+    Export(ExportArgs),
+    /// Run a collector that stations can send their results to
+    Serve(ServeArgs),
+    /// Control a running instance
+    Ctl(CtlArgs),
+    /// Show what sharing with a community collector involves, and give or withdraw consent to it
+    Share(ShareArgs),
+    /// Download the bitflip rates of the community by altitude band and memory generation, for analyze --community
+    FetchCommunity(FetchCommunityArgs),
+    /// Collect results from every station on the local network that runs with --lan-sync
+    Sync(SyncArgs),
+    /// Pack the log and everything belonging to it into a checksummed archive, for stations without a network
+    ExportBundle(ExportBundleArgs),
+    /// Verify and unpack an archive made by export-bundle
+    ImportBundle(ImportBundleArgs),
+    /// Make a verified copy of a log or database with the current time in its name
+    Backup(BackupArgs),
+    /// Show which optional features this system and the current privileges allow
+    Capabilities,
+    /// Print the translatable messages as a gettext template, or the catalog of a language updated with them
+    Messages(MessagesArgs),
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct RunArgs {
+    #[arg(short, required = false, value_parser(parse_size_spec), default_value = "auto")]
+    /// The size of the memory to monitor for bitflips, understands e.g. 200, 5kB, 2GB, 1.5GiB and 3Mb, a share of the
+    /// memory such as 25%, or auto to fill all available memory. auto:1GB leaves 1GB of it free on machines without
+    /// swap instead of --free-headroom. 0 is refused, older versions took it for auto
+    pub memory_to_occupy: SizeSpec,
+
+    #[arg(long, required = false, value_parser(parse_size_string))]
+    /// When filling all available memory, how much more swap may be used before the detector counts as too large,
+    /// e.g. 10MB. Defaults to swap_tolerance in the station file, or 10MB
+    pub swap_tolerance: Option<usize>,
+
+    #[arg(long, required = false, value_parser(parse_size_string))]
+    /// When filling all available memory on a machine without swap, how much of it to leave available, e.g. 50MB.
+    /// Defaults to free_headroom in the station file, or 50MB
+    pub free_headroom: Option<usize>,
+
+    #[arg(long, required = false, default_value_t = false)]
+    /// Keep resizing a detector that fills all available memory while running: shrink it as soon as other programs
+    /// run short of memory, and grow it again once memory has been free for a while
+    pub resize: bool,
+
+    #[arg(long, required = false, default_value_t = RESIZE_GROW_AFTER_DEFAULT)]
+    /// How long memory has to be free before --resize grows the detector (in minutes)
+    pub resize_grow_after: f64,
+
+    #[arg(long, required = false, default_value_t = RESIZE_COOLDOWN_DEFAULT)]
+    /// How long after a resize --resize waits before it grows the detector (in minutes)
+    pub resize_cooldown: f64,
 
     #[arg(short, required = false, default_value_t = DELAY_DEFAULT)]
     /// An optional delay in between each integrity check (in milliseconds)
     pub delay_between_checks: u64,
 
-    #[arg(long, required = true)]
-    /// The longitude of where the computer is that is running the program
-    pub longitude: String,
+    #[arg(long, required = false, value_parser(parse_percentage))]
+    /// Keep the average CPU use within this share of all cores together, e.g. 5%, by picking the number of checking
+    /// threads and the delay between checks, which then replaces -d. Both are adjusted as the load of the machine changes
+    pub cpu_budget: Option<f64>,
 
-    #[arg(long, required = true)]
-    /// The latitude of where the computer is that is running the program
-    pub latitude: String,
+    #[arg(long, required = false, default_value_t = STALL_FACTOR_DEFAULT)]
+    /// Warn when a scan takes more than this many times as long as usual, e.g. because of a stuck network filesystem,
+    /// a thrashing machine or a livelock. 0 turns the watchdog off
+    pub stall_factor: f64,
+
+    #[arg(long, required = false, default_value_t = false)]
+    /// Cancel stalled scans and start them over, instead of only warning about them
+    pub cancel_stalled_scans: bool,
+
+    #[arg(long, required = false, default_value_t = 0)]
+    /// Add a random extra delay of up to this many milliseconds to every wait between integrity checks,
+    /// so that the checks don't line up with periodic activity on the machine
+    pub jitter: u64,
+
+    #[arg(long, required = false, default_value_t = false, conflicts_with_all = ["jitter", "cpu_budget"])]
+    /// Start the integrity checks at fixed times, every -d milliseconds, instead of waiting -d after every check.
+    /// Deadlines a check doesn't start on time for are counted in the heartbeats
+    pub fixed_cadence: bool,
+
+    #[arg(long, required = false, value_parser(parse_duration_string), default_value = "0")]
+    /// Check for this long after starting, e.g. 10m, before counting any exposure. Bitflips found meanwhile are logged
+    /// marked as warm-up and left out of the rates, the machine is still settling from boot-time DMA and firmware
+    pub warmup: Duration,
+
+    #[arg(long, required = false, default_value_t = EXPECTED_RATE_DEFAULT)]
+    /// The flips per GB-hour to expect at sea level, for judging the delay between checks. Scaled up with the altitude
+    pub expected_rate: f64,
+
+    #[arg(long, required = false, default_value_t = INTERVAL_FRACTION_DEFAULT)]
+    /// Warn when the time between checks is more than this fraction of the expected time between flips
+    pub interval_fraction: f64,
+
+    #[arg(long, required = false, default_value_t = false)]
+    /// Instead of warning, lower the delay between checks to fit within --interval-fraction
+    pub auto_interval: bool,
+
+    #[arg(long, required = false)]
+    /// The seed for everything random the detector does. Runs with the same seed and settings are scheduled identically.
+    /// A random seed is used if none is given, it is written to the session line of the log either way
+    pub seed: Option<u64>,
+
+    #[arg(long, required = false, allow_hyphen_values = true, value_parser(parse_longitude))]
+    /// The longitude of where the computer is that is running the program, in degrees (-180 to 180). Overrides the station file
+    pub longitude: Option<f64>,
+
+    #[arg(long, required = false, allow_hyphen_values = true, value_parser(parse_latitude))]
+    /// The latitude of where the computer is that is running the program, in degrees (-90 to 90). Overrides the station file
+    pub latitude: Option<f64>,
+
+    #[arg(long, required = false, value_parser(parse_tag))]
+    /// A key=value tag put on every record, such as room=basement or shielding=none, so that the site's context travels
+    /// with the data. Can be given more than once
+    pub tag: Vec<(String, String)>,
 
     #[arg(long, required = true, value_parser(parse_logging_file_path))]
     /// The file path to save bitflip results
     pub file_path: String,
 
-    #[arg(short, required = false, long, default_value_t = true)]
-    /// Whether to print extra information
-    pub verbose: bool,
+    #[arg(long, required = false)]
+    /// Where to keep the per-day exposure ledger, defaults to the log file path with .ledger added
+    pub ledger_path: Option<PathBuf>,
+
+    #[arg(long, required = false)]
+    /// Where to write a crash report with the settings, the last output and log records and a backtrace when the run
+    /// ends with an error, defaults to the directory of the log file
+    pub crash_dir: Option<PathBuf>,
+
+    #[arg(long, required = false)]
+    /// Where to write the JSON summary of the run when it shuts down, defaults to the log file path with .summary.json added
+    pub summary_path: Option<PathBuf>,
+
+    #[arg(long, required = false, value_enum)]
+    /// The format of the log file, binary is much more compact when heartbeats are frequent. Defaults to csv
+    pub log_format: Option<LogFormat>,
+
+    #[arg(long, required = false, value_enum, default_value_t = LogBackend::File)]
+    /// Where the records go: appended to the log file, or into a SQLite database at its path with a table of runs and
+    /// one of events, which needs a build with the sqlite feature. The database can't take --log-format, --sync-policy
+    /// or --s3-url
+    pub log_backend: LogBackend,
+
+    #[arg(long, required = false, value_enum)]
+    /// When to make sure the log has reached the disk. Syncing after every heartbeat wears out SD cards quickly.
+    /// Defaults to always
+    pub sync_policy: Option<SyncPolicy>,
+
+    #[arg(long, required = false, default_value_t = LOG_SYNC_INTERVAL_DEFAULT)]
+    /// How often to sync the log with --sync-policy interval (in milliseconds)
+    pub sync_interval: u64,
+
+    #[arg(long, required = false, default_value_t = false)]
+    /// Spare the SD card of single board computers: heartbeats and the exposure ledger are kept in memory and only
+    /// written along with other records, at shutdown or every --sd-card-flush-hours. A crash loses at most that many hours of them
+    pub sd_card: bool,
+
+    #[arg(long, required = false, default_value_t = SD_CARD_FLUSH_HOURS_DEFAULT)]
+    /// How often to write the heartbeats and the ledger kept in memory with --sd-card (in hours)
+    pub sd_card_flush_hours: u64,
+
+    #[arg(long, required = false, value_parser(parse_size_string), default_value = DISK_WARNING_DEFAULT)]
+    /// Warn, and post to --alert-webhook, when less than this is free on the filesystem holding the log, e.g. 100MB.
+    /// 0 doesn't watch the free space
+    pub disk_warning: usize,
+
+    #[arg(long, required = false, value_parser(parse_size_string), default_value_t = 0)]
+    /// Leave heartbeats out of the log while less than this is free on its filesystem, keeping what is left for
+    /// bitflips, e.g. 20MB. 0 never pauses them
+    pub disk_pause_heartbeats: usize,
+
+    #[arg(long, required = false, default_value_t = 0)]
+    /// How often to write a heartbeat to the log file (in milliseconds), 0 never writes any
+    pub heartbeat_interval: u64,
+
+    #[arg(long, required = false, default_value_t = RATE_WINDOW_DEFAULT)]
+    /// The live bitflip rate shown in the status and the metrics mostly covers this many hours, events and exposure
+    /// count less the longer ago they were
+    pub rate_window: f64,
+
+    #[arg(long, required = false, default_value_t = ANOMALY_SIGMA_DEFAULT)]
+    /// Alert when the bitflips of the last --rate-window are this many standard deviations above the ones the
+    /// long-term rate expects. Cosmic rays don't come in bursts, failing hardware does. 0 turns alerts off
+    pub anomaly_sigma: f64,
+
+    #[arg(long, required = false, default_value_t = ANOMALY_BASELINE_WINDOW_DEFAULT)]
+    /// The long-term rate alerts compare with mostly covers this many hours. It starts out at the rate expected
+    /// from --expected-rate and the altitude
+    pub anomaly_baseline_window: f64,
+
+    #[arg(long, required = false)]
+    /// Also post alerts as JSON to this URL, e.g. an incoming webhook of a chat service
+    pub alert_webhook: Option<String>,
+
+    #[arg(long, required = false)]
+    /// Pulse this GPIO line (BCM number) of a Raspberry Pi high for every bitflip, for counters, LEDs or
+    /// coincidence electronics. Needs a build with --features gpio
+    pub gpio_pulse: Option<u8>,
+
+    #[arg(long, required = false, default_value_t = GPIO_PULSE_WIDTH_DEFAULT)]
+    /// How long each pulse of --gpio-pulse lasts (in microseconds)
+    pub gpio_pulse_width: u64,
+
+    #[arg(long, required = false, value_parser(parse_serial_spec))]
+    /// Write a short frame for every bitflip to this serial port, e.g. /dev/ttyUSB0:115200, for lab data
+    /// acquisition systems. The baud rate defaults to 115200
+    pub serial: Option<SerialSpec>,
+
+    #[arg(long, required = false)]
+    /// Correct the times of bitflips by the pulses of a PPS source to better than a millisecond: a device such as
+    /// /dev/pps0, or gpsd, optionally with its address as gpsd:host:port
+    pub pps: Option<String>,
+
+    #[arg(long, required = false, default_value_t = 0)]
+    /// How often to sample the memory error counts of the whole machine from EDAC (in milliseconds), 0 never does.
+    /// The counts are only logged when they change
+    pub edac_interval: u64,
+
+    #[arg(long, required = false)]
+    /// A dead man's switch URL, e.g. from healthchecks.io, to ping at most once a minute while integrity checks pass
+    pub healthcheck_url: Option<String>,
+
+    #[arg(long, required = false)]
+    /// Also write statistics and events to InfluxDB, the complete write URL e.g. http://localhost:8086/write?db=cosmic_rays
+    pub influx_url: Option<String>,
+
+    #[arg(long, required = false, requires = "influx_url")]
+    /// The API token for InfluxDB 2 write endpoints
+    pub influx_token: Option<String>,
+
+    #[arg(long, required = false, default_value_t = INFLUX_INTERVAL_DEFAULT)]
+    /// How often to write statistics to InfluxDB (in milliseconds)
+    pub influx_interval: u64,
+
+    #[arg(long, required = false)]
+    /// Send every record written to the log to a collector at this URL, see docs/upload_protocol.md
+    pub upload_url: Option<String>,
+
+    #[arg(long, required = false)]
+    /// Also copy the log to an S3 compatible bucket as gzipped segments, given in path style, e.g.
+    /// https://s3.eu-central-1.amazonaws.com/my-bucket. The keys are taken from AWS_ACCESS_KEY_ID and AWS_SECRET_ACCESS_KEY
+    pub s3_url: Option<String>,
+
+    #[arg(long, required = false, default_value = S3_REGION_DEFAULT)]
+    /// The region of the bucket, which requests to it are signed for
+    pub s3_region: String,
+
+    #[arg(long, required = false, default_value_t = S3_INTERVAL_DEFAULT)]
+    /// How often to copy what was added to the log to the bucket (in milliseconds)
+    pub s3_interval: u64,
+
+    #[arg(long, required = false, value_parser(parse_s3_tag))]
+    /// A key=value tag to put on every segment in the bucket, for matching them in lifecycle rules. Can be given more than once
+    pub s3_tag: Vec<String>,
+
+    #[arg(long, required = false, default_value = STATION_KEY_DEFAULT)]
+    /// The file holding the key uploads are signed with, a new key is generated there if it doesn't exist. Keep it private
+    pub station_key: PathBuf,
+
+    #[arg(long, required = false, default_value_t = false)]
+    /// Share anonymized bitflips and heartbeats with the community collector consented to with the share subcommand.
+    /// Everything shared is also appended to <log>.shared
+    pub share: bool,
+
+    #[arg(long, required = false, default_value = SHARE_CONSENT_DEFAULT)]
+    /// The file the share subcommand keeps the consent to sharing in
+    pub share_consent: PathBuf,
+
+    #[arg(long, required = false)]
+    /// Serve metrics on this address, e.g. 127.0.0.1:9184, at /metrics for Prometheus and /annotations for Grafana
+    pub metrics_addr: Option<String>,
+
+    #[arg(long, required = false, default_value_t = false)]
+    /// Advertise this station on the local network with mDNS so that `sync` can collect its results
+    pub lan_sync: bool,
+
+    #[arg(long, required = false, value_enum, default_value_t = LockedLogPolicy::Refuse)]
+    /// What to do if another instance is already logging to the same file
+    pub on_locked_log: LockedLogPolicy,
+
+    #[arg(long, required = false, default_value_t = false, conflicts_with = "takeover")]
+    /// If an instance is already running for this station, print its status instead of refusing to start
+    pub attach: bool,
+
+    #[arg(long, required = false, default_value_t = false)]
+    /// If an instance is already running for this station, ask it to shut down and take its place
+    pub takeover: bool,
+
+    #[arg(long, required = false, default_value_t = false)]
+    /// Check online whether a newer release is available and warn about it
+    pub check_update: bool,
+
+    #[arg(long, required = false, default_value_t = false)]
+    /// Guard the reads of the detector memory with extra optimization barriers,
+    /// and refuse to start if checking the detector turns out to be impossibly fast
+    pub paranoid_reads: bool,
+
+    #[arg(long, required = false, value_parser(parse_scan_mode), default_value = SCAN_MODE_DEFAULT)]
+    /// How to read the detector when checking it: bytes for a read per byte, words for a read per 64-bit word, or simd
+    /// for 16 bytes at a time on x86_64. Single bytes are only looked at where something changed
+    pub scan_mode: ScanMode,
+
+    #[arg(long, required = false, value_parser(parse_pattern), default_value = PATTERN_DEFAULT)]
+    /// What to write to the detector before every scrub cycle: a byte such as 0x00, 0xFF, 0x55 or 0xAA, random for a
+    /// new random byte every cycle, rotate to go through those four in turn, or checkerboard for 0x55 and 0xAA in turn.
+    /// A bit can only be seen flipping away from the value it holds, so patterns differ in how they see 0→1 and 1→0
+    /// flips
+    pub pattern: Pattern,
+
+    #[arg(long, required = false, value_parser(parse_duration_string), default_value = SCRUB_INTERVAL_DEFAULT)]
+    /// After a bitflip only the changed bytes are written back, the whole detector only once this long has passed
+    /// since it last was, e.g. 30m, starting a new scrub cycle. 0 writes the whole detector after every bitflip
+    pub scrub_interval: Duration,
+
+    #[arg(long, required = false, default_value_t = false)]
+    /// Make every other cache line of the detector hold the inverse of the others, as a second detector checked along
+    /// with the first. Flips record which half they were in: a particle hits one, a bug in the checks shows in both
+    pub inverted_halves: bool,
+
+    #[arg(long, required = false, default_value_t = false)]
+    /// Refuse to start when a pre-flight check fails, such as a sink that can't be written to. Without it failures are
+    /// only reported
+    pub strict_preflight: bool,
+
+    #[arg(long, required = false, default_value_t = 0)]
+    /// Check the detector with CRC32 checksums of its chunks, and scan it byte by byte only every this many checks as a
+    /// cross-check. When the two disagree the full scan is trusted and a disagreement record is logged. 0 always scans
+    /// byte by byte without checksums
+    pub full_scan_every: u64,
+
+    #[arg(long, required = false, default_value_t = MIN_RESIDENCY_DEFAULT)]
+    /// Before every check, ask Linux which of the detector's pages are in memory. When less than this fraction of them
+    /// is, the rest was swapped out where no cosmic ray could hit it: it is faulted back in first and the check counts as
+    /// reduced coverage in the flip and heartbeat records. 0 turns the check off
+    pub min_residency: f64,
+
+    #[arg(long, required = false, default_value_t = false)]
+    /// Back the detector with transparent huge pages on Linux, which makes scanning large detectors faster and their
+    /// physical layout steadier. Ignored with a warning where transparent huge pages are turned off
+    pub huge_pages: bool,
+
+    #[arg(long, required = false, default_value_t = false)]
+    /// On machines with more than one NUMA node, bind the detector's 64 MB chunks to the nodes in turn, so that the
+    /// memory of every socket is watched equally. The node of every flip is logged on such machines either way
+    pub numa: bool,
+
+    #[arg(long, required = false, default_value_t = false)]
+    /// Don't lock the detector's memory with mlock. Locking keeps it from being swapped out, when RLIMIT_MEMLOCK is too
+    /// low for it the detector runs unlocked with a warning
+    pub no_mlock: bool,
+
+    #[arg(long, required = false, default_value_t = HEAVY_SMI_RATE_DEFAULT)]
+    /// Mark check windows with more than this many system management interrupts per second as heavy SMI activity, in
+    /// the flips and heartbeats. The SMI counter needs an Intel processor, the msr module and root, elsewhere only
+    /// scans that take far longer than usual are marked
+    pub heavy_smi_rate: f64,
+
+    #[arg(long, required = false, default_value_t = false)]
+    /// Use Linux soft-dirty page tracking to tell whether a changed byte was written by software rather than flipped.
+    /// Ignored with a warning where it isn't supported
+    pub soft_dirty: bool,
+
+    #[arg(long, required = false, default_value_t = false)]
+    /// Once started, restrict the files the process can access with Landlock, on Linux. Only filesystem access is
+    /// restricted, there is no system call filter. Only the directories of the log, the ledger, the summary and the
+    /// crash reports, the temporary directory and /proc/self/clear_refs stay writable
+    pub sandbox: bool,
+
+    #[arg(long, required = false, default_value_t = false)]
+    /// Run in a memory cgroup of its own, limited to a little more than the detector, on Linux with cgroup v2.
+    /// Needs write access to /sys/fs/cgroup, usually root
+    pub self_cgroup: bool,
+
+    #[arg(long, required = false, default_value_t = false)]
+    /// Write every event as a single line of JSON to stdout, all other output goes to stderr
+    pub events_to_stdout: bool,
+
+    #[arg(long, required = false, default_value_t = false, requires = "events_to_stdout")]
+    /// Also write a heartbeat line to stdout after every passed integrity check
+    pub heartbeats_to_stdout: bool,
+
+    #[arg(long, required = false, default_value_t = false)]
+    /// For demonstrations in science class: a big banner for every bitflip and a running count of today's and all
+    /// bitflips, kept next to the log file with .classroom added, instead of the technical details
+    pub classroom: bool,
 }
 
-/// Parses a string describing a number of bytes into an integer.
-/// The string can use common SI prefixes as well, like '4GB' or '30kB'.
-pub fn parse_size_string(size_string: &str) -> Result<usize, String> {
-    println!("test");
-    let chars: Vec<char> = size_string.chars().collect();
-    let len: usize = chars.len();
-    let last: char = match chars.last() {
-        Some(l) => *l,
-        None => return Err("memory_to_occupy was empty".into()),
-    };
+impl RunArgs {
+    /// Refuses the settings of the log file with --log-backend sqlite, which writes every record in a transaction of
+    /// its own and whose database can't be copied while it is written to.
+    pub fn check_log_backend(&self) -> Result<(), String> {
+        if self.log_backend != LogBackend::Sqlite {
+            return Ok(());
+        }
+        let conflicting = [
+            ("--log-format", self.log_format.is_some()),
+            ("--sync-policy", self.sync_policy.is_some()),
+            ("--s3-url", self.s3_url.is_some()),
+        ];
+        match conflicting.iter().find(|(_, given)| *given) {
+            Some((option, _)) => Err(format!("{} can't be used with --log-backend sqlite", option)),
+            None => Ok(()),
+        }
+    }
+}
+
+/// How records are written to the log file.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum LogFormat {
+    /// One line of comma separated values per record
+    Csv,
+    /// Delta encoded binary records, convert them with the export subcommand
+    Binary,
+    /// One JSON object per line and record, with named fields and the kind of record in `type`
+    Jsonl,
+}
+
+/// What keeps the log.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum LogBackend {
+    /// A file the records are appended to in --log-format
+    File,
+    /// A SQLite database, every write a transaction of its own
+    Sqlite,
+}
+
+/// When the log file is synced to the disk.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum SyncPolicy {
+    /// After every write, nothing is lost in a power cut
+    Always,
+    /// At most once every --sync-interval, a power cut can lose what was written since
+    Interval,
+    /// Leave it to the operating system, which usually writes within half a minute
+    Never,
+}
+
+/// What the export subcommand converts logs to.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum ExportFormat {
+    /// One line of comma separated values per record
+    Csv,
+    /// One JSON object per line
+    Json,
+}
+
+/// What to do when the log file is locked by another instance.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum LockedLogPolicy {
+    /// Refuse to start
+    Refuse,
+    /// Log to a file with the process ID added to its name instead
+    PerPid,
+}
+
+#[derive(Args, Debug)]
+pub struct AnalyzeArgs {
+    /// The log file to analyze
+    pub file_path: PathBuf,
+
+    #[arg(long, required = false)]
+    /// The exposure ledger of the log, defaults to the log file path with .ledger added
+    pub ledger_path: Option<PathBuf>,
+
+    #[arg(long, required = false, value_parser(parse_time_zone), default_value = "local")]
+    /// The time zone of the histograms of bitflips by hour and weekday: local for this machine's, utc, or an offset
+    /// from UTC such as +05:30 for where the station is
+    pub time_zone: TimeZone,
+
+    #[arg(long, required = false)]
+    /// The events of another detector, such as a muon scintillator, to look for coincidences with the bitflips:
+    /// one Unix time in seconds per line
+    pub coincidences: Option<PathBuf>,
+
+    #[arg(long, required = false, default_values_t = COINCIDENCE_WINDOWS_DEFAULT)]
+    /// How far an event may be from the time a bitflip may have happened in to count as coincident (in
+    /// milliseconds). Can be given several times
+    pub coincidence_window: Vec<f64>,
+
+    #[arg(long, required = false, default_value_t = REFERENCE_FLUX_DEFAULT)]
+    /// The particle flux the detector was exposed to for working out the per-bit cross-section, in particles per cm²
+    /// per hour. Defaults to the JESD89A sea level neutron flux, scale it for the station's altitude and shielding
+    pub reference_flux: f64,
+
+    #[arg(long, required = false, default_value_t = 0.0)]
+    /// The relative uncertainty of --reference-flux, e.g. 0.2 for 20%, which is carried into the cross-section's interval
+    pub reference_flux_uncertainty: f64,
+
+    #[arg(long, required = false)]
+    /// The community rates downloaded by fetch-community, to compare the station's rate with that of stations at the
+    /// same altitude with the same memory
+    pub community: Option<PathBuf>,
+}
+
+#[derive(Args, Debug)]
+pub struct HealthArgs {
+    /// The log file to check
+    pub file_path: PathBuf,
 
-    if last == '0' {
-        // Use up all available memory
-        return Ok(0);
+    #[arg(long, required = false)]
+    /// The exposure ledger of the log, defaults to the log file path with .ledger added
+    pub ledger_path: Option<PathBuf>,
+
+    #[arg(long, required = false, default_value_t = EXPECTED_RATE_DEFAULT)]
+    /// The flips per GB-hour to expect from cosmic rays at sea level. Scaled up with the altitude of the station
+    pub expected_rate: f64,
+}
+
+/// A serial port and the baud rate to open it with.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SerialSpec {
+    pub path: String,
+    pub baud_rate: u32,
+}
+
+/// What the detector is written with before every scrub cycle.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Pattern {
+    /// The same byte every cycle.
+    Fixed(u8),
+    /// A new random byte every cycle.
+    Random,
+    /// 0x00, 0xFF, 0x55 and 0xAA in turn.
+    Rotate,
+    /// 0x55 and 0xAA in turn, every bit next to neighbours of the other value and holding both values.
+    Checkerboard,
+}
+
+/// The patterns `--pattern rotate` goes through, every bit holds both values and sits next to both.
+const ROTATING_PATTERNS: [u8; 4] = [0x00, 0xFF, 0x55, 0xAA];
+const CHECKERBOARD_PATTERNS: [u8; 2] = [0x55, 0xAA];
+
+impl Pattern {
+    /// The byte the detector is written with in the given scrub cycle, counted from 0.
+    pub fn value(self, cycle: usize, rng: &mut Rng) -> u8 {
+        match self {
+            Pattern::Fixed(value) => value,
+            Pattern::Random => rng.next_u64() as u8,
+            Pattern::Rotate => ROTATING_PATTERNS[cycle % ROTATING_PATTERNS.len()],
+            Pattern::Checkerboard => CHECKERBOARD_PATTERNS[cycle % CHECKERBOARD_PATTERNS.len()],
+        }
     }
+}
+
+/// How large a detector to make.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SizeSpec {
+    /// This many bytes.
+    Bytes(u64),
+    /// This share of the machine's memory, in percent.
+    Percent(f32),
+    /// As large as fits into the available memory, leaving `min_free` bytes of it on machines without swap, or the
+    /// default headroom when that isn't given.
+    Auto { min_free: Option<u64> },
+}
 
-    if (last != 'B' && last != 'b') || len < 2 {
-        return Err("Unable to parse memory_to_occupy".into());
+impl SizeSpec {
+    /// The size in bytes on a machine with the given amount of memory.
+    pub fn bytes(self, total_memory: u64) -> Result<usize, String> {
+        let bytes = match self {
+            SizeSpec::Bytes(bytes) => bytes,
+            SizeSpec::Percent(percent) if percent > 0.0 && percent <= 100.0 => {
+                (total_memory as f64 * percent as f64 / 100.0) as u64
+            }
+            SizeSpec::Percent(percent) => return Err(format!("{}% is not a share of the memory", percent)),
+            SizeSpec::Auto { .. } => return Err("An automatic size is only known once the memory was filled".into()),
+        };
+        match bytes {
+            0 => Err("The detector can't be empty".into()),
+            bytes => usize::try_from(bytes).map_err(|_| format!("{} bytes don't fit into the address space", bytes)),
+        }
     }
+}
+
+/// Which time of day events are put at.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TimeZone {
+    /// The time zone of this machine, with its daylight saving time.
+    Local,
+    /// A fixed offset from UTC, in seconds.
+    Fixed(i64),
+}
+
+#[derive(Args, Debug)]
+pub struct TailArgs {
+    #[arg(required_unless_present = "storage")]
+    /// The log file to follow, in any format
+    pub file_path: Option<PathBuf>,
+
+    #[arg(long, required = false, conflicts_with = "file_path")]
+    /// Follow the records received by a collector instead, stored as with serve --storage
+    pub storage: Option<String>,
+
+    #[arg(long, required = false, default_value = DATA_DIR_DEFAULT)]
+    /// The collector's data directory, for --storage
+    pub data_dir: PathBuf,
+
+    #[arg(short = 'n', long, required = false, default_value_t = TAIL_LINES_DEFAULT)]
+    /// How many of the existing records to print before following
+    pub lines: usize,
+
+    #[arg(long, required = false, default_value_t = TAIL_INTERVAL_DEFAULT)]
+    /// How often to look for new records (in milliseconds)
+    pub interval: u64,
+}
+
+#[derive(Args, Debug)]
+pub struct BenchArgs {
+    #[arg(short, required = false, value_parser(parse_size_string), default_value = BENCH_SIZE_DEFAULT)]
+    /// The size of the detector to benchmark, understands e.g. 200, 5kB, 2GB and 3Mb. Can be given several times
+    pub memory_to_occupy: Vec<usize>,
+
+    #[arg(short = 'n', long, required = false, default_value_t = BENCH_ITERATIONS_DEFAULT)]
+    /// How many times to repeat each measurement
+    pub iterations: u32,
+
+    #[arg(long, required = false, default_value_t = false)]
+    /// Print the results as JSON, for comparing releases
+    pub json: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct SelftestArgs {
+    #[arg(short, required = false, value_parser(parse_size_string), default_value = SELFTEST_SIZE_DEFAULT)]
+    /// The size of the detector to test with, understands e.g. 200, 5kB, 2GB and 3Mb
+    pub memory_to_occupy: usize,
+}
+
+#[derive(Args, Debug)]
+pub struct SimulateArgs {
+    #[arg(short, required = false, value_parser(parse_size_string), default_value = SIMULATE_SIZE_DEFAULT)]
+    /// The size of the detector, understands e.g. 200, 5kB, 2GB and 3Mb
+    pub memory_to_occupy: usize,
+
+    #[arg(short, required = false, default_value_t = DELAY_DEFAULT)]
+    /// The delay in between each integrity check (in milliseconds), as given to run
+    pub delay_between_checks: u64,
+
+    #[arg(long, required = false, default_value_t = 0)]
+    /// The random extra delay of up to this many milliseconds, as given to run
+    pub jitter: u64,
+
+    #[arg(long, required = false, value_parser(parse_size_string))]
+    /// How many bytes per second a check reads, e.g. 10GB. Measured on this machine if not given
+    pub scan_rate: Option<usize>,
+
+    #[arg(long, required = false, value_parser(parse_size_string))]
+    /// How many bytes per second a reset writes, e.g. 10GB. Measured on this machine if not given
+    pub write_rate: Option<usize>,
+
+    #[arg(long, required = false, default_value_t = SIMULATE_EVENTS_DEFAULT)]
+    /// How many events to inject
+    pub events: u64,
+
+    #[arg(long, required = false, default_value_t = SIMULATE_RATE_DEFAULT)]
+    /// How many events hit the whole detector per hour. Only matters for events that are close enough together to
+    /// be logged as one, so it can be exaggerated to see where that starts
+    pub rate: f64,
+
+    #[arg(long, required = false)]
+    /// The seed for the injected events, random if not given
+    pub seed: Option<u64>,
+}
+
+#[derive(Args, Debug)]
+pub struct ExportArgs {
+    /// The log file to export, in any format
+    pub file_path: PathBuf,
+
+    #[arg(long, required = false, value_enum, default_value_t = ExportFormat::Csv)]
+    /// What to convert the log to, the result is written to stdout
+    pub format: ExportFormat,
+
+    #[arg(long, required = false, default_value_t = false)]
+    /// Coarsen the location to a 0.1 degree grid and leave out process IDs, for publishing the data
+    pub anonymize: bool,
+
+    #[arg(long, required = false)]
+    /// Instead of writing to stdout, write a dataset for an archival repository such as Zenodo or figshare into
+    /// this directory: the log, the exposure ledger and metadata.json describing them
+    pub doi_metadata: Option<PathBuf>,
+
+    #[arg(long, required = false)]
+    /// The exposure ledger of the log for --doi-metadata, defaults to the log file path with .ledger added
+    pub ledger_path: Option<PathBuf>,
+
+    #[arg(long, required = false)]
+    /// The title of the dataset for --doi-metadata, defaults to one naming the station
+    pub title: Option<String>,
+
+    #[arg(long, required = false)]
+    /// A creator of the dataset for --doi-metadata, as "Family name, Given names". Can be given several times
+    pub creator: Vec<String>,
+
+    #[arg(long, required = false, default_value = DOI_LICENSE_DEFAULT)]
+    /// The license of the dataset for --doi-metadata, as an SPDX identifier in lowercase
+    pub license: String,
+}
+
+#[derive(Args, Debug)]
+pub struct ServeArgs {
+    #[arg(long, required = false, default_value = LISTEN_DEFAULT)]
+    /// The address to listen for stations on
+    pub listen: String,
+
+    #[arg(long, required = false, default_value = DATA_DIR_DEFAULT)]
+    /// Where to keep the station registry and the received records
+    pub data_dir: PathBuf,
+
+    #[arg(long, required = false, default_value = STORAGE_DEFAULT)]
+    /// How to store them: files, sqlite or parquet in the data directory, or a postgres:// URL.
+    /// Everything but files has to be enabled with the feature of the same name when building
+    pub storage: String,
+
+    #[arg(long, required = false, default_value_t = false)]
+    /// Only print the schema migrations the storage's database needs, without applying them or starting the
+    /// collector. Without this the collector migrates the database when it starts
+    pub migrate_dry_run: bool,
+
+    #[arg(long, required = false, default_value_t = false)]
+    /// Accept uploads from stations that aren't in the registry yet, registering them with the key they sign with
+    pub open_registration: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct ExportBundleArgs {
+    /// The log file to bundle
+    pub file_path: PathBuf,
+
+    #[arg(long, required = true)]
+    /// Where to write the bundle, its checksum is written next to it with .sha256 added
+    pub output: PathBuf,
+
+    #[arg(long, required = false)]
+    /// The exposure ledger to bundle, defaults to the log file path with .ledger added
+    pub ledger_path: Option<PathBuf>,
+
+    #[arg(long, required = false)]
+    /// Extra files to bundle, such as quarantine lists. Can be given several times
+    pub include: Vec<PathBuf>,
+}
+
+#[derive(Args, Debug)]
+pub struct ImportBundleArgs {
+    /// The bundle to import
+    pub bundle: PathBuf,
+
+    #[arg(long, required = false, default_value = IMPORT_DIRECTORY_DEFAULT)]
+    /// The directory to unpack into, every bundle ends up in <station ID>/<creation time> below it
+    pub into: PathBuf,
+}
+
+#[derive(Args, Debug)]
+pub struct BackupArgs {
+    /// The log, ledger or database file to back up
+    pub file_path: PathBuf,
+}
+
+#[derive(Args, Debug)]
+pub struct MessagesArgs {
+    /// The language of the catalog to update, e.g. de. Without it an empty template for a new language is printed
+    pub language: Option<String>,
+}
+
+#[derive(Args, Debug)]
+pub struct SyncArgs {
+    #[arg(long, required = false, default_value = SYNC_OUTPUT_DEFAULT)]
+    /// The file to append the collected summaries to, as JSON lines
+    pub output: PathBuf,
+
+    #[arg(long, required = false, default_value_t = SYNC_INTERVAL_DEFAULT)]
+    /// How long to wait between collecting rounds (in milliseconds)
+    pub interval: u64,
+
+    #[arg(long, required = false, default_value_t = SYNC_DISCOVERY_TIME_DEFAULT)]
+    /// How long to look for stations every round (in milliseconds)
+    pub discovery_time: u64,
+
+    #[arg(long, required = false, default_value_t = false)]
+    /// Collect a single round and exit
+    pub once: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct CtlArgs {
+    #[arg(value_enum)]
+    /// What to ask the running instance to do
+    pub action: CtlAction,
+}
+
+#[derive(Args, Debug)]
+pub struct ShareArgs {
+    #[arg(long, required = false)]
+    /// Consent to sharing with the community collector at this URL, after being shown what is shared
+    pub url: Option<String>,
+
+    #[arg(long, required = false, default_value_t = false, requires = "url")]
+    /// Consent without being asked, for setting up stations by script once what is shared has been read
+    pub yes: bool,
+
+    #[arg(long, required = false, default_value_t = false, conflicts_with = "url")]
+    /// Withdraw consent, running stations stop sharing when they next start
+    pub revoke: bool,
+
+    #[arg(long, required = false, default_value = SHARE_CONSENT_DEFAULT)]
+    /// The file the consent is kept in
+    pub consent: PathBuf,
+}
+
+#[derive(Args, Debug)]
+pub struct FetchCommunityArgs {
+    #[arg(long, required = false)]
+    /// The collector to fetch from, defaults to the one consent to sharing was given to
+    pub url: Option<String>,
 
-    let next_to_last: char = chars[len - 2];
+    #[arg(long, required = false, default_value = SHARE_CONSENT_DEFAULT)]
+    /// The file the consent to sharing is kept in
+    pub consent: PathBuf,
 
-    let si_prefix_factor: f64 = if next_to_last == 'k' {
-        1e3
-    } else if next_to_last == 'M' {
-        1e6
-    } else if next_to_last == 'G' {
-        1e9
-    } else if next_to_last == 'T' {
+    #[arg(long, required = false, default_value = COMMUNITY_DEFAULT)]
+    /// The file to save the community rates in
+    pub output: PathBuf,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum CtlAction {
+    /// Print the status of the running instance
+    Status,
+    /// Make the running instance shut down cleanly
+    Shutdown,
+}
+
+impl CtlAction {
+    /// The command sent to the running instance.
+    pub fn command(&self) -> &'static str {
+        match self {
+            CtlAction::Status => "status",
+            CtlAction::Shutdown => "shutdown",
+        }
+    }
+}
+
+/// Parses a string describing a number of bytes into an integer, such as `200`, `4GB`, `1.5 GB`, `512MiB` or `3Mb`.
+/// SI prefixes are powers of 1000 and binary ones such as `Gi` powers of 1024, a lowercase b counts bits and a
+/// prefix alone, as in `10M`, bytes.
+pub fn parse_size_string(size_string: &str) -> Result<usize, String> {
+    let size_string = size_string.trim();
+    if size_string.is_empty() {
+        return Err("The size is empty".into());
+    }
+    let unit_start = size_string.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(size_string.len());
+    let (number, unit) = size_string.split_at(unit_start);
+    let number: f64 = number.parse().map_err(|_| format!("Unable to parse the size {}", size_string))?;
+    let unit = unit.trim();
+    let (prefix, bit_size) = match unit.strip_suffix('b') {
+        Some(prefix) => (prefix, 1.0 / 8.0),
+        None => (unit.strip_suffix('B').unwrap_or(unit), 1.0),
+    };
+    let si_prefix_factor: f64 = match prefix {
+        "" => 1.0,
+        "k" | "K" => 1e3,
+        "M" => 1e6,
+        "G" => 1e9,
         //Future proofing...
-        1e12
-    } else if next_to_last == 'P' {
+        "T" => 1e12,
         //HOW?!
-        1e15
-    } else if !next_to_last.is_ascii_digit() {
-        return Err("Unsupported memory size".into());
+        "P" => 1e15,
+        "Ki" => 1024.0,
+        "Mi" => 1024f64.powi(2),
+        "Gi" => 1024f64.powi(3),
+        "Ti" => 1024f64.powi(4),
+        "Pi" => 1024f64.powi(5),
+        _ => return Err(format!("Unsupported unit {} in the size {}", unit, size_string)),
+    };
+    Ok((number * si_prefix_factor * bit_size) as usize)
+}
+
+/// Parses the size of the detector: a number of bytes as `parse_size_string` understands it, a share of the memory
+/// such as `25%`, or `auto`, optionally followed by the memory to leave free as in `auto:1GB`. A size of no bytes is an
+/// error that points at `auto`, which older versions took `0` for.
+pub fn parse_size_spec(size_spec: &str) -> Result<SizeSpec, String> {
+    let size_spec = size_spec.trim();
+    if size_spec.eq_ignore_ascii_case("auto") {
+        return Ok(SizeSpec::Auto { min_free: None });
+    }
+    if let Some(min_free) = size_spec.get(..5).filter(|prefix| prefix.eq_ignore_ascii_case("auto:")) {
+        let min_free = parse_size_string(&size_spec[min_free.len()..])?;
+        return Ok(SizeSpec::Auto { min_free: Some(min_free as u64) });
+    }
+    if let Some(percent) = size_spec.strip_suffix('%') {
+        let percent: f32 = percent.trim().parse().map_err(|_| format!("Unable to parse the share {}", size_spec))?;
+        return match percent > 0.0 && percent <= 100.0 {
+            true => Ok(SizeSpec::Percent(percent)),
+            false => Err(format!("{} is not a share of the memory", size_spec)),
+        };
+    }
+    match parse_size_string(size_spec)? {
+        0 => Err(format!("{} is no memory at all, give -m auto to fill all available memory", size_spec)),
+        bytes => Ok(SizeSpec::Bytes(bytes as u64)),
+    }
+}
+
+/// Parses a duration given as a number with an optional unit of s, m, h or d, e.g. 10m. Without a unit it is seconds.
+pub fn parse_duration_string(duration_string: &str) -> Result<Duration, String> {
+    let duration_string = duration_string.trim();
+    let (number, unit) = duration_string.split_at(
+        duration_string
+            .find(|c: char| !c.is_ascii_digit() && c != '.')
+            .unwrap_or(duration_string.len()),
+    );
+    let number: f64 = number.parse().map_err(|_| format!("Unable to parse the duration {}", duration_string))?;
+    let seconds = match unit.trim() {
+        "" | "s" => 1.0,
+        "m" => 60.0,
+        "h" => 3600.0,
+        "d" => 86400.0,
+        unit => return Err(format!("Unsupported unit {} in the duration {}", unit, duration_string)),
+    };
+    Duration::try_from_secs_f64(number * seconds).map_err(|_| format!("The duration {} is out of range", duration_string))
+}
+
+/// Parses a latitude in degrees, rejecting values outside of -90 to 90.
+pub fn parse_latitude(latitude: &str) -> Result<f64, String> {
+    let latitude: f64 = latitude.trim().parse().map_err(|e: ParseFloatError| e.to_string())?;
+    validate_latitude(latitude)
+}
+
+/// Parses a longitude in degrees, rejecting values outside of -180 to 180.
+pub fn parse_longitude(longitude: &str) -> Result<f64, String> {
+    let longitude: f64 = longitude.trim().parse().map_err(|e: ParseFloatError| e.to_string())?;
+    validate_longitude(longitude)
+}
+
+/// Parses a percentage such as `5%` or `5` into a fraction, rejecting anything outside of 0 to 100%.
+pub fn parse_percentage(percentage: &str) -> Result<f64, String> {
+    let percent: f64 = percentage
+        .trim()
+        .trim_end_matches('%')
+        .trim()
+        .parse()
+        .map_err(|e: ParseFloatError| e.to_string())?;
+    if percent > 0.0 && percent <= 100.0 {
+        Ok(percent / 100.0)
     } else {
-        return Err("Could not parse memory size".into());
+        Err(format!("{}% is not above 0 and at most 100%", percent))
+    }
+}
+
+/// Parses a fill pattern, a byte in hex such as 0xAA, random, rotate or checkerboard.
+pub fn parse_pattern(pattern: &str) -> Result<Pattern, String> {
+    let pattern = pattern.trim();
+    match pattern.to_lowercase().as_str() {
+        "random" => Ok(Pattern::Random),
+        "rotate" => Ok(Pattern::Rotate),
+        "checkerboard" => Ok(Pattern::Checkerboard),
+        lowercase => lowercase
+            .strip_prefix("0x")
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+            .map(Pattern::Fixed)
+            .ok_or_else(|| format!("{} is not a byte in hex such as 0xAA, random, rotate or checkerboard", pattern)),
+    }
+}
+
+/// Parses a scan mode, bytes, words or simd.
+pub fn parse_scan_mode(scan_mode: &str) -> Result<ScanMode, String> {
+    match scan_mode.trim().to_lowercase().as_str() {
+        "bytes" => Ok(ScanMode::Bytes),
+        "words" => Ok(ScanMode::Words),
+        "simd" => Ok(ScanMode::Simd),
+        _ => Err(format!("{} is not bytes, words or simd", scan_mode)),
+    }
+}
+
+/// Understands `local`, `utc` and offsets from UTC such as `+2`, `-05:00` and `+0530`.
+pub fn parse_time_zone(time_zone: &str) -> Result<TimeZone, String> {
+    let time_zone = time_zone.trim();
+    match time_zone.to_lowercase().as_str() {
+        "local" => return Ok(TimeZone::Local),
+        "utc" | "z" => return Ok(TimeZone::Fixed(0)),
+        _ => {}
+    }
+    let invalid = || format!("{} is not local, utc or an offset from UTC such as +05:30", time_zone);
+    let (sign, offset) = match (time_zone.strip_prefix('+'), time_zone.strip_prefix('-')) {
+        (Some(offset), _) => (1, offset),
+        (_, Some(offset)) => (-1, offset),
+        _ => return Err(invalid()),
+    };
+    let (hours, minutes) = match offset.split_once(':') {
+        Some((hours, minutes)) => (hours, minutes),
+        None if offset.len() == 4 && offset.is_ascii() => offset.split_at(2),
+        None => (offset, "0"),
     };
+    let hours: i64 = hours.parse().map_err(|_| invalid())?;
+    let minutes: i64 = minutes.parse().map_err(|_| invalid())?;
+    if hours > 14 || minutes >= 60 {
+        return Err(invalid());
+    }
+    Ok(TimeZone::Fixed(sign * (hours * 3600 + minutes * 60)))
+}
 
-    let bit_size: f64 = if last == 'B' { 1.0 } else { 1.0 / 8.0 };
+/// Understands a port such as `/dev/ttyUSB0` or `COM3`, optionally followed by a colon and the baud rate.
+pub fn parse_serial_spec(spec: &str) -> Result<SerialSpec, String> {
+    let spec = spec.trim();
+    let (path, baud_rate) = match spec.rsplit_once(':') {
+        Some((path, baud_rate)) => {
+            let baud_rate = baud_rate.parse().map_err(|_| format!("{} is not a baud rate", baud_rate))?;
+            (path, baud_rate)
+        }
+        None => (spec, SERIAL_BAUD_RATE_DEFAULT),
+    };
+    if path.is_empty() {
+        return Err("The serial port is empty".into());
+    }
+    Ok(SerialSpec { path: path.to_string(), baud_rate })
+}
 
-    let factor: usize = (si_prefix_factor * bit_size) as usize;
+/// Checks that an S3 object tag is a `key=value` pair.
+pub fn parse_s3_tag(tag: &str) -> Result<String, String> {
+    match tag.split_once('=') {
+        Some((key, _)) if !key.is_empty() => Ok(tag.to_string()),
+        _ => Err(format!("The tag '{}' is not of the form key=value", tag)),
+    }
+}
 
-    let digits: String = chars[..len - 2].iter().collect();
-    let number: usize = digits.parse().map_err(|e: ParseIntError| e.to_string())?;
+/// Parses a `--tag`. Keys are letters, digits, `_` and `-`, values can't hold commas, which separate the columns
+/// of the CSV log.
+pub fn parse_tag(tag: &str) -> Result<(String, String), String> {
+    let (key, value) = tag
+        .split_once('=')
+        .ok_or_else(|| format!("The tag '{}' is not of the form key=value", tag))?;
+    if key.is_empty() || !key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+        return Err(format!("The tag key '{}' can only have letters, digits, _ and -", key));
+    }
+    if value.chars().any(|c| c == ',' || c.is_control()) {
+        return Err(format!("The value of the tag '{}' can't have commas or control characters", key));
+    }
+    Ok((key.to_string(), value.to_string()))
+}
 
-    Ok(number * factor)
+pub fn validate_latitude(latitude: f64) -> Result<f64, String> {
+    if (-90.0..=90.0).contains(&latitude) {
+        Ok(latitude)
+    } else {
+        Err(format!("Latitude {} is not between -90 and 90 degrees", latitude))
+    }
+}
+
+pub fn validate_longitude(longitude: f64) -> Result<f64, String> {
+    if (-180.0..=180.0).contains(&longitude) {
+        Ok(longitude)
+    } else {
+        Err(format!("Longitude {} is not between -180 and 180 degrees", longitude))
+    }
 }
 
 pub fn parse_logging_file_path(file_path: &str) -> Result<String, String> {
     match File::open(file_path) {
-        Ok(_open_file) => println!("Found existing file {}", file_path),
+        Ok(_open_file) => eprintln!("Found existing file {}", file_path),
         Err(_open_err) => {
-            println!("File {} does not exist, trying to create it.", file_path);
+            eprintln!("File {} does not exist, trying to create it.", file_path);
             match File::create(file_path) {
-                Ok(_create_file) => println!("Created file {}", file_path),
+                Ok(_create_file) => eprintln!("Created file {}", file_path),
                 Err(create_err) => {
                     // Unable to create file
                     return Err(format!("Unable to create file: {}", create_err));
@@ -101,6 +1182,42 @@ pub fn parse_logging_file_path(file_path: &str) -> Result<String, String> {
         }
     }
 
-    println!("Logging bitflips to {}", file_path);
+    eprintln!("Logging bitflips to {}", file_path);
     return Ok(file_path.to_string());
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn time_zones_are_offsets_with_an_ascii_sign() {
+        assert_eq!(parse_time_zone("+05:30"), Ok(TimeZone::Fixed(5 * 3600 + 30 * 60)));
+        assert_eq!(parse_time_zone("-0100"), Ok(TimeZone::Fixed(-3600)));
+        assert!(parse_time_zone("\u{2212}05:00").is_err());
+        assert!(parse_time_zone("+\u{20ac}1").is_err());
+    }
+
+    #[test]
+    fn durations_out_of_range_are_errors() {
+        assert_eq!(parse_duration_string("10m"), Ok(Duration::from_secs(600)));
+        assert!(parse_duration_string("99999999999999999999999d").is_err());
+        assert!(parse_duration_string("NaN").is_err());
+    }
+
+    #[test]
+    fn the_sqlite_backend_refuses_the_settings_of_a_log_file() {
+        let run_args = |args: &[&str]| {
+            let command_line = [&["cosmic_ray_detector", "run", "--file-path", "results.txt"], args].concat();
+            match Cli::try_parse_from(command_line) {
+                Ok(Cli { command: Command::Run(args), .. }) => *args,
+                other => panic!("Not a run: {:?}", other),
+            }
+        };
+        assert!(run_args(&["--log-backend", "sqlite"]).check_log_backend().is_ok());
+        assert!(run_args(&["--log-format", "binary"]).check_log_backend().is_ok());
+        for option in [["--log-format", "jsonl"], ["--sync-policy", "never"], ["--s3-url", "https://s3.example.com/b"]] {
+            assert!(run_args(&[&["--log-backend", "sqlite"], &option[..]].concat()).check_log_backend().is_err());
+        }
+    }
+}
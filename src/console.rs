@@ -0,0 +1,173 @@
+use std::collections::VecDeque;
+use std::env;
+use std::io::{self, IsTerminal, Write};
+use std::sync::{Arc, Mutex, PoisonError};
+
+/// What a piece of console output is about, which picks its color.
+#[derive(Clone, Copy, Debug)]
+pub enum Style {
+    Flip,
+    Warning,
+    Dim,
+    Heading,
+}
+
+impl Style {
+    fn code(self) -> &'static str {
+        match self {
+            Style::Flip => "\x1b[1;31m",
+            Style::Warning => "\x1b[33m",
+            Style::Dim => "\x1b[2m",
+            Style::Heading => "\x1b[1m",
+        }
+    }
+}
+
+const RESET: &str = "\x1b[0m";
+/// How many of the last lines and status lines are kept for a crash report.
+const RECENT_LINES: usize = 64;
+/// Returns to the start of the line and clears it.
+const CLEAR_LINE: &[u8] = b"\r\x1b[K";
+
+/// Output meant for people, shared by every thread that writes to it. On a terminal it keeps a status line at
+/// the bottom that is updated in place and cleared before anything else is written, so that messages from
+/// different threads never end up in the middle of it. Elsewhere, e.g. in logs captured by a supervisor, the
+/// status line is left out and the output is plain lines of text.
+#[derive(Clone)]
+pub struct Console {
+    inner: Arc<Mutex<Inner>>,
+    color: bool,
+    live: bool,
+}
+
+struct Inner {
+    out: Box<dyn Write + Send>,
+    status_shown: bool,
+    /// Whether the last thing written ended a line, so that a status line can be drawn.
+    at_line_start: bool,
+    /// The last lines written and status lines shown, without colors, the line being written last.
+    recent: VecDeque<String>,
+}
+
+impl Inner {
+    fn remember(&mut self, text: &str, continues_line: bool) {
+        let mut lines = text.split('\n');
+        if continues_line {
+            if let (Some(last), Some(first)) = (self.recent.back_mut(), lines.next()) {
+                last.push_str(&without_colors(first));
+            }
+        }
+        for line in lines {
+            if self.recent.len() == RECENT_LINES {
+                self.recent.pop_front();
+            }
+            self.recent.push_back(without_colors(line));
+        }
+    }
+}
+
+impl Console {
+    pub fn stdout(plain: bool) -> Self {
+        Console::new(Box::new(io::stdout()), io::stdout().is_terminal(), plain)
+    }
+
+    pub fn stderr(plain: bool) -> Self {
+        Console::new(Box::new(io::stderr()), io::stderr().is_terminal(), plain)
+    }
+
+    fn new(out: Box<dyn Write + Send>, is_terminal: bool, plain: bool) -> Self {
+        Console {
+            inner: Arc::new(Mutex::new(Inner {
+                out,
+                status_shown: false,
+                at_line_start: true,
+                recent: VecDeque::from([String::new()]),
+            })),
+            color: use_color(is_terminal, plain),
+            live: !plain && is_terminal && !dumb_terminal(),
+        }
+    }
+
+    /// The text in the given style, or unchanged when colors are off.
+    pub fn paint(&self, style: Style, text: &str) -> String {
+        paint(self.color, style, text)
+    }
+
+    /// The last lines of output and status lines, oldest first, for a crash report.
+    pub fn recent(&self) -> Vec<String> {
+        let inner = self.inner.lock().unwrap_or_else(PoisonError::into_inner);
+        inner.recent.iter().filter(|line| !line.is_empty()).cloned().collect()
+    }
+
+    /// Replaces the status line, does nothing unless writing to a terminal. It is remembered for a crash report either
+    /// way.
+    pub fn status(&self, text: &str) -> io::Result<()> {
+        let mut inner = self.inner.lock().unwrap_or_else(PoisonError::into_inner);
+        if inner.at_line_start {
+            inner.remember(&format!("[status] {}\n", text), true);
+        }
+        if !self.live {
+            return Ok(());
+        }
+        if !inner.status_shown && !inner.at_line_start {
+            // Something is in the middle of a line, e.g. "Writing 0x00 to the detector memory... ", let it finish first.
+            return Ok(());
+        }
+        inner.out.write_all(CLEAR_LINE)?;
+        inner.out.write_all(text.as_bytes())?;
+        inner.status_shown = true;
+        inner.out.flush()
+    }
+}
+
+impl Write for Console {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let mut inner = self.inner.lock().unwrap_or_else(PoisonError::into_inner);
+        if inner.status_shown {
+            inner.out.write_all(CLEAR_LINE)?;
+            inner.status_shown = false;
+        }
+        inner.out.write_all(buf)?;
+        inner.remember(&String::from_utf8_lossy(buf), true);
+        inner.at_line_start = buf.ends_with(b"\n");
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.lock().unwrap_or_else(PoisonError::into_inner).out.flush()
+    }
+}
+
+/// Whether to color output going to a terminal or not, following https://no-color.org and `TERM=dumb`.
+pub fn use_color(is_terminal: bool, plain: bool) -> bool {
+    !plain && is_terminal && !dumb_terminal() && env::var_os("NO_COLOR").is_none_or(|value| value.is_empty())
+}
+
+pub fn paint(color: bool, style: Style, text: &str) -> String {
+    match color {
+        true => format!("{}{}{}", style.code(), text, RESET),
+        false => text.to_string(),
+    }
+}
+
+/// The text without the escape sequences that color it.
+fn without_colors(text: &str) -> String {
+    let mut plain = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            // The sequences used here all end with their first letter.
+            chars.by_ref().find(|c| c.is_ascii_alphabetic());
+        } else {
+            plain.push(c);
+        }
+    }
+    plain
+}
+
+fn dumb_terminal() -> bool {
+    env::var_os("TERM").is_some_and(|term| term == "dumb")
+}
@@ -0,0 +1,161 @@
+use std::backtrace::Backtrace;
+use std::fmt::Write as _;
+use std::fs;
+use std::panic;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::thread;
+
+use sysinfo::{RefreshKind, System, SystemExt};
+
+use crate::{backup::utc_timestamp, console::Console, mem_size, record::{read_log, Record}};
+
+/// How many of the last records of the log a crash report shows.
+pub const CRASH_LOG_RECORDS: usize = 50;
+/// The lines of /proc/self/status about the process's memory.
+#[cfg(target_os = "linux")]
+const PROCESS_MEMORY_KEYS: [&str; 5] = ["VmPeak", "VmSize", "VmHWM", "VmRSS", "VmLck"];
+
+static REPORTER: OnceLock<CrashReporter> = OnceLock::new();
+
+/// Writes what is needed to make sense of a fatal error into a file next to the log, for stations that nobody watches
+/// while they run: the settings, the console's last messages, the memory, the last records of the log and a backtrace.
+pub struct CrashReporter {
+    directory: PathBuf,
+    /// The settings of the run, with secrets left out.
+    config: String,
+    log_path: PathBuf,
+    console: Console,
+}
+
+impl CrashReporter {
+    pub fn new(directory: PathBuf, config: String, log_path: PathBuf, console: Console) -> Self {
+        CrashReporter { directory, config, log_path, console }
+    }
+
+    /// Makes this the reporter of the process, which also writes a report when the main thread panics. Panics on other
+    /// threads aren't fatal to the run, the checks carry on after them. Only the first reporter installed is kept.
+    pub fn install(self) {
+        if REPORTER.set(self).is_err() {
+            return;
+        }
+        let previous = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            previous(info);
+            if thread::current().name() != Some("main") {
+                return;
+            }
+            if let Some(reporter) = REPORTER.get() {
+                reporter.report_and_tell(&format!("Panicked: {}", info));
+            }
+        }));
+    }
+
+    /// Writes a report about the given error and says where to on stderr, which still works when the console broke.
+    pub fn report_and_tell(&self, error: &str) {
+        match self.write(error, &Backtrace::force_capture()) {
+            Ok(path) => eprintln!("Wrote a crash report to {}, please attach it to a bug report", path.display()),
+            Err(err) => eprintln!("{}", err),
+        }
+    }
+
+    /// Writes the report into a file named after the time, returning its path.
+    pub fn write(&self, error: &str, backtrace: &Backtrace) -> Result<PathBuf, String> {
+        let path = self.directory.join(format!("crash-{}.txt", utc_timestamp()));
+        fs::write(&path, self.contents(error, backtrace))
+            .map_err(|e| format!("Unable to write the crash report {}: {}", path.display(), e))?;
+        Ok(path)
+    }
+
+    fn contents(&self, error: &str, backtrace: &Backtrace) -> String {
+        let mut report = String::new();
+        // Writing to a String can't fail.
+        let _ = writeln!(report, "cosmic_ray_detector {} crash report, {}\n", env!("CARGO_PKG_VERSION"), utc_timestamp());
+        let _ = writeln!(report, "## Error\n{}\n", error);
+        let _ = writeln!(report, "## Settings\n{}\n", self.config);
+        let _ = writeln!(report, "## Recent console output");
+        for line in self.console.recent() {
+            let _ = writeln!(report, "{}", line);
+        }
+        let _ = writeln!(report, "\n## Memory\n{}", memory_stats());
+        let _ = writeln!(report, "## Last records of {}\n{}", self.log_path.display(), last_records(&self.log_path));
+        let _ = writeln!(report, "## Backtrace\n{}", backtrace);
+        report
+    }
+}
+
+/// Reports a fatal error with the reporter of the process, if one is installed.
+pub fn report(error: &str) {
+    if let Some(reporter) = REPORTER.get() {
+        reporter.report_and_tell(error);
+    }
+}
+
+/// The machine's memory and swap, and on Linux how much of it the process takes.
+fn memory_stats() -> String {
+    let sys_info = System::new_with_specifics(RefreshKind::new().with_memory());
+    let mut stats = format!(
+        "Total: {}, available: {}, swap used: {} of {}\n",
+        mem_size(sys_info.total_memory()),
+        mem_size(sys_info.available_memory()),
+        mem_size(sys_info.used_swap()),
+        mem_size(sys_info.total_swap())
+    );
+    #[cfg(target_os = "linux")]
+    if let Ok(status) = fs::read_to_string("/proc/self/status") {
+        for line in status.lines().filter(|line| PROCESS_MEMORY_KEYS.iter().any(|key| line.starts_with(key))) {
+            stats.push_str(line);
+            stats.push('\n');
+        }
+    }
+    stats
+}
+
+/// The last records of the log as CSV lines, whichever format it is in.
+fn last_records(log_path: &Path) -> String {
+    match read_log(log_path) {
+        Ok(records) => records
+            .iter()
+            .skip(records.len().saturating_sub(CRASH_LOG_RECORDS))
+            .map(Record::to_csv)
+            .collect(),
+        Err(err) => format!("{}\n", err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+    use crate::record::Tags;
+
+    #[test]
+    fn crash_reports_show_the_last_output_and_records() {
+        let dir = std::env::temp_dir().join(format!("cosmic_ray_detector-crash-report-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let log_path = dir.join("results.log");
+        let record = Record::Panic {
+            timestamp_ms: 1_700_000_000_000,
+            total_checks: 12,
+            message: "it broke".into(),
+            tags: Tags::new(),
+            sequence: None,
+        };
+        fs::write(&log_path, record.to_csv()).unwrap();
+        let mut console = Console::stderr(true);
+        write!(console, "Writing to the detector... ").unwrap();
+        writeln!(console, "\x1b[33mdone\x1b[0m").unwrap();
+        console.status("Checks: 12").unwrap();
+        assert_eq!(console.recent(), ["Writing to the detector... done", "[status] Checks: 12"]);
+
+        let reporter = CrashReporter::new(dir.clone(), "settings".into(), log_path, console);
+        let path = reporter.write("Out of memory", &Backtrace::disabled()).unwrap();
+        assert!(path.starts_with(&dir));
+        let report = fs::read_to_string(path).unwrap();
+        for expected in ["## Error\nOut of memory", "## Settings\nsettings", "[status] Checks: 12", "## Memory", "it broke"] {
+            assert!(report.contains(expected), "{} is missing from\n{}", expected, report);
+        }
+    }
+}
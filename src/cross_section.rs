@@ -0,0 +1,84 @@
+use crate::health::poisson_tail;
+
+/// Exposure is counted in gigabytes of 10^9 bytes.
+const BITS_PER_GB: f64 = 8e9;
+/// SEU literature gives rates in FIT, failures per 10^9 device hours, per megabit of 10^6 bits.
+const FIT_HOURS: f64 = 1e9;
+const BITS_PER_MBIT: f64 = 1e6;
+
+/// How likely a bit is to be upset by a particle, in the units published measurements are in.
+pub struct CrossSection {
+    /// The upset cross-section of a bit in cm².
+    pub per_bit_cm2: f64,
+    /// The interval at the confidence level it was worked out for, taking in both the counting statistics and the
+    /// uncertainty of the flux.
+    pub lower_cm2: f64,
+    pub upper_cm2: f64,
+    /// The interval from counting statistics alone.
+    pub statistical_cm2: (f64, f64),
+    /// The bitflip rate in FIT per Mbit.
+    pub fit_per_mbit: f64,
+}
+
+/// Works out the per-bit cross-section, events / (bits × time × flux), of `flips` over an exposure of `gb_hours`
+/// at a reference `flux` of particles per cm² per hour, which has a relative uncertainty of `flux_uncertainty`.
+/// The counting interval is the exact Poisson one, the flux shifts both ends by its uncertainty in quadrature
+/// with their distance from the estimate. None without exposure or flux.
+pub fn cross_section(
+    flips: u64,
+    gb_hours: f64,
+    flux: f64,
+    flux_uncertainty: f64,
+    confidence: f64,
+) -> Option<CrossSection> {
+    if gb_hours <= 0.0 || flux <= 0.0 {
+        return None;
+    }
+    let bit_hours = gb_hours * BITS_PER_GB;
+    let fluence = bit_hours * flux;
+    let (lower_count, upper_count) = poisson_interval(flips, confidence);
+    let per_bit_cm2 = flips as f64 / fluence;
+    let (lower_cm2, upper_cm2) = match flips {
+        // Without an estimate to be relative to, a flux that is lower than assumed raises the limit the most.
+        0 => (0.0, upper_count / fluence * (1.0 + flux_uncertainty)),
+        _ => {
+            let relative = |count: f64| (count / flips as f64 - 1.0).abs();
+            let combined = |count: f64| relative(count).hypot(flux_uncertainty);
+            (
+                (per_bit_cm2 * (1.0 - combined(lower_count))).max(0.0),
+                per_bit_cm2 * (1.0 + combined(upper_count)),
+            )
+        }
+    };
+    Some(CrossSection {
+        per_bit_cm2,
+        lower_cm2,
+        upper_cm2,
+        statistical_cm2: (lower_count / fluence, upper_count / fluence),
+        fit_per_mbit: flips as f64 / (bit_hours / BITS_PER_MBIT) * FIT_HOURS,
+    })
+}
+
+/// The central interval of the Poisson mean at the given confidence after seeing `count` events, found by bisection
+/// on the tail probabilities.
+pub fn poisson_interval(count: u64, confidence: f64) -> (f64, f64) {
+    let alpha = (1.0 - confidence) / 2.0;
+    // The chance of at least `count` events only grows with the mean, so each end is where it crosses a level.
+    let solve = |count: u64, level: f64| {
+        let (mut low, mut high) = (0.0, count as f64 + 10.0 * (count as f64 + 1.0).sqrt() + 10.0);
+        for _ in 0..200 {
+            let middle = (low + high) / 2.0;
+            match poisson_tail(count, middle) < level {
+                true => low = middle,
+                false => high = middle,
+            }
+        }
+        (low + high) / 2.0
+    };
+    let lower = match count {
+        0 => 0.0,
+        _ => solve(count, alpha),
+    };
+    // No more than `count` events is as unlikely as alpha where at least `count + 1` is as likely as 1 - alpha.
+    (lower, solve(count + 1, 1.0 - alpha))
+}
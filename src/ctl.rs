@@ -0,0 +1,16 @@
+use std::error::Error;
+
+use crate::{
+    config::{CtlArgs, GlobalArgs},
+    instance::send_command,
+    station::StationConfig,
+};
+
+/// Sends a command to the running instance of the station and prints its reply.
+pub fn ctl(global: &GlobalArgs, args: CtlArgs) -> Result<(), Box<dyn Error>> {
+    let station = StationConfig::load(&global.station_config)?;
+    let station_id = station.station_id(global.station_id.as_deref())?;
+
+    print!("{}", send_command(&station_id, args.action.command())?);
+    Ok(())
+}
@@ -1,13 +1,83 @@
+use std::alloc::{alloc_zeroed, Layout};
+use std::hint::black_box;
 use std::ptr::{read_volatile, write_volatile};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Instant;
 
 use rayon::prelude::*;
+use rayon::{ThreadPool, ThreadPoolBuilder};
+
+/// How much of the memory a cancellable check reads between two looks at the cancel flag.
+const CANCEL_CHECK_BYTES: usize = 1 << 20;
+/// How much of the memory one checksum covers.
+const CRC_CHUNK_BYTES: usize = 1 << 20;
+/// The size of a cache line, the halves of a detector with inverted halves alternate by it.
+pub const CACHE_LINE_BYTES: usize = 64;
+/// How much of the memory a thread looks for a change in at a time.
+const SCAN_CHUNK_BYTES: usize = 1 << 16;
+
+/// How the detector memory is read when it is checked. Every mode reads all of it with volatile reads, they only
+/// differ in how much one read takes in, and look at single bytes only where something changed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScanMode {
+    /// A read per byte, the slowest but simplest.
+    Bytes,
+    /// A read per 64-bit word.
+    Words,
+    /// A read per 16 bytes into an SSE2 register on x86_64, the same as words elsewhere.
+    Simd,
+}
 
 /// In order to prevent the optimizer from removing the reads of the memory that make up the detector
 /// this struct will only use volatile reads and writes to its memory.
+///
+/// Elements are identified by their index from the start of the detector. Resizing only adds or removes memory at the
+/// end, so every element that stays keeps its index. A `DetectorArray` numbers the elements of all its chunks the same
+/// way, and that index is the one used in the output and by the bindings, see `DetectorArray::chunk_of`.
 pub struct Detector {
     default: u8,
     capacity: usize,
     detector_mass: Vec<u8>,
+    paranoid_reads: bool,
+    /// Whether every other cache line holds the inverse of the default value, making up a second detector.
+    inverted_halves: bool,
+    scan_mode: ScanMode,
+    /// The threads to check and write with, rayon's global pool if none.
+    pool: Option<ThreadPool>,
+    /// The bits that held a one in some element since the detector was created, so flips from one to zero in them
+    /// could be found, and the bits that held a zero.
+    held_ones: u8,
+    held_zeros: u8,
+}
+
+/// How a byte of the detector differs from the value it was written with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FlipInfo {
+    pub index: usize,
+    /// The value the whole detector was written with.
+    pub expected: u8,
+    /// The value the byte held when it was read.
+    pub observed: u8,
+    /// Whether the byte is in the inverted half of a detector with inverted halves, none without them.
+    pub inverted: Option<bool>,
+}
+
+impl FlipInfo {
+    /// The bits that differ, set in a mask.
+    pub fn changed_mask(&self) -> u8 {
+        self.expected ^ self.observed
+    }
+
+    /// The positions of the bits that differ, 0 being the least significant one.
+    pub fn changed_bits(&self) -> Vec<u8> {
+        (0..8).filter(|bit| self.changed_mask() & (1 << bit) != 0).collect()
+    }
+
+    /// Whether more than one bit of the byte flipped. A single particle rarely does that, several bits flipping at
+    /// once points at a different kind of event, or at a fault of the memory.
+    pub fn is_multi_bit(&self) -> bool {
+        self.changed_mask().count_ones() > 1
+    }
 }
 
 impl Detector {
@@ -16,31 +86,507 @@ impl Detector {
             default,
             capacity: initial_capacity,
             detector_mass: vec![default; initial_capacity],
+            paranoid_reads: false,
+            inverted_halves: false,
+            scan_mode: ScanMode::Words,
+            pool: None,
+            held_ones: default,
+            held_zeros: !default,
+        }
+    }
+
+    /// Like `new`, but fails instead of aborting when the memory can't be allocated. The memory is left untouched
+    /// when the default value is zero, like a new detector's.
+    pub fn try_new(default: u8, initial_capacity: usize) -> Result<Self, String> {
+        let detector_mass = match initial_capacity {
+            0 => Vec::new(),
+            _ => {
+                let layout = Layout::array::<u8>(initial_capacity).map_err(|e| e.to_string())?;
+                // The layout isn't empty, and a null pointer is checked for before the memory is used.
+                let memory = unsafe { alloc_zeroed(layout) };
+                if memory.is_null() {
+                    return Err(format!("Unable to allocate {} bytes of detector memory", initial_capacity));
+                }
+                // The memory was allocated by the global allocator with the layout of a vector of this length.
+                let mut detector_mass = unsafe { Vec::from_raw_parts(memory, initial_capacity, initial_capacity) };
+                if default != 0 {
+                    detector_mass.fill(default);
+                }
+                detector_mass
+            }
+        };
+        Ok(Detector { capacity: initial_capacity, detector_mass, ..Detector::new(default, 0) })
+    }
+
+    /// Splits the detector into two that are checked together: every other cache line holds the inverse of the default
+    /// value. A particle only flips bits in one of them, while a bug in the scanner or the compiler would show up in
+    /// both alike. Takes effect at the next reset.
+    pub fn set_inverted_halves(&mut self, inverted_halves: bool) {
+        self.inverted_halves = inverted_halves;
+    }
+
+    /// Changes the value the detector is written with, so that scrub cycles can go through different patterns. Takes
+    /// effect at the next reset.
+    pub fn set_default(&mut self, default: u8) {
+        self.default = default;
+    }
+
+    /// The value the detector is written with.
+    pub fn default_value(&self) -> u8 {
+        self.default
+    }
+
+    /// Whether the element at the given index is in the inverted half, none when the detector has no halves.
+    pub fn is_inverted(&self, index: usize) -> Option<bool> {
+        self.inverted_halves.then_some((index / CACHE_LINE_BYTES) % 2 == 1)
+    }
+
+    /// The value the element at the given index was written with.
+    fn expected_at(&self, index: usize) -> u8 {
+        match self.is_inverted(index) {
+            Some(true) => !self.default,
+            _ => self.default,
+        }
+    }
+
+    /// Whether the element read differs from the value expected of it.
+    fn differs(&self, r: &u8, expected: u8) -> bool {
+        match self.paranoid_reads {
+            true => black_box(unsafe { read_volatile(black_box(r)) }) != expected,
+            false => unsafe { read_volatile(r) != expected },
+        }
+    }
+
+    /// Whether any element of the memory starting at the given index differs from the value expected of it. The
+    /// index has to be the start of a cache line.
+    fn any_differs(&self, start: usize, memory: &[u8]) -> bool {
+        if !self.inverted_halves {
+            return self.position_differs(memory, self.default).is_some();
+        }
+        memory.chunks(CACHE_LINE_BYTES).enumerate().any(|(line, bytes)| {
+            let expected = self.expected_at(start + line * CACHE_LINE_BYTES);
+            self.position_differs(bytes, expected).is_some()
+        })
+    }
+
+    /// The offset of the first element of the memory that differs from `expected`, read as the scan mode says.
+    fn position_differs(&self, memory: &[u8], expected: u8) -> Option<usize> {
+        match self.scan_mode {
+            ScanMode::Bytes => memory.iter().position(|r| self.differs(r, expected)),
+            ScanMode::Words => self.word_position_differs(memory, expected),
+            ScanMode::Simd => self.simd_position_differs(memory, expected),
         }
     }
 
-    /// Checks if every element of the detector memory is equal to the default value.
+    fn word_position_differs(&self, memory: &[u8], expected: u8) -> Option<usize> {
+        // Every bit pattern is a valid u64, so viewing the bytes as words is sound.
+        let (head, words, tail) = unsafe { memory.align_to::<u64>() };
+        let expected_word = u64::from_ne_bytes([expected; 8]);
+        let in_bytes = |bytes: &[u8], offset: usize| {
+            bytes.iter().position(|r| self.differs(r, expected)).map(|position| offset + position)
+        };
+        in_bytes(head, 0)
+            .or_else(|| {
+                words.iter().enumerate().find_map(|(n, word)| {
+                    let read = match self.paranoid_reads {
+                        true => black_box(unsafe { read_volatile(black_box(word)) }),
+                        false => unsafe { read_volatile(word) },
+                    };
+                    // The value read tells which byte changed, reading it again could find it changed back.
+                    let changed = (read ^ expected_word).to_ne_bytes().iter().position(|&byte| byte != 0)?;
+                    Some(head.len() + n * 8 + changed)
+                })
+            })
+            .or_else(|| in_bytes(tail, memory.len() - tail.len()))
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    fn simd_position_differs(&self, memory: &[u8], expected: u8) -> Option<usize> {
+        use std::arch::x86_64::{__m128i, _mm_cmpeq_epi8, _mm_movemask_epi8, _mm_set1_epi8};
+
+        // Every bit pattern is a valid vector, so viewing the bytes as vectors is sound.
+        let (head, vectors, tail) = unsafe { memory.align_to::<__m128i>() };
+        // SSE2 is part of every x86_64 processor, so its intrinsics can always be called.
+        let expected_vector = unsafe { _mm_set1_epi8(expected as i8) };
+        let in_bytes = |bytes: &[u8], offset: usize| {
+            bytes.iter().position(|r| self.differs(r, expected)).map(|position| offset + position)
+        };
+        in_bytes(head, 0)
+            .or_else(|| {
+                vectors.iter().enumerate().find_map(|(n, vector)| {
+                    let read = match self.paranoid_reads {
+                        true => black_box(unsafe { read_volatile(black_box(vector)) }),
+                        false => unsafe { read_volatile(vector) },
+                    };
+                    // A bit for every byte that is equal to the expected value.
+                    let equal = unsafe { _mm_movemask_epi8(_mm_cmpeq_epi8(read, expected_vector)) } as u32;
+                    (equal != 0xFFFF).then(|| head.len() + n * 16 + (!equal).trailing_zeros() as usize)
+                })
+            })
+            .or_else(|| in_bytes(tail, memory.len() - tail.len()))
+    }
+
+    #[cfg(not(target_arch = "x86_64"))]
+    fn simd_position_differs(&self, memory: &[u8], expected: u8) -> Option<usize> {
+        self.word_position_differs(memory, expected)
+    }
+
+    /// Reads the memory a byte, a word or a vector at a time when checking it. Finding which bytes changed reads them
+    /// the same way.
+    pub fn set_scan_mode(&mut self, scan_mode: ScanMode) {
+        self.scan_mode = scan_mode;
+    }
+
+    /// Additionally passes every address and value read through `black_box`, so that even a compiler that
+    /// sees through `read_volatile` can't prove the reads are unnecessary. Makes checking somewhat slower.
+    pub fn set_paranoid_reads(&mut self, paranoid_reads: bool) {
+        self.paranoid_reads = paranoid_reads;
+    }
+
+    /// Checks and writes the memory with the given number of threads instead of one per core.
+    pub fn set_threads(&mut self, threads: Option<usize>) -> Result<(), String> {
+        self.pool = match threads {
+            Some(threads) => Some(
+                ThreadPoolBuilder::new()
+                    .num_threads(threads)
+                    .build()
+                    .map_err(|e| format!("Unable to start {} detector threads: {}", threads, e))?,
+            ),
+            None => None,
+        };
+        Ok(())
+    }
+
+    /// Checks if every element of the detector memory is equal to the value it was written with.
     pub fn is_intact(&self) -> bool {
         !self.find_index_of_changed_element().is_some()
     }
 
     /// Writes the given value to every element of the detector memory.
     pub fn write(&mut self, value: u8) {
-        self.detector_mass
-            .par_iter_mut()
-            .for_each(|n| unsafe { write_volatile(n, value) });
+        self.note_pattern(value);
+        let write = |detector_mass: &mut Vec<u8>| {
+            detector_mass.par_iter_mut().for_each(|n| unsafe { write_volatile(n, value) });
+        };
+        match &self.pool {
+            Some(pool) => pool.install(|| write(&mut self.detector_mass)),
+            None => write(&mut self.detector_mass),
+        }
+    }
+
+    /// Checks like `is_intact`, but gives up soon after `cancel` is set and returns none.
+    pub fn is_intact_unless_cancelled(&self, cancel: &AtomicBool) -> Option<bool> {
+        // Cancelling makes every remaining chunk end the search right away.
+        let check = || {
+            self.detector_mass
+                .par_chunks(CANCEL_CHECK_BYTES)
+                .enumerate()
+                .any(|(n, chunk)| cancel.load(Ordering::Relaxed) || self.any_differs(n * CANCEL_CHECK_BYTES, chunk))
+        };
+        let changed = match &self.pool {
+            Some(pool) => pool.install(check),
+            None => check(),
+        };
+        match cancel.load(Ordering::Relaxed) {
+            true => None,
+            false => Some(!changed),
+        }
+    }
+
+    /// Checks the detector by comparing the CRC32 of every chunk with that of a chunk holding only the values it was
+    /// written with. It finds the same changes as `is_intact` along an independent path, so that the two can check
+    /// each other.
+    pub fn checksums_intact(&self) -> bool {
+        // Chunks start on a multiple of two cache lines, so the pattern is the same in all of them.
+        let block: Vec<u8> = (0..PATTERN_BLOCK_BYTES).map(|index| self.expected_at(index)).collect();
+        let full_chunk = pattern_crc(&block, CRC_CHUNK_BYTES);
+        let check = || {
+            self.detector_mass.par_chunks(CRC_CHUNK_BYTES).all(|chunk| {
+                let expected = match chunk.len() {
+                    CRC_CHUNK_BYTES => full_chunk,
+                    length => pattern_crc(&block, length),
+                };
+                crc32fast::hash(chunk) == expected
+            })
+        };
+        match &self.pool {
+            Some(pool) => pool.install(check),
+            None => check(),
+        }
     }
 
     /// If an element in the detector does not match its default value, return it's index.
     pub fn find_index_of_changed_element(&self) -> Option<usize> {
+        match &self.pool {
+            Some(pool) => pool.install(|| self.find_changed()),
+            None => self.find_changed(),
+        }
+    }
+
+    /// Returns the indices of every element in the detector that does not match its default value, in order.
+    /// A shower of particles can change several bytes between two checks, which `find_index_of_changed_element`
+    /// would report as one.
+    pub fn find_all_changed_elements(&self) -> Vec<usize> {
+        match &self.pool {
+            Some(pool) => pool.install(|| self.find_all_changed()),
+            None => self.find_all_changed(),
+        }
+    }
+
+    /// Goes through the cache lines, looking at single bytes only in the lines where something changed.
+    fn find_all_changed(&self) -> Vec<usize> {
         self.detector_mass
-            .par_iter()
-            .position_any(|r| unsafe { read_volatile(r) != self.default })
+            .par_chunks(CACHE_LINE_BYTES)
+            .enumerate()
+            .flat_map_iter(|(line, bytes)| {
+                let start = line * CACHE_LINE_BYTES;
+                let expected = self.expected_at(start);
+                let first = self.position_differs(bytes, expected).unwrap_or(bytes.len());
+                let changed = bytes[first..].iter().enumerate().filter(move |(_, r)| self.differs(r, expected));
+                changed.map(move |(offset, _)| start + first + offset)
+            })
+            .collect()
+    }
+
+    fn find_changed(&self) -> Option<usize> {
+        if self.inverted_halves {
+            return self.detector_mass.par_chunks(CACHE_LINE_BYTES).enumerate().find_map_any(|(line, bytes)| {
+                let expected = self.expected_at(line * CACHE_LINE_BYTES);
+                let offset = self.position_differs(bytes, expected)?;
+                Some(line * CACHE_LINE_BYTES + offset)
+            });
+        }
+        self.detector_mass.par_chunks(SCAN_CHUNK_BYTES).enumerate().find_map_any(|(n, chunk)| {
+            let offset = self.position_differs(chunk, self.default)?;
+            Some(n * SCAN_CHUNK_BYTES + offset)
+        })
+    }
+
+    /// Times a full check of an intact detector and returns how many bytes per second it read.
+    /// A check that is faster than memory can possibly be read was not actually done.
+    pub fn measure_check_bandwidth(&mut self) -> f64 {
+        self.reset();
+        let start = Instant::now();
+        self.is_intact();
+        self.detector_mass.len() as f64 / start.elapsed().as_secs_f64().max(f64::EPSILON)
+    }
+
+    /// The fraction of all possible single bit flips, every bit of a byte in both directions, that the patterns
+    /// written since the detector was created could reveal. A bit that always holds the same value can only show a
+    /// flip away from it, so a single pattern covers half of them, and inverted halves or patterns that change from
+    /// one scrub cycle to the next cover the rest.
+    pub fn pattern_coverage(&self) -> f64 {
+        (self.held_ones.count_ones() + self.held_zeros.count_ones()) as f64 / 16.0
     }
 
-    /// Resets the detector to its default value.
+    /// Records that every element was written with the given value.
+    fn note_pattern(&mut self, value: u8) {
+        self.held_ones |= value;
+        self.held_zeros |= !value;
+    }
+
+    /// Changes the size of the detector, keeping the memory that stays as it is so that flips in it are still found.
+    /// New memory is written with a nonzero value first, like a new detector's, to make sure it is really there.
+    pub fn resize(&mut self, size: usize) {
+        let old_size = self.detector_mass.len();
+        if size < old_size {
+            self.detector_mass.truncate(size);
+            self.detector_mass.shrink_to_fit();
+        } else {
+            self.detector_mass.resize(size, 42);
+            for index in old_size..size {
+                let expected = self.expected_at(index);
+                unsafe { write_volatile(&mut self.detector_mass[index], expected) };
+            }
+        }
+        self.capacity = size;
+    }
+
+    /// The fraction of the detector's pages that are in memory rather than swapped out, according to mincore.
+    /// Fails on systems other than Linux.
+    #[cfg(target_os = "linux")]
+    pub fn resident_fraction(&self) -> Result<f64, String> {
+        if self.detector_mass.is_empty() {
+            return Ok(1.0);
+        }
+        let page_size = page_size();
+        // mincore wants the start of a page, the first page may hold other memory as well.
+        let start = self.detector_mass.as_ptr() as usize / page_size * page_size;
+        let length = self.detector_mass.as_ptr() as usize + self.detector_mass.len() - start;
+        let mut pages = vec![0u8; length.div_ceil(page_size)];
+        // The range only covers pages of the detector's memory and the vector has a byte for each of them.
+        let result = unsafe { libc::mincore(start as *mut libc::c_void, length, pages.as_mut_ptr()) };
+        if result != 0 {
+            return Err(format!("Unable to tell which pages are in memory: {}", std::io::Error::last_os_error()));
+        }
+        // Only the lowest bit says whether the page is resident, the others are reserved.
+        let resident = pages.iter().filter(|page| *page & 1 != 0).count();
+        Ok(resident as f64 / pages.len() as f64)
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn resident_fraction(&self) -> Result<f64, String> {
+        Err("Telling which pages are in memory is only supported on Linux".into())
+    }
+
+    /// Asks Linux to back the detector with transparent huge pages, which cuts the TLB misses of scanning gigabytes
+    /// and keeps its physical layout steadier over long runs. Pages already in memory are only collapsed later by
+    /// khugepaged, so it works best before the detector is first written. Fails where transparent huge pages are
+    /// turned off, and on systems other than Linux.
+    #[cfg(target_os = "linux")]
+    pub fn use_huge_pages(&self) -> Result<(), String> {
+        const ENABLED: &str = "/sys/kernel/mm/transparent_hugepage/enabled";
+        if let Ok(enabled) = std::fs::read_to_string(ENABLED) {
+            if enabled.contains("[never]") {
+                return Err(format!("transparent huge pages are turned off in {}", ENABLED));
+            }
+        }
+        let page_size = page_size();
+        // madvise wants the start of a page, only whole pages of the detector are advised.
+        let start = (self.detector_mass.as_ptr() as usize).next_multiple_of(page_size);
+        let end = (self.detector_mass.as_ptr() as usize + self.detector_mass.len()) / page_size * page_size;
+        if end <= start {
+            return Ok(());
+        }
+        // The range only covers whole pages of the detector's memory, and the advice doesn't change its contents.
+        let result = unsafe { libc::madvise(start as *mut libc::c_void, end - start, libc::MADV_HUGEPAGE) };
+        if result != 0 {
+            return Err(format!("Unable to ask for huge pages: {}", std::io::Error::last_os_error()));
+        }
+        Ok(())
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn use_huge_pages(&self) -> Result<(), String> {
+        Err("Huge pages are only supported on Linux".into())
+    }
+
+    /// Binds the detector's memory to the given NUMA node with mbind, moving the pages that are elsewhere already.
+    /// Only whole pages are bound, like with huge pages. Fails on systems other than Linux.
+    #[cfg(target_os = "linux")]
+    pub fn bind_to_node(&self, node: u32) -> Result<(), String> {
+        // From the kernel's uapi/linux/mempolicy.h, libc doesn't have them.
+        const MPOL_BIND: libc::c_long = 2;
+        const MPOL_MF_MOVE: libc::c_ulong = 1 << 1;
+        const MASK_BITS: usize = libc::c_ulong::BITS as usize;
+
+        let page_size = page_size();
+        let start = (self.detector_mass.as_ptr() as usize).next_multiple_of(page_size);
+        let end = (self.detector_mass.as_ptr() as usize + self.detector_mass.len()) / page_size * page_size;
+        if end <= start {
+            return Ok(());
+        }
+        let mut nodemask: Vec<libc::c_ulong> = vec![0; node as usize / MASK_BITS + 1];
+        nodemask[node as usize / MASK_BITS] |= 1 << (node as usize % MASK_BITS);
+        // The kernel reads one bit less than it is told, so it is told one more than the mask holds.
+        let max_node = nodemask.len() * MASK_BITS + 1;
+        // The range only covers whole pages of the detector's memory, and the mask outlives the call.
+        let result = unsafe {
+            libc::syscall(libc::SYS_mbind, start, end - start, MPOL_BIND, nodemask.as_ptr(), max_node, MPOL_MF_MOVE)
+        };
+        if result != 0 {
+            return Err(format!("Unable to bind memory to NUMA node {}: {}", node, std::io::Error::last_os_error()));
+        }
+        Ok(())
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn bind_to_node(&self, _node: u32) -> Result<(), String> {
+        Err("NUMA placement is only supported on Linux".into())
+    }
+
+    /// Locks the detector's memory with mlock, so that it can never be swapped out to where no cosmic ray could hit it.
+    /// Fails when the memory would go over RLIMIT_MEMLOCK, and on systems other than Linux. Memory added by a resize
+    /// isn't locked until this is called again.
+    #[cfg(target_os = "linux")]
+    pub fn lock_memory(&self) -> Result<(), String> {
+        if self.detector_mass.is_empty() {
+            return Ok(());
+        }
+        // The range is exactly the detector's memory, which stays allocated for as long as it is locked.
+        let result = unsafe { libc::mlock(self.detector_mass.as_ptr() as *const libc::c_void, self.detector_mass.len()) };
+        if result == 0 {
+            return Ok(());
+        }
+        let err = std::io::Error::last_os_error();
+        let mut limit = libc::rlimit { rlim_cur: 0, rlim_max: 0 };
+        // getrlimit only fills in the struct it is given.
+        match unsafe { libc::getrlimit(libc::RLIMIT_MEMLOCK, &mut limit) } {
+            0 if limit.rlim_cur != libc::RLIM_INFINITY => Err(format!(
+                "Unable to lock {} bytes of memory: {}. RLIMIT_MEMLOCK allows {} bytes, raise it with ulimit -l or run with CAP_IPC_LOCK",
+                self.detector_mass.len(),
+                err,
+                limit.rlim_cur
+            )),
+            _ => Err(format!("Unable to lock {} bytes of memory: {}", self.detector_mass.len(), err)),
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn lock_memory(&self) -> Result<(), String> {
+        Err("Locking the memory is only supported on Linux".into())
+    }
+
+    /// Reads a byte of every page, which brings the pages that were swapped out back into memory without writing.
+    pub fn fault_in(&self) {
+        for page in self.detector_mass.chunks(page_size()) {
+            unsafe { read_volatile(page.as_ptr()) };
+        }
+    }
+
+    /// Resets the detector to its default value, and its inverse in the inverted half.
     pub fn reset(&mut self) {
-        self.write(self.default);
+        if !self.inverted_halves {
+            return self.write(self.default);
+        }
+        let (normal, inverted) = (self.default, !self.default);
+        self.note_pattern(normal);
+        self.note_pattern(inverted);
+        let write = |detector_mass: &mut Vec<u8>| {
+            detector_mass.par_chunks_mut(CACHE_LINE_BYTES).enumerate().for_each(|(line, bytes)| {
+                let value = if line % 2 == 1 { inverted } else { normal };
+                bytes.iter_mut().for_each(|n| unsafe { write_volatile(n, value) });
+            });
+        };
+        match &self.pool {
+            Some(pool) => pool.install(|| write(&mut self.detector_mass)),
+            None => write(&mut self.detector_mass),
+        }
+    }
+
+    /// Writes the value it should hold back to every element at the given indices, so that a flip doesn't take
+    /// rewriting the whole detector. Indices past its end are ignored.
+    pub fn repair(&mut self, indices: &[usize]) {
+        for &index in indices {
+            let expected = self.expected_at(index);
+            if let Some(element) = self.detector_mass.get_mut(index) {
+                unsafe { write_volatile(element, expected) };
+            }
+        }
+    }
+
+    /// Flips a single bit of the element at the given index, for making sure that flips are found. Returns none when
+    /// there is no such element or bit.
+    pub fn flip_bit(&mut self, index: usize, bit: u8) -> Option<()> {
+        let mask = 1u8.checked_shl(bit as u32)?;
+        let element = self.detector_mass.get_mut(index)?;
+        unsafe { write_volatile(element, read_volatile(element) ^ mask) };
+        Some(())
+    }
+
+    /// Compares the element at the given index with the value the detector was written with, if it exists.
+    pub fn diff_at(&self, index: usize) -> Option<FlipInfo> {
+        self.get(index).map(|observed| FlipInfo {
+            index,
+            expected: self.expected_at(index),
+            observed,
+            inverted: self.is_inverted(index),
+        })
+    }
+
+    /// The virtual address of the element at the given index.
+    pub fn address_of(&self, index: usize) -> usize {
+        self.detector_mass.as_ptr() as usize + index
     }
 
     /// Returns the value of the element at the given index, if it exists.
@@ -52,3 +598,577 @@ impl Detector {
         }
     }
 }
+
+/// The length of the block a chunk's expected checksum is worked out from, a multiple of two cache lines.
+const PATTERN_BLOCK_BYTES: usize = 4096;
+
+/// The CRC32 of `length` bytes of the given block repeated.
+fn pattern_crc(block: &[u8], length: usize) -> u32 {
+    let mut hasher = crc32fast::Hasher::new();
+    let mut remaining = length;
+    while remaining > 0 {
+        let bytes = remaining.min(block.len());
+        hasher.update(&block[..bytes]);
+        remaining -= bytes;
+    }
+    hasher.finalize()
+}
+
+#[cfg(target_os = "linux")]
+fn page_size() -> usize {
+    (unsafe { libc::sysconf(libc::_SC_PAGESIZE) }).max(1) as usize
+}
+
+/// The smallest page size in common use, reading more often than needed only costs a little time.
+#[cfg(not(target_os = "linux"))]
+fn page_size() -> usize {
+    4096
+}
+
+/// What a detection loop needs from the memory it watches, so that tests can swap in a scripted one.
+pub trait DetectorMemory {
+    /// Writes the default value to the whole memory again.
+    fn reset(&mut self);
+    /// Changes the value the next reset writes, where the memory has one.
+    fn set_default(&mut self, _value: u8) {}
+    fn is_intact(&self) -> bool;
+    fn find_index_of_changed_element(&self) -> Option<usize>;
+    fn get(&self, index: usize) -> Option<u8>;
+}
+
+impl DetectorMemory for Detector {
+    fn reset(&mut self) {
+        Detector::reset(self)
+    }
+
+    fn set_default(&mut self, value: u8) {
+        Detector::set_default(self, value)
+    }
+
+    fn is_intact(&self) -> bool {
+        Detector::is_intact(self)
+    }
+
+    fn find_index_of_changed_element(&self) -> Option<usize> {
+        Detector::find_index_of_changed_element(self)
+    }
+
+    fn get(&self, index: usize) -> Option<u8> {
+        Detector::get(self, index)
+    }
+}
+
+/// How much memory one chunk of a detector array holds, a multiple of the checksum chunks and of two cache lines so
+/// that every chunk starts the same as the first.
+pub const ARRAY_CHUNK_BYTES: usize = 64 << 20;
+
+/// A detector made of many chunks of memory, each of them a detector of its own, rather than one allocation. Growing
+/// and shrinking it only allocates or frees chunks at the end, an allocation that fails only leaves it smaller, and
+/// the chunks are checked in parallel.
+///
+/// Elements are identified by their index from the start of the first chunk, like those of a single detector. Every
+/// chunk but the last is full, so the index of an element is its chunk's number times the chunk size plus its offset
+/// in the chunk, see `chunk_of` and `index_of`.
+pub struct DetectorArray {
+    chunks: Vec<Detector>,
+    chunk_bytes: usize,
+    default: u8,
+    paranoid_reads: bool,
+    inverted_halves: bool,
+    scan_mode: ScanMode,
+    /// The NUMA nodes the chunks are spread over in turn, none to leave them wherever the kernel puts them.
+    numa_nodes: Vec<u32>,
+    /// The threads to check and write with, rayon's global pool if none.
+    pool: Option<ThreadPool>,
+}
+
+impl DetectorArray {
+    /// Allocates chunks until the array holds `size` bytes or an allocation fails, `size` tells how far it got.
+    pub fn new(default: u8, size: usize) -> Self {
+        DetectorArray::with_size_of_chunks(default, size, ARRAY_CHUNK_BYTES)
+    }
+
+    /// Like `new`, with chunks of the given size, which has to be a multiple of two cache lines.
+    pub fn with_chunk_bytes(default: u8, size: usize, chunk_bytes: usize) -> Result<Self, String> {
+        if chunk_bytes == 0 || !chunk_bytes.is_multiple_of(2 * CACHE_LINE_BYTES) {
+            return Err(format!("Chunks of {} bytes aren't a multiple of {} bytes", chunk_bytes, 2 * CACHE_LINE_BYTES));
+        }
+        Ok(DetectorArray::with_size_of_chunks(default, size, chunk_bytes))
+    }
+
+    fn with_size_of_chunks(default: u8, size: usize, chunk_bytes: usize) -> Self {
+        let mut array = DetectorArray {
+            chunks: Vec::new(),
+            chunk_bytes,
+            default,
+            paranoid_reads: false,
+            inverted_halves: false,
+            scan_mode: ScanMode::Words,
+            numa_nodes: Vec::new(),
+            pool: None,
+        };
+        array.grow(size);
+        array
+    }
+
+    /// How many bytes the array holds.
+    pub fn size(&self) -> usize {
+        self.chunks.iter().map(|chunk| chunk.detector_mass.len()).sum()
+    }
+
+    /// Adds chunks at the end until the array holds `size` bytes, stopping at the first allocation that fails. New
+    /// memory is written with the value expected of it.
+    fn grow(&mut self, size: usize) {
+        let full_chunks = self.chunks.len().saturating_sub(1);
+        if let Some(last) = self.chunks.last_mut() {
+            let filled = (size - full_chunks * self.chunk_bytes).min(self.chunk_bytes);
+            if filled > last.detector_mass.len() {
+                last.resize(filled);
+            }
+        }
+        while self.size() < size {
+            let bytes = (size - self.size()).min(self.chunk_bytes);
+            let Ok(mut chunk) = Detector::try_new(self.default, bytes) else {
+                break;
+            };
+            chunk.paranoid_reads = self.paranoid_reads;
+            chunk.inverted_halves = self.inverted_halves;
+            chunk.scan_mode = self.scan_mode;
+            if let Some(&node) = self.numa_nodes.get(self.chunks.len() % self.numa_nodes.len().max(1)) {
+                // Placement is only a preference, a chunk that can't be bound is still a good detector.
+                let _ = chunk.bind_to_node(node);
+            }
+            if self.inverted_halves {
+                chunk.reset();
+            }
+            self.chunks.push(chunk);
+        }
+    }
+
+    /// Changes the size of the array, keeping the memory that stays as it is so that flips in it are still found.
+    /// Returns the size it has now, which is smaller than asked for when memory for new chunks couldn't be allocated.
+    pub fn resize(&mut self, size: usize) -> usize {
+        if size < self.size() {
+            self.chunks.truncate(size.div_ceil(self.chunk_bytes));
+            let remainder = size - self.chunks.len().saturating_sub(1) * self.chunk_bytes;
+            if let Some(last) = self.chunks.last_mut() {
+                last.resize(remainder);
+            }
+        } else {
+            self.grow(size);
+        }
+        self.size()
+    }
+
+    /// Runs the given function on the array's threads.
+    fn install<R: Send>(&self, op: impl FnOnce() -> R + Send) -> R {
+        match &self.pool {
+            Some(pool) => pool.install(op),
+            None => op(),
+        }
+    }
+
+    /// The number of the chunk holding the element at the given index and the offset of the element in it, none past
+    /// the end of the array.
+    pub fn chunk_of(&self, index: usize) -> Option<(usize, usize)> {
+        let (chunk, offset) = (index / self.chunk_bytes, index % self.chunk_bytes);
+        (offset < self.chunks.get(chunk)?.detector_mass.len()).then_some((chunk, offset))
+    }
+
+    /// The index of the element at the given offset in the given chunk, the inverse of `chunk_of`.
+    pub fn index_of(&self, chunk: usize, offset: usize) -> Option<usize> {
+        (offset < self.chunks.get(chunk)?.detector_mass.len()).then_some(chunk * self.chunk_bytes + offset)
+    }
+
+    /// The chunk holding the element at the given index, and the offset of the element in it.
+    fn locate(&self, index: usize) -> Option<(&Detector, usize)> {
+        let (chunk, offset) = self.chunk_of(index)?;
+        Some((&self.chunks[chunk], offset))
+    }
+
+    /// Splits the array into two detectors that are checked together, see `Detector::set_inverted_halves`.
+    pub fn set_inverted_halves(&mut self, inverted_halves: bool) {
+        self.inverted_halves = inverted_halves;
+        self.chunks.iter_mut().for_each(|chunk| chunk.set_inverted_halves(inverted_halves));
+    }
+
+    /// Changes the value the array is written with. Takes effect at the next reset.
+    pub fn set_default(&mut self, default: u8) {
+        self.default = default;
+        self.chunks.iter_mut().for_each(|chunk| chunk.set_default(default));
+    }
+
+    /// The value the array is written with.
+    pub fn default_value(&self) -> u8 {
+        self.default
+    }
+
+    pub fn set_scan_mode(&mut self, scan_mode: ScanMode) {
+        self.scan_mode = scan_mode;
+        self.chunks.iter_mut().for_each(|chunk| chunk.set_scan_mode(scan_mode));
+    }
+
+    pub fn set_paranoid_reads(&mut self, paranoid_reads: bool) {
+        self.paranoid_reads = paranoid_reads;
+        self.chunks.iter_mut().for_each(|chunk| chunk.set_paranoid_reads(paranoid_reads));
+    }
+
+    /// Checks and writes the chunks with the given number of threads instead of one per core.
+    pub fn set_threads(&mut self, threads: Option<usize>) -> Result<(), String> {
+        self.pool = match threads {
+            Some(threads) => Some(
+                ThreadPoolBuilder::new()
+                    .num_threads(threads)
+                    .build()
+                    .map_err(|e| format!("Unable to start {} detector threads: {}", threads, e))?,
+            ),
+            None => None,
+        };
+        Ok(())
+    }
+
+    pub fn is_intact(&self) -> bool {
+        self.find_index_of_changed_element().is_none()
+    }
+
+    /// Checks like `is_intact`, but gives up soon after `cancel` is set and returns none.
+    pub fn is_intact_unless_cancelled(&self, cancel: &AtomicBool) -> Option<bool> {
+        let changed = self.install(|| {
+            self.chunks.par_iter().any(|chunk| chunk.is_intact_unless_cancelled(cancel) != Some(true))
+        });
+        match cancel.load(Ordering::Relaxed) {
+            true => None,
+            false => Some(!changed),
+        }
+    }
+
+    /// Checks every chunk by its checksums, see `Detector::checksums_intact`.
+    pub fn checksums_intact(&self) -> bool {
+        self.install(|| self.chunks.par_iter().all(Detector::checksums_intact))
+    }
+
+    pub fn find_index_of_changed_element(&self) -> Option<usize> {
+        self.install(|| {
+            self.chunks
+                .par_iter()
+                .enumerate()
+                .find_map_any(|(n, chunk)| chunk.find_changed().and_then(|offset| self.index_of(n, offset)))
+        })
+    }
+
+    /// Returns the indices of every element of every chunk that does not match its default value, in order.
+    pub fn find_all_changed_elements(&self) -> Vec<usize> {
+        self.install(|| {
+            self.chunks
+                .par_iter()
+                .enumerate()
+                .flat_map_iter(|(n, chunk)| {
+                    chunk.find_all_changed_elements().into_iter().filter_map(move |offset| self.index_of(n, offset))
+                })
+                .collect()
+        })
+    }
+
+    /// Writes the given value to every element of every chunk.
+    pub fn write(&mut self, value: u8) {
+        let chunks = &mut self.chunks;
+        match &self.pool {
+            Some(pool) => pool.install(|| chunks.par_iter_mut().for_each(|chunk| chunk.write(value))),
+            None => chunks.par_iter_mut().for_each(|chunk| chunk.write(value)),
+        }
+    }
+
+    /// Resets every chunk to the default value, and its inverse in the inverted half.
+    pub fn reset(&mut self) {
+        let chunks = &mut self.chunks;
+        match &self.pool {
+            Some(pool) => pool.install(|| chunks.par_iter_mut().for_each(Detector::reset)),
+            None => chunks.par_iter_mut().for_each(Detector::reset),
+        }
+    }
+
+    /// Times a full check of an intact array and returns how many bytes per second it read.
+    pub fn measure_check_bandwidth(&mut self) -> f64 {
+        self.reset();
+        let start = Instant::now();
+        self.is_intact();
+        self.size() as f64 / start.elapsed().as_secs_f64().max(f64::EPSILON)
+    }
+
+    /// The fraction of all possible single bit flips that the patterns written to any of the chunks could reveal,
+    /// see `Detector::pattern_coverage`.
+    pub fn pattern_coverage(&self) -> f64 {
+        let (held_ones, held_zeros) = self
+            .chunks
+            .iter()
+            .fold((0, 0), |(ones, zeros), chunk| (ones | chunk.held_ones, zeros | chunk.held_zeros));
+        (held_ones.count_ones() + held_zeros.count_ones()) as f64 / 16.0
+    }
+
+    /// The fraction of the pages of all chunks that are in memory, see `Detector::resident_fraction`.
+    pub fn resident_fraction(&self) -> Result<f64, String> {
+        let size = self.size();
+        if size == 0 {
+            return Ok(1.0);
+        }
+        let mut resident = 0.0;
+        for chunk in &self.chunks {
+            resident += chunk.resident_fraction()? * chunk.detector_mass.len() as f64;
+        }
+        Ok(resident / size as f64)
+    }
+
+    /// Asks for transparent huge pages for every chunk, see `Detector::use_huge_pages`.
+    pub fn use_huge_pages(&self) -> Result<(), String> {
+        self.chunks.iter().try_for_each(Detector::use_huge_pages)
+    }
+
+    /// Binds the chunks to the given NUMA nodes in turn, so that the detector is spread evenly over the memory of every
+    /// node, moving the memory that is written already. Chunks added by a resize follow on. Stops at the first chunk
+    /// that can't be bound.
+    pub fn spread_over_nodes(&mut self, nodes: Vec<u32>) -> Result<(), String> {
+        self.numa_nodes = nodes;
+        if self.numa_nodes.is_empty() {
+            return Ok(());
+        }
+        self.chunks
+            .iter()
+            .enumerate()
+            .try_for_each(|(n, chunk)| chunk.bind_to_node(self.numa_nodes[n % self.numa_nodes.len()]))
+    }
+
+    /// Locks the memory of every chunk, see `Detector::lock_memory`. Chunks added by a resize aren't locked until
+    /// this is called again.
+    pub fn lock_memory(&self) -> Result<(), String> {
+        self.chunks.iter().try_for_each(Detector::lock_memory)
+    }
+
+    pub fn fault_in(&self) {
+        self.chunks.iter().for_each(Detector::fault_in);
+    }
+
+    /// Writes the value it should hold back to every element at the given indices. Indices past the end are ignored.
+    pub fn repair(&mut self, indices: &[usize]) {
+        for &index in indices {
+            if let Some((chunk, offset)) = self.chunk_of(index) {
+                self.chunks[chunk].repair(&[offset]);
+            }
+        }
+    }
+
+    /// Flips a single bit of the element at the given index, for making sure that flips are found. Returns none when
+    /// there is no such element or bit.
+    pub fn flip_bit(&mut self, index: usize, bit: u8) -> Option<()> {
+        let (chunk, offset) = self.chunk_of(index)?;
+        self.chunks[chunk].flip_bit(offset, bit)
+    }
+
+    pub fn diff_at(&self, index: usize) -> Option<FlipInfo> {
+        let (chunk, offset) = self.locate(index)?;
+        chunk.diff_at(offset).map(|info| FlipInfo { index, ..info })
+    }
+
+    /// The virtual address of the element at the given index, if it exists. Chunks lie anywhere in memory, so
+    /// addresses are only contiguous within one.
+    pub fn address_of(&self, index: usize) -> Option<usize> {
+        let (chunk, offset) = self.locate(index)?;
+        Some(chunk.address_of(offset))
+    }
+
+    pub fn get(&self, index: usize) -> Option<u8> {
+        let (chunk, offset) = self.locate(index)?;
+        chunk.get(offset)
+    }
+}
+
+impl DetectorMemory for DetectorArray {
+    fn reset(&mut self) {
+        DetectorArray::reset(self)
+    }
+
+    fn set_default(&mut self, value: u8) {
+        DetectorArray::set_default(self, value)
+    }
+
+    fn is_intact(&self) -> bool {
+        DetectorArray::is_intact(self)
+    }
+
+    fn find_index_of_changed_element(&self) -> Option<usize> {
+        DetectorArray::find_index_of_changed_element(self)
+    }
+
+    fn get(&self, index: usize) -> Option<u8> {
+        DetectorArray::get(self, index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::record::FlipDiff;
+
+    #[test]
+    fn diff_at_reports_the_flipped_bits() {
+        let mut detector = Detector::new(0, 64);
+        detector.flip_bit(9, 3);
+        let single = detector.diff_at(9).unwrap();
+        assert_eq!((single.expected, single.observed), (0, 8));
+        assert_eq!(single.changed_bits(), vec![3]);
+        assert!(!single.is_multi_bit());
+
+        detector.flip_bit(9, 6);
+        let multi = detector.diff_at(9).unwrap();
+        assert_eq!(multi.changed_bits(), vec![3, 6]);
+        assert!(multi.is_multi_bit());
+        assert_eq!(detector.diff_at(64), None);
+    }
+
+    #[test]
+    fn inverted_halves_alternate_by_cache_line() {
+        let mut detector = Detector::new(0, 4 * CACHE_LINE_BYTES + 10);
+        detector.set_inverted_halves(true);
+        detector.reset();
+        assert!(detector.is_intact());
+        assert!(detector.checksums_intact());
+        assert_eq!(detector.get(CACHE_LINE_BYTES - 1), Some(0));
+        assert_eq!(detector.get(CACHE_LINE_BYTES), Some(255));
+        assert_eq!(detector.get(4 * CACHE_LINE_BYTES + 9), Some(0));
+
+        detector.flip_bit(CACHE_LINE_BYTES + 3, 2);
+        assert!(!detector.checksums_intact());
+        assert_eq!(detector.find_index_of_changed_element(), Some(CACHE_LINE_BYTES + 3));
+        let info = detector.diff_at(CACHE_LINE_BYTES + 3).unwrap();
+        assert_eq!((info.expected, info.observed, info.inverted), (255, 251, Some(true)));
+        assert_eq!(info.changed_bits(), vec![2]);
+
+        detector.flip_bit(2 * CACHE_LINE_BYTES, 0);
+        assert_eq!(detector.find_all_changed_elements(), vec![CACHE_LINE_BYTES + 3, 2 * CACHE_LINE_BYTES]);
+        let diff = FlipDiff::from(detector.diff_at(2 * CACHE_LINE_BYTES).unwrap());
+        let fields: Vec<(&str, String)> = diff.fields();
+        assert!(fields.contains(&("inverted", "false".to_string())));
+
+        detector.resize(6 * CACHE_LINE_BYTES);
+        detector.flip_bit(CACHE_LINE_BYTES + 3, 2);
+        detector.flip_bit(2 * CACHE_LINE_BYTES, 0);
+        assert!(detector.is_intact());
+        assert_eq!(detector.get(5 * CACHE_LINE_BYTES), Some(255));
+    }
+
+    #[test]
+    fn flipping_a_bit_outside_the_detector_changes_nothing() {
+        let mut detector = DetectorArray::with_chunk_bytes(0, 300, 128).unwrap();
+        assert_eq!(detector.flip_bit(300, 0), None);
+        assert_eq!(detector.flip_bit(10, 8), None);
+        assert!(detector.is_intact());
+        assert_eq!(detector.flip_bit(299, 7), Some(()));
+        assert_eq!(detector.find_all_changed_elements(), vec![299]);
+    }
+
+    #[test]
+    fn pattern_coverage_grows_with_the_patterns_written() {
+        let mut detector = Detector::new(0x55, 256);
+        assert_eq!(detector.pattern_coverage(), 0.5);
+        detector.reset();
+        assert_eq!(detector.pattern_coverage(), 0.5);
+        detector.set_default(0xAA);
+        assert_eq!(detector.pattern_coverage(), 0.5);
+        detector.reset();
+        assert_eq!(detector.pattern_coverage(), 1.0);
+
+        let mut detector = Detector::new(0x00, 256);
+        detector.set_default(0x0F);
+        detector.reset();
+        assert_eq!(detector.pattern_coverage(), 0.75);
+
+        let mut array = DetectorArray::with_chunk_bytes(0, 512, 128).unwrap();
+        assert_eq!(array.pattern_coverage(), 0.5);
+        array.set_inverted_halves(true);
+        array.reset();
+        assert_eq!(array.pattern_coverage(), 1.0);
+    }
+
+    #[test]
+    fn a_new_pattern_is_expected_after_the_next_reset() {
+        let mut detector = Detector::new(0, 4096);
+        detector.set_default(0xAA);
+        detector.reset();
+        assert!(detector.is_intact());
+        assert!(detector.checksums_intact());
+        detector.flip_bit(100, 0);
+        let info = detector.diff_at(100).unwrap();
+        assert_eq!((info.expected, info.observed), (0xAA, 0xAB));
+        assert_eq!(detector.find_all_changed_elements(), vec![100]);
+    }
+
+    #[test]
+    fn repairing_writes_back_only_the_changed_bytes() {
+        let mut detector = Detector::new(0, 4096);
+        detector.set_inverted_halves(true);
+        detector.reset();
+        detector.flip_bit(10, 3);
+        detector.flip_bit(100, 0);
+        let changed = detector.find_all_changed_elements();
+        assert_eq!(changed, vec![10, 100]);
+        detector.repair(&changed);
+        assert!(detector.is_intact());
+        assert!(detector.checksums_intact());
+        assert_eq!(detector.get(100), Some(0xFF));
+    }
+
+    #[test]
+    fn every_scan_mode_finds_the_same_changes() {
+        for scan_mode in [ScanMode::Bytes, ScanMode::Words, ScanMode::Simd] {
+            for inverted_halves in [false, true] {
+                // An odd size leaves bytes before and after the words and vectors.
+                let mut detector = Detector::new(0, 4099);
+                detector.set_scan_mode(scan_mode);
+                detector.set_inverted_halves(inverted_halves);
+                detector.reset();
+                assert!(detector.is_intact());
+                for index in [0, 67, 4098] {
+                    detector.flip_bit(index, 5);
+                }
+                assert_eq!(detector.find_all_changed_elements(), vec![0, 67, 4098], "{:?}", scan_mode);
+                detector.repair(&[0, 67]);
+                assert_eq!(detector.find_index_of_changed_element(), Some(4098), "{:?}", scan_mode);
+                assert!(!detector.is_intact());
+            }
+        }
+    }
+
+    #[test]
+    fn detector_array_finds_changes_across_chunks() {
+        let mut detector = DetectorArray::with_chunk_bytes(0, 1000, 256).unwrap();
+        assert_eq!(detector.size(), 1000);
+        detector.set_inverted_halves(true);
+        detector.reset();
+        assert!(detector.is_intact());
+        assert!(detector.checksums_intact());
+        for index in [3, 330, 999] {
+            detector.flip_bit(index, 2);
+        }
+        assert_eq!(detector.find_all_changed_elements(), vec![3, 330, 999]);
+        // Index 330 is in the second cache line of the second chunk, in the inverted half.
+        let info = detector.diff_at(330).unwrap();
+        assert_eq!((info.index, info.expected, info.inverted), (330, 0xFF, Some(true)));
+        detector.repair(&[3, 999]);
+        assert_eq!(detector.find_index_of_changed_element(), Some(330));
+        assert!(!detector.checksums_intact());
+        assert_eq!(detector.get(1000), None);
+    }
+
+    #[test]
+    fn resizing_a_detector_array_keeps_the_chunks_that_stay() {
+        let mut detector = DetectorArray::with_chunk_bytes(0, 600, 256).unwrap();
+        detector.reset();
+        detector.flip_bit(100, 0);
+        assert_eq!(detector.resize(300), 300);
+        assert_eq!(detector.resize(1100), 1100);
+        assert_eq!(detector.get(1099), Some(0));
+        assert_eq!(detector.find_all_changed_elements(), vec![100]);
+        assert_eq!(detector.resize(0), 0);
+        assert!(detector.is_intact());
+        assert_eq!(detector.address_of(0), None);
+    }
+}
@@ -0,0 +1,100 @@
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// How often the free space is looked at. Filling gigabytes takes far longer than this.
+const DISK_POLL_INTERVAL: Duration = Duration::from_secs(60);
+/// A level is only left again once the free space is this much above its threshold, so that a disk hovering
+/// around it doesn't raise an alert every minute.
+const RECOVERY_MARGIN: f64 = 1.1;
+
+/// How the free space of the filesystem holding the log compares with the thresholds.
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+pub enum DiskSpace {
+    Enough,
+    /// Below the warning threshold.
+    Low,
+    /// Below the threshold heartbeats are paused at, what is left is kept for bitflips.
+    Critical,
+}
+
+/// Watches the free space of the filesystem holding the log. Once it is full every write fails, which has
+/// stopped stations without anyone noticing.
+pub struct DiskWatch {
+    path: PathBuf,
+    warning_bytes: u64,
+    pause_bytes: u64,
+    level: DiskSpace,
+    last_poll: Option<Instant>,
+}
+
+impl DiskWatch {
+    /// Fails where the free space can't be found, which is everywhere but Linux. A threshold of 0 is never crossed.
+    pub fn new(path: PathBuf, warning_bytes: u64, pause_bytes: u64) -> Result<Self, String> {
+        free_bytes(&path)?;
+        Ok(DiskWatch { path, warning_bytes, pause_bytes, level: DiskSpace::Enough, last_poll: None })
+    }
+
+    /// Returns the new level and the free bytes when the level changed since the last poll, at most once
+    /// every poll interval.
+    pub fn poll(&mut self) -> Result<Option<(DiskSpace, u64)>, String> {
+        if self.last_poll.is_some_and(|last_poll| last_poll.elapsed() < DISK_POLL_INTERVAL) {
+            return Ok(None);
+        }
+        self.last_poll = Some(Instant::now());
+        let free = free_bytes(&self.path)?;
+        let level = self.level_of(free);
+        if level == self.level {
+            return Ok(None);
+        }
+        self.level = level;
+        Ok(Some((level, free)))
+    }
+
+    /// Whether heartbeats should be left out of the log for now.
+    pub fn heartbeats_paused(&self) -> bool {
+        self.level == DiskSpace::Critical
+    }
+
+    fn level_of(&self, free: u64) -> DiskSpace {
+        let below = |threshold: u64, current: DiskSpace| {
+            // Staying at a level takes less than getting there.
+            let margin = if self.level >= current { RECOVERY_MARGIN } else { 1.0 };
+            (free as f64) < threshold as f64 * margin
+        };
+        if below(self.pause_bytes, DiskSpace::Critical) {
+            DiskSpace::Critical
+        } else if below(self.warning_bytes, DiskSpace::Low) {
+            DiskSpace::Low
+        } else {
+            DiskSpace::Enough
+        }
+    }
+}
+
+/// The bytes an unprivileged process can still write to the filesystem holding the path.
+#[cfg(target_os = "linux")]
+pub fn free_bytes(path: &Path) -> Result<u64, String> {
+    use std::ffi::CString;
+    use std::io;
+    use std::mem::MaybeUninit;
+    use std::os::unix::ffi::OsStrExt;
+
+    let c_path = CString::new(path.as_os_str().as_bytes())
+        .map_err(|_| format!("The path {} contains a nul byte", path.display()))?;
+    let mut stats = MaybeUninit::<libc::statvfs>::uninit();
+    // The path is nul terminated and statvfs only writes to the struct it is given.
+    let result = unsafe { libc::statvfs(c_path.as_ptr(), stats.as_mut_ptr()) };
+    if result != 0 {
+        return Err(format!("Unable to find the free space of {}: {}", path.display(), io::Error::last_os_error()));
+    }
+    // statvfs succeeded, so it filled in the whole struct.
+    let stats = unsafe { stats.assume_init() };
+    // Both are 32 bits wide on 32-bit platforms.
+    #[allow(clippy::unnecessary_cast)]
+    Ok(stats.f_bavail as u64 * stats.f_frsize as u64)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn free_bytes(_path: &Path) -> Result<u64, String> {
+    Err("Free disk space is only watched on Linux".into())
+}
@@ -0,0 +1,134 @@
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// The kernel's EDAC drivers count the errors the ECC memory of the whole machine corrected (or failed to) since boot.
+const EDAC_MC_PATH: &str = "/sys/devices/system/edac/mc";
+
+/// Memory error counts summed over every memory controller.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EdacCounts {
+    pub corrected: u64,
+    pub uncorrected: u64,
+}
+
+/// Reads the current error counts, or `None` if the machine has no EDAC memory controllers,
+/// which is the case without ECC memory and on systems other than Linux.
+pub fn read_counts() -> Result<Option<EdacCounts>, String> {
+    let controllers = match fs::read_dir(EDAC_MC_PATH) {
+        Ok(controllers) => controllers,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(format!("Unable to read {}: {}", EDAC_MC_PATH, err)),
+    };
+
+    let mut counts = EdacCounts { corrected: 0, uncorrected: 0 };
+    let mut found = false;
+    for controller in controllers.flatten() {
+        let path = controller.path();
+        let is_controller = path
+            .file_name()
+            .map_or(false, |name| name.to_string_lossy().starts_with("mc"));
+        if !is_controller {
+            continue;
+        }
+        counts.corrected += read_count(&path.join("ce_count"))?;
+        counts.uncorrected += read_count(&path.join("ue_count"))?;
+        found = true;
+    }
+    Ok(found.then_some(counts))
+}
+
+/// The error counts of one memory module, as far as the memory controller can tell them apart.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DimmCounts {
+    /// The slot label from the firmware, e.g. `DIMM_A2`, or the module's place in sysfs such as `mc0/dimm2`
+    /// when there is none.
+    pub label: String,
+    /// The size of the module in MB, if the driver knows it.
+    pub size_mb: Option<u64>,
+    pub corrected: u64,
+    pub uncorrected: u64,
+}
+
+/// Reads the error counts of every memory module. Older drivers only know chip select rows (`rank*`) instead
+/// of modules, and some neither, which leaves the list empty.
+pub fn read_dimm_counts() -> Result<Vec<DimmCounts>, String> {
+    let controllers = match fs::read_dir(EDAC_MC_PATH) {
+        Ok(controllers) => controllers,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(format!("Unable to read {}: {}", EDAC_MC_PATH, err)),
+    };
+
+    let mut dimms = Vec::new();
+    for controller in controllers.flatten() {
+        let controller_name = controller.file_name().to_string_lossy().to_string();
+        if !controller_name.starts_with("mc") {
+            continue;
+        }
+        let modules = fs::read_dir(controller.path())
+            .map_err(|e| format!("Unable to read {}: {}", controller.path().display(), e))?;
+        for module in modules.flatten() {
+            let name = module.file_name().to_string_lossy().to_string();
+            let prefix = match ["dimm", "rank"].into_iter().find(|prefix| name.starts_with(prefix)) {
+                Some(prefix) => prefix,
+                None => continue,
+            };
+            let path = module.path();
+            let label = fs::read_to_string(path.join(format!("{}_label", prefix)))
+                .map(|label| label.trim().to_string())
+                .ok()
+                .filter(|label| !label.is_empty())
+                .unwrap_or_else(|| format!("{}/{}", controller_name, name));
+            dimms.push(DimmCounts {
+                label,
+                size_mb: read_count(&path.join("size")).ok().filter(|&size| size > 0),
+                corrected: read_count(&path.join(format!("{}_ce_count", prefix)))?,
+                uncorrected: read_count(&path.join(format!("{}_ue_count", prefix)))?,
+            });
+        }
+    }
+    dimms.sort_by(|a, b| a.label.cmp(&b.label));
+    Ok(dimms)
+}
+
+/// Samples the error counts at a fixed interval and reports them whenever they change.
+pub struct EdacSampler {
+    interval: Duration,
+    last_sample: Instant,
+    last_counts: Option<EdacCounts>,
+}
+
+impl EdacSampler {
+    /// Returns `None` if there is nothing to sample on this machine.
+    pub fn new(interval: Duration) -> Result<Option<Self>, String> {
+        Ok(read_counts()?.map(|_| EdacSampler {
+            interval,
+            last_sample: Instant::now(),
+            last_counts: None,
+        }))
+    }
+
+    /// Returns the counts if they are due for sampling and changed since the last time,
+    /// the first call always returns them so that the log has a starting point.
+    pub fn poll(&mut self) -> Result<Option<EdacCounts>, String> {
+        if self.last_counts.is_some() && self.last_sample.elapsed() < self.interval {
+            return Ok(None);
+        }
+        self.last_sample = Instant::now();
+        let counts = read_counts()?;
+        if counts.is_none() || counts == self.last_counts {
+            return Ok(None);
+        }
+        self.last_counts = counts;
+        Ok(counts)
+    }
+}
+
+fn read_count(path: &Path) -> Result<u64, String> {
+    fs::read_to_string(path)
+        .map_err(|e| format!("Unable to read {}: {}", path.display(), e))?
+        .trim()
+        .parse()
+        .map_err(|e| format!("Unable to parse {}: {}", path.display(), e))
+}
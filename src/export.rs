@@ -0,0 +1,166 @@
+use std::error::Error;
+use std::fs;
+use std::io::{stdout, Write};
+use std::path::Path;
+
+use serde_json::json;
+use sha2::{Digest, Sha256};
+
+use crate::{
+    analyze::exposure,
+    config::{ExportArgs, ExportFormat, GlobalArgs},
+    exposure::utc_date,
+    record::{read_log, Record, SCHEMA_VERSION},
+    station::StationConfig,
+};
+
+/// The size of the grid locations are snapped to by --anonymize, roughly 11 km.
+const ANONYMIZE_GRID_DEGREES: f64 = 0.1;
+const METADATA_NAME: &str = "metadata.json";
+const KEYWORDS: [&str; 4] = ["cosmic rays", "bitflips", "soft errors", "single event upsets"];
+
+/// Converts a log file in any format to CSV or JSON lines on stdout, or to a dataset with metadata.
+pub fn export(global: &GlobalArgs, args: ExportArgs) -> Result<(), Box<dyn Error>> {
+    let records: Vec<Record> = read_log(&args.file_path)?
+        .iter()
+        .map(|record| match args.anonymize {
+            true => record.anonymized(ANONYMIZE_GRID_DEGREES),
+            false => record.clone(),
+        })
+        .collect();
+
+    if let Some(directory) = &args.doi_metadata {
+        return export_dataset(global, &args, &records, directory);
+    }
+    let mut out = stdout().lock();
+    write_records(&mut out, &records, args.format)?;
+    out.flush()?;
+    Ok(())
+}
+
+fn write_records(out: &mut dyn Write, records: &[Record], format: ExportFormat) -> Result<(), Box<dyn Error>> {
+    for record in records {
+        match format {
+            ExportFormat::Csv => out.write_all(record.to_csv().as_bytes())?,
+            ExportFormat::Json => writeln!(out, "{}", serde_json::to_string(record)?)?,
+        }
+    }
+    Ok(())
+}
+
+/// Writes the log, its exposure ledger and metadata describing them into a directory, ready to be uploaded to an
+/// archival repository. `metadata` in metadata.json is in the form Zenodo's deposit API takes, which figshare's
+/// import tools understand as well, the rest describes the station and the data for those who reuse it.
+fn export_dataset(global: &GlobalArgs, args: &ExportArgs, records: &[Record], directory: &Path) -> Result<(), Box<dyn Error>> {
+    if args.creator.is_empty() {
+        return Err("Archival repositories need to know who made the dataset, pass at least one --creator".into());
+    }
+    let station = StationConfig::load(&global.station_config)?;
+    let station_id = station.station_id(global.station_id.as_deref())?;
+    fs::create_dir_all(directory).map_err(|e| format!("Unable to create {}: {}", directory.display(), e))?;
+
+    let mut files = Vec::new();
+    let log_name = format!(
+        "{}.{}",
+        station_id,
+        match args.format {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Json => "jsonl",
+        }
+    );
+    let mut log = Vec::new();
+    write_records(&mut log, records, args.format)?;
+    files.push((log_name, log));
+    let ledger_path = args
+        .ledger_path
+        .clone()
+        .unwrap_or_else(|| format!("{}.ledger", args.file_path.display()).into());
+    if ledger_path.exists() {
+        let ledger = fs::read(&ledger_path).map_err(|e| format!("Unable to read {}: {}", ledger_path.display(), e))?;
+        files.push((format!("{}.ledger.csv", station_id), ledger));
+    }
+    for (name, contents) in &files {
+        let path = directory.join(name);
+        fs::write(&path, contents).map_err(|e| format!("Unable to write {}: {}", path.display(), e))?;
+    }
+
+    let (gb_hours, _) = exposure(&args.file_path, args.ledger_path.clone(), records)?;
+    let flips = records.iter().filter(|record| matches!(record, Record::Flip { .. })).count();
+    let sessions = records.iter().filter(|record| matches!(record, Record::Start { .. })).count();
+    let times: Vec<u128> = records.iter().filter_map(Record::timestamp_ms).collect();
+    let (first_ms, last_ms) = (times.iter().min().copied(), times.iter().max().copied());
+    let location = records.iter().rev().find_map(|record| match record {
+        Record::Start { location, .. } | Record::Flip { location, .. } => *location,
+        _ => None,
+    });
+
+    let title = args
+        .title
+        .clone()
+        .unwrap_or_else(|| format!("Bitflips in computer memory recorded by cosmic ray detector station {}", station_id));
+    let description = format!(
+        "<p>Bitflips in the memory of a computer, which cosmic rays among other things cause, recorded by station {} \
+         with cosmic_ray_detector. The dataset has {} bitflips in {} sessions, with an exposure of {:.3} GB hours.</p>\
+         <p>The log has one record per line in the format of log schema {}, described in the README of \
+         cosmic_ray_detector. The exposure ledger has the exposure, dead time and detector size of every UTC day.</p>",
+        station_id, flips, sessions, gb_hours, SCHEMA_VERSION
+    );
+    let mut metadata = json!({
+        "upload_type": "dataset",
+        "title": title,
+        "creators": args.creator.iter().map(|name| json!({ "name": name })).collect::<Vec<_>>(),
+        "description": description,
+        "access_right": "open",
+        "license": args.license,
+        "keywords": KEYWORDS,
+        "notes": format!("Recorded with cosmic_ray_detector, exported by version {}", env!("CARGO_PKG_VERSION")),
+    });
+    if let (Some(first_ms), Some(last_ms)) = (first_ms, last_ms) {
+        metadata["dates"] = json!([{
+            "start": utc_date((first_ms / 1000) as u64),
+            "end": utc_date((last_ms / 1000) as u64),
+            "type": "Collected",
+        }]);
+    }
+    if let Some(location) = location {
+        metadata["locations"] = json!([{
+            "lat": location.latitude,
+            "lon": location.longitude,
+            "place": format!("Station {}", station_id),
+        }]);
+    }
+    let document = json!({
+        "metadata": metadata,
+        "station": {
+            "station_id": station_id,
+            "altitude_m": station.altitude,
+            "anonymized": args.anonymize,
+        },
+        "data": {
+            "schema": SCHEMA_VERSION,
+            "sessions": sessions,
+            "bitflips": flips,
+            "exposure_gb_hours": gb_hours,
+            // The exposure comes from the ledger when there is one, it keeps better track than the log.
+            "exposure_from": if ledger_path.exists() { "ledger" } else { "log" },
+            "first_record_ms": first_ms,
+            "last_record_ms": last_ms,
+            "files": files
+                .iter()
+                .map(|(name, contents)| json!({
+                    "name": name,
+                    "size": contents.len(),
+                    "sha256": format!("{:x}", Sha256::digest(contents)),
+                }))
+                .collect::<Vec<_>>(),
+        },
+    });
+    let metadata_path = directory.join(METADATA_NAME);
+    fs::write(&metadata_path, serde_json::to_vec_pretty(&document)?)
+        .map_err(|e| format!("Unable to write {}: {}", metadata_path.display(), e))?;
+
+    if global.verbose {
+        println!("Wrote a dataset of {} files and {} to {}", files.len(), METADATA_NAME, directory.display());
+    }
+    Ok(())
+}
@@ -0,0 +1,161 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const BYTES_PER_GB: f64 = 1e9;
+const SECONDS_PER_HOUR: f64 = 3600.0;
+
+/// How much a detector of the given size has been exposed over the given time, in gigabyte hours.
+/// The chance of seeing a flip grows with both, so this is what rates have to be normalised by.
+pub fn gb_hours(detector_bytes: usize, duration: Duration) -> f64 {
+    detector_bytes as f64 / BYTES_PER_GB * duration.as_secs_f64() / SECONDS_PER_HOUR
+}
+
+/// Accumulated exposure per UTC day, kept in its own small file next to the log.
+/// It documents how sensitive a station has been even if it never sees a single event.
+#[derive(Clone)]
+pub struct Ledger {
+    path: PathBuf,
+    days: BTreeMap<String, LedgerDay>,
+}
+
+#[derive(Default, Clone, Copy)]
+struct LedgerDay {
+    gb_hours: f64,
+    /// Time spent not monitoring while running, e.g. rewriting the detector after a flip.
+    dead_seconds: f64,
+    min_detector_bytes: usize,
+    max_detector_bytes: usize,
+    checks: u64,
+}
+
+impl Ledger {
+    /// Opens the ledger at the given path, picking up where earlier sessions left off.
+    pub fn open(path: PathBuf) -> Result<Self, String> {
+        let mut days = BTreeMap::new();
+        match fs::read_to_string(&path) {
+            Ok(contents) => {
+                for line in contents.lines().skip(1).filter(|line| !line.trim().is_empty()) {
+                    let (date, day) = parse_ledger_line(line)
+                        .ok_or_else(|| format!("Malformed line '{}' in ledger {}", line, path.display()))?;
+                    days.insert(date, day);
+                }
+            }
+            Err(err) if err.kind() == ErrorKind::NotFound => {}
+            Err(err) => return Err(format!("Unable to read ledger {}: {}", path.display(), err)),
+        }
+        Ok(Ledger { path, days })
+    }
+
+    /// Adds time spent monitoring a detector of the given size to today's entry.
+    pub fn add_exposure(&mut self, detector_bytes: usize, duration: Duration, checks: u64) {
+        let day = self.today();
+        day.gb_hours += gb_hours(detector_bytes, duration);
+        day.checks += checks;
+        if day.min_detector_bytes == 0 || detector_bytes < day.min_detector_bytes {
+            day.min_detector_bytes = detector_bytes;
+        }
+        day.max_detector_bytes = day.max_detector_bytes.max(detector_bytes);
+    }
+
+    /// Adds time spent not monitoring to today's entry.
+    pub fn add_dead_time(&mut self, duration: Duration) {
+        self.today().dead_seconds += duration.as_secs_f64();
+    }
+
+    /// The exposure of every day in the ledger added up, in gigabyte hours.
+    pub fn total_gb_hours(&self) -> f64 {
+        self.days.values().map(|day| day.gb_hours).sum()
+    }
+
+    /// Writes the whole ledger to a temporary file and moves it into place, so it is never left half written.
+    pub fn save(&self) -> io::Result<()> {
+        let mut contents = String::from("date,gb_hours,dead_seconds,min_detector_bytes,max_detector_bytes,checks\n");
+        for (date, day) in &self.days {
+            contents += &format!(
+                "{},{},{},{},{},{}\n",
+                date, day.gb_hours, day.dead_seconds, day.min_detector_bytes, day.max_detector_bytes, day.checks
+            );
+        }
+        let temporary_path = self.path.with_extension("tmp");
+        fs::write(&temporary_path, contents)?;
+        fs::rename(&temporary_path, &self.path)
+    }
+
+    fn today(&mut self) -> &mut LedgerDay {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        self.days.entry(utc_date(now.as_secs())).or_default()
+    }
+}
+
+fn parse_ledger_line(line: &str) -> Option<(String, LedgerDay)> {
+    let fields: Vec<&str> = line.split(',').collect();
+    if fields.len() != 6 {
+        return None;
+    }
+    Some((
+        fields[0].to_string(),
+        LedgerDay {
+            gb_hours: fields[1].parse().ok()?,
+            dead_seconds: fields[2].parse().ok()?,
+            min_detector_bytes: fields[3].parse().ok()?,
+            max_detector_bytes: fields[4].parse().ok()?,
+            checks: fields[5].parse().ok()?,
+        },
+    ))
+}
+
+/// Formats a unix timestamp as a `YYYY-MM-DD` date in UTC.
+pub fn utc_date(unix_seconds: u64) -> String {
+    let (year, month, day) = civil_from_days((unix_seconds / 86400) as i64);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// How far local time is ahead of UTC at the given unix timestamp, in seconds, following the TZ variable and
+/// /etc/localtime. Zero where the time zone can't be read.
+#[cfg(target_os = "linux")]
+pub fn local_utc_offset(unix_seconds: u64) -> i64 {
+    let time = unix_seconds as libc::time_t;
+    // localtime_r only writes to the struct it is given, unlike localtime it shares no state between threads.
+    let mut local: libc::tm = unsafe { std::mem::zeroed() };
+    match unsafe { libc::localtime_r(&time, &mut local) }.is_null() {
+        true => 0,
+        false => local.tm_gmtoff,
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn local_utc_offset(_unix_seconds: u64) -> i64 {
+    0
+}
+
+/// Converts a (year, month, day) date into days since 1970-01-01, the inverse of `civil_from_days`, see
+/// http://howardhinnant.github.io/date_algorithms.html#days_from_civil
+pub fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let shifted_month = if month > 2 { month - 3 } else { month + 9 } as i64;
+    let day_of_year = (153 * shifted_month + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+/// Converts days since 1970-01-01 into a (year, month, day) date, see
+/// http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+pub fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * shifted_month + 2) / 5 + 1) as u32;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 } as u32;
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
@@ -0,0 +1,224 @@
+//! C bindings for embedding the detector, declared in `include/cosmic_ray_detector.h`, and Python bindings with
+//! the `python` feature.
+#![allow(non_camel_case_types)]
+
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+#[cfg(feature = "python")]
+#[path = "python.rs"]
+mod python;
+
+use crate::detector::Detector;
+
+/// A change found in the detector memory, laid out as `crd_event` in the header.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct crd_event {
+    /// When the check that found the change finished, in milliseconds since 1970.
+    pub detected_ms: u64,
+    /// The index of the changed byte, -1 if it had changed back by the time it was looked for.
+    pub index: i64,
+    /// The value the changed byte held, -1 if it had changed back.
+    pub value: i32,
+    /// The number of checks that passed since the previous event.
+    pub checks: u64,
+    /// The change happened at most this many milliseconds before it was detected.
+    pub window_ms: u64,
+}
+
+/// The detector memory and what the checks have seen of it, shared with the checking thread.
+struct Checker {
+    detector: Detector,
+    checks: u64,
+    /// The start of the last check that passed, or the end of the last reset.
+    window_start: Instant,
+}
+
+impl Checker {
+    /// Checks the memory once, resetting it and returning the event if anything changed.
+    fn check(&mut self) -> Option<crd_event> {
+        let check_start = Instant::now();
+        if self.detector.is_intact() {
+            self.checks += 1;
+            self.window_start = check_start;
+            return None;
+        }
+        let index = self.detector.find_index_of_changed_element();
+        let event = crd_event {
+            detected_ms: SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_millis() as u64),
+            index: index.map_or(-1, |index| index as i64),
+            value: index.and_then(|index| self.detector.get(index)).map_or(-1, i32::from),
+            checks: self.checks,
+            window_ms: self.window_start.elapsed().as_millis() as u64,
+        };
+        self.detector.reset();
+        self.checks = 0;
+        self.window_start = Instant::now();
+        Some(event)
+    }
+}
+
+/// A detector and its checking thread, opaque to C. Every function but `crd_free` may be called from any thread.
+pub struct crd_detector {
+    checker: Arc<Mutex<Checker>>,
+    events: Arc<Mutex<VecDeque<crd_event>>>,
+    stop: Arc<AtomicBool>,
+    thread: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl crd_detector {
+    fn new(bytes: usize) -> Self {
+        let mut detector = Detector::new(0, bytes);
+        detector.reset();
+        crd_detector {
+            checker: Arc::new(Mutex::new(Checker { detector, checks: 0, window_start: Instant::now() })),
+            events: Arc::new(Mutex::new(VecDeque::new())),
+            stop: Arc::new(AtomicBool::new(false)),
+            thread: Mutex::new(None),
+        }
+    }
+
+    /// Checks once on the calling thread, queueing the event if anything changed.
+    fn check(&self) -> bool {
+        let event = self.checker.lock().unwrap_or_else(|e| e.into_inner()).check();
+        if let Some(event) = event {
+            self.events.lock().unwrap_or_else(|e| e.into_inner()).push_back(event);
+        }
+        event.is_some()
+    }
+
+    /// Starts checking every `delay` on a thread of its own, unless that is already happening.
+    fn start(&self, delay: Duration) {
+        let mut running = self.thread.lock().unwrap_or_else(|e| e.into_inner());
+        if running.is_some() {
+            return;
+        }
+        self.stop.store(false, Ordering::Relaxed);
+        let (checker, events, stop) = (self.checker.clone(), self.events.clone(), self.stop.clone());
+        *running = Some(thread::spawn(move || loop {
+            let deadline = Instant::now() + delay;
+            while !stop.load(Ordering::Relaxed) && Instant::now() < deadline {
+                thread::park_timeout(deadline.saturating_duration_since(Instant::now()));
+            }
+            if stop.load(Ordering::Relaxed) {
+                return;
+            }
+            if let Some(event) = checker.lock().unwrap_or_else(|e| e.into_inner()).check() {
+                events.lock().unwrap_or_else(|e| e.into_inner()).push_back(event);
+            }
+        }));
+    }
+
+    /// Stops the checking thread and waits for it, which takes at most one check.
+    fn stop(&self) {
+        // Holding the lock until the thread is gone keeps a concurrent start from being stopped right away.
+        let mut running = self.thread.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(thread) = running.take() {
+            self.stop.store(true, Ordering::Relaxed);
+            thread.thread().unpark();
+            let _ = thread.join();
+        }
+    }
+
+    /// Takes up to `capacity` of the queued events, oldest first.
+    fn take_events(&self, capacity: usize) -> Vec<crd_event> {
+        let mut events = self.events.lock().unwrap_or_else(|e| e.into_inner());
+        let count = capacity.min(events.len());
+        events.drain(..count).collect()
+    }
+}
+
+impl Drop for crd_detector {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// Allocates a detector of `bytes` bytes, or returns null if `bytes` is zero. Nothing is checked until
+/// `crd_start` or `crd_check` is called. Free it with `crd_free`.
+#[no_mangle]
+pub extern "C" fn crd_new(bytes: usize) -> *mut crd_detector {
+    if bytes == 0 {
+        return std::ptr::null_mut();
+    }
+    Box::into_raw(Box::new(crd_detector::new(bytes)))
+}
+
+/// Checks the detector once on the calling thread. Returns 1 if something changed and an event was queued,
+/// 0 if the memory was intact and -1 if the detector is null.
+///
+/// # Safety
+/// `detector` has to be null or come from `crd_new` and not have been freed.
+#[no_mangle]
+pub unsafe extern "C" fn crd_check(detector: *mut crd_detector) -> i32 {
+    match unsafe { detector.as_ref() } {
+        Some(detector) => detector.check() as i32,
+        None => -1,
+    }
+}
+
+/// Starts checking the detector every `delay_ms` milliseconds on a thread of its own. Returns -1 if the detector
+/// is null, 0 otherwise, also when it was already started.
+///
+/// # Safety
+/// `detector` has to be null or come from `crd_new` and not have been freed.
+#[no_mangle]
+pub unsafe extern "C" fn crd_start(detector: *mut crd_detector, delay_ms: u64) -> i32 {
+    match unsafe { detector.as_ref() } {
+        Some(detector) => {
+            detector.start(Duration::from_millis(delay_ms));
+            0
+        }
+        None => -1,
+    }
+}
+
+/// Stops the checking thread started by `crd_start`, returning once it has finished its current check.
+/// Returns -1 if the detector is null, 0 otherwise.
+///
+/// # Safety
+/// `detector` has to be null or come from `crd_new` and not have been freed.
+#[no_mangle]
+pub unsafe extern "C" fn crd_stop(detector: *mut crd_detector) -> i32 {
+    match unsafe { detector.as_ref() } {
+        Some(detector) => {
+            detector.stop();
+            0
+        }
+        None => -1,
+    }
+}
+
+/// Moves up to `capacity` queued events into `events`, oldest first, and returns how many it moved.
+/// Events stay queued until they are taken, so call it regularly. Returns 0 if either pointer is null.
+///
+/// # Safety
+/// `detector` has to be null or come from `crd_new` and not have been freed, and `events` has to be null or
+/// point at room for `capacity` events.
+#[no_mangle]
+pub unsafe extern "C" fn crd_events(detector: *mut crd_detector, events: *mut crd_event, capacity: usize) -> usize {
+    let Some(detector) = (unsafe { detector.as_ref() }) else {
+        return 0;
+    };
+    if events.is_null() {
+        return 0;
+    }
+    let taken = detector.take_events(capacity);
+    unsafe { std::ptr::copy_nonoverlapping(taken.as_ptr(), events, taken.len()) };
+    taken.len()
+}
+
+/// Stops the detector and frees its memory. Does nothing if it is null.
+///
+/// # Safety
+/// `detector` has to be null or come from `crd_new`, and can't be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn crd_free(detector: *mut crd_detector) {
+    if !detector.is_null() {
+        drop(unsafe { Box::from_raw(detector) });
+    }
+}
@@ -0,0 +1,490 @@
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+
+use sysinfo::{RefreshKind, System, SystemExt};
+
+use crate::{
+    detector::{Detector, DetectorArray},
+    i18n::tr,
+    mem_size,
+};
+
+const SWAP_TOLERANCE_DEFAULT: u64 = 10_000_000; // 10MB
+const FREE_HEADROOM_DEFAULT: u64 = 50_000_000; // 50MB
+/// How much memory probing commits at a time before looking at the memory pressure again.
+#[cfg(target_os = "linux")]
+const COMMIT_STEP: usize = 64_000_000; // 64MB
+/// The smallest change of the detector size worth making while running, and the smallest detector resizing
+/// leaves. Smaller changes in free memory are noise.
+const MIN_RESIZE: usize = 64_000_000; // 64MB
+
+/// When the memory counts as full. What suits a board with 512MB hardly suits a server with 1TB.
+#[derive(Clone, Copy, Debug)]
+pub struct Thresholds {
+    /// How much more swap may be used than before filling the memory.
+    pub swap_tolerance: u64,
+    /// How much memory to leave available on machines without swap.
+    pub free_headroom: u64,
+}
+
+impl Default for Thresholds {
+    fn default() -> Self {
+        Thresholds { swap_tolerance: SWAP_TOLERANCE_DEFAULT, free_headroom: FREE_HEADROOM_DEFAULT }
+    }
+}
+
+/// Finds the largest detector that fits in memory without pushing more than the tolerated amount into swap, or
+/// leaving less than the headroom available on machines without swap.
+pub fn fill_memory(out: &mut dyn Write, sys_info: &mut System, thresholds: Thresholds, verbose: bool) -> io::Result<usize> {
+    #[cfg(target_os = "linux")]
+    if let Some(size) = probe(sys_info, thresholds) {
+        if verbose {
+            writeln!(out, "{}", tr!("Probing found room for {}", mem_size(size as u64)))?;
+        }
+        return Ok(size);
+    }
+    search(out, sys_info, thresholds, verbose)
+}
+
+/// Whether a detector of the given size still fits the way fill_memory found it to last time. Other programs may
+/// use more memory by now.
+pub fn fits_in_memory(sys_info: &mut System, thresholds: Thresholds, size: usize) -> bool {
+    let previous_swap_usage = sys_info.used_swap();
+    #[cfg(target_os = "linux")]
+    if let Some(mut reservation) = Reservation::new(size) {
+        reservation.commit(size);
+        sys_info.refresh_specifics(RefreshKind::new().with_memory());
+        return !memory_exhausted(Memory::of(sys_info), previous_swap_usage, thresholds);
+    }
+    let mut detector = Detector::new(0, size);
+    detector.write(42);
+    sys_info.refresh_specifics(RefreshKind::new().with_memory());
+    !memory_exhausted(Memory::of(sys_info), previous_swap_usage, thresholds)
+}
+
+/// Reserves as much address space as there is memory available, which costs nothing until it is used, and
+/// commits it a step at a time until the memory runs short. This touches every page only once, unlike
+/// allocating and writing detectors over and over, and backs off before more than a step of other memory
+/// has been pushed out to swap. None if the address space can't be reserved, e.g. when overcommitting is off.
+#[cfg(target_os = "linux")]
+fn probe(sys_info: &mut System, thresholds: Thresholds) -> Option<usize> {
+    let previous_swap_usage = sys_info.used_swap();
+    let available = sys_info.available_memory() as usize;
+    let mut reservation = Reservation::new(available)?;
+    while reservation.committed < available {
+        let committed = reservation.committed;
+        reservation.commit((committed + COMMIT_STEP).min(available));
+        sys_info.refresh_specifics(RefreshKind::new().with_memory());
+        if memory_exhausted(Memory::of(sys_info), previous_swap_usage, thresholds) {
+            // The last step went too far.
+            return Some(committed);
+        }
+    }
+    Some(reservation.committed)
+}
+
+/// The old way of finding the size, by allocating detectors that are halved in size each time, for systems
+/// without MAP_NORESERVE.
+fn search(out: &mut dyn Write, sys_info: &mut System, thresholds: Thresholds, verbose: bool) -> io::Result<usize> {
+    // Calculate 1/2 of the available memory
+    // Evaluate how much is left after attempting to use all the memory. Check if any swap has been used
+    // If swap has been used, decrement by 1/2 of the original amount
+    // If swap has not been used, increase by 1/2 of the previous amount until the amount is less than 10MB increments
+    let previous_swap_usage = sys_info.used_swap();
+    // Start at 1/2 of available memory
+    let mut size = (sys_info.available_memory() / 2) as usize;
+    let mut increment = size;
+    if verbose {
+        print_detector_stats(out, sys_info, size)?;
+    }
+    // Every step grows the array, and a step that went too far is taken back by shrinking it again.
+    let mut detectors = DetectorArray::new(0, size);
+    detectors.write(42);
+    size = detectors.size();
+    let mut total_size = size;
+    loop {
+        sys_info.refresh_specifics(RefreshKind::new().with_memory());
+        increment /= 2;
+        if memory_exhausted(Memory::of(sys_info), previous_swap_usage, thresholds) {
+            // Passed the threshold, reduce memory consumption by removing the previous step
+            total_size = detectors.resize(total_size - size);
+        } else if thresholds.free_headroom.max(1) > increment as u64 {
+            // Only increase in steps larger than the headroom
+            break;
+        }
+        size = size.saturating_sub(increment);
+
+        if verbose {
+            print_detector_stats(out, sys_info, size)?;
+        }
+
+        // An allocation that fails is as good as the memory running out, the next step is smaller.
+        size = detectors.resize(total_size + size) - total_size;
+        total_size += size;
+    }
+    Ok(total_size)
+}
+
+/// Why the detector is resized while running.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ResizeReason {
+    /// This many more bytes of swap are used than before.
+    SwapGrew(u64),
+    /// Only this many bytes of memory are available, less than the headroom.
+    LowMemory(u64),
+    /// At least this many bytes of memory beyond the headroom have been available for the whole grow delay.
+    FreeMemory(u64),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Resize {
+    pub size: usize,
+    pub reason: ResizeReason,
+}
+
+/// Resizes a detector that fills all available memory as other programs need more or less of it. Memory they
+/// need can't wait, so the detector shrinks as soon as it runs short. It only grows again once memory has been
+/// free for a while, and never sooner than the cooldown after the last resize, so that a program that comes and
+/// goes doesn't keep the detector growing and shrinking.
+pub struct Resizer {
+    thresholds: Thresholds,
+    grow_after: Duration,
+    cooldown: Duration,
+    /// The swap usage growth is measured from, moved along whenever the detector shrank because of it.
+    swap_baseline: u64,
+    /// Since when memory beyond the headroom has been available, and the least of it in that time.
+    free_since: Option<(Instant, u64)>,
+    last_resize: Option<Instant>,
+}
+
+impl Resizer {
+    pub fn new(sys_info: &mut System, thresholds: Thresholds, grow_after: Duration, cooldown: Duration) -> Self {
+        sys_info.refresh_specifics(RefreshKind::new().with_memory());
+        Resizer {
+            thresholds,
+            grow_after,
+            cooldown,
+            swap_baseline: sys_info.used_swap(),
+            free_since: None,
+            last_resize: None,
+        }
+    }
+
+    /// Looks at the memory and returns the size the detector should have now, if it should change.
+    pub fn poll(&mut self, sys_info: &mut System, size: usize) -> Option<Resize> {
+        sys_info.refresh_specifics(RefreshKind::new().with_memory());
+        self.decide(Memory::of(sys_info), size, Instant::now())
+    }
+
+    fn decide(&mut self, memory: Memory, size: usize, now: Instant) -> Option<Resize> {
+        let swap_growth = MemDelta::between(self.swap_baseline, memory.used_swap);
+        let headroom = MemDelta::between(self.thresholds.free_headroom, memory.available);
+        let shortage = match memory.total_swap > 0 {
+            true if swap_growth.exceeds(self.thresholds.swap_tolerance) => {
+                self.swap_baseline = memory.used_swap;
+                Some((swap_growth.or_zero(), ResizeReason::SwapGrew(swap_growth.or_zero())))
+            }
+            false if headroom.is_negative() => Some((
+                MemDelta::between(memory.available, self.thresholds.free_headroom).or_zero(),
+                ResizeReason::LowMemory(memory.available),
+            )),
+            _ => None,
+        };
+        if let Some((missing, reason)) = shortage {
+            // Right away, whatever the cooldown says.
+            let new_size = size.saturating_sub((missing as usize).max(MIN_RESIZE)).max(MIN_RESIZE.min(size));
+            return self.resized(size, new_size, reason, now);
+        }
+
+        // Some memory beyond what growing takes is left free, so that the next small allocation of another
+        // program doesn't shrink the detector right back.
+        let free = headroom.or_zero();
+        if free < 2 * MIN_RESIZE as u64 {
+            self.free_since = None;
+            return None;
+        }
+        let (since, least_free) = self.free_since.get_or_insert((now, free));
+        *least_free = (*least_free).min(free);
+        let cooled_down = self.last_resize.is_none_or(|last_resize| now.duration_since(last_resize) >= self.cooldown);
+        if now.duration_since(*since) < self.grow_after || !cooled_down {
+            return None;
+        }
+        let least_free = *least_free;
+        self.resized(size, size + least_free as usize - MIN_RESIZE, ResizeReason::FreeMemory(least_free), now)
+    }
+
+    fn resized(&mut self, size: usize, new_size: usize, reason: ResizeReason, now: Instant) -> Option<Resize> {
+        if new_size == size {
+            return None;
+        }
+        self.free_since = None;
+        self.last_resize = Some(now);
+        Some(Resize { size: new_size, reason })
+    }
+}
+
+/// The signed difference between two amounts of memory in bytes. sysinfo reports them unsigned, and a plain
+/// subtraction underflows as soon as e.g. the kernel swaps pages back in and less swap is used than before.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct MemDelta(i128);
+
+impl MemDelta {
+    /// How much `after` is above `before`, negative when it is below.
+    pub fn between(before: u64, after: u64) -> Self {
+        MemDelta(after as i128 - before as i128)
+    }
+
+    /// Whether it grew by more than `bytes`.
+    pub fn exceeds(self, bytes: u64) -> bool {
+        self.0 > bytes as i128
+    }
+
+    pub fn is_negative(self) -> bool {
+        self.0 < 0
+    }
+
+    /// The growth, 0 if it shrank.
+    pub fn or_zero(self) -> u64 {
+        self.0.clamp(0, u64::MAX as i128) as u64
+    }
+}
+
+/// The figures of the memory fill_memory goes by.
+#[derive(Clone, Copy, Debug)]
+struct Memory {
+    available: u64,
+    total_swap: u64,
+    used_swap: u64,
+}
+
+impl Memory {
+    fn of(sys_info: &System) -> Self {
+        Memory {
+            available: sys_info.available_memory(),
+            total_swap: sys_info.total_swap(),
+            used_swap: sys_info.used_swap(),
+        }
+    }
+}
+
+/// With swap, whether more of it is used than before. Without, whether less than the headroom is available.
+fn memory_exhausted(memory: Memory, previous_swap_usage: u64, thresholds: Thresholds) -> bool {
+    match memory.total_swap > 0 {
+        true => MemDelta::between(previous_swap_usage, memory.used_swap).exceeds(thresholds.swap_tolerance),
+        false => MemDelta::between(thresholds.free_headroom, memory.available).is_negative(),
+    }
+}
+
+fn print_detector_stats(out: &mut dyn Write, sys_info: &System, size: usize) -> io::Result<()> {
+    let unused = MemDelta::between(sys_info.used_memory(), sys_info.total_memory()).or_zero();
+    writeln!(out, "Total: {:>12} Free: {:>12} Available: {:>12} Used: {:>12} Total-Used: {:>12}", sys_info.total_memory(), sys_info.free_memory(), sys_info.available_memory(), sys_info.used_memory(), unused)?;
+    writeln!(out, "Total: {:>12} Free: {:>12} Available: {:>12} Used: {:>12} Total-Used: {:>12}", mem_size(sys_info.total_memory()), mem_size(sys_info.free_memory()), mem_size(sys_info.available_memory()), mem_size(sys_info.used_memory()), mem_size(unused))?;
+    writeln!(out, "{}", tr!("Creating next detector of size {} ({})", size, mem_size(size as u64)))
+}
+
+/// Address space mapped without reserving swap for it, so that the kernel only provides memory for the pages
+/// that are written to. Unmapped, and the memory given back, when dropped.
+#[cfg(target_os = "linux")]
+struct Reservation {
+    address: *mut u8,
+    length: usize,
+    /// The bytes from the start that have been written to.
+    committed: usize,
+}
+
+#[cfg(target_os = "linux")]
+impl Reservation {
+    fn new(length: usize) -> Option<Self> {
+        if length == 0 {
+            return None;
+        }
+        // A fresh anonymous mapping doesn't touch any memory of ours, the result is checked before it is used.
+        let address = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                length,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS | libc::MAP_NORESERVE,
+                -1,
+                0,
+            )
+        };
+        match address == libc::MAP_FAILED {
+            true => None,
+            false => Some(Reservation { address: address as *mut u8, length, committed: 0 }),
+        }
+    }
+
+    /// Writes to every page up to `end`, which makes the kernel provide memory for them.
+    fn commit(&mut self, end: usize) {
+        // sysconf only reads a constant of the system.
+        let page_size = (unsafe { libc::sysconf(libc::_SC_PAGESIZE) }).max(1) as usize;
+        let end = end.min(self.length);
+        for offset in (self.committed..end).step_by(page_size) {
+            // The offset is within the mapping, which is readable and writable. Volatile, so that the write
+            // isn't optimized away.
+            unsafe { std::ptr::write_volatile(self.address.add(offset), 42) };
+        }
+        self.committed = self.committed.max(end);
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        // The mapping is ours and nothing points into it any more.
+        unsafe { libc::munmap(self.address as *mut libc::c_void, self.length) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GB: u64 = 1_000_000_000;
+
+    fn with_swap(used_swap: u64) -> Memory {
+        Memory { available: GB, total_swap: 4 * GB, used_swap }
+    }
+
+    fn without_swap(available: u64) -> Memory {
+        Memory { available, total_swap: 0, used_swap: 0 }
+    }
+
+    #[test]
+    fn mem_delta_is_signed() {
+        assert_eq!(MemDelta::between(GB, 3 * GB), MemDelta(2 * GB as i128));
+        assert_eq!(MemDelta::between(3 * GB, GB), MemDelta(-2 * GB as i128));
+        assert!(MemDelta::between(3 * GB, GB).is_negative());
+        assert!(!MemDelta::between(GB, GB).is_negative());
+    }
+
+    #[test]
+    fn mem_delta_covers_the_whole_range() {
+        assert_eq!(MemDelta::between(0, u64::MAX), MemDelta(u64::MAX as i128));
+        assert_eq!(MemDelta::between(u64::MAX, 0), MemDelta(-(u64::MAX as i128)));
+        assert_eq!(MemDelta::between(0, u64::MAX).or_zero(), u64::MAX);
+    }
+
+    #[test]
+    fn shrinking_is_no_growth() {
+        let shrunk = MemDelta::between(2 * GB, GB);
+        assert_eq!(shrunk.or_zero(), 0);
+        assert!(!shrunk.exceeds(0));
+        assert!(MemDelta::between(GB, GB + SWAP_TOLERANCE_DEFAULT + 1).exceeds(SWAP_TOLERANCE_DEFAULT));
+        assert!(!MemDelta::between(GB, GB + SWAP_TOLERANCE_DEFAULT).exceeds(SWAP_TOLERANCE_DEFAULT));
+    }
+
+    #[test]
+    fn shrinking_swap_is_not_exhaustion() {
+        // The kernel swapped pages back in since the sizing started.
+        assert!(!memory_exhausted(with_swap(GB), 2 * GB, Thresholds::default()));
+        assert!(!memory_exhausted(with_swap(0), u64::MAX, Thresholds::default()));
+    }
+
+    #[test]
+    fn growing_swap_is_exhaustion() {
+        assert!(!memory_exhausted(with_swap(GB + SWAP_TOLERANCE_DEFAULT), GB, Thresholds::default()));
+        assert!(memory_exhausted(with_swap(GB + SWAP_TOLERANCE_DEFAULT + 1), GB, Thresholds::default()));
+    }
+
+    #[test]
+    fn fluctuating_availability_without_swap() {
+        let verdicts: Vec<bool> = [GB, FREE_HEADROOM_DEFAULT - 1, FREE_HEADROOM_DEFAULT, 0, GB]
+            .into_iter()
+            .map(|available| memory_exhausted(without_swap(available), 0, Thresholds::default()))
+            .collect();
+        assert_eq!(verdicts, [false, true, false, true, false]);
+    }
+
+    #[test]
+    fn availability_is_ignored_with_swap() {
+        let memory = Memory { available: 0, ..with_swap(GB) };
+        assert!(!memory_exhausted(memory, GB, Thresholds::default()));
+    }
+
+    #[test]
+    fn thresholds_are_configurable() {
+        let small_board = Thresholds { swap_tolerance: 1_000_000, free_headroom: 5_000_000 };
+        assert!(memory_exhausted(with_swap(GB + 2_000_000), GB, small_board));
+        assert!(!memory_exhausted(without_swap(10_000_000), 0, small_board));
+        assert!(memory_exhausted(without_swap(10_000_000), 0, Thresholds::default()));
+    }
+
+    fn resizer() -> Resizer {
+        Resizer {
+            thresholds: Thresholds::default(),
+            grow_after: Duration::from_secs(600),
+            cooldown: Duration::from_secs(300),
+            swap_baseline: 0,
+            free_since: None,
+            last_resize: None,
+        }
+    }
+
+    #[test]
+    fn shrinks_right_away() {
+        let mut resizer = resizer();
+        let now = Instant::now();
+        let resize = resizer.decide(without_swap(FREE_HEADROOM_DEFAULT - 1), 4 * GB as usize, now);
+        assert_eq!(resize.map(|resize| resize.reason), Some(ResizeReason::LowMemory(FREE_HEADROOM_DEFAULT - 1)));
+        assert_eq!(resize.map(|resize| resize.size), Some(4 * GB as usize - MIN_RESIZE));
+        // The cooldown doesn't hold back shrinking either.
+        let resize = resizer.decide(without_swap(0), 3 * GB as usize, now);
+        assert_eq!(resize.map(|resize| resize.reason), Some(ResizeReason::LowMemory(0)));
+    }
+
+    #[test]
+    fn shrinks_by_the_swap_used_and_measures_from_there() {
+        let mut resizer = resizer();
+        let now = Instant::now();
+        let resize = resizer.decide(with_swap(GB), 4 * GB as usize, now);
+        assert_eq!(resize, Some(Resize { size: 3 * GB as usize, reason: ResizeReason::SwapGrew(GB) }));
+        assert_eq!(resizer.decide(with_swap(GB), 3 * GB as usize, now), None);
+    }
+
+    #[test]
+    fn never_shrinks_away() {
+        let mut resizer = resizer();
+        let now = Instant::now();
+        let resize = resizer.decide(without_swap(0), MIN_RESIZE + 1, now);
+        assert_eq!(resize.map(|resize| resize.size), Some(MIN_RESIZE));
+        assert_eq!(resizer.decide(without_swap(0), MIN_RESIZE, now), None);
+    }
+
+    #[test]
+    fn grows_only_after_memory_stayed_free() {
+        let mut resizer = resizer();
+        let start = Instant::now();
+        let free = FREE_HEADROOM_DEFAULT + GB;
+        assert_eq!(resizer.decide(without_swap(free), GB as usize, start), None);
+        assert_eq!(resizer.decide(without_swap(free), GB as usize, start + Duration::from_secs(300)), None);
+        // A moment without free memory starts the wait over.
+        assert_eq!(resizer.decide(without_swap(FREE_HEADROOM_DEFAULT), GB as usize, start + Duration::from_secs(400)), None);
+        assert_eq!(resizer.decide(without_swap(free), GB as usize, start + Duration::from_secs(500)), None);
+        assert_eq!(resizer.decide(without_swap(free), GB as usize, start + Duration::from_secs(1000)), None);
+        let resize = resizer.decide(without_swap(free), GB as usize, start + Duration::from_secs(1100));
+        assert_eq!(resize, Some(Resize { size: 2 * GB as usize - MIN_RESIZE, reason: ResizeReason::FreeMemory(GB) }));
+    }
+
+    #[test]
+    fn grows_by_the_least_free_memory_seen() {
+        let mut resizer = resizer();
+        let start = Instant::now();
+        resizer.decide(without_swap(FREE_HEADROOM_DEFAULT + 2 * GB), GB as usize, start);
+        resizer.decide(without_swap(FREE_HEADROOM_DEFAULT + GB), GB as usize, start + Duration::from_secs(300));
+        let resize = resizer.decide(without_swap(FREE_HEADROOM_DEFAULT + 2 * GB), GB as usize, start + Duration::from_secs(600));
+        assert_eq!(resize.map(|resize| resize.reason), Some(ResizeReason::FreeMemory(GB)));
+    }
+
+    #[test]
+    fn waits_for_the_cooldown_after_shrinking() {
+        let mut resizer = resizer();
+        resizer.grow_after = Duration::ZERO;
+        let start = Instant::now();
+        assert!(resizer.decide(without_swap(0), 4 * GB as usize, start).is_some());
+        let free = without_swap(FREE_HEADROOM_DEFAULT + GB);
+        assert_eq!(resizer.decide(free, 3 * GB as usize, start + Duration::from_secs(299)), None);
+        assert!(resizer.decide(free, 3 * GB as usize, start + Duration::from_secs(300)).is_some());
+    }
+}
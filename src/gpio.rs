@@ -0,0 +1,41 @@
+use std::time::Duration;
+
+/// Pulses a GPIO line of a Raspberry Pi for every bitflip, for counters, LEDs or coincidence electronics
+/// that are fed by physical detectors as well. The line is low between pulses.
+#[cfg(all(feature = "gpio", target_os = "linux"))]
+pub struct GpioPulser {
+    pin: rppal::gpio::OutputPin,
+    width: Duration,
+}
+
+#[cfg(all(feature = "gpio", target_os = "linux"))]
+impl GpioPulser {
+    /// Takes the line with the given BCM number, which needs access to /dev/gpiomem, e.g. through the gpio group.
+    pub fn open(pin: u8, width: Duration) -> Result<Self, String> {
+        let pin = rppal::gpio::Gpio::new()
+            .and_then(|gpio| gpio.get(pin))
+            .map_err(|e| format!("Unable to use GPIO {}: {}", pin, e))?
+            .into_output_low();
+        Ok(GpioPulser { pin, width })
+    }
+
+    /// Holds the line high for the pulse width. The caller waits for it, which is still far shorter than
+    /// resetting the detector after the flip.
+    pub fn pulse(&mut self) {
+        self.pin.set_high();
+        std::thread::sleep(self.width);
+        self.pin.set_low();
+    }
+}
+
+#[cfg(not(all(feature = "gpio", target_os = "linux")))]
+pub struct GpioPulser;
+
+#[cfg(not(all(feature = "gpio", target_os = "linux")))]
+impl GpioPulser {
+    pub fn open(_pin: u8, _width: Duration) -> Result<Self, String> {
+        Err("This build has no GPIO support, rebuild with --features gpio on a Raspberry Pi".into())
+    }
+
+    pub fn pulse(&mut self) {}
+}
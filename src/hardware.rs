@@ -0,0 +1,147 @@
+use std::fs;
+use std::path::Path;
+
+use crate::{numa, record::Hardware};
+
+const CPUINFO_PATH: &str = "/proc/cpuinfo";
+/// The name of the microarchitecture the kernel's Intel PMU driver was set up for.
+const PMU_NAME_PATH: &str = "/sys/devices/cpu/caps/pmu_name";
+/// The firmware's SMBIOS tables, one directory per structure named `<type>-<instance>`. Reading them takes root.
+const DMI_ENTRIES_PATH: &str = "/sys/firmware/dmi/entries";
+/// SMBIOS structure type 17, a memory device, see DSP0134.
+const MEMORY_DEVICE_PREFIX: &str = "17-";
+
+/// Offsets into the formatted area of a memory device structure.
+const LENGTH_OFFSET: usize = 0x01;
+const SIZE_OFFSET: usize = 0x0C;
+const MEMORY_TYPE_OFFSET: usize = 0x12;
+const SPEED_OFFSET: usize = 0x15;
+const PART_NUMBER_OFFSET: usize = 0x1A;
+const CONFIGURED_SPEED_OFFSET: usize = 0x20;
+const EXTENDED_SPEED_OFFSET: usize = 0x54;
+const EXTENDED_CONFIGURED_SPEED_OFFSET: usize = 0x58;
+/// A speed that doesn't fit into 16 bits is in the extended field instead.
+const SPEED_IN_EXTENDED_FIELD: u16 = 0xFFFF;
+
+/// Finds out what it can about the processor and memory of this machine, leaving out whatever it can't. Runs before
+/// the sandbox, which doesn't let the firmware tables be read.
+pub fn detect() -> Hardware {
+    let mut hardware = Hardware::default();
+    if let Ok(cpuinfo) = fs::read_to_string(CPUINFO_PATH) {
+        read_cpuinfo(&cpuinfo, &mut hardware);
+    }
+    hardware.cpu_microarchitecture = fs::read_to_string(PMU_NAME_PATH)
+        .ok()
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty());
+    let modules = memory_devices(Path::new(DMI_ENTRIES_PATH));
+    let mut parts: Vec<String> = modules.iter().filter_map(|module| module.part_number.clone()).collect();
+    parts.sort();
+    parts.dedup();
+    hardware.memory_type = modules.iter().find_map(|module| module.memory_type).map(String::from);
+    hardware.memory_parts = (!parts.is_empty()).then(|| parts.join(";"));
+    hardware.memory_rated_mts = modules.iter().filter_map(|module| module.speed_mts).min();
+    hardware.memory_configured_mts = modules.iter().filter_map(|module| module.configured_mts).max();
+    if let (Some(rated), Some(configured)) = (hardware.memory_rated_mts, hardware.memory_configured_mts) {
+        hardware.memory_xmp = Some(configured > rated);
+    }
+    hardware.numa_nodes = numa::online_nodes().ok().map(|nodes| nodes.len() as u32).filter(|&nodes| nodes > 0);
+    hardware
+}
+
+/// Takes the first processor's description, they are all the same but for hybrid designs.
+fn read_cpuinfo(cpuinfo: &str, hardware: &mut Hardware) {
+    let processor = cpuinfo.split("\n\n").next().unwrap_or_default();
+    let value = |key: &str| {
+        processor.lines().find_map(|line| {
+            let (name, value) = line.split_once(':')?;
+            (name.trim() == key).then(|| without_separators(value.trim()))
+        })
+    };
+    hardware.cpu_model = value("model name");
+    if let (Some(vendor), Some(family), Some(model), Some(stepping)) =
+        (value("vendor_id"), value("cpu family"), value("model"), value("stepping"))
+    {
+        hardware.cpu_signature = Some(format!("{} {}/{}/{}", vendor, family, model, stepping));
+    }
+}
+
+/// The metadata is stored as comma separated key=value pairs.
+fn without_separators(value: &str) -> String {
+    value.replace(',', " ")
+}
+
+/// An installed memory module.
+struct MemoryDevice {
+    memory_type: Option<&'static str>,
+    part_number: Option<String>,
+    speed_mts: Option<u64>,
+    configured_mts: Option<u64>,
+}
+
+/// Reads every installed memory module from the SMBIOS tables, none where they can't be read.
+fn memory_devices(directory: &Path) -> Vec<MemoryDevice> {
+    let Ok(entries) = fs::read_dir(directory) else {
+        return Vec::new();
+    };
+    let mut names: Vec<String> = entries
+        .flatten()
+        .map(|entry| entry.file_name().to_string_lossy().into_owned())
+        .filter(|name| name.starts_with(MEMORY_DEVICE_PREFIX))
+        .collect();
+    names.sort();
+    names
+        .iter()
+        .filter_map(|name| fs::read(directory.join(name).join("raw")).ok())
+        .filter_map(|raw| parse_memory_device(&raw))
+        .collect()
+}
+
+fn parse_memory_device(raw: &[u8]) -> Option<MemoryDevice> {
+    let length = *raw.get(LENGTH_OFFSET)? as usize;
+    let formatted = raw.get(..length)?;
+    let word = |offset: usize| Some(u16::from_le_bytes(formatted.get(offset..offset + 2)?.try_into().ok()?));
+    let dword = |offset: usize| Some(u32::from_le_bytes(formatted.get(offset..offset + 4)?.try_into().ok()?));
+    // Empty slots are listed too, with a size of 0.
+    if word(SIZE_OFFSET)? == 0 {
+        return None;
+    }
+    let speed = |offset: usize, extended_offset: usize| match word(offset)? {
+        0 => None,
+        SPEED_IN_EXTENDED_FIELD => dword(extended_offset).map(u64::from).filter(|&speed| speed != 0),
+        speed => Some(speed as u64),
+    };
+    Some(MemoryDevice {
+        memory_type: formatted.get(MEMORY_TYPE_OFFSET).and_then(|&memory_type| memory_type_name(memory_type)),
+        part_number: formatted
+            .get(PART_NUMBER_OFFSET)
+            .and_then(|&index| smbios_string(&raw[length..], index))
+            .map(|part_number| without_separators(part_number.trim()))
+            .filter(|part_number| !part_number.is_empty()),
+        speed_mts: speed(SPEED_OFFSET, EXTENDED_SPEED_OFFSET),
+        configured_mts: speed(CONFIGURED_SPEED_OFFSET, EXTENDED_CONFIGURED_SPEED_OFFSET),
+    })
+}
+
+/// The strings follow the formatted area, each ended by a zero byte and numbered from 1. 0 means none.
+fn smbios_string(strings: &[u8], index: u8) -> Option<String> {
+    let index = (index as usize).checked_sub(1)?;
+    let string = strings.split(|&byte| byte == 0).nth(index)?;
+    Some(String::from_utf8_lossy(string).into_owned())
+}
+
+fn memory_type_name(memory_type: u8) -> Option<&'static str> {
+    Some(match memory_type {
+        0x12 => "DDR",
+        0x13 => "DDR2",
+        0x18 => "DDR3",
+        0x1A => "DDR4",
+        0x1B => "LPDDR",
+        0x1C => "LPDDR2",
+        0x1D => "LPDDR3",
+        0x1E => "LPDDR4",
+        0x22 => "DDR5",
+        0x23 => "LPDDR5",
+        _ => return None,
+    })
+}
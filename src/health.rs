@@ -0,0 +1,211 @@
+use std::error::Error;
+use std::fmt;
+
+use crate::{
+    analyze::exposure,
+    config::{GlobalArgs, HealthArgs},
+    edac::{read_dimm_counts, DimmCounts},
+    interval::altitude_factor,
+    record::{read_log, Record},
+};
+
+/// Fewer bitflips than this, with fewer expected, don't tell cosmic rays from anything else. The same goes for
+/// bursts, however unlikely they are.
+const MIN_EVENTS: u64 = 3;
+/// How unlikely something has to be to happen by chance before it counts as a sign of a problem.
+const SIGNIFICANCE: f64 = 1e-3;
+/// The window bursts of bitflips are looked for in.
+const BURST_WINDOW_MS: u128 = 3_600_000;
+/// Corrected errors the memory controller counts on fewer modules than this are not told apart.
+const MIN_DIMMS: usize = 2;
+
+/// What the history of a station says about where its bitflips come from.
+#[derive(Debug)]
+pub enum Verdict {
+    InsufficientData,
+    CosmicRays,
+    /// One memory module has far more errors than its share of the memory, by its slot label.
+    FailingDimm(String),
+    /// More bitflips, or more of them at once, than cosmic rays explain, or errors the memory couldn't correct.
+    HardwareProblem,
+}
+
+impl fmt::Display for Verdict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Verdict::InsufficientData => write!(f, "insufficient data"),
+            Verdict::CosmicRays => write!(f, "consistent with cosmic rays"),
+            Verdict::FailingDimm(label) => write!(f, "suspected failing DIMM {}", label),
+            Verdict::HardwareProblem => write!(f, "suspected hardware problem"),
+        }
+    }
+}
+
+/// Looks at the bitflips of a station for the signs of failing hardware, prints what it found and a verdict.
+pub fn health(_global: &GlobalArgs, args: HealthArgs) -> Result<(), Box<dyn Error>> {
+    let records = read_log(&args.file_path)?;
+    let (gb_hours, source) = exposure(&args.file_path, args.ledger_path.clone(), &records)?;
+
+    let mut flips: Vec<(u128, Option<u64>)> = Vec::new();
+    let mut detector_bytes: Option<u64> = None;
+    let mut altitude_m: Option<f64> = None;
+    let mut uncorrected: u64 = 0;
+    let mut last_uncorrected: Option<u64> = None;
+    for record in &records {
+        match record {
+            Record::Session(session) => {
+                detector_bytes = session.detector_bytes;
+                altitude_m = session.altitude_m.or(altitude_m);
+            }
+            Record::Start { .. } => last_uncorrected = None,
+            Record::MemoryErrors { uncorrected: count, .. } => {
+                // The counts start over when the machine reboots.
+                uncorrected += last_uncorrected.map_or(0, |last| count.saturating_sub(last));
+                last_uncorrected = Some(*count);
+            }
+            Record::Flip { warmup: true, .. } => {}
+            Record::Flip { detected_ms, .. } => flips.push((*detected_ms, detector_bytes)),
+            Record::Heartbeat { .. }
+            | Record::Dropped { .. }
+            | Record::Disagreement { .. }
+            | Record::Panic { .. }
+            | Record::Shutdown { .. } => {}
+        }
+    }
+    let per_gb_hour = args.expected_rate * altitude_factor(altitude_m.unwrap_or(0.0));
+    let expected = per_gb_hour * gb_hours;
+    let mut problems = 0;
+
+    println!("Exposure: {:.3} GB hours, from {}", gb_hours, source);
+    println!(
+        "Bitflips: {}, {:.2} expected at {} m altitude",
+        flips.len(),
+        expected,
+        altitude_m.unwrap_or(0.0)
+    );
+
+    let count = flips.len() as u64;
+    let p = poisson_tail(count, expected);
+    if expected <= 0.0 {
+        println!("Rate: no exposure to compare with, run with --heartbeat-interval or keep the ledger");
+    } else if count >= MIN_EVENTS && p < SIGNIFICANCE {
+        problems += 1;
+        println!("Rate: {:.1} times the expected one, {:.1e} likely by chance", count as f64 / expected, p);
+    } else if count >= MIN_EVENTS || expected >= MIN_EVENTS as f64 {
+        println!("Rate: as expected");
+    } else {
+        println!("Rate: too few bitflips and too little exposure to tell");
+    }
+
+    match largest_burst(&flips, per_gb_hour) {
+        Some((size, p)) if size >= MIN_EVENTS && p < SIGNIFICANCE => {
+            problems += 1;
+            println!("Bursts: {} bitflips within an hour, {:.1e} likely by chance", size, p);
+        }
+        _ => println!("Bursts: none"),
+    }
+
+    // Bitflips are logged without where they were, which leaves repeats to the memory controller.
+    println!("Repeat addresses: not recorded in the log");
+
+    if uncorrected > 0 {
+        problems += 1;
+        println!("Uncorrectable memory errors while running: {}", uncorrected);
+    }
+
+    let dimms = match read_dimm_counts() {
+        Ok(dimms) => dimms,
+        Err(err) => {
+            eprintln!("{}", err);
+            Vec::new()
+        }
+    };
+    let failing = failing_dimm(&dimms);
+    if dimms.iter().any(|dimm| dimm.corrected > 0 || dimm.uncorrected > 0) {
+        println!("Memory errors since boot, by module:");
+        for dimm in &dimms {
+            println!("  {:<32} {:>10} corrected {:>10} uncorrected", dimm.label, dimm.corrected, dimm.uncorrected);
+        }
+    }
+
+    let verdict = match failing {
+        Some(dimm) => Verdict::FailingDimm(dimm.label.clone()),
+        None if problems > 0 => Verdict::HardwareProblem,
+        None if expected <= 0.0 || (count < MIN_EVENTS && expected < MIN_EVENTS as f64) => Verdict::InsufficientData,
+        None => Verdict::CosmicRays,
+    };
+    println!("\nVerdict: {}", verdict);
+    Ok(())
+}
+
+/// The chance of at least `count` events when `expected` are expected, with Poisson statistics.
+pub fn poisson_tail(count: u64, expected: f64) -> f64 {
+    if count == 0 {
+        return 1.0;
+    }
+    if expected <= 0.0 {
+        return 0.0;
+    }
+    // The probability of k events, worked out in logarithms so that large counts don't overflow.
+    let log_probability = |k: u64| -expected + k as f64 * expected.ln() - (1..=k).map(|i| (i as f64).ln()).sum::<f64>();
+    if count as f64 > expected {
+        // Above the mean the terms only get smaller, so summing the tail directly keeps its precision.
+        let mut tail = 0.0;
+        let mut term = log_probability(count).exp();
+        let mut k = count;
+        while term > tail * f64::EPSILON {
+            tail += term;
+            k += 1;
+            term *= expected / k as f64;
+        }
+        tail.min(1.0)
+    } else {
+        let below: f64 = (0..count).map(|k| log_probability(k).exp()).sum();
+        (1.0 - below).max(0.0)
+    }
+}
+
+/// The most bitflips within any one burst window, and the chance of that happening anywhere in the log.
+fn largest_burst(flips: &[(u128, Option<u64>)], per_gb_hour: f64) -> Option<(u64, f64)> {
+    let mut times: Vec<u128> = flips.iter().map(|(detected_ms, _)| *detected_ms).collect();
+    times.sort_unstable();
+    let (first, last) = (*times.first()?, *times.last()?);
+    let mut largest = 0;
+    let mut start = 0;
+    for (end, &time) in times.iter().enumerate() {
+        while time - times[start] > BURST_WINDOW_MS {
+            start += 1;
+        }
+        largest = largest.max(end - start + 1);
+    }
+    // The detector is taken to be as large as the largest one in the log, which errs on the side of chance.
+    // Logs of older versions don't say, which leaves nothing to compare with.
+    let detector_gb = flips.iter().filter_map(|(_, bytes)| *bytes).max()? as f64 / 1e9;
+    let expected = per_gb_hour * detector_gb * BURST_WINDOW_MS as f64 / 3_600_000.0;
+    let windows = ((last - first) / BURST_WINDOW_MS + 1) as f64;
+    Some((largest as u64, (poisson_tail(largest as u64, expected) * windows).min(1.0)))
+}
+
+/// The module with far more errors than its share of the memory, or with errors the memory couldn't correct.
+/// Cosmic rays hit every gigabyte alike, so their errors spread over the modules by size.
+fn failing_dimm(dimms: &[DimmCounts]) -> Option<&DimmCounts> {
+    if let Some(dimm) = dimms.iter().filter(|dimm| dimm.uncorrected > 0).max_by_key(|dimm| dimm.uncorrected) {
+        return Some(dimm);
+    }
+    if dimms.len() < MIN_DIMMS {
+        return None;
+    }
+    let corrected: u64 = dimms.iter().map(|dimm| dimm.corrected).sum();
+    // Unless the driver knows the size of every module they are taken to be alike.
+    let known = dimms.iter().all(|dimm| dimm.size_mb.is_some());
+    let sizes: Vec<f64> = dimms.iter().map(|dimm| if known { dimm.size_mb.unwrap_or(1) } else { 1 } as f64).collect();
+    let total_size: f64 = sizes.iter().sum();
+    dimms
+        .iter()
+        .zip(&sizes)
+        .filter(|(dimm, size)| {
+            dimm.corrected >= MIN_EVENTS && poisson_tail(dimm.corrected, corrected as f64 * *size / total_size) < SIGNIFICANCE
+        })
+        .map(|(dimm, _)| dimm)
+        .max_by_key(|dimm| dimm.corrected)
+}
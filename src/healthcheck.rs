@@ -0,0 +1,32 @@
+use std::time::{Duration, Instant};
+
+const HEALTHCHECK_TIMEOUT: Duration = Duration::from_secs(10);
+/// Pinging after every check would hammer the service when checks are continuous.
+const MIN_PING_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Pings a dead man's switch such as healthchecks.io while the detector is checking,
+/// so that the operator gets alerted by the service when the pings stop.
+pub struct HealthcheckPinger {
+    url: String,
+    last_ping: Option<Instant>,
+}
+
+impl HealthcheckPinger {
+    pub fn new(url: String) -> Self {
+        HealthcheckPinger { url, last_ping: None }
+    }
+
+    /// Reports a successful check cycle, unless the last ping was too recent.
+    pub fn ping(&mut self) -> Result<(), String> {
+        if self.last_ping.map_or(false, |last_ping| last_ping.elapsed() < MIN_PING_INTERVAL) {
+            return Ok(());
+        }
+        // A failed ping is not retried before the interval is up, the service tolerates missing a few.
+        self.last_ping = Some(Instant::now());
+        ureq::get(&self.url)
+            .timeout(HEALTHCHECK_TIMEOUT)
+            .call()
+            .map_err(|e| format!("Unable to ping the health check: {}", e))?;
+        Ok(())
+    }
+}
@@ -0,0 +1,181 @@
+//! Translations of the messages printed for people, gettext style: the English text of a message is its ID, and
+//! the catalogs in `locales/` map it to other languages. `cosmic_ray_detector messages` writes the template for
+//! a new catalog, or updates an existing one.
+
+use std::collections::HashMap;
+use std::env;
+use std::error::Error;
+use std::fmt::{Display, Write};
+use std::io::{self, Write as _};
+use std::sync::OnceLock;
+
+use crate::config::{GlobalArgs, MessagesArgs};
+
+// Every message passed to `tr!`, collected from the source by the build script.
+include!(concat!(env!("OUT_DIR"), "/messages.rs"));
+
+/// The catalogs built into the program, by language code.
+const CATALOGS: &[(&str, &str)] = &[("de", include_str!("../locales/de.po")), ("es", include_str!("../locales/es.po"))];
+
+static CATALOG: OnceLock<HashMap<String, String>> = OnceLock::new();
+
+/// Translates a message and fills in its arguments. `{}` takes the next argument and `{0}`, `{1}`... a given one,
+/// so that translations can put them in a different order.
+#[macro_export]
+macro_rules! tr {
+    ($message:literal) => {
+        $crate::i18n::translate($message).to_string()
+    };
+    ($message:literal, $($argument:expr),+ $(,)?) => {
+        $crate::i18n::fill($crate::i18n::translate($message), &[$(&$argument as &dyn std::fmt::Display),+])
+    };
+}
+pub use tr;
+
+/// Prints a gettext catalog with every message of the program, translated as far as the built in catalog of the
+/// language goes. Translations of messages the program no longer has are kept as obsolete entries.
+pub fn messages(_global: &GlobalArgs, args: MessagesArgs) -> Result<(), Box<dyn Error>> {
+    let existing = match &args.language {
+        Some(language) => parse_po(catalog_source(language).unwrap_or_default()),
+        None => Vec::new(),
+    };
+    let mut out = io::stdout().lock();
+    writeln!(out, "# Messages of cosmic_ray_detector {}", env!("CARGO_PKG_VERSION"))?;
+    writeln!(out, "msgid \"\"")?;
+    writeln!(out, "msgstr \"\"")?;
+    writeln!(out, "{}", quote("Content-Type: text/plain; charset=UTF-8\n"))?;
+    if let Some(language) = &args.language {
+        writeln!(out, "{}", quote(&format!("Language: {}\n", language)))?;
+    }
+    let mut untranslated = 0;
+    for message in MESSAGES {
+        let translation = existing.iter().find(|(id, _)| id == message).map_or("", |(_, translation)| translation);
+        if translation.is_empty() {
+            untranslated += 1;
+        }
+        writeln!(out, "\nmsgid {}\nmsgstr {}", quote(message), quote(translation))?;
+    }
+    for (id, translation) in existing.iter().filter(|(id, _)| !MESSAGES.contains(&id.as_str())) {
+        writeln!(out, "\n#~ msgid {}\n#~ msgstr {}", quote(id), quote(translation))?;
+    }
+    if args.language.is_some() {
+        eprintln!("{} of {} messages are not translated yet", untranslated, MESSAGES.len());
+    }
+    Ok(())
+}
+
+/// Picks the catalog of `language`, or of the language the environment asks for like gettext does.
+/// Messages stay English when there is no catalog for it.
+pub fn init(language: Option<&str>) {
+    let language = language.map(str::to_string).or_else(|| {
+        ["LC_ALL", "LC_MESSAGES", "LANG"]
+            .iter()
+            .filter_map(|name| env::var(name).ok())
+            .find(|value| !value.is_empty())
+    });
+    let catalog = language
+        .and_then(|language| catalog_source(&language))
+        .map(|source| parse_po(source).into_iter().filter(|(_, translation)| !translation.is_empty()).collect())
+        .unwrap_or_default();
+    let _ = CATALOG.set(catalog);
+}
+
+/// The built in catalog for a locale such as `de_AT.UTF-8`.
+pub fn catalog_source(locale: &str) -> Option<&'static str> {
+    let language = locale.split(['_', '.', '@', '-']).next().unwrap_or_default().to_lowercase();
+    CATALOGS.iter().find(|(code, _)| *code == language).map(|(_, source)| *source)
+}
+
+pub fn translate(message: &'static str) -> &'static str {
+    CATALOG.get().and_then(|catalog| catalog.get(message)).map_or(message, String::as_str)
+}
+
+pub fn fill(template: &str, arguments: &[&dyn Display]) -> String {
+    let mut filled = String::with_capacity(template.len());
+    let mut next = 0;
+    let mut rest = template;
+    while let Some(position) = rest.find(['{', '}']) {
+        filled.push_str(&rest[..position]);
+        rest = &rest[position..];
+        if rest.starts_with("{{") || rest.starts_with("}}") {
+            filled.push_str(&rest[..1]);
+            rest = &rest[2..];
+            continue;
+        }
+        let placeholder = rest.find('}').filter(|_| rest.starts_with('{')).map(|end| &rest[1..end]);
+        let index = match placeholder {
+            Some("") => {
+                next += 1;
+                Some(next - 1)
+            }
+            Some(index) => index.parse().ok(),
+            None => None,
+        };
+        match (placeholder, index.and_then(|index| arguments.get(index))) {
+            (Some(placeholder), Some(argument)) => {
+                let _ = write!(filled, "{}", argument);
+                rest = &rest[placeholder.len() + 2..];
+            }
+            // Not a placeholder, or one without an argument, is kept as it is.
+            _ => {
+                filled.push_str(&rest[..1]);
+                rest = &rest[1..];
+            }
+        }
+    }
+    filled.push_str(rest);
+    filled
+}
+
+/// Reads the `msgid`/`msgstr` pairs of a gettext catalog.
+pub fn parse_po(source: &str) -> Vec<(String, String)> {
+    let mut entries = Vec::new();
+    let mut id: Option<String> = None;
+    let mut translation: Option<String> = None;
+    for line in source.lines().map(str::trim) {
+        if let Some(rest) = line.strip_prefix("msgid ") {
+            if let (Some(id), Some(translation)) = (id.take(), translation.take()) {
+                entries.push((id, translation));
+            }
+            id = Some(unquote(rest));
+        } else if let Some(rest) = line.strip_prefix("msgstr ") {
+            translation = Some(unquote(rest));
+        } else if line.starts_with('"') {
+            // A continuation of the msgid or msgstr above.
+            match (&mut id, &mut translation) {
+                (_, Some(translation)) => translation.push_str(&unquote(line)),
+                (Some(id), None) => id.push_str(&unquote(line)),
+                _ => {}
+            }
+        }
+    }
+    if let (Some(id), Some(translation)) = (id, translation) {
+        entries.push((id, translation));
+    }
+    // The header has an empty msgid.
+    entries.retain(|(id, _)| !id.is_empty());
+    entries
+}
+
+fn unquote(quoted: &str) -> String {
+    let quoted = quoted.trim();
+    let inner = quoted.strip_prefix('"').and_then(|quoted| quoted.strip_suffix('"')).unwrap_or(quoted);
+    let mut unquoted = String::with_capacity(inner.len());
+    let mut characters = inner.chars();
+    while let Some(character) = characters.next() {
+        match character {
+            '\\' => match characters.next() {
+                Some('n') => unquoted.push('\n'),
+                Some('t') => unquoted.push('\t'),
+                Some(other) => unquoted.push(other),
+                None => {}
+            },
+            other => unquoted.push(other),
+        }
+    }
+    unquoted
+}
+
+pub fn quote(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n"))
+}
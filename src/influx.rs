@@ -0,0 +1,71 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const INFLUX_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Writes detector statistics and events to InfluxDB using the line protocol.
+pub struct InfluxWriter {
+    url: String,
+    token: Option<String>,
+    station_id: String,
+}
+
+impl InfluxWriter {
+    /// `url` is the complete write endpoint, e.g. `http://localhost:8086/write?db=cosmic_rays`
+    /// or `http://localhost:8086/api/v2/write?org=home&bucket=cosmic_rays` together with a token.
+    pub fn new(url: String, token: Option<String>, station_id: String) -> Self {
+        InfluxWriter { url, token, station_id }
+    }
+
+    /// Reports how the detector is doing.
+    pub fn write_status(
+        &self,
+        checks_per_second: f64,
+        total_checks: u64,
+        detector_bytes: usize,
+        exposure_gb_hours: f64,
+    ) -> Result<(), String> {
+        self.write(&format!(
+            "cosmic_ray_detector,station={} checks_per_second={},total_checks={}i,detector_bytes={}i,exposure_gb_hours={} {}",
+            self.station_id,
+            checks_per_second,
+            total_checks,
+            detector_bytes,
+            exposure_gb_hours,
+            now_ns()
+        ))
+    }
+
+    /// Reports a detected bitflip.
+    pub fn write_flip(&self, checks: u64, vanished: bool) -> Result<(), String> {
+        self.write(&format!(
+            "cosmic_ray_flip,station={} checks={}i,vanished={} {}",
+            self.station_id,
+            checks,
+            vanished,
+            now_ns()
+        ))
+    }
+
+    /// Writes a point to a measurement of its own, to find out whether writing works at all.
+    pub fn write_test(&self) -> Result<(), String> {
+        self.write(&format!("cosmic_ray_preflight,station={} ok=true {}", self.station_id, now_ns()))
+    }
+
+    fn write(&self, line: &str) -> Result<(), String> {
+        let mut request = ureq::post(&self.url).timeout(INFLUX_TIMEOUT);
+        if let Some(token) = &self.token {
+            request = request.set("Authorization", &format!("Token {}", token));
+        }
+        request
+            .send_string(line)
+            .map_err(|e| format!("Unable to write to InfluxDB: {}", e))?;
+        Ok(())
+    }
+}
+
+fn now_ns() -> u128 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos()
+}
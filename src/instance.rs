@@ -0,0 +1,187 @@
+use std::env;
+use std::fs::{self, File, OpenOptions, TryLockError};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::collections::VecDeque;
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::process;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::record::Record;
+
+const LOCK_POLL_INTERVAL: Duration = Duration::from_millis(250);
+/// How many of the latest bitflips are kept for the metrics server and the LAN summary. A station hit by a flood of
+/// them would otherwise hold on to every one for as long as it runs.
+pub const MAX_EVENTS: usize = 10_000;
+
+/// Held for as long as an instance is running, there can only be one instance per station.
+pub struct InstanceLock {
+    _file: File,
+    info_path: PathBuf,
+}
+
+impl InstanceLock {
+    /// Takes the instance lock of the station, or returns `None` if another instance is holding it.
+    pub fn try_acquire(station_id: &str) -> Result<Option<Self>, String> {
+        let lock_path = runtime_path(station_id, "lock");
+        let file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(false)
+            .open(&lock_path)
+            .map_err(|e| format!("Unable to open {}: {}", lock_path.display(), e))?;
+
+        match file.try_lock() {
+            Ok(()) => Ok(Some(InstanceLock {
+                _file: file,
+                info_path: runtime_path(station_id, "ctl"),
+            })),
+            Err(TryLockError::WouldBlock) => Ok(None),
+            Err(TryLockError::Error(err)) => Err(format!("Unable to lock {}: {}", lock_path.display(), err)),
+        }
+    }
+
+    /// Waits for the running instance of the station to let go of the lock and takes it.
+    pub fn wait_for(station_id: &str, timeout: Duration) -> Result<Self, String> {
+        let start = Instant::now();
+        loop {
+            if let Some(lock) = InstanceLock::try_acquire(station_id)? {
+                return Ok(lock);
+            }
+            if start.elapsed() > timeout {
+                return Err(format!("The running instance did not shut down within {:?}", timeout));
+            }
+            thread::sleep(LOCK_POLL_INTERVAL);
+        }
+    }
+}
+
+impl Drop for InstanceLock {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.info_path);
+    }
+}
+
+/// Where the files of a station's instance live, one per station so that several stations can share a machine.
+fn runtime_path(station_id: &str, extension: &str) -> PathBuf {
+    env::temp_dir().join(format!("cosmic_ray_detector-{}.{}", station_id, extension))
+}
+
+/// Shared between the detection loop and the control server.
+#[derive(Default)]
+pub struct InstanceState {
+    pub station_id: String,
+    pub session_id: String,
+    pub shutdown: AtomicBool,
+    pub total_checks: AtomicU64,
+    pub flips: AtomicU64,
+    /// Scans that took far longer than usual.
+    pub stalls: AtomicU64,
+    /// The bits of the f64 current bitflip rate estimate in events per GB-day, see `rate_per_gb_day`.
+    pub rate_per_gb_day_bits: AtomicU64,
+    pub detector_size: AtomicUsize,
+    /// The latest bitflips detected during this session, up to `MAX_EVENTS` of them.
+    pub events: Mutex<VecDeque<Record>>,
+}
+
+impl InstanceState {
+    pub fn rate_per_gb_day(&self) -> f64 {
+        f64::from_bits(self.rate_per_gb_day_bits.load(Ordering::Relaxed))
+    }
+
+    pub fn set_rate_per_gb_day(&self, rate: f64) {
+        self.rate_per_gb_day_bits.store(rate.to_bits(), Ordering::Relaxed);
+    }
+
+    /// Keeps the bitflip, forgetting the oldest one kept once there are `MAX_EVENTS` of them.
+    pub fn push_event(&self, event: Record) {
+        let mut events = self.events.lock().unwrap_or_else(PoisonError::into_inner);
+        if events.len() >= MAX_EVENTS {
+            events.pop_front();
+        }
+        events.push_back(event);
+    }
+
+    pub fn shutdown_requested(&self) -> bool {
+        self.shutdown.load(Ordering::SeqCst)
+    }
+
+    /// Sleeps for the given duration, waking up early if a shutdown is requested.
+    /// Returns whether a shutdown was requested.
+    pub fn sleep(&self, duration: Duration) -> bool {
+        let end = Instant::now() + duration;
+        loop {
+            if self.shutdown_requested() {
+                return true;
+            }
+            let now = Instant::now();
+            if now >= end {
+                return false;
+            }
+            thread::sleep((end - now).min(LOCK_POLL_INTERVAL));
+        }
+    }
+}
+
+/// Listens on localhost for `ctl` commands in a background thread,
+/// and tells future `ctl` invocations where to find it through the info file.
+pub fn start_control_server(lock: &InstanceLock, state: Arc<InstanceState>) -> Result<(), String> {
+    let listener = TcpListener::bind("127.0.0.1:0").map_err(|e| format!("Unable to start control server: {}", e))?;
+    let address = listener.local_addr().map_err(|e| e.to_string())?;
+    fs::write(&lock.info_path, format!("{}\n{}\n", process::id(), address))
+        .map_err(|e| format!("Unable to write {}: {}", lock.info_path.display(), e))?;
+
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            // A misbehaving client should never take down the detector.
+            let _ = handle_client(stream, &state);
+        }
+    });
+    Ok(())
+}
+
+fn handle_client(stream: TcpStream, state: &InstanceState) -> io::Result<()> {
+    let mut command = String::new();
+    BufReader::new(&stream).read_line(&mut command)?;
+
+    let mut stream = stream;
+    match command.trim() {
+        "status" => write!(
+            stream,
+            "pid {}\ntotal_checks {}\nflips {}\ndetector_size {}\nstalls {}\nrate_per_gb_day {}\n",
+            process::id(),
+            state.total_checks.load(Ordering::Relaxed),
+            state.flips.load(Ordering::Relaxed),
+            state.detector_size.load(Ordering::Relaxed),
+            state.stalls.load(Ordering::Relaxed),
+            state.rate_per_gb_day(),
+        ),
+        "shutdown" => {
+            state.shutdown.store(true, Ordering::SeqCst);
+            writeln!(stream, "shutting down")
+        }
+        other => writeln!(stream, "unknown command {}", other),
+    }
+}
+
+/// Sends a command to the running instance of a station and returns its reply.
+pub fn send_command(station_id: &str, command: &str) -> Result<String, String> {
+    let info_path = runtime_path(station_id, "ctl");
+    let info = fs::read_to_string(&info_path)
+        .map_err(|_| format!("No instance seems to be running for station {}", station_id))?;
+    let address = info
+        .lines()
+        .nth(1)
+        .ok_or_else(|| format!("{} is malformed", info_path.display()))?;
+
+    let mut stream = TcpStream::connect(address).map_err(|e| format!("Unable to reach the running instance: {}", e))?;
+    writeln!(stream, "{}", command).map_err(|e| e.to_string())?;
+    stream.shutdown(Shutdown::Write).map_err(|e| e.to_string())?;
+
+    let mut reply = String::new();
+    stream.read_to_string(&mut reply).map_err(|e| e.to_string())?;
+    Ok(reply)
+}
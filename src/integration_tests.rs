@@ -0,0 +1,327 @@
+//! Drives the runner, the log and the upload pipeline together with a scripted detector and a local collector.
+
+use std::fs::{self, OpenOptions};
+use std::io;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, UNIX_EPOCH};
+
+use serde::Deserialize;
+
+use crate::{
+    community::{community_url, CommunityDataset},
+    config::{LogFormat, SyncPolicy},
+    detector::FlipInfo,
+    leaderboard::Leaderboard,
+    log_writer::LogWriter,
+    mock::{Corruption, MockDetector},
+    pipeline::{Event, Pipeline, Sinks},
+    record::{read_log, Record, SessionInfo, Tags},
+    runner::{FlipEvent, Runner},
+    signing::{self, StationKey},
+    state::StateFile,
+    upload::{UploadRecord, Uploader},
+};
+
+const STATION_ID: &str = "test-station";
+/// The script every test runs: a flip that stays, then one that vanishes, then passing checks up to `CHECKS`.
+const SCRIPT: [(u64, Corruption); 2] = [(3, Corruption::At { index: 42, value: 4 }), (7, Corruption::Vanished)];
+const CHECKS: u64 = 10;
+/// The `--tag`s of the station.
+const TAGS: [(&str, &str); 2] = [("room", "basement"), ("shielding", "none")];
+
+#[derive(Deserialize)]
+struct Batch {
+    station_id: String,
+    records: Vec<UploadRecord>,
+}
+
+/// A collector that checks signatures and acknowledges everything, keeping what it received.
+struct Collector {
+    url: String,
+    server: Arc<tiny_http::Server>,
+    received: Arc<Mutex<Vec<UploadRecord>>>,
+    bad_signatures: Arc<Mutex<u64>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Collector {
+    fn start() -> Self {
+        let server = Arc::new(tiny_http::Server::http("127.0.0.1:0").unwrap());
+        let url = format!("http://{}/upload", server.server_addr().to_ip().unwrap());
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let bad_signatures = Arc::new(Mutex::new(0));
+        let thread = {
+            let (server, received, bad_signatures) = (server.clone(), received.clone(), bad_signatures.clone());
+            thread::spawn(move || {
+                for mut request in server.incoming_requests() {
+                    let header = |name: &'static str| {
+                        request
+                            .headers()
+                            .iter()
+                            .find(|header| header.field.equiv(name))
+                            .map(|header| header.value.as_str().to_string())
+                            .unwrap_or_default()
+                    };
+                    let (public_key, signature) = (header("X-Station-Key"), header("X-Signature"));
+                    let mut body = Vec::new();
+                    request.as_reader().read_to_end(&mut body).unwrap();
+                    if signing::verify(&public_key, &body, &signature).is_err() {
+                        *bad_signatures.lock().unwrap() += 1;
+                    }
+                    let batch: Batch = serde_json::from_slice(&body).unwrap();
+                    assert_eq!(batch.station_id, STATION_ID);
+                    let acked: Vec<String> = batch.records.iter().map(|record| record.id.clone()).collect();
+                    received.lock().unwrap().extend(batch.records);
+                    let response = serde_json::json!({ "acked": acked }).to_string();
+                    let _ = request.respond(tiny_http::Response::from_string(response));
+                }
+            })
+        };
+        Collector { url, server, received, bad_signatures, thread: Some(thread) }
+    }
+}
+
+impl Drop for Collector {
+    fn drop(&mut self) {
+        self.server.unblock();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("cosmic_ray_detector-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn flip_record(event: &FlipEvent) -> Record {
+    let detected_ms = event.detected_at.duration_since(UNIX_EPOCH).unwrap().as_millis();
+    Record::Flip {
+        started_ms: detected_ms - event.window.as_millis(),
+        delay_ms: 0,
+        checks: event.checks,
+        vanished: event.index.is_none(),
+        detected_ms,
+        location: None,
+        uncertainty: None,
+        changed_bytes: Some(event.index.is_some() as u64),
+        // The mock detector is written with zeros.
+        diffs: event
+            .index
+            .zip(event.value)
+            .map(|(index, observed)| FlipInfo { index, expected: 0, observed, inverted: None }.into())
+            .into_iter()
+            .collect(),
+        warmup: false,
+        tags: Tags::new(),
+        sequence: None,
+    }
+}
+
+/// Runs the script through the runner into a log of the given format and the collector, returning what ended up
+/// in the log.
+fn run_script(name: &str, format: LogFormat, collector: &Collector) -> Vec<Record> {
+    let dir = temp_dir(name);
+    let log_path = dir.join("results.log");
+    let file = OpenOptions::new().create(true).append(true).open(&log_path).unwrap();
+    let key = StationKey::load_or_create(&dir.join("station.key")).unwrap();
+    let session = SessionInfo::current();
+
+    let mut pipeline = Pipeline::start(Sinks {
+        log: LogWriter::new(file, format, SyncPolicy::Always, Duration::ZERO).unwrap(),
+        state: Some(StateFile::open(dir.join("results.log.state")).unwrap()),
+        tags: station_tags(),
+        uploader: Some(Uploader::new(collector.url.clone(), STATION_ID.into(), session.clone(), key)),
+        sharer: None,
+        influx: None,
+        healthcheck: None,
+        exporter: None,
+        alert_webhook: None,
+        serial: None,
+        events_to_stdout: false,
+        heartbeats_to_stdout: false,
+        out: Box::new(io::sink()),
+    });
+    pipeline.send(Event::Log(vec![Record::Session(session)]));
+
+    let pipeline = Arc::new(Mutex::new(pipeline));
+    let stop = Arc::new(AtomicBool::new(false));
+    let (flip_pipeline, heartbeat_pipeline, heartbeat_stop) = (pipeline.clone(), pipeline.clone(), stop.clone());
+    Runner::new(MockDetector::new(SCRIPT.to_vec()), Duration::ZERO)
+        .on_flip(move |event| flip_pipeline.lock().unwrap().send(Event::Log(vec![flip_record(event)])))
+        .on_heartbeat(Duration::ZERO, move |heartbeat| {
+            let timestamp_ms = heartbeat.timestamp.duration_since(UNIX_EPOCH).unwrap().as_millis();
+            let record = Record::Heartbeat {
+                timestamp_ms,
+                total_checks: heartbeat.total_checks,
+                energy: None,
+                paging: None,
+                residency: None,
+                cadence: None,
+                smi: None,
+                tags: Tags::new(),
+                sequence: None,
+            };
+            heartbeat_pipeline.lock().unwrap().send(Event::Log(vec![record]));
+            if heartbeat.total_checks >= CHECKS {
+                heartbeat_stop.store(true, Ordering::Relaxed);
+            }
+        })
+        .run(&stop);
+
+    let pipeline = Arc::try_unwrap(pipeline).ok().unwrap().into_inner().unwrap();
+    pipeline.finish().unwrap();
+    let records = read_log(&log_path).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    records
+}
+
+fn station_tags() -> Tags {
+    TAGS.iter().map(|(key, value)| (key.to_string(), value.to_string())).collect()
+}
+
+/// The flips of the script, as (checks since the previous flip, vanished).
+fn flips(records: &[Record]) -> Vec<(u64, bool)> {
+    records
+        .iter()
+        .filter_map(|record| match record {
+            Record::Flip { checks, vanished, .. } => Some((*checks, *vanished)),
+            _ => None,
+        })
+        .collect()
+}
+
+fn heartbeat_checks(records: &[Record]) -> Vec<u64> {
+    records
+        .iter()
+        .filter_map(|record| match record {
+            Record::Heartbeat { total_checks, .. } => Some(*total_checks),
+            _ => None,
+        })
+        .collect()
+}
+
+fn assert_script_logged(records: &[Record]) {
+    assert!(matches!(records.first(), Some(Record::Session(_))));
+    assert_eq!(flips(records), vec![(2, false), (3, true)]);
+    assert_eq!(heartbeat_checks(records), vec![1, 2, 4, 5, 6, 8, 9, 10]);
+    // The flip that stayed has the bit it flipped, the one that vanished has nothing to compare.
+    let bits: Vec<Vec<Vec<u8>>> = records
+        .iter()
+        .filter_map(|record| match record {
+            Record::Flip { diffs, .. } => Some(diffs.iter().map(|diff| diff.bits.clone()).collect()),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(bits, vec![vec![vec![2]], vec![]]);
+    // Every flip is logged right after the heartbeat of the last check that passed before it.
+    let kinds: Vec<&str> = records
+        .iter()
+        .map(|record| match record {
+            Record::Session(_) => "session",
+            Record::Flip { .. } => "flip",
+            Record::Heartbeat { .. } => "heartbeat",
+            _ => "other",
+        })
+        .collect();
+    assert_eq!(kinds[3], "flip");
+    assert_eq!(kinds[7], "flip");
+    // Everything but the session is numbered without gaps.
+    let sequences: Vec<Option<u64>> = records[1..].iter().map(Record::sequence).collect();
+    assert_eq!(sequences, (0..records.len() as u64 - 1).map(Some).collect::<Vec<_>>());
+    // And carries the station's tags.
+    assert!(records[1..].iter().all(|record| record.tags() == Some(&station_tags())));
+}
+
+#[test]
+fn scripted_flips_reach_the_csv_log_and_the_collector() {
+    let collector = Collector::start();
+    let records = run_script("csv", LogFormat::Csv, &collector);
+    assert_script_logged(&records);
+
+    let received = collector.received.lock().unwrap();
+    assert_eq!(*collector.bad_signatures.lock().unwrap(), 0);
+    assert_eq!(received.iter().map(|record| record.sequence).collect::<Vec<_>>(), (0..records.len() as u64).collect::<Vec<_>>());
+    let uploaded: Vec<Record> = received.iter().map(|record| record.record.clone()).collect();
+    assert_eq!(flips(&uploaded), flips(&records));
+    assert_eq!(heartbeat_checks(&uploaded), heartbeat_checks(&records));
+    assert!(uploaded[1..].iter().all(|record| record.tags() == Some(&station_tags())));
+}
+
+#[test]
+fn scripted_flips_reach_the_binary_log() {
+    let collector = Collector::start();
+    let records = run_script("binary", LogFormat::Binary, &collector);
+    assert_script_logged(&records);
+    assert_eq!(collector.received.lock().unwrap().len(), records.len());
+}
+
+#[test]
+fn scripted_flips_reach_the_json_lines_log() {
+    let collector = Collector::start();
+    let records = run_script("jsonl", LogFormat::Jsonl, &collector);
+    assert_script_logged(&records);
+    assert_eq!(collector.received.lock().unwrap().len(), records.len());
+
+    // Every line stands on its own, with the kind of record and every field named.
+    let path = temp_dir("jsonl-lines").join("results.log");
+    let file = OpenOptions::new().create(true).append(true).open(&path).unwrap();
+    let mut writer = LogWriter::new(file, LogFormat::Jsonl, SyncPolicy::Always, Duration::ZERO).unwrap();
+    let flip = records.iter().find(|record| matches!(record, Record::Flip { .. })).unwrap();
+    writer.write(&[flip]).unwrap();
+    writer.flush().unwrap();
+    let line: serde_json::Value = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+    assert_eq!(line["type"], "flip");
+    assert_eq!(line["diffs"][0]["index"], 42);
+}
+
+#[test]
+fn the_mock_detector_holds_corruption_until_reset() {
+    use crate::detector::DetectorMemory;
+
+    let mut detector = MockDetector::new(vec![(2, Corruption::At { index: 7, value: 1 })]);
+    assert!(detector.is_intact());
+    assert!(!detector.is_intact());
+    assert!(!detector.is_intact());
+    assert_eq!(detector.find_index_of_changed_element(), Some(7));
+    assert_eq!(detector.get(7), Some(1));
+    detector.reset();
+    assert!(detector.is_intact());
+    assert_eq!(detector.find_index_of_changed_element(), None);
+}
+
+#[test]
+fn community_rates_are_grouped_by_altitude_band_and_memory() {
+    let mut leaderboard = Leaderboard::default();
+    for (station, altitude_m, memory_type) in [("a", 1500.0, "DDR4"), ("b", 1200.0, "DDR4"), ("c", 1900.0, "DDR5")] {
+        let mut session = SessionInfo::current();
+        session.detector_bytes = Some(1 << 30);
+        session.altitude_m = Some(altitude_m);
+        session.hardware.memory_type = Some(memory_type.to_string());
+        leaderboard.add(station, &format!("{}:0", station), &Record::Session(session));
+        for (n, started_ms) in [0, 3_600_000].into_iter().enumerate() {
+            let start =
+                Record::Start { started_ms, delay_ms: 0, pid: None, location: None, tags: Tags::new(), sequence: None };
+            leaderboard.add(station, &format!("{}:{}", station, n + 1), &start);
+        }
+    }
+    let dataset = CommunityDataset { source: String::new(), generated_ms: 0, rates: leaderboard.community_rates() };
+    assert_eq!(dataset.rates.len(), 2);
+    assert!(dataset.rates.iter().all(|rate| rate.band == "1000-2000 m"));
+
+    let ddr4 = dataset.comparable("1000-2000 m", "DDR4").unwrap();
+    assert_eq!((ddr4.memory_type.as_str(), ddr4.stations), ("DDR4", 2));
+    let ddr3 = dataset.comparable("1000-2000 m", "DDR3").unwrap();
+    assert_eq!((ddr3.memory_type.as_str(), ddr3.stations), ("any", 3));
+    assert_eq!(ddr3.flips_per_gb_hour, Some(0.0));
+    assert!(dataset.comparable("0-1000 m", "DDR4").is_none());
+
+    assert_eq!(community_url("http://collector:8080/upload"), "http://collector:8080/community");
+    assert_eq!(community_url("http://collector:8080/"), "http://collector:8080/community");
+}
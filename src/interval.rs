@@ -0,0 +1,97 @@
+use std::io::{self, Write};
+
+/// The flux of the neutrons that cause most flips grows roughly e-fold every this many meters of altitude,
+/// over the altitudes people live at.
+const ALTITUDE_SCALE_M: f64 = 1300.0;
+const BYTES_PER_GB: f64 = 1e9;
+
+/// How many times more flips to expect at an altitude than at sea level.
+pub fn altitude_factor(altitude_m: f64) -> f64 {
+    (altitude_m.max(0.0) / ALTITUDE_SCALE_M).exp()
+}
+
+/// How the time between checks compares to the time between flips expected for the detector.
+pub struct IntervalAdvice {
+    pub detector_gb: f64,
+    pub altitude_m: f64,
+    pub altitude_factor: f64,
+    pub sea_level_rate: f64,
+    /// Expected flips per hour in the whole detector.
+    pub flips_per_hour: f64,
+    /// The longest time between two checks, in milliseconds.
+    pub window_ms: u64,
+    /// The fraction of the expected time between flips that the window may take up.
+    pub fraction: f64,
+}
+
+impl IntervalAdvice {
+    /// `sea_level_rate` is in flips per GB-hour. Stations without a known altitude are taken to be at sea level.
+    pub fn new(
+        detector_bytes: usize,
+        altitude_m: Option<f64>,
+        sea_level_rate: f64,
+        delay_ms: u64,
+        jitter_ms: u64,
+        fraction: f64,
+    ) -> Self {
+        let detector_gb = detector_bytes as f64 / BYTES_PER_GB;
+        let altitude_m = altitude_m.unwrap_or(0.0);
+        let altitude_factor = altitude_factor(altitude_m);
+        IntervalAdvice {
+            detector_gb,
+            altitude_m,
+            altitude_factor,
+            sea_level_rate,
+            flips_per_hour: sea_level_rate * altitude_factor * detector_gb,
+            window_ms: delay_ms + jitter_ms,
+            fraction,
+        }
+    }
+
+    /// The expected time between two flips, in milliseconds.
+    pub fn expected_spacing_ms(&self) -> f64 {
+        3_600_000.0 / self.flips_per_hour
+    }
+
+    /// Whether the time between checks is too large a part of the time between flips, which makes the
+    /// timestamps mostly a matter of when the checks happened and lets flips pile up in one window.
+    pub fn is_too_long(&self) -> bool {
+        self.window_ms as f64 > self.fraction * self.expected_spacing_ms()
+    }
+
+    /// The longest delay between checks that stays within the fraction, given the jitter.
+    pub fn suggested_delay_ms(&self, jitter_ms: u64) -> u64 {
+        ((self.fraction * self.expected_spacing_ms()) as u64).saturating_sub(jitter_ms)
+    }
+
+    /// Explains how the expected rate was worked out.
+    pub fn print(&self, out: &mut dyn Write) -> io::Result<()> {
+        writeln!(
+            out,
+            "Expecting {:.3e} flips per hour: {} flips per GB-hour at sea level x {:.2} for {} m altitude x {:.2} GB, one every {}",
+            self.flips_per_hour,
+            self.sea_level_rate,
+            self.altitude_factor,
+            self.altitude_m,
+            self.detector_gb,
+            human_duration(self.expected_spacing_ms())
+        )?;
+        writeln!(
+            out,
+            "Up to {} ms between checks is {:.4}% of that, the limit is {}%",
+            self.window_ms,
+            100.0 * self.window_ms as f64 / self.expected_spacing_ms(),
+            100.0 * self.fraction
+        )
+    }
+}
+
+fn human_duration(ms: f64) -> String {
+    let hours = ms / 3_600_000.0;
+    match hours {
+        hours if !hours.is_finite() => "never".into(),
+        hours if hours >= 48.0 => format!("{:.1} days", hours / 24.0),
+        hours if hours >= 1.0 => format!("{:.1} hours", hours),
+        _ => format!("{:.1} seconds", ms / 1000.0),
+    }
+}
@@ -0,0 +1,131 @@
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::atomic::Ordering;
+use std::sync::PoisonError;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use mdns_sd::{ServiceDaemon, ServiceEvent, ServiceInfo};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::instance::InstanceState;
+
+/// The mDNS service type stations advertise themselves under on the local network.
+const SERVICE_TYPE: &str = "_cosmic-ray._tcp.local.";
+const SUMMARY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// What a station tells the machine aggregating the data of a lab.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct LanSummary {
+    pub station_id: String,
+    pub session_id: String,
+    /// The station's clock when the summary was made, used to estimate its offset.
+    pub station_time_ms: u64,
+    pub total_checks: u64,
+    pub flips: u64,
+    pub detector_size: usize,
+    /// The latest bitflips of the session, in the same form as the log records.
+    pub events: Vec<Value>,
+}
+
+impl LanSummary {
+    pub fn new(state: &InstanceState) -> Self {
+        let events = state.events.lock().unwrap_or_else(PoisonError::into_inner);
+        LanSummary {
+            station_id: state.station_id.clone(),
+            session_id: state.session_id.clone(),
+            station_time_ms: unix_ms(),
+            total_checks: state.total_checks.load(Ordering::Relaxed),
+            flips: state.flips.load(Ordering::Relaxed),
+            detector_size: state.detector_size.load(Ordering::Relaxed),
+            events: events.iter().filter_map(|event| serde_json::to_value(event).ok()).collect(),
+        }
+    }
+}
+
+/// Advertises the station's summary endpoint on the local network for as long as the returned daemon lives.
+pub fn advertise(station_id: &str, port: u16) -> Result<ServiceDaemon, String> {
+    let daemon = ServiceDaemon::new().map_err(|e| format!("Unable to start mDNS: {}", e))?;
+    let host_name = format!("{}.local.", station_id);
+    let properties = [("station_id", station_id)];
+    let service = ServiceInfo::new(SERVICE_TYPE, station_id, &host_name, "", port, &properties[..])
+        .map_err(|e| format!("Unable to describe the mDNS service: {}", e))?
+        .enable_addr_auto();
+    daemon
+        .register(service)
+        .map_err(|e| format!("Unable to advertise over mDNS: {}", e))?;
+    Ok(daemon)
+}
+
+/// Looks for stations on the local network for the given time, returning where to reach each of them.
+pub fn discover(timeout: Duration) -> Result<HashMap<String, SocketAddr>, String> {
+    let daemon = ServiceDaemon::new().map_err(|e| format!("Unable to start mDNS: {}", e))?;
+    let receiver = daemon
+        .browse(SERVICE_TYPE)
+        .map_err(|e| format!("Unable to browse mDNS: {}", e))?;
+
+    let mut stations = HashMap::new();
+    let end = Instant::now() + timeout;
+    while let Some(remaining) = end.checked_duration_since(Instant::now()) {
+        match receiver.recv_timeout(remaining) {
+            Ok(ServiceEvent::ServiceResolved(info)) => {
+                let station_id = info
+                    .get_property_val_str("station_id")
+                    .unwrap_or_else(|| info.get_fullname())
+                    .to_string();
+                // Prefer IPv4, link local IPv6 addresses need a scope to be reachable.
+                let address = info
+                    .get_addresses()
+                    .iter()
+                    .copied()
+                    .min_by_key(|address| !matches!(address, IpAddr::V4(_)));
+                if let Some(address) = address {
+                    stations.insert(station_id, SocketAddr::new(address, info.get_port()));
+                }
+            }
+            Ok(_) => {}
+            Err(_) => break,
+        }
+    }
+    let _ = daemon.shutdown();
+    Ok(stations)
+}
+
+/// A summary fetched from a station, with an NTP style estimate of how far its clock is off from ours.
+#[derive(Serialize, Debug)]
+pub struct SyncedSummary {
+    pub received_ms: u64,
+    pub address: String,
+    /// Add this to the station's timestamps to get ours.
+    pub clock_offset_ms: i64,
+    pub round_trip_ms: u64,
+    pub summary: LanSummary,
+}
+
+pub fn fetch_summary(address: SocketAddr) -> Result<SyncedSummary, String> {
+    let sent_ms = unix_ms();
+    let summary: LanSummary = ureq::get(&format!("http://{}/summary", address))
+        .timeout(SUMMARY_TIMEOUT)
+        .call()
+        .map_err(|e| e.to_string())?
+        .into_json()
+        .map_err(|e| format!("Malformed summary: {}", e))?;
+    let received_ms = unix_ms();
+
+    // Assume the summary was made halfway through the round trip.
+    let midpoint_ms = (sent_ms + received_ms) / 2;
+    Ok(SyncedSummary {
+        received_ms,
+        address: address.to_string(),
+        clock_offset_ms: midpoint_ms as i64 - summary.station_time_ms as i64,
+        round_trip_ms: received_ms - sent_ms,
+        summary,
+    })
+}
+
+fn unix_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
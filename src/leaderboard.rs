@@ -0,0 +1,215 @@
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{exposure::gb_hours, record::Record};
+
+/// Stations are compared within altitude bands this many meters high, since the flux grows quickly with altitude.
+const ALTITUDE_BAND_M: f64 = 1000.0;
+
+/// What the collector has seen of a single session, sessions are identified by the first part of the record IDs.
+#[derive(Default)]
+struct SessionStats {
+    station_id: String,
+    detector_bytes: Option<u64>,
+    altitude_m: Option<f64>,
+    memory_type: Option<String>,
+    first_ms: Option<u64>,
+    last_ms: Option<u64>,
+    flips: u64,
+}
+
+impl SessionStats {
+    fn hours(&self) -> f64 {
+        match (self.first_ms, self.last_ms) {
+            (Some(first), Some(last)) => (last - first) as f64 / 3_600_000.0,
+            _ => 0.0,
+        }
+    }
+
+    /// Sessions of older stations don't say how large their detector was, they count as no exposure.
+    fn gb_hours(&self) -> f64 {
+        match (self.detector_bytes, self.first_ms, self.last_ms) {
+            (Some(bytes), Some(first), Some(last)) => gb_hours(bytes as usize, Duration::from_millis(last - first)),
+            _ => 0.0,
+        }
+    }
+}
+
+#[derive(Serialize, Default)]
+pub struct StationStanding {
+    pub station_id: String,
+    pub sessions: u64,
+    pub flips: u64,
+    pub hours: f64,
+    pub gb_hours: f64,
+    /// Missing until the station has some exposure.
+    pub flips_per_gb_hour: Option<f64>,
+}
+
+#[derive(Serialize, Default)]
+pub struct AltitudeBand {
+    /// E.g. `1000-2000 m`, or `unknown` for stations that haven't said how high up they are.
+    pub band: String,
+    pub stations: u64,
+    pub flips: u64,
+    pub gb_hours: f64,
+    pub flips_per_gb_hour: Option<f64>,
+}
+
+/// The rate of the stations at the same altitude band with the same memory generation, the public aggregate other
+/// stations compare their own rate with.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct CommunityRate {
+    /// See `AltitudeBand::band`.
+    pub band: String,
+    /// E.g. `DDR4`, or `unknown` for stations that couldn't tell.
+    pub memory_type: String,
+    pub stations: u64,
+    pub flips: u64,
+    pub gb_hours: f64,
+    pub flips_per_gb_hour: Option<f64>,
+}
+
+#[derive(Serialize)]
+pub struct Summary {
+    pub stations: Vec<StationStanding>,
+    pub altitude_bands: Vec<AltitudeBand>,
+}
+
+/// Aggregates the records received by the collector into standings for a community leaderboard.
+#[derive(Default)]
+pub struct Leaderboard {
+    sessions: HashMap<String, SessionStats>,
+}
+
+impl Leaderboard {
+    /// Accounts for a stored record, `record_id` is the `<session ID>:<sequence>` ID it was uploaded with.
+    pub fn add(&mut self, station_id: &str, record_id: &str, record: &Record) {
+        let session_id = record_id.rsplit_once(':').map_or(record_id, |(session_id, _)| session_id);
+        let session = self.sessions.entry(session_id.to_string()).or_default();
+        session.station_id = station_id.to_string();
+
+        let timestamp = match record {
+            Record::Session(info) => {
+                session.detector_bytes = info.detector_bytes;
+                session.altitude_m = info.altitude_m;
+                session.memory_type = info.hardware.memory_type.clone();
+                None
+            }
+            Record::Start { started_ms, .. } => Some(*started_ms),
+            Record::Flip { detected_ms, .. } => {
+                session.flips += 1;
+                Some(*detected_ms)
+            }
+            Record::Heartbeat { timestamp_ms, .. }
+            | Record::MemoryErrors { timestamp_ms, .. }
+            | Record::Dropped { timestamp_ms, .. }
+            | Record::Disagreement { timestamp_ms, .. }
+            | Record::Panic { timestamp_ms, .. }
+            | Record::Shutdown { timestamp_ms, .. } => Some(*timestamp_ms),
+        };
+        if let Some(timestamp) = timestamp {
+            let timestamp = timestamp as u64;
+            session.first_ms = Some(session.first_ms.map_or(timestamp, |first| first.min(timestamp)));
+            session.last_ms = Some(session.last_ms.map_or(timestamp, |last| last.max(timestamp)));
+        }
+    }
+
+    /// The standings of every station, most flips first, and the rates per altitude band.
+    pub fn summary(&self) -> Summary {
+        let mut stations: BTreeMap<&str, StationStanding> = BTreeMap::new();
+        let mut bands: BTreeMap<Option<i64>, (AltitudeBand, Vec<&str>)> = BTreeMap::new();
+        for session in self.sessions.values() {
+            let standing = stations.entry(&session.station_id).or_default();
+            standing.station_id = session.station_id.clone();
+            standing.sessions += 1;
+            standing.flips += session.flips;
+            standing.hours += session.hours();
+            standing.gb_hours += session.gb_hours();
+
+            let band_start = session.altitude_m.map(band_start);
+            let (band, band_stations) = bands.entry(band_start).or_default();
+            band.flips += session.flips;
+            band.gb_hours += session.gb_hours();
+            if !band_stations.contains(&session.station_id.as_str()) {
+                band_stations.push(&session.station_id);
+            }
+        }
+
+        let mut stations: Vec<StationStanding> = stations.into_values().collect();
+        for standing in &mut stations {
+            standing.flips_per_gb_hour = rate(standing.flips, standing.gb_hours);
+        }
+        stations.sort_by(|a, b| {
+            b.flips
+                .cmp(&a.flips)
+                .then(b.gb_hours.partial_cmp(&a.gb_hours).unwrap_or(Ordering::Equal))
+        });
+
+        let altitude_bands = bands
+            .into_iter()
+            .map(|(band_start, (band, band_stations))| AltitudeBand {
+                band: band_name(band_start),
+                stations: band_stations.len() as u64,
+                flips_per_gb_hour: rate(band.flips, band.gb_hours),
+                ..band
+            })
+            .collect();
+
+        Summary { stations, altitude_bands }
+    }
+
+    /// The rates per altitude band and memory generation, the ones with most exposure first within a band.
+    pub fn community_rates(&self) -> Vec<CommunityRate> {
+        let mut groups: BTreeMap<_, (CommunityRate, Vec<&str>)> = BTreeMap::new();
+        for session in self.sessions.values() {
+            let key = (session.altitude_m.map(band_start), session.memory_type.as_deref());
+            let (group, group_stations) = groups.entry(key).or_default();
+            group.flips += session.flips;
+            group.gb_hours += session.gb_hours();
+            if !group_stations.contains(&session.station_id.as_str()) {
+                group_stations.push(&session.station_id);
+            }
+        }
+        let mut rates: Vec<(Option<i64>, CommunityRate)> = groups
+            .into_iter()
+            .map(|((band_start, memory_type), (group, group_stations))| {
+                let rate = CommunityRate {
+                    band: band_name(band_start),
+                    memory_type: memory_type.unwrap_or("unknown").to_string(),
+                    stations: group_stations.len() as u64,
+                    flips_per_gb_hour: rate(group.flips, group.gb_hours),
+                    ..group
+                };
+                (band_start, rate)
+            })
+            .collect();
+        rates.sort_by(|(a_band, a), (b_band, b)| {
+            a_band.cmp(b_band).then(b.gb_hours.partial_cmp(&a.gb_hours).unwrap_or(Ordering::Equal))
+        });
+        rates.into_iter().map(|(_, rate)| rate).collect()
+    }
+}
+
+/// The altitude band the given altitude is in, e.g. `1000-2000 m`, or `unknown` when the altitude isn't known.
+pub fn altitude_band(altitude_m: Option<f64>) -> String {
+    band_name(altitude_m.map(band_start))
+}
+
+fn band_start(altitude_m: f64) -> i64 {
+    (altitude_m / ALTITUDE_BAND_M).floor() as i64 * ALTITUDE_BAND_M as i64
+}
+
+fn band_name(band_start: Option<i64>) -> String {
+    match band_start {
+        Some(start) => format!("{}-{} m", start, start + ALTITUDE_BAND_M as i64),
+        None => "unknown".into(),
+    }
+}
+
+fn rate(flips: u64, gb_hours: f64) -> Option<f64> {
+    (gb_hours > 0.0).then(|| flips as f64 / gb_hours)
+}
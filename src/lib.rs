@@ -0,0 +1,38 @@
+//! The detector and what it logs, for monitoring agents that embed it. The program itself is a command line
+//! wrapper around this library, and the C and Python bindings are built from it as well.
+//!
+//! [`detector::Detector`] is the memory that is checked for bitflips, [`runner::Runner`] checks it on a thread and
+//! calls back for every flip, [`builder::RunnerBuilder`] puts the two together from typed settings, [`fill`] finds
+//! out how large a detector the machine has room for, and [`record::Record`] and [`log_writer::LogWriter`] are the
+//! log the program keeps, in any of its formats.
+
+pub mod binlog;
+pub mod builder;
+pub mod clock;
+pub mod config;
+pub mod detector;
+pub mod ffi;
+pub mod fill;
+pub mod i18n;
+pub mod log_writer;
+pub mod mock;
+pub mod record;
+pub mod rng;
+pub mod runner;
+#[cfg(feature = "sqlite")]
+pub mod sqlite_log;
+pub mod station;
+#[cfg(feature = "tokio")]
+pub mod stream;
+
+/// Get human readable byte sizes
+pub fn mem_size(mem_size: u64) -> String {
+    let mut mem_units: Vec<&str> = vec![" TiB", " GiB", " MiB", " KiB", " B"];
+    let mut mem_size: f32 = mem_size as f32;
+    let mut unit: &str = mem_units.pop().unwrap();
+    while mem_size > 1024.0 && !mem_units.is_empty() {
+        mem_size /= 1024.0;
+        unit = mem_units.pop().unwrap();
+    }
+    mem_size.to_string() + unit
+}
@@ -0,0 +1,54 @@
+use std::fs::{File, OpenOptions, TryLockError};
+use std::path::Path;
+use std::process;
+
+use crate::config::LockedLogPolicy;
+
+/// Opens the log file for appending and takes an exclusive advisory lock on it,
+/// so that two instances never interleave their entries in the same file.
+/// Returns the open file together with the path that was actually used.
+pub fn open_locked_log(file_path: &str, policy: LockedLogPolicy) -> Result<(File, String), String> {
+    let file = open_for_append(file_path)?;
+    match file.try_lock() {
+        Ok(()) => return Ok((file, file_path.to_string())),
+        Err(TryLockError::WouldBlock) => {}
+        Err(TryLockError::Error(err)) => return Err(format!("Unable to lock {}: {}", file_path, err)),
+    }
+
+    match policy {
+        LockedLogPolicy::Refuse => Err(format!(
+            "The log file {} is in use by another instance, refusing to start",
+            file_path
+        )),
+        LockedLogPolicy::PerPid => {
+            let pid_path = per_pid_path(file_path, process::id());
+            eprintln!(
+                "The log file {} is in use by another instance, logging to {} instead",
+                file_path, pid_path
+            );
+            let file = open_for_append(&pid_path)?;
+            file.try_lock().map_err(|e| format!("Unable to lock {}: {}", pid_path, e))?;
+            Ok((file, pid_path))
+        }
+    }
+}
+
+fn open_for_append(file_path: &str) -> Result<File, String> {
+    // Readable as well, for telling whether it is a database.
+    OpenOptions::new()
+        .create(true)
+        .read(true)
+        .append(true)
+        .open(file_path)
+        .map_err(|e| format!("Unable to open {}: {}", file_path, e))
+}
+
+/// Puts the PID in front of the extension, `results.txt` becomes `results.1234.txt`.
+fn per_pid_path(file_path: &str, pid: u32) -> String {
+    let path = Path::new(file_path);
+    let extension = match path.extension() {
+        Some(extension) => format!("{}.{}", pid, extension.to_string_lossy()),
+        None => pid.to_string(),
+    };
+    path.with_extension(extension).to_string_lossy().into_owned()
+}
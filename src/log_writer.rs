@@ -0,0 +1,155 @@
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+#[cfg(feature = "sqlite")]
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use crate::{
+    binlog,
+    config::{LogFormat, SyncPolicy},
+    record::{Record, SQLITE_MAGIC},
+};
+#[cfg(feature = "sqlite")]
+use crate::sqlite_log::SqliteLog;
+
+/// Appends records to the log file in the configured format, syncing them to the disk as often as the policy says.
+pub struct LogWriter {
+    file: File,
+    format: LogFormat,
+    encoder: binlog::BinaryEncoder,
+    sync_policy: SyncPolicy,
+    sync_interval: Duration,
+    /// Whether something was written since the last sync.
+    unsynced: bool,
+    last_sync: Instant,
+    /// How long heartbeats may be held back in memory, if they are.
+    heartbeat_batching: Option<Duration>,
+    /// Encoded heartbeats that haven't been written yet, and since when.
+    held_back: Vec<u8>,
+    held_back_since: Option<Instant>,
+    /// The database the records go to instead of the file with --log-backend sqlite. The file stays open for the
+    /// lock on it.
+    #[cfg(feature = "sqlite")]
+    database: Option<SqliteLog>,
+}
+
+impl LogWriter {
+    /// Refuses a file that holds a --log-backend sqlite database, appending to it would break the database. A file
+    /// opened for writing only can't be checked.
+    pub fn new(mut file: File, format: LogFormat, sync_policy: SyncPolicy, sync_interval: Duration) -> io::Result<Self> {
+        let mut magic = [0; SQLITE_MAGIC.len()];
+        if file.seek(SeekFrom::Start(0)).and_then(|_| file.read_exact(&mut magic)).is_ok() && magic == SQLITE_MAGIC {
+            return Err(io::Error::other("The log file is a SQLite database, log to it with --log-backend sqlite"));
+        }
+        if format == LogFormat::Binary && file.metadata()?.len() == 0 {
+            file.write_all(&binlog::header())?;
+        }
+        Ok(Self::with_file(file, format, sync_policy, sync_interval))
+    }
+
+    fn with_file(file: File, format: LogFormat, sync_policy: SyncPolicy, sync_interval: Duration) -> Self {
+        LogWriter {
+            file,
+            format,
+            encoder: binlog::BinaryEncoder::default(),
+            sync_policy,
+            sync_interval,
+            unsynced: false,
+            last_sync: Instant::now(),
+            heartbeat_batching: None,
+            held_back: Vec::new(),
+            held_back_since: None,
+            #[cfg(feature = "sqlite")]
+            database: None,
+        }
+    }
+
+    /// Writes the records into the SQLite database at the given path, the locked log file opened on it. Every write is
+    /// a transaction that SQLite syncs itself, so the sync policy and heartbeat batching don't apply.
+    #[cfg(feature = "sqlite")]
+    pub fn sqlite(file: File, path: &Path) -> Result<Self, String> {
+        let database = SqliteLog::open(path)?;
+        let mut writer = Self::with_file(file, LogFormat::Csv, SyncPolicy::Never, Duration::ZERO);
+        writer.database = Some(database);
+        Ok(writer)
+    }
+
+    /// Holds heartbeats back in memory and only writes them along with the next other record, or once they have
+    /// been held back for the given time. Saves flash storage from a write every few seconds.
+    pub fn set_heartbeat_batching(&mut self, flush_interval: Option<Duration>) {
+        self.heartbeat_batching = flush_interval;
+    }
+
+    /// Writes the records in a single write, so that they end up next to each other.
+    pub fn write(&mut self, records: &[&Record]) -> io::Result<()> {
+        #[cfg(feature = "sqlite")]
+        if let Some(database) = &mut self.database {
+            return database.append(records).map_err(io::Error::other);
+        }
+        // Binary records are delta encoded, so they are encoded right away to keep the deltas in order.
+        let mut bytes = Vec::new();
+        for record in records {
+            match self.format {
+                LogFormat::Csv => bytes.extend_from_slice(record.to_csv().as_bytes()),
+                LogFormat::Binary => bytes.extend(self.encoder.encode(record)),
+                LogFormat::Jsonl => {
+                    serde_json::to_writer(&mut bytes, record)?;
+                    bytes.push(b'\n');
+                }
+            }
+        }
+        let only_heartbeats = records.iter().all(|record| matches!(record, Record::Heartbeat { .. }));
+        if self.heartbeat_batching.is_some() && only_heartbeats {
+            self.held_back.extend(bytes);
+            self.held_back_since.get_or_insert_with(Instant::now);
+            return self.sync_if_due();
+        }
+        self.write_with_held_back(bytes)?;
+        self.sync_if_due()
+    }
+
+    /// Writes the held back heartbeats followed by the given bytes.
+    fn write_with_held_back(&mut self, bytes: Vec<u8>) -> io::Result<()> {
+        let mut all = std::mem::take(&mut self.held_back);
+        all.extend(bytes);
+        self.held_back_since = None;
+        if all.is_empty() {
+            return Ok(());
+        }
+        self.file.write_all(&all)?;
+        self.file.flush()?;
+        self.unsynced = true;
+        Ok(())
+    }
+
+    /// Writes heartbeats that have been held back for long enough and syncs what was written if the policy says
+    /// it is time. Should also be called regularly when nothing is written, so that nothing waits for long.
+    pub fn sync_if_due(&mut self) -> io::Result<()> {
+        if let (Some(flush_interval), Some(since)) = (self.heartbeat_batching, self.held_back_since) {
+            if since.elapsed() >= flush_interval {
+                self.write_with_held_back(Vec::new())?;
+            }
+        }
+        match self.sync_policy {
+            SyncPolicy::Always => self.sync(),
+            SyncPolicy::Interval if self.last_sync.elapsed() >= self.sync_interval => self.sync(),
+            SyncPolicy::Interval | SyncPolicy::Never => Ok(()),
+        }
+    }
+
+    /// Makes sure everything written so far has reached the disk, whatever the policy.
+    pub fn sync(&mut self) -> io::Result<()> {
+        if self.unsynced {
+            self.file.sync_data()?;
+            self.unsynced = false;
+        }
+        self.last_sync = Instant::now();
+        Ok(())
+    }
+
+    /// Writes the held back heartbeats and syncs everything, for shutting down.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.write_with_held_back(Vec::new())?;
+        self.sync()
+    }
+}
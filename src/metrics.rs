@@ -0,0 +1,195 @@
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, PoisonError};
+use std::thread;
+
+use serde_json::{json, Value};
+use tiny_http::{Header, Method, Request, Response, Server};
+
+use crate::{
+    exposure::days_from_civil,
+    instance::InstanceState,
+    lan::LanSummary,
+    record::{FlipDiff, Record},
+};
+
+/// Serves the state of the running instance over HTTP in a background thread:
+/// `/metrics` in the Prometheus text format, `/annotations` for the Grafana simple-json datasource,
+/// and `/summary` for LAN sync. Returns the port it listens on.
+pub fn start_metrics_server(address: &str, state: Arc<InstanceState>) -> Result<u16, String> {
+    let server = Server::http(address).map_err(|e| format!("Unable to serve metrics on {}: {}", address, e))?;
+    let port = server
+        .server_addr()
+        .to_ip()
+        .map(|address| address.port())
+        .ok_or("The metrics server is not listening on an IP address")?;
+    thread::spawn(move || {
+        for request in server.incoming_requests() {
+            // A misbehaving client should never take down the detector.
+            let _ = handle_request(request, &state);
+        }
+    });
+    Ok(port)
+}
+
+fn handle_request(mut request: Request, state: &InstanceState) -> std::io::Result<()> {
+    match (request.method(), request.url()) {
+        (Method::Get, "/metrics") => request.respond(Response::from_string(metrics(state))),
+        (Method::Post, "/annotations") => {
+            let mut body = String::new();
+            request.as_reader().read_to_string(&mut body)?;
+            let query: Value = serde_json::from_str(&body).unwrap_or(Value::Null);
+            let annotations = annotations(state, &query);
+            request.respond(Response::from_string(annotations.to_string()).with_header(json_header()))
+        }
+        (Method::Get, "/summary") => {
+            let summary = serde_json::to_string(&LanSummary::new(state))?;
+            request.respond(Response::from_string(summary).with_header(json_header()))
+        }
+        // Grafana checks that the datasource answers on the root path when it is added.
+        (Method::Get, "/") => request.respond(Response::from_string("OK")),
+        _ => request.respond(Response::from_string("Not found").with_status_code(404)),
+    }
+}
+
+fn metrics(state: &InstanceState) -> String {
+    format!(
+        "# TYPE cosmic_ray_detector_checks_total counter\n\
+         cosmic_ray_detector_checks_total {}\n\
+         # TYPE cosmic_ray_detector_flips_total counter\n\
+         cosmic_ray_detector_flips_total {}\n\
+         # TYPE cosmic_ray_detector_detector_bytes gauge\n\
+         cosmic_ray_detector_detector_bytes {}\n\
+         # TYPE cosmic_ray_detector_stalls_total counter\n\
+         cosmic_ray_detector_stalls_total {}\n\
+         # HELP cosmic_ray_detector_rate_per_gb_day Bitflips per GB-day, weighted towards the last --rate-window\n\
+         # TYPE cosmic_ray_detector_rate_per_gb_day gauge\n\
+         cosmic_ray_detector_rate_per_gb_day {}\n",
+        state.total_checks.load(Ordering::Relaxed),
+        state.flips.load(Ordering::Relaxed),
+        state.detector_size.load(Ordering::Relaxed),
+        state.stalls.load(Ordering::Relaxed),
+        state.rate_per_gb_day(),
+    )
+}
+
+/// The kept bitflips inside the time range of the Grafana query as annotations, all of them if it has no range.
+fn annotations(state: &InstanceState, query: &Value) -> Value {
+    let from = query["range"]["from"].as_str().and_then(unix_ms).unwrap_or(0);
+    let to = query["range"]["to"].as_str().and_then(unix_ms).unwrap_or(u128::MAX);
+    let in_range = |time: &u128| (from..=to).contains(time);
+    let events = state.events.lock().unwrap_or_else(PoisonError::into_inner);
+    let annotations: Vec<Value> = events
+        .iter()
+        .filter_map(|event| match event {
+            Record::Flip { checks, vanished, detected_ms, diffs, .. } if in_range(detected_ms) => Some(json!({
+                "annotation": query["annotation"],
+                "time": *detected_ms as u64,
+                "title": "Bitflip",
+                "text": if *vanished {
+                    format!("Bitflip after {} checks, it flipped back before it could be located", checks)
+                } else {
+                    let bytes: Vec<String> = diffs.iter().map(describe_byte).collect();
+                    format!("Bitflip after {} checks at {}", checks, bytes.join(", "))
+                },
+                "tags": ["bitflip"],
+                "bytes": diffs
+                    .iter()
+                    .map(|diff| json!({ "index": diff.index, "chunk": diff.chunk, "offset": diff.offset }))
+                    .collect::<Vec<Value>>(),
+            })),
+            _ => None,
+        })
+        .collect();
+    Value::Array(annotations)
+}
+
+/// Milliseconds since the Unix epoch of a UTC time the way Grafana sends it, e.g. `2024-01-31T12:00:00.000Z`.
+fn unix_ms(time: &str) -> Option<u128> {
+    let (date, clock) = time.strip_suffix('Z')?.split_once('T')?;
+    let mut date = date.splitn(3, '-');
+    let year: i64 = date.next()?.parse().ok()?;
+    let month: u32 = date.next()?.parse().ok()?;
+    let day: u32 = date.next()?.parse().ok()?;
+    let (clock, fraction) = clock.split_once('.').unwrap_or((clock, ""));
+    let mut clock = clock.splitn(3, ':').map(|part| part.parse::<u64>().ok());
+    let seconds = clock.next()?? * 3600 + clock.next()?? * 60 + clock.next()??;
+    let millis: u64 = format!("{:0<3}", fraction).get(..3)?.parse().ok()?;
+    let days = u64::try_from(days_from_civil(year, month, day)).ok()?;
+    Some(((days * 86400 + seconds) * 1000 + millis) as u128)
+}
+
+/// The index of a changed byte, with its chunk and offset in it where the diff has them.
+fn describe_byte(diff: &FlipDiff) -> String {
+    match diff.chunk.zip(diff.offset) {
+        Some((chunk, offset)) => format!("index {} (chunk {}, offset {})", diff.index, chunk, offset),
+        None => format!("index {}", diff.index),
+    }
+}
+
+fn json_header() -> Header {
+    Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..]).expect("Static header is valid")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{instance::MAX_EVENTS, record::Tags};
+
+    fn flip(detected_ms: u128) -> Record {
+        Record::Flip {
+            started_ms: detected_ms - 10,
+            delay_ms: 10,
+            checks: 3,
+            vanished: true,
+            detected_ms,
+            location: None,
+            uncertainty: None,
+            changed_bytes: None,
+            diffs: Vec::new(),
+            warmup: false,
+            tags: Tags::new(),
+            sequence: None,
+        }
+    }
+
+    #[test]
+    fn grafana_times_are_read_as_unix_milliseconds() {
+        assert_eq!(unix_ms("1970-01-01T00:00:00Z"), Some(0));
+        assert_eq!(unix_ms("2024-01-31T12:00:00.250Z"), Some(1_706_702_400_250));
+        assert_eq!(unix_ms("2024-01-31 12:00:00"), None);
+    }
+
+    #[test]
+    fn annotations_are_limited_to_the_time_range() {
+        let state = InstanceState::default();
+        for detected_ms in [1_706_702_300_000, 1_706_702_400_000, 1_706_702_500_000] {
+            state.push_event(flip(detected_ms));
+        }
+        let query = json!({
+            "range": { "from": "2024-01-31T11:59:00.000Z", "to": "2024-01-31T12:01:00.000Z" },
+            "annotation": { "name": "bitflips" },
+        });
+        let Value::Array(annotations) = annotations(&state, &query) else {
+            panic!("The annotations aren't an array");
+        };
+        assert_eq!(annotations.len(), 1);
+        assert_eq!(annotations[0]["time"], 1_706_702_400_000u64);
+        assert_eq!(annotations[0]["annotation"]["name"], "bitflips");
+
+        let Value::Array(annotations) = super::annotations(&state, &Value::Null) else {
+            panic!("The annotations aren't an array");
+        };
+        assert_eq!(annotations.len(), 3);
+    }
+
+    #[test]
+    fn only_the_latest_events_are_kept() {
+        let state = InstanceState::default();
+        for detected_ms in 0..MAX_EVENTS as u128 + 5 {
+            state.push_event(flip(detected_ms + 10));
+        }
+        let events = state.events.lock().unwrap();
+        assert_eq!(events.len(), MAX_EVENTS);
+        assert!(matches!(events.front(), Some(Record::Flip { detected_ms: 15, .. })));
+    }
+}
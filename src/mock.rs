@@ -0,0 +1,55 @@
+use std::cell::Cell;
+
+use crate::detector::DetectorMemory;
+
+/// How a scripted check finds the memory.
+#[derive(Clone, Copy, Debug)]
+pub enum Corruption {
+    /// The byte at the index holds the value until the next reset.
+    At { index: usize, value: u8 },
+    /// Something changed, but had changed back by the time it was looked for.
+    Vanished,
+}
+
+/// A detector without memory whose checks find the scripted corruption, for testing the flow of events
+/// deterministically. Checks are numbered from 1 and counted across resets.
+pub struct MockDetector {
+    script: Vec<(u64, Corruption)>,
+    checks: Cell<u64>,
+    corruption: Cell<Option<Corruption>>,
+}
+
+impl MockDetector {
+    pub fn new(script: Vec<(u64, Corruption)>) -> Self {
+        MockDetector { script, checks: Cell::new(0), corruption: Cell::new(None) }
+    }
+}
+
+impl DetectorMemory for MockDetector {
+    fn reset(&mut self) {
+        self.corruption.set(None);
+    }
+
+    fn is_intact(&self) -> bool {
+        let check = self.checks.get() + 1;
+        self.checks.set(check);
+        if let Some((_, corruption)) = self.script.iter().find(|(number, _)| *number == check) {
+            self.corruption.set(Some(*corruption));
+        }
+        self.corruption.get().is_none()
+    }
+
+    fn find_index_of_changed_element(&self) -> Option<usize> {
+        match self.corruption.get()? {
+            Corruption::At { index, .. } => Some(index),
+            Corruption::Vanished => None,
+        }
+    }
+
+    fn get(&self, index: usize) -> Option<u8> {
+        match self.corruption.get()? {
+            Corruption::At { index: changed, value } if changed == index => Some(value),
+            _ => Some(0),
+        }
+    }
+}
@@ -0,0 +1,69 @@
+use std::fs;
+
+#[cfg(target_os = "linux")]
+use crate::softdirty::page_size;
+
+const ONLINE_NODES_PATH: &str = "/sys/devices/system/node/online";
+
+/// The NUMA nodes that are online, from a list of ranges like "0-3,5". Fails on systems without NUMA support, which
+/// are treated like a single node by the caller.
+pub fn online_nodes() -> Result<Vec<u32>, String> {
+    let list = fs::read_to_string(ONLINE_NODES_PATH)
+        .map_err(|e| format!("Unable to read the NUMA nodes from {}: {}", ONLINE_NODES_PATH, e))?;
+    parse_node_list(&list)
+}
+
+/// Parses a kernel list of ranges of node numbers, like "0-3,5".
+pub fn parse_node_list(list: &str) -> Result<Vec<u32>, String> {
+    let mut nodes = Vec::new();
+    for range in list.trim().split(',').filter(|range| !range.is_empty()) {
+        let parse = |n: &str| n.parse::<u32>().map_err(|e| format!("Invalid NUMA node list {}: {}", list.trim(), e));
+        match range.split_once('-') {
+            Some((first, last)) => nodes.extend(parse(first)?..=parse(last)?),
+            None => nodes.push(parse(range)?),
+        }
+    }
+    Ok(nodes)
+}
+
+/// The NUMA node the page of the given address of the process is on, with move_pages without moving it. None when the
+/// page isn't in memory.
+#[cfg(target_os = "linux")]
+pub fn node_of(address: usize) -> Result<Option<u32>, String> {
+    let page_size = page_size()?;
+    let page = (address / page_size * page_size) as *mut libc::c_void;
+    let mut status: libc::c_int = 0;
+    // Without nodes to move to, move_pages only tells where the one page is.
+    let result = unsafe {
+        libc::syscall(libc::SYS_move_pages, 0, 1, &page, std::ptr::null::<libc::c_int>(), &mut status, 0)
+    };
+    if result != 0 {
+        return Err(format!("Unable to find the NUMA node of {:#x}: {}", address, std::io::Error::last_os_error()));
+    }
+    match status {
+        node if node >= 0 => Ok(Some(node as u32)),
+        status if status == -libc::ENOENT => Ok(None),
+        status => Err(format!(
+            "Unable to find the NUMA node of {:#x}: {}",
+            address,
+            std::io::Error::from_raw_os_error(-status)
+        )),
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn node_of(_address: usize) -> Result<Option<u32>, String> {
+    Err("NUMA placement is only supported on Linux".into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn node_lists_are_parsed() {
+        assert_eq!(parse_node_list("0-3,5\n").unwrap(), vec![0, 1, 2, 3, 5]);
+        assert_eq!(parse_node_list("0\n").unwrap(), vec![0]);
+        assert!(parse_node_list("0-x").is_err());
+    }
+}
@@ -0,0 +1,46 @@
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+
+use crate::softdirty::page_size;
+
+const PAGEMAP_PATH: &str = "/proc/self/pagemap";
+const PAGE_PRESENT_BIT: u64 = 1 << 63;
+/// The page frame number takes up the lowest 55 bits of an entry of a page that is present.
+const PFN_MASK: u64 = (1 << 55) - 1;
+
+/// Translates virtual addresses of the process into physical ones with /proc/self/pagemap, see
+/// https://docs.kernel.org/admin-guide/mm/pagemap.html. Physical addresses tie a flip to a DIMM, rank and row, which
+/// is what an analysis of where flips happen needs. The kernel only shows the frame numbers to CAP_SYS_ADMIN.
+pub struct Pagemap {
+    pagemap: File,
+    page_size: usize,
+}
+
+impl Pagemap {
+    /// Fails on systems other than Linux, and where the frame numbers are hidden from the process, which is checked
+    /// with the given address of memory that is in use.
+    pub fn open(probe_address: usize) -> Result<Self, String> {
+        let pagemap = File::open(PAGEMAP_PATH).map_err(|e| format!("Unable to open {}: {}", PAGEMAP_PATH, e))?;
+        let pagemap = Pagemap { pagemap, page_size: page_size()? };
+        // Without the capability the entries of present pages read as frame 0 rather than failing.
+        match pagemap.physical_address(probe_address)? {
+            Some(0) => Err("The kernel only shows physical addresses to processes with CAP_SYS_ADMIN".into()),
+            _ => Ok(pagemap),
+        }
+    }
+
+    /// The physical address of the given virtual one, none when its page isn't in memory.
+    pub fn physical_address(&self, address: usize) -> Result<Option<u64>, String> {
+        let offset = (address / self.page_size * 8) as u64;
+        let mut entry = [0; 8];
+        (&self.pagemap)
+            .seek(SeekFrom::Start(offset))
+            .and_then(|_| (&self.pagemap).read_exact(&mut entry))
+            .map_err(|e| format!("Unable to read {}: {}", PAGEMAP_PATH, e))?;
+        let entry = u64::from_le_bytes(entry);
+        if entry & PAGE_PRESENT_BIT == 0 {
+            return Ok(None);
+        }
+        Ok(Some((entry & PFN_MASK) * self.page_size as u64 + (address % self.page_size) as u64))
+    }
+}
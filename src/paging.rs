@@ -0,0 +1,71 @@
+use std::fs;
+
+use crate::record::{Paging, Residency};
+
+/// Where Linux counts the paging of the whole machine since it booted.
+const VMSTAT_PATH: &str = "/proc/vmstat";
+
+/// Tells how much the machine paged since it was asked last, from the kernel's counters.
+pub struct PagingSampler {
+    last: Paging,
+}
+
+impl PagingSampler {
+    /// None where the counters can't be read, which is everywhere but Linux.
+    pub fn new() -> Option<Self> {
+        read_vmstat().map(|last| PagingSampler { last })
+    }
+
+    /// The paging since the previous call, or since the sampler was made.
+    pub fn take(&mut self) -> Option<Paging> {
+        let now = read_vmstat()?;
+        let paging = Paging {
+            paged_in_kb: now.paged_in_kb.saturating_sub(self.last.paged_in_kb),
+            paged_out_kb: now.paged_out_kb.saturating_sub(self.last.paged_out_kb),
+            swapped_in_pages: now.swapped_in_pages.saturating_sub(self.last.swapped_in_pages),
+            swapped_out_pages: now.swapped_out_pages.saturating_sub(self.last.swapped_out_pages),
+        };
+        self.last = now;
+        Some(paging)
+    }
+}
+
+/// The counters since boot. Paging counts the kilobytes read from and written to disk for any reason, swapping
+/// only the pages that went to and came back from swap.
+fn read_vmstat() -> Option<Paging> {
+    let contents = fs::read_to_string(VMSTAT_PATH).ok()?;
+    let counter = |name: &str| {
+        contents
+            .lines()
+            .filter_map(|line| line.split_once(' '))
+            .find(|(key, _)| *key == name)
+            .and_then(|(_, value)| value.trim().parse().ok())
+    };
+    Some(Paging {
+        paged_in_kb: counter("pgpgin")?,
+        paged_out_kb: counter("pgpgout")?,
+        swapped_in_pages: counter("pswpin")?,
+        swapped_out_pages: counter("pswpout")?,
+    })
+}
+
+/// Adds up how much of the detector was in memory for the checks between two heartbeats.
+#[derive(Default)]
+pub struct ResidencyTally {
+    reduced_checks: u64,
+    min_resident_fraction: Option<f64>,
+}
+
+impl ResidencyTally {
+    pub fn add(&mut self, resident_fraction: f64, reduced: bool) {
+        self.reduced_checks += reduced as u64;
+        self.min_resident_fraction =
+            Some(self.min_resident_fraction.map_or(resident_fraction, |fraction| fraction.min(resident_fraction)));
+    }
+
+    /// The residency of the checks so far, starting over afterwards.
+    pub fn take(&mut self) -> Option<Residency> {
+        let tally = std::mem::take(self);
+        Some(Residency { reduced_checks: tally.reduced_checks, min_resident_fraction: tally.min_resident_fraction? })
+    }
+}
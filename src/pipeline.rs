@@ -0,0 +1,389 @@
+use std::any::Any;
+use std::io::{stdout, Write};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{sync_channel, Receiver, RecvTimeoutError, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::{
+    anomaly::{AlertWebhook, Anomaly},
+    exposure::Ledger, healthcheck::HealthcheckPinger, influx::InfluxWriter, log_writer::LogWriter,
+    record::{Record, Tags}, s3::S3Exporter, serial::SerialOutput, share::Sharer, state::StateFile, upload::Uploader,
+};
+
+/// How often the worker retries uploads when nothing else is happening.
+const UPLOAD_POLL_INTERVAL: Duration = Duration::from_secs(1);
+/// How many events can wait for the worker. Flips are far too rare to ever fill it, but a heartbeat after every
+/// check can when the disk or stdout stalls for long enough.
+const QUEUE_CAPACITY: usize = 4096;
+
+/// Something for the worker to pass on to the sinks.
+pub enum Event {
+    /// Records to write to the log in a single write, upload, and stream to stdout.
+    Log(Vec<Record>),
+    /// A heartbeat that is only streamed to stdout, if heartbeats are.
+    Stream(Box<Record>),
+    /// The state of the detector, for InfluxDB.
+    Status {
+        checks_per_second: f64,
+        total_checks: u64,
+        detector_bytes: usize,
+        gb_hours: f64,
+    },
+    /// An integrity check passed, for the health check.
+    Healthy,
+    /// A copy of the exposure ledger to save.
+    Ledger(Ledger),
+    /// The bitflip rate jumped, for the alert webhook.
+    Alert { anomaly: Anomaly, text: String },
+    /// The free space of the log's filesystem crossed a threshold, for the alert webhook.
+    DiskSpace { free_bytes: u64, text: String },
+}
+
+/// Everything that does blocking I/O with the events of the detector.
+pub struct Sinks {
+    pub log: LogWriter,
+    /// Numbers the logged records, which are left without sequence numbers if there is none.
+    pub state: Option<StateFile>,
+    /// Put on every logged record but the sessions.
+    pub tags: Tags,
+    pub uploader: Option<Uploader>,
+    /// Shares anonymized records with a community collector, with `--share`.
+    pub sharer: Option<Sharer>,
+    pub influx: Option<InfluxWriter>,
+    pub healthcheck: Option<HealthcheckPinger>,
+    pub exporter: Option<S3Exporter>,
+    pub alert_webhook: Option<AlertWebhook>,
+    pub serial: Option<SerialOutput>,
+    pub events_to_stdout: bool,
+    pub heartbeats_to_stdout: bool,
+    /// Where to report problems, stderr when events are streamed to stdout.
+    pub out: Box<dyn Write + Send>,
+}
+
+/// Hands events to a worker thread that owns the sinks, so that a slow disk, network or webhook never delays
+/// the integrity checks or distorts their timing. Events are handled in the order they were sent.
+/// When the queue is full heartbeats and status updates are dropped instead of waiting, and the number of dropped
+/// events is logged with the next record that makes it. Flips, alerts and everything else wait for room instead.
+pub struct Pipeline {
+    sender: SyncSender<Event>,
+    /// Events dropped since the last record that made it into the queue.
+    dropped: u64,
+    /// Events dropped since the pipeline started.
+    dropped_total: u64,
+    worker: JoinHandle<()>,
+    /// Set when the log can't be written any more, which is the one failure the detector can't run through.
+    failure: Arc<Mutex<Option<String>>>,
+}
+
+impl Pipeline {
+    pub fn start(sinks: Sinks) -> Self {
+        let (sender, receiver) = sync_channel(QUEUE_CAPACITY);
+        let failure = Arc::new(Mutex::new(None));
+        let worker_failure = failure.clone();
+        let worker = thread::spawn(move || work(sinks, receiver, worker_failure));
+        Pipeline { sender, dropped: 0, dropped_total: 0, worker, failure }
+    }
+
+    /// Queues the event without waiting for it to be handled. If the queue is full a heartbeat or status update is
+    /// dropped, anything else waits until there is room.
+    pub fn send(&mut self, event: Event) {
+        let (event, carries_dropped_count) = match event {
+            Event::Log(mut records) if self.dropped > 0 => {
+                let timestamp_ms = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_millis();
+                records.insert(0, Record::Dropped { timestamp_ms, records: self.dropped, tags: Tags::new(), sequence: None });
+                (Event::Log(records), true)
+            }
+            event => (event, false),
+        };
+        let sent = match self.sender.try_send(event) {
+            Ok(()) => true,
+            Err(TrySendError::Full(event)) if droppable(&event) => {
+                let dropped = dropped_events(&event, carries_dropped_count);
+                self.dropped += dropped;
+                self.dropped_total += dropped;
+                false
+            }
+            Err(TrySendError::Full(event)) => self.sender.send(event).is_ok(),
+            // The worker only stops after a failure that `check` reports.
+            Err(TrySendError::Disconnected(_)) => false,
+        };
+        if sent && carries_dropped_count {
+            self.dropped = 0;
+        }
+    }
+
+    /// The number of events dropped since the pipeline started.
+    pub fn dropped_total(&self) -> u64 {
+        self.dropped_total
+    }
+
+    /// Returns the error that stopped the worker from writing the log, if any.
+    pub fn check(&self) -> Result<(), String> {
+        match self.failure.lock().unwrap_or_else(PoisonError::into_inner).as_ref() {
+            Some(err) => Err(err.clone()),
+            None => Ok(()),
+        }
+    }
+
+    /// Waits for every queued event to be handled.
+    pub fn finish(self) -> Result<(), String> {
+        drop(self.sender);
+        self.worker.join().map_err(|_| "The event worker panicked".to_string())?;
+        match self.failure.lock().unwrap_or_else(PoisonError::into_inner).take() {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Whether the event can be dropped when the queue is full. Only heartbeats and status updates can, a later one
+/// tells the same.
+fn droppable(event: &Event) -> bool {
+    match event {
+        Event::Log(records) => records
+            .iter()
+            .all(|record| matches!(record, Record::Heartbeat { .. } | Record::Dropped { .. })),
+        Event::Stream(_) | Event::Status { .. } | Event::Healthy => true,
+        Event::Ledger(_) | Event::Alert { .. } | Event::DiskSpace { .. } => false,
+    }
+}
+
+/// The number of events lost with a dropped event, every record of it counting as one but the dropped event count
+/// it carries.
+fn dropped_events(event: &Event, carries_dropped_count: bool) -> u64 {
+    match event {
+        Event::Log(records) => records.len() as u64 - carries_dropped_count as u64,
+        _ => 1,
+    }
+}
+
+fn work(mut sinks: Sinks, receiver: Receiver<Event>, failure: Arc<Mutex<Option<String>>>) {
+    // A worker that died without a word would leave the detector checking with nothing logged.
+    let result = panic::catch_unwind(AssertUnwindSafe(|| drain(&mut sinks, &receiver)))
+        .unwrap_or_else(|panic| Err(format!("The event worker panicked: {}", panic_message(&panic))));
+    if let Err(err) = result {
+        *failure.lock().unwrap_or_else(PoisonError::into_inner) = Some(err);
+    }
+}
+
+fn panic_message(panic: &Box<dyn Any + Send>) -> &str {
+    match (panic.downcast_ref::<&str>(), panic.downcast_ref::<String>()) {
+        (Some(message), _) => message,
+        (_, Some(message)) => message,
+        _ => "unknown cause",
+    }
+}
+
+/// Handles events until the pipeline is finished, or the log can't be written any more.
+fn drain(sinks: &mut Sinks, receiver: &Receiver<Event>) -> Result<(), String> {
+    loop {
+        match receiver.recv_timeout(UPLOAD_POLL_INTERVAL) {
+            Ok(event) => handle(sinks, event)?,
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+        sinks.log.sync_if_due().map_err(|e| format!("Unable to sync the log: {}", e))?;
+        if let Some(uploader) = &mut sinks.uploader {
+            if let Err(err) = uploader.flush() {
+                let _ = writeln!(sinks.out, "{}", err);
+            }
+        }
+        if let Some(sharer) = &mut sinks.sharer {
+            if let Err(err) = sharer.flush() {
+                let _ = writeln!(sinks.out, "{}", err);
+            }
+        }
+        if let Some(exporter) = &mut sinks.exporter {
+            if let Err(err) = exporter.export_if_due() {
+                let _ = writeln!(sinks.out, "{}", err);
+            }
+        }
+    }
+
+    sinks.log.flush().map_err(|e| format!("Unable to write the log: {}", e))?;
+    if let Some(state) = &mut sinks.state {
+        if let Err(err) = state.release_sequences() {
+            let _ = writeln!(sinks.out, "{}", err);
+        }
+    }
+    if let Some(exporter) = &mut sinks.exporter {
+        if let Err(err) = exporter.export() {
+            let _ = writeln!(sinks.out, "Unable to export the end of the log to S3, it will be with the next run: {}", err);
+        }
+    }
+    if let Some(uploader) = &sinks.uploader {
+        if uploader.pending() > 0 {
+            let _ = writeln!(
+                sinks.out,
+                "{} records were never acknowledged by the collector, they are still in the log",
+                uploader.pending()
+            );
+        }
+    }
+    if let Some(sharer) = &sinks.sharer {
+        if sharer.pending() > 0 {
+            let _ = writeln!(sinks.out, "{} records were never acknowledged by the community collector", sharer.pending());
+        }
+    }
+    Ok(())
+}
+
+/// Passes the event on to the sinks. Only failing to write the log is an error, everything else is reported and skipped.
+fn handle(sinks: &mut Sinks, event: Event) -> Result<(), String> {
+    match event {
+        Event::Log(mut records) => {
+            if !sinks.tags.is_empty() {
+                for record in records.iter_mut().filter(|record| !matches!(record, Record::Session(_))) {
+                    record.set_tags(sinks.tags.clone());
+                }
+            }
+            if let Some(state) = &mut sinks.state {
+                for record in records.iter_mut().filter(|record| !matches!(record, Record::Session(_))) {
+                    match state.take_sequence() {
+                        Ok(sequence) => record.set_sequence(Some(sequence)),
+                        // A number that may be handed out again is worse than none.
+                        Err(err) => {
+                            let _ = writeln!(sinks.out, "{}", err);
+                        }
+                    }
+                }
+            }
+            sinks
+                .log
+                .write(&records.iter().collect::<Vec<_>>())
+                .map_err(|e| format!("Unable to write to the log: {}", e))?;
+            for record in &records {
+                if let Some(uploader) = &mut sinks.uploader {
+                    uploader.push(record);
+                }
+                if let Some(sharer) = &mut sinks.sharer {
+                    if let Err(err) = sharer.push(record) {
+                        let _ = writeln!(sinks.out, "{}", err);
+                    }
+                }
+                if let (Some(influx), Record::Flip { checks, vanished, .. }) = (&sinks.influx, record) {
+                    if let Err(err) = influx.write_flip(*checks, *vanished) {
+                        let _ = writeln!(sinks.out, "{}", err);
+                    }
+                }
+                if let Some(serial) = &mut sinks.serial {
+                    if let Err(err) = serial.write(record) {
+                        let _ = writeln!(sinks.out, "{}", err);
+                    }
+                }
+                if sinks.events_to_stdout && (sinks.heartbeats_to_stdout || !matches!(record, Record::Heartbeat { .. })) {
+                    stream(sinks, record);
+                }
+            }
+        }
+        Event::Stream(record) => {
+            if sinks.heartbeats_to_stdout {
+                stream(sinks, &record);
+            }
+        }
+        Event::Status { checks_per_second, total_checks, detector_bytes, gb_hours } => {
+            if let Some(influx) = &sinks.influx {
+                if let Err(err) = influx.write_status(checks_per_second, total_checks, detector_bytes, gb_hours) {
+                    let _ = writeln!(sinks.out, "{}", err);
+                }
+            }
+        }
+        Event::Healthy => {
+            if let Some(healthcheck) = &mut sinks.healthcheck {
+                if let Err(err) = healthcheck.ping() {
+                    let _ = writeln!(sinks.out, "{}", err);
+                }
+            }
+        }
+        Event::Ledger(ledger) => {
+            if let Err(err) = ledger.save() {
+                let _ = writeln!(sinks.out, "Unable to save the exposure ledger: {}", err);
+            }
+        }
+        Event::Alert { anomaly, text } => {
+            if let Some(webhook) = &sinks.alert_webhook {
+                if let Err(err) = webhook.send(&anomaly, &text) {
+                    let _ = writeln!(sinks.out, "{}", err);
+                }
+            }
+        }
+        Event::DiskSpace { free_bytes, text } => {
+            if let Some(webhook) = &sinks.alert_webhook {
+                if let Err(err) = webhook.send_disk_space(free_bytes, &text) {
+                    let _ = writeln!(sinks.out, "{}", err);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Streams the record to stdout. When nothing reads stdout any more, e.g. after `| head`, that is reported once and
+/// streaming stops, while the log and the other sinks go on.
+fn stream(sinks: &mut Sinks, record: &Record) {
+    if let Err(err) = emit_event(record) {
+        let _ = writeln!(sinks.out, "{}, no more events will be streamed to it", err);
+        sinks.events_to_stdout = false;
+        sinks.heartbeats_to_stdout = false;
+    }
+}
+
+/// Writes an event to stdout as a single line of JSON.
+fn emit_event(record: &Record) -> Result<(), String> {
+    let line = serde_json::to_string(record).map_err(|e| e.to_string())?;
+    let mut out = stdout().lock();
+    writeln!(out, "{}", line)
+        .and_then(|_| out.flush())
+        .map_err(|e| format!("Unable to write to stdout: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shutdown() -> Record {
+        Record::Shutdown {
+            timestamp_ms: 1_700_000_000_000,
+            total_checks: 10,
+            flips: 1,
+            uptime_ms: 5000,
+            tags: Tags::new(),
+            sequence: None,
+        }
+    }
+
+    #[test]
+    fn a_full_queue_drops_status_updates_but_not_records() {
+        // A pipeline without a worker, so the test decides when the queue empties.
+        let (sender, receiver) = sync_channel(2);
+        let mut pipeline = Pipeline {
+            sender,
+            dropped: 0,
+            dropped_total: 0,
+            worker: thread::spawn(|| {}),
+            failure: Arc::new(Mutex::new(None)),
+        };
+        pipeline.send(Event::Healthy);
+        pipeline.send(Event::Healthy);
+        pipeline.send(Event::Healthy);
+        pipeline.send(Event::Status { checks_per_second: 1.0, total_checks: 10, detector_bytes: 64, gb_hours: 0.0 });
+        assert_eq!(pipeline.dropped_total(), 2);
+
+        // The record waits for room instead of being dropped, and carries the count.
+        let reader = thread::spawn(move || receiver.iter().collect::<Vec<Event>>());
+        pipeline.send(Event::Log(vec![shutdown()]));
+        drop(pipeline);
+        let events = reader.join().unwrap();
+        assert_eq!(events.len(), 3);
+        let Event::Log(records) = &events[2] else {
+            panic!("The last event isn't a log event");
+        };
+        assert!(matches!(records[0], Record::Dropped { records: 2, .. }));
+        assert!(matches!(records[1], Record::Shutdown { .. }));
+    }
+}
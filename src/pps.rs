@@ -0,0 +1,209 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpStream;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+use serde_json::Value;
+
+use crate::clock::TimeSource;
+
+/// Pulses come every second, an offset older than this means the source stopped and the system clock is used alone.
+const PPS_MAX_AGE: Duration = Duration::from_secs(10);
+const GPSD_ADDR_DEFAULT: &str = "127.0.0.1:2947";
+/// How long to wait before connecting to gpsd again after it went away.
+const GPSD_RECONNECT_INTERVAL: Duration = Duration::from_secs(10);
+const NANOS_PER_SECOND: i64 = 1_000_000_000;
+
+/// The offset of the system clock from the pulses of a PPS source, kept up to date by a thread of its own.
+/// NTP keeps the system clock within a millisecond or so, the edge of a pulse from a GPS receiver marks the
+/// start of a second to within a microsecond.
+pub struct PpsClock {
+    source: TimeSource,
+    /// How far the system clock was ahead at the latest pulse in nanoseconds, and when that was.
+    latest: Arc<Mutex<Option<(i64, Instant)>>>,
+}
+
+/// A PPS source that is open but not followed yet, so that the device can be opened before the sandbox
+/// forbids it and the thread following it started after, inside the sandbox.
+pub enum PpsSource {
+    Device(File),
+    Gpsd { stream: TcpStream, addr: String },
+}
+
+impl PpsSource {
+    /// Opens a PPS device such as /dev/pps0, or connects to gpsd with `gpsd` or `gpsd:host:port`.
+    pub fn open(spec: &str) -> Result<Self, String> {
+        match spec.strip_prefix("gpsd") {
+            Some(addr) => {
+                let addr = addr.strip_prefix(':').filter(|addr| !addr.is_empty()).unwrap_or(GPSD_ADDR_DEFAULT).to_string();
+                Ok(PpsSource::Gpsd { stream: connect_gpsd(&addr)?, addr })
+            }
+            None => Ok(PpsSource::Device(device::open(spec)?)),
+        }
+    }
+}
+
+impl PpsClock {
+    /// Starts following the source's pulses in a thread of its own.
+    pub fn start(source: PpsSource) -> Self {
+        let latest = Arc::new(Mutex::new(None));
+        let thread_latest = latest.clone();
+        let source = match source {
+            PpsSource::Device(device) => {
+                thread::spawn(move || device::follow(device, &thread_latest));
+                TimeSource::Pps
+            }
+            PpsSource::Gpsd { stream, addr } => {
+                thread::spawn(move || follow_gpsd(stream, &addr, &thread_latest));
+                TimeSource::Gpsd
+            }
+        };
+        PpsClock { source, latest }
+    }
+
+    /// The time now, corrected by the latest pulse if it is recent enough, and where it came from.
+    pub fn now(&self) -> (SystemTime, TimeSource) {
+        let now = SystemTime::now();
+        match *self.latest.lock().unwrap_or_else(PoisonError::into_inner) {
+            Some((offset, at)) if at.elapsed() <= PPS_MAX_AGE => {
+                let correction = Duration::from_nanos(offset.unsigned_abs());
+                match offset >= 0 {
+                    true => (now - correction, self.source),
+                    false => (now + correction, self.source),
+                }
+            }
+            _ => (now, TimeSource::System),
+        }
+    }
+}
+
+fn connect_gpsd(addr: &str) -> Result<TcpStream, String> {
+    let mut stream = TcpStream::connect(addr).map_err(|e| format!("Unable to connect to gpsd at {}: {}", addr, e))?;
+    stream
+        .write_all(b"?WATCH={\"enable\":true,\"json\":true,\"pps\":true};\n")
+        .map_err(|e| format!("Unable to ask gpsd at {} for PPS reports: {}", addr, e))?;
+    Ok(stream)
+}
+
+/// Reads gpsd's PPS reports, which carry the time of the pulse and the system clock at it, for as long as we run.
+fn follow_gpsd(mut stream: TcpStream, addr: &str, latest: &Mutex<Option<(i64, Instant)>>) {
+    loop {
+        for line in BufReader::new(&stream).lines().map_while(Result::ok) {
+            let Ok(report) = serde_json::from_str::<Value>(&line) else {
+                continue;
+            };
+            if report["class"] != "PPS" {
+                continue;
+            }
+            let field = |name: &str| report[name].as_i64();
+            if let (Some(real_sec), Some(real_nsec), Some(clock_sec), Some(clock_nsec)) =
+                (field("real_sec"), field("real_nsec"), field("clock_sec"), field("clock_nsec"))
+            {
+                let offset = (clock_sec - real_sec) * NANOS_PER_SECOND + clock_nsec - real_nsec;
+                *latest.lock().unwrap_or_else(PoisonError::into_inner) = Some((offset, Instant::now()));
+            }
+        }
+        // gpsd went away, the offset goes stale and the times fall back to the system clock until it is back.
+        loop {
+            thread::sleep(GPSD_RECONNECT_INTERVAL);
+            if let Ok(reconnected) = connect_gpsd(addr) {
+                stream = reconnected;
+                break;
+            }
+        }
+    }
+}
+
+/// The Linux PPS API, see https://docs.kernel.org/driver-api/pps.html.
+#[cfg(target_os = "linux")]
+mod device {
+    use std::fs::File;
+    use std::os::unix::io::AsRawFd;
+    use std::sync::{Mutex, PoisonError};
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use super::NANOS_PER_SECOND;
+
+    /// How long one fetch waits for the next pulse.
+    const FETCH_TIMEOUT_SECONDS: i64 = 3;
+
+    // These mirror the kernel's structs, not every field is read.
+    #[repr(C)]
+    #[derive(Default)]
+    #[allow(dead_code)]
+    struct PpsKtime {
+        sec: i64,
+        nsec: i32,
+        flags: u32,
+    }
+
+    #[repr(C)]
+    #[derive(Default)]
+    #[allow(dead_code)]
+    struct PpsKinfo {
+        assert_sequence: u32,
+        clear_sequence: u32,
+        assert_tu: PpsKtime,
+        clear_tu: PpsKtime,
+        current_mode: i32,
+    }
+
+    #[repr(C)]
+    #[derive(Default)]
+    struct PpsFdata {
+        info: PpsKinfo,
+        timeout: PpsKtime,
+    }
+
+    /// `_IOWR('p', 0xa4, struct pps_fdata *)`, the kernel encodes the size of a pointer rather than of the struct.
+    const PPS_FETCH: u64 = (3 << 30) | ((std::mem::size_of::<*mut PpsFdata>() as u64) << 16) | ((b'p' as u64) << 8) | 0xa4;
+
+    pub fn open(path: &str) -> Result<File, String> {
+        File::open(path).map_err(|e| format!("Unable to open the PPS device {}: {}", path, e))
+    }
+
+    /// Waits for pulses and keeps the offset of the system clock at the latest one, for as long as we run.
+    pub fn follow(device: File, latest: &Mutex<Option<(i64, Instant)>>) {
+        let mut last_sequence = None;
+        loop {
+            let mut data = PpsFdata {
+                timeout: PpsKtime { sec: FETCH_TIMEOUT_SECONDS, ..Default::default() },
+                ..Default::default()
+            };
+            // The struct outlives the call, and the kernel only writes a pps_fdata into it.
+            let result = unsafe { libc::ioctl(device.as_raw_fd(), PPS_FETCH as _, &mut data as *mut PpsFdata) };
+            if result != 0 {
+                // No pulse within the timeout, the offset goes stale until there is one.
+                thread::sleep(Duration::from_secs(1));
+                continue;
+            }
+            // Devices that also capture the falling edge report it without a new rising one.
+            if last_sequence.replace(data.info.assert_sequence) == Some(data.info.assert_sequence) {
+                continue;
+            }
+            // The pulse marks the start of a second, the system clock is taken to be less than half a second off.
+            let nanos = data.info.assert_tu.nsec as i64;
+            let offset = match nanos < NANOS_PER_SECOND / 2 {
+                true => nanos,
+                false => nanos - NANOS_PER_SECOND,
+            };
+            *latest.lock().unwrap_or_else(PoisonError::into_inner) = Some((offset, Instant::now()));
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod device {
+    use std::fs::File;
+    use std::sync::Mutex;
+    use std::time::Instant;
+
+    pub fn open(_path: &str) -> Result<File, String> {
+        Err("PPS devices can only be read on Linux, use gpsd instead".into())
+    }
+
+    pub fn follow(_device: File, _latest: &Mutex<Option<(i64, Instant)>>) {}
+}
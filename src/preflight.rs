@@ -0,0 +1,113 @@
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use crate::{
+    capabilities::{self, Capability},
+    config::RunArgs,
+    healthcheck::HealthcheckPinger,
+    influx::InfluxWriter,
+    record::SessionInfo,
+    s3::S3Exporter,
+    signing::StationKey,
+    softdirty::SoftDirty,
+    upload::check_collector,
+};
+
+/// The outcome of one pre-flight check.
+pub struct Check {
+    pub name: &'static str,
+    pub passed: bool,
+    /// Whether the run depends on it, as opposed to something only worth knowing.
+    pub required: bool,
+    pub detail: String,
+}
+
+impl Check {
+    fn of(name: &'static str, result: Result<String, String>) -> Self {
+        match result {
+            Ok(detail) => Check { name, passed: true, required: true, detail },
+            Err(detail) => Check { name, passed: false, required: true, detail },
+        }
+    }
+
+    fn informational(capability: Capability) -> Self {
+        Check { name: capability.name, passed: capability.available, required: false, detail: capability.detail }
+    }
+}
+
+/// What the checks need to know about the run besides its arguments.
+pub struct Context<'a> {
+    pub log_path: &'a Path,
+    pub ledger_path: &'a Path,
+    pub station_id: &'a str,
+    pub session: &'a SessionInfo,
+    pub station_key: Option<&'a StationKey>,
+    pub exporter: Option<&'a S3Exporter>,
+}
+
+/// Tries everything the run will need before the detector memory is allocated, so that a wrong URL or a
+/// read-only disk shows up within seconds rather than after gigabytes have been filled. Every configured sink
+/// gets a test write that is harmless to the data: a file next to the log, an empty upload batch, a point in a
+/// measurement of its own, a ping and a small object in the bucket.
+pub fn run(conf: &RunArgs, context: &Context) -> Vec<Check> {
+    let mut checks = vec![
+        Check::of("Log directory", probe_directory(context.log_path)),
+        Check::of("Ledger directory", probe_directory(context.ledger_path)),
+    ];
+    if let (Some(url), Some(key)) = (&conf.upload_url, context.station_key) {
+        checks.push(Check::of(
+            "Collector (--upload-url)",
+            check_collector(url, context.station_id, context.session, key).map(|_| format!("{} accepts uploads", url)),
+        ));
+    }
+    if let Some(url) = &conf.influx_url {
+        let influx = InfluxWriter::new(url.clone(), conf.influx_token.clone(), context.station_id.to_string());
+        checks.push(Check::of("InfluxDB (--influx-url)", influx.write_test().map(|_| "test point written".into())));
+    }
+    if let Some(url) = &conf.healthcheck_url {
+        let mut healthcheck = HealthcheckPinger::new(url.clone());
+        checks.push(Check::of("Health check (--healthcheck-url)", healthcheck.ping().map(|_| "pinged".into())));
+    }
+    if let Some(exporter) = context.exporter {
+        checks.push(Check::of("Bucket (--s3-url)", exporter.write_test().map(|_| "test object written".into())));
+    }
+    if conf.soft_dirty {
+        checks.push(Check::of("Soft-dirty tracking (--soft-dirty)", SoftDirty::open().map(|_| "supported".into())));
+    }
+    checks.push(Check::informational(capabilities::memory_locking()));
+    checks.push(Check::informational(capabilities::physical_addresses()));
+    checks
+}
+
+/// Whether a check the run depends on failed.
+pub fn failed(checks: &[Check]) -> bool {
+    checks.iter().any(|check| check.required && !check.passed)
+}
+
+/// Prints the checks as a table.
+pub fn print(out: &mut dyn Write, checks: &[Check]) -> io::Result<()> {
+    let width = checks.iter().map(|check| check.name.len()).max().unwrap_or(0);
+    for check in checks {
+        let status = match (check.passed, check.required) {
+            (true, _) => "pass",
+            (false, true) => "FAIL",
+            (false, false) => "no",
+        };
+        writeln!(out, "{:width$}  {:4}  {}", check.name, status, check.detail, width = width)?;
+    }
+    Ok(())
+}
+
+/// Writes, syncs and removes a file in the directory of the given file.
+fn probe_directory(path: &Path) -> Result<String, String> {
+    let probe_path = PathBuf::from(format!("{}.preflight", path.display()));
+    File::create(&probe_path)
+        .and_then(|mut probe| {
+            probe.write_all(b"preflight\n")?;
+            probe.sync_all()
+        })
+        .and_then(|_| fs::remove_file(&probe_path))
+        .map_err(|e| format!("Unable to write {}: {}", probe_path.display(), e))?;
+    Ok("writable".into())
+}
@@ -0,0 +1,72 @@
+use std::time::Duration;
+
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+
+use super::{crd_detector, crd_event};
+
+/// A change found in the detector memory, with the same fields as `crd_event` but without the -1 markers.
+#[pyclass(name = "FlipEvent", get_all)]
+#[derive(Clone)]
+struct FlipEvent {
+    detected_ms: u64,
+    index: Option<u64>,
+    value: Option<u8>,
+    checks: u64,
+    window_ms: u64,
+}
+
+impl From<crd_event> for FlipEvent {
+    fn from(event: crd_event) -> Self {
+        FlipEvent {
+            detected_ms: event.detected_ms,
+            index: u64::try_from(event.index).ok(),
+            value: u8::try_from(event.value).ok(),
+            checks: event.checks,
+            window_ms: event.window_ms,
+        }
+    }
+}
+
+/// The same detector as the C bindings, checks release the GIL so other Python threads keep running.
+#[pyclass(name = "Detector")]
+struct PyDetector {
+    inner: crd_detector,
+}
+
+#[pymethods]
+impl PyDetector {
+    #[new]
+    fn new(bytes: usize) -> PyResult<Self> {
+        match bytes {
+            0 => Err(PyValueError::new_err("The detector needs at least one byte")),
+            bytes => Ok(PyDetector { inner: crd_detector::new(bytes) }),
+        }
+    }
+
+    /// Checks once, returning whether something changed.
+    fn check(&self, py: Python<'_>) -> bool {
+        py.allow_threads(|| self.inner.check())
+    }
+
+    /// Starts checking every `delay_ms` milliseconds in the background.
+    fn start(&self, delay_ms: u64) {
+        self.inner.start(Duration::from_millis(delay_ms));
+    }
+
+    fn stop(&self, py: Python<'_>) {
+        py.allow_threads(|| self.inner.stop());
+    }
+
+    /// Takes every queued event, oldest first.
+    fn events(&self) -> Vec<FlipEvent> {
+        self.inner.take_events(usize::MAX).into_iter().map(FlipEvent::from).collect()
+    }
+}
+
+#[pymodule]
+fn cosmic_ray_detector(_py: Python<'_>, module: &PyModule) -> PyResult<()> {
+    module.add_class::<PyDetector>()?;
+    module.add_class::<FlipEvent>()?;
+    Ok(())
+}
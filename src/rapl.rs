@@ -0,0 +1,119 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::record::Energy;
+
+/// Where Linux exposes the RAPL energy counters of Intel and AMD processors.
+const POWERCAP_PATH: &str = "/sys/class/powercap";
+const BYTES_PER_GB: f64 = 1e9;
+
+/// One energy counter, in microjoules, that wraps around at `max_range_uj`.
+struct Domain {
+    energy_path: PathBuf,
+    max_range_uj: u64,
+}
+
+/// The energy counters of every processor package and its memory controller. They count the energy of the whole
+/// machine's processors and memory, not just the detector's, so they tell the cost of a check best on an otherwise
+/// idle machine.
+pub struct Rapl {
+    domains: Vec<Domain>,
+}
+
+/// The values of all counters at one moment.
+pub struct Reading(Vec<u64>);
+
+impl Rapl {
+    /// Finds the counters. Since they can be used as a side channel, most kernels only let root read them.
+    pub fn open() -> Result<Self, String> {
+        let entries = fs::read_dir(POWERCAP_PATH).map_err(|_| "RAPL energy counters are not supported by this system")?;
+        let mut paths: Vec<PathBuf> = entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| {
+                path.file_name()
+                    .and_then(|name| name.to_str())
+                    .is_some_and(|name| name.starts_with("intel-rapl:"))
+            })
+            .collect();
+        paths.sort();
+
+        let mut domains = Vec::new();
+        for path in paths {
+            let name = fs::read_to_string(path.join("name")).unwrap_or_default();
+            let name = name.trim();
+            // The packages and the memory below them count separately, the platform domain would count both again.
+            if !name.starts_with("package") && name != "dram" {
+                continue;
+            }
+            let energy_path = path.join("energy_uj");
+            read_counter(&energy_path)?;
+            let max_range_uj = read_counter(&path.join("max_energy_range_uj"))?;
+            domains.push(Domain { energy_path, max_range_uj });
+        }
+        if domains.is_empty() {
+            return Err("No RAPL package energy counters found".into());
+        }
+        Ok(Rapl { domains })
+    }
+
+    pub fn read(&self) -> Result<Reading, String> {
+        self.domains
+            .iter()
+            .map(|domain| read_counter(&domain.energy_path))
+            .collect::<Result<_, _>>()
+            .map(Reading)
+    }
+
+    /// The energy used between two readings, in joules. Each counter may have wrapped around once in between.
+    pub fn joules_between(&self, start: &Reading, end: &Reading) -> f64 {
+        let microjoules: u64 = self
+            .domains
+            .iter()
+            .zip(start.0.iter().zip(&end.0))
+            .map(|(domain, (&start, &end))| match end >= start {
+                true => end - start,
+                false => domain.max_range_uj - start + end,
+            })
+            .sum();
+        microjoules as f64 / 1e6
+    }
+}
+
+fn read_counter(path: &Path) -> Result<u64, String> {
+    let value = fs::read_to_string(path).map_err(|e| match e.kind() {
+        std::io::ErrorKind::PermissionDenied => format!("{} is only readable by root", path.display()),
+        _ => format!("Unable to read {}: {}", path.display(), e),
+    })?;
+    value
+        .trim()
+        .parse()
+        .map_err(|_| format!("Unexpected value '{}' in {}", value.trim(), path.display()))
+}
+
+/// Adds up the energy of the checks between two heartbeats.
+#[derive(Default)]
+pub struct EnergyTally {
+    joules: f64,
+    checks: u64,
+}
+
+impl EnergyTally {
+    pub fn add(&mut self, joules: f64) {
+        self.joules += joules;
+        self.checks += 1;
+    }
+
+    /// The average cost of the checks so far for a detector of the given size, starting over afterwards.
+    pub fn take(&mut self, detector_bytes: usize) -> Option<Energy> {
+        let tally = std::mem::take(self);
+        if tally.checks == 0 {
+            return None;
+        }
+        let joules_per_check = tally.joules / tally.checks as f64;
+        Some(Energy {
+            joules_per_check,
+            joules_per_gb: joules_per_check / (detector_bytes as f64 / BYTES_PER_GB),
+        })
+    }
+}
@@ -0,0 +1,47 @@
+use std::time::Duration;
+
+use crate::exposure::gb_hours;
+
+const HOURS_PER_DAY: f64 = 24.0;
+
+/// An estimate of the current bitflip rate in events per GB-day, from the events and the exposure of the last
+/// `window` or so. Both are weighted down exponentially with their age, so a rate that changes shows up within
+/// about one window without having to keep any history.
+#[derive(Clone, Debug)]
+pub struct RateEstimate {
+    window: Duration,
+    events: f64,
+    gb_days: f64,
+}
+
+impl RateEstimate {
+    pub fn new(window: Duration) -> Self {
+        RateEstimate { window, events: 0.0, gb_days: 0.0 }
+    }
+
+    /// Adds the exposure of a detector of the given size over `duration`, weighting everything before down.
+    pub fn add_exposure(&mut self, detector_bytes: usize, duration: Duration) {
+        let decay = (-duration.as_secs_f64() / self.window.as_secs_f64()).exp();
+        self.events *= decay;
+        self.gb_days = self.gb_days * decay + gb_hours(detector_bytes, duration) / HOURS_PER_DAY;
+    }
+
+    pub fn add_event(&mut self) {
+        self.events += 1.0;
+    }
+
+    /// The weighted number of events the estimate is based on.
+    pub fn events(&self) -> f64 {
+        self.events
+    }
+
+    /// The weighted exposure the estimate is based on, in GB-days.
+    pub fn gb_days(&self) -> f64 {
+        self.gb_days
+    }
+
+    /// None until there is any exposure.
+    pub fn per_gb_day(&self) -> Option<f64> {
+        (self.gb_days > 0.0).then(|| self.events / self.gb_days)
+    }
+}
@@ -0,0 +1,1556 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::num::{ParseFloatError, ParseIntError};
+use std::path::Path;
+use std::str::ParseBoolError;
+
+use serde::{Deserialize, Deserializer, Serialize};
+use uuid::Uuid;
+
+use crate::{
+    binlog,
+    clock::{ClockSync, TimeSource},
+    detector::FlipInfo,
+    station::{location_columns, Location},
+};
+
+/// The version of the log format, bumped whenever records change in a way readers need to know about.
+pub const SCHEMA_VERSION: u32 = 6;
+
+/// The static key=value tags given with `run --tag`, such as the room or the shielding of the station.
+pub type Tags = BTreeMap<String, String>;
+
+/// A flip that changed more bytes than this, which no particle does, logs the diffs of only the first ones.
+pub const MAX_LOGGED_DIFFS: usize = 64;
+
+/// CSV columns starting with this are tags, as in `tag.room=basement`.
+const TAG_PREFIX: &str = "tag.";
+
+/// What the first bytes of every SQLite database are, for telling a --log-backend sqlite log from a file one.
+pub const SQLITE_MAGIC: &[u8] = b"SQLite format 3\0";
+
+/// One line of the log file.
+///
+/// Every record but the session carries the station's `sequence` number, which goes up by one with every record logged
+/// by the station across restarts, so that gaps and duplicates show in merged data. Older logs don't have them.
+/// They also carry the station's tags, so that site specific context travels with every record.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Record {
+    /// Describes the software that wrote the following entries, written right before every start entry.
+    Session(SessionInfo),
+    /// Written once every time the program starts.
+    Start {
+        #[serde(deserialize_with = "deserialize_ms")]
+        started_ms: u128,
+        delay_ms: u64,
+        /// The process ID of the instance, missing in logs written by older versions.
+        pid: Option<u32>,
+        location: Option<Location>,
+        #[serde(default, skip_serializing_if = "Tags::is_empty")]
+        tags: Tags,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        sequence: Option<u64>,
+    },
+    /// Written every time a bitflip is detected.
+    Flip {
+        #[serde(deserialize_with = "deserialize_ms")]
+        started_ms: u128,
+        delay_ms: u64,
+        /// The number of integrity checks since the previous bitflip.
+        checks: u64,
+        /// The changed byte could no longer be found when we went looking for it.
+        vanished: bool,
+        #[serde(deserialize_with = "deserialize_ms")]
+        detected_ms: u128,
+        location: Option<Location>,
+        /// How precisely the flip is known, missing in logs written by older versions.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        uncertainty: Option<Uncertainty>,
+        /// The number of bytes the check found changed, missing in logs of older versions.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        changed_bytes: Option<u64>,
+        /// Which bits of the changed bytes flipped, for up to `MAX_LOGGED_DIFFS` of them.
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        diffs: Vec<FlipDiff>,
+        /// Found during `--warmup`, while the machine was still settling, and left out of the rates.
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        warmup: bool,
+        #[serde(default, skip_serializing_if = "Tags::is_empty")]
+        tags: Tags,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        sequence: Option<u64>,
+    },
+    /// Written regularly while nothing happens, so that it's clear the detector was running and checking.
+    Heartbeat {
+        #[serde(deserialize_with = "deserialize_ms")]
+        timestamp_ms: u128,
+        total_checks: u64,
+        /// What the checks since the previous heartbeat cost, where the processor's energy counters can be read.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        energy: Option<Energy>,
+        /// How much the machine paged and swapped since the previous heartbeat, on Linux.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        paging: Option<Paging>,
+        /// Whether the detector was in memory for the checks since the previous heartbeat, where that is checked.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        residency: Option<Residency>,
+        /// How well the checks since the previous heartbeat kept to their deadlines, with --fixed-cadence.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        cadence: Option<Cadence>,
+        /// How much firmware got in the way of the checks since the previous heartbeat.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        smi: Option<Smi>,
+        #[serde(default, skip_serializing_if = "Tags::is_empty")]
+        tags: Tags,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        sequence: Option<u64>,
+    },
+    /// The memory error counts of the whole machine reported by EDAC, written whenever they change.
+    /// The counts are totals since the machine booted.
+    MemoryErrors {
+        #[serde(deserialize_with = "deserialize_ms")]
+        timestamp_ms: u128,
+        corrected: u64,
+        uncorrected: u64,
+        #[serde(default, skip_serializing_if = "Tags::is_empty")]
+        tags: Tags,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        sequence: Option<u64>,
+    },
+    /// Written in front of the next record that made it into the log after heartbeats or status updates had to be
+    /// dropped, because the log, the collector or stdout couldn't keep up with the detector.
+    Dropped {
+        #[serde(deserialize_with = "deserialize_ms")]
+        timestamp_ms: u128,
+        records: u64,
+        #[serde(default, skip_serializing_if = "Tags::is_empty")]
+        tags: Tags,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        sequence: Option<u64>,
+    },
+    /// Written when the CRC checksums and the full scan of the detector disagree about whether it is intact. A bitflip
+    /// can't do that, it means a bug in one of the two, and the full scan is taken to be right.
+    Disagreement {
+        #[serde(deserialize_with = "deserialize_ms")]
+        timestamp_ms: u128,
+        total_checks: u64,
+        crc_intact: bool,
+        scan_intact: bool,
+        #[serde(default, skip_serializing_if = "Tags::is_empty")]
+        tags: Tags,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        sequence: Option<u64>,
+    },
+    /// Written when a check panicked. The panic is caught, the detector written again and the session goes on, but
+    /// nothing is known about flips during the check.
+    Panic {
+        #[serde(deserialize_with = "deserialize_ms")]
+        timestamp_ms: u128,
+        total_checks: u64,
+        /// What the panic said, without commas or line breaks.
+        message: String,
+        #[serde(default, skip_serializing_if = "Tags::is_empty")]
+        tags: Tags,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        sequence: Option<u64>,
+    },
+    /// Written when the program stops cleanly, so that a session without one ended in a crash or a power cut.
+    Shutdown {
+        #[serde(deserialize_with = "deserialize_ms")]
+        timestamp_ms: u128,
+        total_checks: u64,
+        /// The bitflips found in the session, those during the warm-up included.
+        flips: u64,
+        uptime_ms: u64,
+        #[serde(default, skip_serializing_if = "Tags::is_empty")]
+        tags: Tags,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        sequence: Option<u64>,
+    },
+}
+
+/// Metadata about the session, so that data can be filtered by the software version that produced it.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SessionInfo {
+    /// Unique for every run, together with a sequence number it identifies records across stations.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session_id: Option<String>,
+    pub version: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub git_hash: Option<String>,
+    pub schema: u32,
+    /// The seed of the random number generator, so that the run can be repeated.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,
+    /// The size of the detector, for working out the exposure of the session.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub detector_bytes: Option<u64>,
+    /// The altitude of the station in meters, from the station file.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub altitude_m: Option<f64>,
+    /// Whether the detector's memory was locked so that it couldn't be swapped out, unknown in older logs.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memory_locked: Option<bool>,
+    /// How long after starting the session counted no exposure, with `--warmup`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub warmup_ms: Option<u64>,
+    /// The processor and memory the detector ran on, as far as they could be found out.
+    #[serde(flatten)]
+    pub hardware: Hardware,
+}
+
+/// The processor and memory of the station. Error rates differ a lot between memory generations and speeds, and
+/// overclocked memory has far more errors, so data from different machines can only be compared knowing them.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Hardware {
+    /// The processor's model name, e.g. `Intel(R) Core(TM) i7-8700K CPU @ 3.70GHz`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cpu_model: Option<String>,
+    /// The vendor, family, model and stepping of the processor, e.g. `GenuineIntel 6/158/10`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cpu_signature: Option<String>,
+    /// The microarchitecture the kernel's performance counters are made for, e.g. `skylake`. Only known on Intel.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cpu_microarchitecture: Option<String>,
+    /// The memory generation, e.g. `DDR4`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memory_type: Option<String>,
+    /// The part numbers of the memory modules, separated by semicolons when they differ.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memory_parts: Option<String>,
+    /// The slowest speed in MT/s any of the modules is made for, without an overclocking profile.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memory_rated_mts: Option<u64>,
+    /// The speed in MT/s the memory actually runs at.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memory_configured_mts: Option<u64>,
+    /// Whether the memory runs faster than its modules are rated for, which is what an XMP or EXPO profile does.
+    /// Some firmware reports the profile's speed as the rated one, so false doesn't rule a profile out.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memory_xmp: Option<bool>,
+    /// How many NUMA nodes the memory is spread over, only known on Linux.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub numa_nodes: Option<u32>,
+}
+
+impl Hardware {
+    pub fn fields(&self) -> Vec<(&'static str, String)> {
+        let mut fields = Vec::new();
+        let strings = [
+            ("cpu_model", &self.cpu_model),
+            ("cpu_signature", &self.cpu_signature),
+            ("cpu_microarchitecture", &self.cpu_microarchitecture),
+            ("memory_type", &self.memory_type),
+            ("memory_parts", &self.memory_parts),
+        ];
+        for (key, value) in strings {
+            if let Some(value) = value {
+                fields.push((key, value.clone()));
+            }
+        }
+        if let Some(memory_rated_mts) = self.memory_rated_mts {
+            fields.push(("memory_rated_mts", memory_rated_mts.to_string()));
+        }
+        if let Some(memory_configured_mts) = self.memory_configured_mts {
+            fields.push(("memory_configured_mts", memory_configured_mts.to_string()));
+        }
+        if let Some(memory_xmp) = self.memory_xmp {
+            fields.push(("memory_xmp", memory_xmp.to_string()));
+        }
+        if let Some(numa_nodes) = self.numa_nodes {
+            fields.push(("numa_nodes", numa_nodes.to_string()));
+        }
+        fields
+    }
+
+    /// Builds the fields from key value pairs, ignoring keys this version doesn't know about.
+    pub fn from_fields<'a>(fields: impl IntoIterator<Item = (&'a str, &'a str)>) -> Result<Self, String> {
+        let mut hardware = Hardware::default();
+        for (key, value) in fields {
+            match key {
+                "cpu_model" => hardware.cpu_model = Some(value.to_string()),
+                "cpu_signature" => hardware.cpu_signature = Some(value.to_string()),
+                "cpu_microarchitecture" => hardware.cpu_microarchitecture = Some(value.to_string()),
+                "memory_type" => hardware.memory_type = Some(value.to_string()),
+                "memory_parts" => hardware.memory_parts = Some(value.to_string()),
+                "memory_rated_mts" => {
+                    hardware.memory_rated_mts = Some(value.parse().map_err(|e: ParseIntError| e.to_string())?)
+                }
+                "memory_configured_mts" => {
+                    hardware.memory_configured_mts = Some(value.parse().map_err(|e: ParseIntError| e.to_string())?)
+                }
+                "memory_xmp" => hardware.memory_xmp = Some(value.parse().map_err(|e: ParseBoolError| e.to_string())?),
+                "numa_nodes" => {
+                    hardware.numa_nodes = Some(value.parse().map_err(|e: ParseIntError| e.to_string())?)
+                }
+                _ => {}
+            }
+        }
+        Ok(hardware)
+    }
+}
+
+/// How precisely a bitflip is known, so that statistical analyses don't have to guess it from the check interval.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Uncertainty {
+    /// The length of the window the flip happened in, from the start of the last check that passed
+    /// (or the end of the reset) to the end of the check that found it.
+    pub window_ms: u64,
+    /// How long the check that found the flip took to read the whole detector.
+    pub scan_ms: u64,
+    /// Whether the system clock was synchronized when the flip was found.
+    pub clock: ClockSync,
+    /// How far off the clock could be at most, when it was synchronized.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub clock_max_error_ms: Option<u64>,
+    /// The fraction of all possible single bit flips in the detector that its pattern can reveal.
+    pub pattern_coverage: f64,
+    /// The smallest fraction of the detector's pages found in memory before the checks around the window, when that
+    /// was below the minimum. The rest was swapped out for part of the window, where no cosmic ray could hit it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resident_fraction: Option<f64>,
+    /// Where the detection time came from, the system clock unless a PPS source corrected it.
+    #[serde(default, skip_serializing_if = "TimeSource::is_system")]
+    pub time_source: TimeSource,
+    /// The detection time in nanoseconds since the Unix epoch, when a PPS source makes it better than a millisecond.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub detected_ns: Option<u128>,
+    /// The SMIs counted in the window, where the processor's counter can be read.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub smi_count: Option<u64>,
+    /// Whether firmware was busy in the window, with SMIs above the heavy rate or a scan that took far too long.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub heavy_smi: bool,
+}
+
+impl Uncertainty {
+    /// The fields as key value pairs, stored the same way as the session metadata so that they can grow freely.
+    pub fn fields(&self) -> Vec<(&'static str, String)> {
+        let mut fields = vec![
+            ("window_ms", self.window_ms.to_string()),
+            ("scan_ms", self.scan_ms.to_string()),
+            ("clock", self.clock.as_str().to_string()),
+        ];
+        if let Some(clock_max_error_ms) = self.clock_max_error_ms {
+            fields.push(("clock_max_error_ms", clock_max_error_ms.to_string()));
+        }
+        fields.push(("pattern_coverage", self.pattern_coverage.to_string()));
+        if let Some(resident_fraction) = self.resident_fraction {
+            fields.push(("resident_fraction", resident_fraction.to_string()));
+        }
+        if !self.time_source.is_system() {
+            fields.push(("time_source", self.time_source.as_str().to_string()));
+        }
+        if let Some(detected_ns) = self.detected_ns {
+            fields.push(("detected_ns", detected_ns.to_string()));
+        }
+        if let Some(smi_count) = self.smi_count {
+            fields.push(("smi_count", smi_count.to_string()));
+        }
+        if self.heavy_smi {
+            fields.push(("heavy_smi", true.to_string()));
+        }
+        fields
+    }
+
+    /// Builds the fields from key value pairs, ignoring keys this version doesn't know about.
+    pub fn from_fields<'a>(fields: impl IntoIterator<Item = (&'a str, &'a str)>) -> Result<Self, String> {
+        let (mut window_ms, mut scan_ms, mut pattern_coverage) = (None, None, None);
+        let mut uncertainty = Uncertainty {
+            window_ms: 0,
+            scan_ms: 0,
+            clock: ClockSync::Unknown,
+            clock_max_error_ms: None,
+            pattern_coverage: 0.0,
+            resident_fraction: None,
+            time_source: TimeSource::System,
+            detected_ns: None,
+            smi_count: None,
+            heavy_smi: false,
+        };
+        for (key, value) in fields {
+            match key {
+                "window_ms" => window_ms = Some(value.parse().map_err(|e: ParseIntError| e.to_string())?),
+                "scan_ms" => scan_ms = Some(value.parse().map_err(|e: ParseIntError| e.to_string())?),
+                "clock" => uncertainty.clock = ClockSync::parse(value)?,
+                "clock_max_error_ms" => {
+                    uncertainty.clock_max_error_ms = Some(value.parse().map_err(|e: ParseIntError| e.to_string())?)
+                }
+                "pattern_coverage" => {
+                    pattern_coverage = Some(value.parse().map_err(|e: ParseFloatError| e.to_string())?)
+                }
+                "resident_fraction" => {
+                    uncertainty.resident_fraction = Some(value.parse().map_err(|e: ParseFloatError| e.to_string())?)
+                }
+                "time_source" => uncertainty.time_source = TimeSource::parse(value)?,
+                "detected_ns" => uncertainty.detected_ns = Some(value.parse().map_err(|e: ParseIntError| e.to_string())?),
+                "smi_count" => uncertainty.smi_count = Some(value.parse().map_err(|e: ParseIntError| e.to_string())?),
+                "heavy_smi" => uncertainty.heavy_smi = value.parse().map_err(|e: ParseBoolError| e.to_string())?,
+                _ => {}
+            }
+        }
+        // Guessing any of these would defeat their purpose.
+        uncertainty.window_ms = window_ms.ok_or("The uncertainty has no window_ms")?;
+        uncertainty.scan_ms = scan_ms.ok_or("The uncertainty has no scan_ms")?;
+        uncertainty.pattern_coverage = pattern_coverage.ok_or("The uncertainty has no pattern_coverage")?;
+        Ok(uncertainty)
+    }
+}
+
+/// How the changed byte differed from the value the detector was written with. Single and multi-bit flips in a byte
+/// are very different events, a single particle rarely flips more than one bit.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct FlipDiff {
+    /// The index of the byte in the detector.
+    pub index: u64,
+    pub expected: u8,
+    pub observed: u8,
+    /// The positions of the bits that flipped, 0 being the least significant one.
+    pub bits: Vec<u8>,
+    /// Whether the byte is in the inverted half of a detector with `--inverted-halves`, none without them.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub inverted: Option<bool>,
+    /// The physical address of the byte, where the kernel shows it. It ties the flip to a DIMM and a row of it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub physical_address: Option<u64>,
+    /// The NUMA node the byte's memory is on, only on machines with more than one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub numa_node: Option<u32>,
+    /// The chunk of the detector the byte is in, missing in logs of older versions.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chunk: Option<u64>,
+    /// The offset of the byte in its chunk, `index` being the same byte's place across all the chunks.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub offset: Option<u64>,
+}
+
+impl FlipDiff {
+    const KEYS: [&'static str; 9] =
+        ["index", "expected", "observed", "bits", "inverted", "physical_address", "numa_node", "chunk", "offset"];
+
+    /// The fields as key value pairs, the bit positions separated by semicolons and the physical address in hex.
+    pub fn fields(&self) -> Vec<(&'static str, String)> {
+        let bits: Vec<String> = self.bits.iter().map(u8::to_string).collect();
+        let mut fields = vec![
+            ("index", self.index.to_string()),
+            ("expected", self.expected.to_string()),
+            ("observed", self.observed.to_string()),
+            ("bits", bits.join(";")),
+        ];
+        if let Some(inverted) = self.inverted {
+            fields.push(("inverted", inverted.to_string()));
+        }
+        if let Some(physical_address) = self.physical_address {
+            fields.push(("physical_address", format!("{:#x}", physical_address)));
+        }
+        if let Some(numa_node) = self.numa_node {
+            fields.push(("numa_node", numa_node.to_string()));
+        }
+        if let Some((chunk, offset)) = self.chunk.zip(self.offset) {
+            fields.push(("chunk", chunk.to_string()));
+            fields.push(("offset", offset.to_string()));
+        }
+        fields
+    }
+
+    /// Builds the fields from key value pairs, ignoring keys this version doesn't know about.
+    pub fn from_fields<'a>(fields: impl IntoIterator<Item = (&'a str, &'a str)>) -> Result<Self, String> {
+        let (mut index, mut expected, mut observed, mut bits) = (None, None, None, None);
+        let (mut inverted, mut physical_address, mut numa_node) = (None, None, None);
+        let (mut chunk, mut offset) = (None, None);
+        for (key, value) in fields {
+            match key {
+                "index" => index = Some(value.parse().map_err(|e: ParseIntError| e.to_string())?),
+                "expected" => expected = Some(value.parse().map_err(|e: ParseIntError| e.to_string())?),
+                "observed" => observed = Some(value.parse().map_err(|e: ParseIntError| e.to_string())?),
+                "bits" => {
+                    bits = Some(
+                        value
+                            .split(';')
+                            .filter(|bit| !bit.is_empty())
+                            .map(|bit| bit.parse().map_err(|e: ParseIntError| e.to_string()))
+                            .collect::<Result<Vec<u8>, _>>()?,
+                    )
+                }
+                "inverted" => inverted = Some(value.parse().map_err(|e: ParseBoolError| e.to_string())?),
+                "physical_address" => {
+                    let hex = value.trim_start_matches("0x");
+                    physical_address = Some(u64::from_str_radix(hex, 16).map_err(|e| e.to_string())?)
+                }
+                "numa_node" => numa_node = Some(value.parse().map_err(|e: ParseIntError| e.to_string())?),
+                "chunk" => chunk = Some(value.parse().map_err(|e: ParseIntError| e.to_string())?),
+                "offset" => offset = Some(value.parse().map_err(|e: ParseIntError| e.to_string())?),
+                _ => {}
+            }
+        }
+        Ok(FlipDiff {
+            index: index.ok_or("The diff has no index")?,
+            expected: expected.ok_or("The diff has no expected value")?,
+            observed: observed.ok_or("The diff has no observed value")?,
+            bits: bits.ok_or("The diff has no bits")?,
+            inverted,
+            physical_address,
+            numa_node,
+            chunk,
+            offset,
+        })
+    }
+}
+
+impl From<FlipInfo> for FlipDiff {
+    fn from(info: FlipInfo) -> Self {
+        FlipDiff {
+            index: info.index as u64,
+            expected: info.expected,
+            observed: info.observed,
+            bits: info.changed_bits(),
+            inverted: info.inverted,
+            physical_address: None,
+            numa_node: None,
+            chunk: None,
+            offset: None,
+        }
+    }
+}
+
+/// What a flip carries besides its times and counts, all stored as key value pairs after the location.
+#[derive(Default)]
+pub struct FlipFields {
+    pub uncertainty: Option<Uncertainty>,
+    pub changed_bytes: Option<u64>,
+    pub diffs: Vec<FlipDiff>,
+    pub warmup: bool,
+}
+
+impl FlipFields {
+    /// The diffs go last, each one starting with its index.
+    pub fn fields(&self) -> Vec<(&'static str, String)> {
+        self.uncertainty
+            .iter()
+            .flat_map(Uncertainty::fields)
+            .chain(self.changed_bytes.map(|changed_bytes| ("changed_bytes", changed_bytes.to_string())))
+            .chain(self.warmup.then(|| ("warmup", true.to_string())))
+            .chain(self.diffs.iter().flat_map(FlipDiff::fields))
+            .collect()
+    }
+
+    /// The uncertainty is there if any key but those of the diffs is.
+    pub fn from_fields<'a>(fields: impl IntoIterator<Item = (&'a str, &'a str)>) -> Result<Self, String> {
+        let fields: Vec<(&str, &str)> = fields.into_iter().collect();
+        let diff_starts: Vec<usize> = (0..fields.len()).filter(|&position| fields[position].0 == "index").collect();
+        let diffs = diff_starts
+            .iter()
+            .enumerate()
+            .map(|(number, &start)| {
+                let end = diff_starts.get(number + 1).copied().unwrap_or(fields.len());
+                FlipDiff::from_fields(fields[start..end].iter().copied())
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let changed_bytes = match fields.iter().find(|(key, _)| *key == "changed_bytes") {
+            Some((_, value)) => Some(value.parse().map_err(|e: ParseIntError| e.to_string())?),
+            None => None,
+        };
+        let warmup = match fields.iter().find(|(key, _)| *key == "warmup") {
+            Some((_, value)) => value.parse().map_err(|e: ParseBoolError| e.to_string())?,
+            None => false,
+        };
+        let other_keys = fields
+            .iter()
+            .any(|(key, _)| !["changed_bytes", "warmup"].contains(key) && !FlipDiff::KEYS.contains(key));
+        Ok(FlipFields {
+            uncertainty: match other_keys {
+                true => Some(Uncertainty::from_fields(fields.iter().copied())?),
+                false => None,
+            },
+            changed_bytes,
+            diffs,
+            warmup,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.uncertainty.is_none() && self.changed_bytes.is_none() && self.diffs.is_empty() && !self.warmup
+    }
+}
+
+/// The average energy the processors and memory used while checking the detector, measured with RAPL.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Energy {
+    pub joules_per_check: f64,
+    /// The energy of a check divided by the size of the detector.
+    pub joules_per_gb: f64,
+}
+
+impl Energy {
+    pub fn fields(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("joules_per_check", self.joules_per_check.to_string()),
+            ("joules_per_gb", self.joules_per_gb.to_string()),
+        ]
+    }
+
+    /// Builds the fields from key value pairs, ignoring keys this version doesn't know about.
+    pub fn from_fields<'a>(fields: impl IntoIterator<Item = (&'a str, &'a str)>) -> Result<Self, String> {
+        let (mut joules_per_check, mut joules_per_gb) = (None, None);
+        for (key, value) in fields {
+            match key {
+                "joules_per_check" => {
+                    joules_per_check = Some(value.parse().map_err(|e: ParseFloatError| e.to_string())?)
+                }
+                "joules_per_gb" => joules_per_gb = Some(value.parse().map_err(|e: ParseFloatError| e.to_string())?),
+                _ => {}
+            }
+        }
+        Ok(Energy {
+            joules_per_check: joules_per_check.ok_or("The energy has no joules_per_check")?,
+            joules_per_gb: joules_per_gb.ok_or("The energy has no joules_per_gb")?,
+        })
+    }
+}
+
+/// The paging activity of the whole machine over a stretch of time, from the kernel's counters. While pages are
+/// swapped out some of the detector may not have been in memory at all, so analyses can leave such times out.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Paging {
+    pub paged_in_kb: u64,
+    pub paged_out_kb: u64,
+    pub swapped_in_pages: u64,
+    pub swapped_out_pages: u64,
+}
+
+impl Paging {
+    pub fn fields(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("paged_in_kb", self.paged_in_kb.to_string()),
+            ("paged_out_kb", self.paged_out_kb.to_string()),
+            ("swapped_in_pages", self.swapped_in_pages.to_string()),
+            ("swapped_out_pages", self.swapped_out_pages.to_string()),
+        ]
+    }
+
+    /// Builds the fields from key value pairs, ignoring keys this version doesn't know about.
+    pub fn from_fields<'a>(fields: impl IntoIterator<Item = (&'a str, &'a str)>) -> Result<Self, String> {
+        let mut paging = Paging { paged_in_kb: 0, paged_out_kb: 0, swapped_in_pages: 0, swapped_out_pages: 0 };
+        for (key, value) in fields {
+            let field = match key {
+                "paged_in_kb" => &mut paging.paged_in_kb,
+                "paged_out_kb" => &mut paging.paged_out_kb,
+                "swapped_in_pages" => &mut paging.swapped_in_pages,
+                "swapped_out_pages" => &mut paging.swapped_out_pages,
+                _ => continue,
+            };
+            *field = value.parse().map_err(|e: ParseIntError| e.to_string())?;
+        }
+        Ok(paging)
+    }
+
+    pub fn swapped(&self) -> bool {
+        self.swapped_in_pages > 0 || self.swapped_out_pages > 0
+    }
+}
+
+/// How much of the detector was in memory before the checks since the previous heartbeat.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Residency {
+    /// The checks that found less of the detector in memory than the minimum, and faulted the rest back in first.
+    pub reduced_checks: u64,
+    /// The smallest fraction of the detector's pages any of the checks found in memory.
+    pub min_resident_fraction: f64,
+}
+
+impl Residency {
+    pub fn fields(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("reduced_checks", self.reduced_checks.to_string()),
+            ("min_resident_fraction", self.min_resident_fraction.to_string()),
+        ]
+    }
+
+    /// Builds the fields from key value pairs, ignoring keys this version doesn't know about.
+    pub fn from_fields<'a>(fields: impl IntoIterator<Item = (&'a str, &'a str)>) -> Result<Self, String> {
+        let (mut reduced_checks, mut min_resident_fraction) = (None, None);
+        for (key, value) in fields {
+            match key {
+                "reduced_checks" => reduced_checks = Some(value.parse().map_err(|e: ParseIntError| e.to_string())?),
+                "min_resident_fraction" => {
+                    min_resident_fraction = Some(value.parse().map_err(|e: ParseFloatError| e.to_string())?)
+                }
+                _ => {}
+            }
+        }
+        Ok(Residency {
+            reduced_checks: reduced_checks.ok_or("The residency has no reduced_checks")?,
+            min_resident_fraction: min_resident_fraction.ok_or("The residency has no min_resident_fraction")?,
+        })
+    }
+}
+
+/// How far the checks started after their deadlines, when they are scheduled at fixed times.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Cadence {
+    /// The checks that started more than the tolerance after their deadline.
+    pub late_checks: u64,
+    /// The deadlines that passed without a check at all, because the one before took too long.
+    pub missed_deadlines: u64,
+    /// The median, 99th percentile and largest time the checks started after their deadlines.
+    pub late_p50_ms: f64,
+    pub late_p99_ms: f64,
+    pub late_max_ms: f64,
+}
+
+impl Cadence {
+    pub fn fields(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("late_checks", self.late_checks.to_string()),
+            ("missed_deadlines", self.missed_deadlines.to_string()),
+            ("late_p50_ms", self.late_p50_ms.to_string()),
+            ("late_p99_ms", self.late_p99_ms.to_string()),
+            ("late_max_ms", self.late_max_ms.to_string()),
+        ]
+    }
+
+    /// Builds the fields from key value pairs, ignoring keys this version doesn't know about.
+    pub fn from_fields<'a>(fields: impl IntoIterator<Item = (&'a str, &'a str)>) -> Result<Self, String> {
+        let mut cadence =
+            Cadence { late_checks: 0, missed_deadlines: 0, late_p50_ms: 0.0, late_p99_ms: 0.0, late_max_ms: 0.0 };
+        for (key, value) in fields {
+            match key {
+                "late_checks" => cadence.late_checks = value.parse().map_err(|e: ParseIntError| e.to_string())?,
+                "missed_deadlines" => {
+                    cadence.missed_deadlines = value.parse().map_err(|e: ParseIntError| e.to_string())?
+                }
+                "late_p50_ms" => cadence.late_p50_ms = value.parse().map_err(|e: ParseFloatError| e.to_string())?,
+                "late_p99_ms" => cadence.late_p99_ms = value.parse().map_err(|e: ParseFloatError| e.to_string())?,
+                "late_max_ms" => cadence.late_max_ms = value.parse().map_err(|e: ParseFloatError| e.to_string())?,
+                _ => {}
+            }
+        }
+        Ok(cadence)
+    }
+}
+
+/// The system management interrupts during a stretch of checks. Firmware runs while they last and can touch memory,
+/// so checks during heavy SMI activity are suspect on some platforms.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Smi {
+    /// The SMIs counted by the processor, where its counter can be read.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub smi_count: Option<u64>,
+    /// The checks whose scan took far longer than the fastest one, which is how SMIs show without the counter.
+    pub slow_checks: u64,
+    /// The checks whose window had more SMIs than the heavy rate, or that were slow.
+    pub heavy_checks: u64,
+}
+
+impl Smi {
+    pub fn fields(&self) -> Vec<(&'static str, String)> {
+        let mut fields = Vec::new();
+        if let Some(smi_count) = self.smi_count {
+            fields.push(("smi_count", smi_count.to_string()));
+        }
+        fields.push(("smi_slow_checks", self.slow_checks.to_string()));
+        fields.push(("smi_heavy_checks", self.heavy_checks.to_string()));
+        fields
+    }
+
+    /// Builds the fields from key value pairs, ignoring keys this version doesn't know about.
+    pub fn from_fields<'a>(fields: impl IntoIterator<Item = (&'a str, &'a str)>) -> Result<Self, String> {
+        let mut smi = Smi { smi_count: None, slow_checks: 0, heavy_checks: 0 };
+        for (key, value) in fields {
+            match key {
+                "smi_count" => smi.smi_count = Some(value.parse().map_err(|e: ParseIntError| e.to_string())?),
+                "smi_slow_checks" => smi.slow_checks = value.parse().map_err(|e: ParseIntError| e.to_string())?,
+                "smi_heavy_checks" => smi.heavy_checks = value.parse().map_err(|e: ParseIntError| e.to_string())?,
+                _ => {}
+            }
+        }
+        Ok(smi)
+    }
+}
+
+/// What a heartbeat carries besides its time and number of checks, each part there if any of its keys is.
+#[derive(Default)]
+pub struct HeartbeatFields {
+    pub energy: Option<Energy>,
+    pub paging: Option<Paging>,
+    pub residency: Option<Residency>,
+    pub cadence: Option<Cadence>,
+    pub smi: Option<Smi>,
+}
+
+impl HeartbeatFields {
+    pub fn fields(&self) -> Vec<(&'static str, String)> {
+        self.energy
+            .iter()
+            .flat_map(Energy::fields)
+            .chain(self.paging.iter().flat_map(Paging::fields))
+            .chain(self.residency.iter().flat_map(Residency::fields))
+            .chain(self.cadence.iter().flat_map(Cadence::fields))
+            .chain(self.smi.iter().flat_map(Smi::fields))
+            .collect()
+    }
+
+    pub fn from_fields<'a>(fields: impl IntoIterator<Item = (&'a str, &'a str)>) -> Result<Self, String> {
+        let fields: Vec<(&str, &str)> = fields.into_iter().collect();
+        let has = |prefixes: &[&str]| fields.iter().any(|(key, _)| prefixes.iter().any(|prefix| key.starts_with(prefix)));
+        Ok(HeartbeatFields {
+            energy: match has(&["joules_"]) {
+                true => Some(Energy::from_fields(fields.iter().copied())?),
+                false => None,
+            },
+            paging: match has(&["paged_", "swapped_"]) {
+                true => Some(Paging::from_fields(fields.iter().copied())?),
+                false => None,
+            },
+            residency: match has(&["reduced_checks", "min_resident_fraction"]) {
+                true => Some(Residency::from_fields(fields.iter().copied())?),
+                false => None,
+            },
+            cadence: match has(&["late_", "missed_deadlines"]) {
+                true => Some(Cadence::from_fields(fields.iter().copied())?),
+                false => None,
+            },
+            smi: match has(&["smi_"]) {
+                true => Some(Smi::from_fields(fields.iter().copied())?),
+                false => None,
+            },
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.energy.is_none()
+            && self.paging.is_none()
+            && self.residency.is_none()
+            && self.cadence.is_none()
+            && self.smi.is_none()
+    }
+}
+
+impl SessionInfo {
+    /// Metadata describing this build of the program, for a new session.
+    pub fn current() -> Self {
+        SessionInfo {
+            session_id: Some(Uuid::new_v4().to_string()),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            git_hash: option_env!("GIT_HASH").map(String::from),
+            schema: SCHEMA_VERSION,
+            seed: None,
+            detector_bytes: None,
+            altitude_m: None,
+            memory_locked: None,
+            warmup_ms: None,
+            hardware: Hardware::default(),
+        }
+    }
+
+    /// The metadata as key value pairs, which is how both log formats store it so that it can grow freely.
+    pub fn fields(&self) -> Vec<(&'static str, String)> {
+        let mut fields = Vec::new();
+        if let Some(session_id) = &self.session_id {
+            fields.push(("session_id", session_id.clone()));
+        }
+        fields.push(("version", self.version.clone()));
+        if let Some(git_hash) = &self.git_hash {
+            fields.push(("git_hash", git_hash.clone()));
+        }
+        fields.push(("schema", self.schema.to_string()));
+        if let Some(seed) = self.seed {
+            fields.push(("seed", seed.to_string()));
+        }
+        if let Some(detector_bytes) = self.detector_bytes {
+            fields.push(("detector_bytes", detector_bytes.to_string()));
+        }
+        if let Some(altitude_m) = self.altitude_m {
+            fields.push(("altitude_m", altitude_m.to_string()));
+        }
+        if let Some(memory_locked) = self.memory_locked {
+            fields.push(("memory_locked", memory_locked.to_string()));
+        }
+        if let Some(warmup_ms) = self.warmup_ms {
+            fields.push(("warmup_ms", warmup_ms.to_string()));
+        }
+        fields.extend(self.hardware.fields());
+        fields
+    }
+
+    /// Builds the metadata from key value pairs, ignoring keys this version doesn't know about.
+    pub fn from_fields<'a>(fields: impl IntoIterator<Item = (&'a str, &'a str)>) -> Result<Self, String> {
+        let fields: Vec<(&str, &str)> = fields.into_iter().collect();
+        let mut session = SessionInfo {
+            session_id: None,
+            version: String::new(),
+            git_hash: None,
+            schema: 1,
+            seed: None,
+            detector_bytes: None,
+            altitude_m: None,
+            memory_locked: None,
+            warmup_ms: None,
+            hardware: Hardware::from_fields(fields.iter().copied())?,
+        };
+        for (key, value) in fields {
+            match key {
+                "session_id" => session.session_id = Some(value.to_string()),
+                "version" => session.version = value.to_string(),
+                "git_hash" => session.git_hash = Some(value.to_string()),
+                "schema" => session.schema = value.parse().map_err(|e: ParseIntError| e.to_string())?,
+                "seed" => session.seed = Some(value.parse().map_err(|e: ParseIntError| e.to_string())?),
+                "detector_bytes" => {
+                    session.detector_bytes = Some(value.parse().map_err(|e: ParseIntError| e.to_string())?)
+                }
+                "altitude_m" => session.altitude_m = Some(value.parse().map_err(|e: ParseFloatError| e.to_string())?),
+                "memory_locked" => {
+                    session.memory_locked = Some(value.parse().map_err(|e: ParseBoolError| e.to_string())?)
+                }
+                "warmup_ms" => session.warmup_ms = Some(value.parse().map_err(|e: ParseIntError| e.to_string())?),
+                _ => {}
+            }
+        }
+        Ok(session)
+    }
+
+    fn to_csv(&self) -> String {
+        let mut line = String::from("#session");
+        for (key, value) in self.fields() {
+            line += &format!(",{}={}", key, value);
+        }
+        line + "\n"
+    }
+
+    /// Parses the key=value pairs following `#session`.
+    fn from_csv(fields: &str) -> Result<Self, String> {
+        let pairs = fields
+            .split(',')
+            .filter(|field| !field.is_empty())
+            .map(|field| {
+                field
+                    .split_once('=')
+                    .ok_or_else(|| format!("Malformed session field '{}'", field))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        SessionInfo::from_fields(pairs)
+    }
+}
+
+impl Record {
+    /// The kind of record, as in `type` of its JSON.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Record::Session(_) => "session",
+            Record::Start { .. } => "start",
+            Record::Flip { .. } => "flip",
+            Record::Heartbeat { .. } => "heartbeat",
+            Record::MemoryErrors { .. } => "memory_errors",
+            Record::Dropped { .. } => "dropped",
+            Record::Disagreement { .. } => "disagreement",
+            Record::Panic { .. } => "panic",
+            Record::Shutdown { .. } => "shutdown",
+        }
+    }
+
+    /// When the record was written in milliseconds since the Unix epoch, sessions have no time of their own.
+    pub fn timestamp_ms(&self) -> Option<u128> {
+        match self {
+            Record::Session(_) => None,
+            Record::Start { started_ms, .. } => Some(*started_ms),
+            Record::Flip { detected_ms, .. } => Some(*detected_ms),
+            Record::Heartbeat { timestamp_ms, .. }
+            | Record::MemoryErrors { timestamp_ms, .. }
+            | Record::Dropped { timestamp_ms, .. }
+            | Record::Disagreement { timestamp_ms, .. }
+            | Record::Panic { timestamp_ms, .. }
+            | Record::Shutdown { timestamp_ms, .. } => Some(*timestamp_ms),
+        }
+    }
+
+    /// The station's sequence number of the record, sessions have none.
+    pub fn sequence(&self) -> Option<u64> {
+        match self {
+            Record::Session(_) => None,
+            Record::Start { sequence, .. }
+            | Record::Flip { sequence, .. }
+            | Record::Heartbeat { sequence, .. }
+            | Record::MemoryErrors { sequence, .. }
+            | Record::Dropped { sequence, .. }
+            | Record::Disagreement { sequence, .. }
+            | Record::Panic { sequence, .. }
+            | Record::Shutdown { sequence, .. } => *sequence,
+        }
+    }
+
+    pub fn set_sequence(&mut self, number: Option<u64>) {
+        match self {
+            Record::Session(_) => {}
+            Record::Start { sequence, .. }
+            | Record::Flip { sequence, .. }
+            | Record::Heartbeat { sequence, .. }
+            | Record::MemoryErrors { sequence, .. }
+            | Record::Dropped { sequence, .. }
+            | Record::Disagreement { sequence, .. }
+            | Record::Panic { sequence, .. }
+            | Record::Shutdown { sequence, .. } => *sequence = number,
+        }
+    }
+
+    /// The station's tags on the record, sessions have none.
+    pub fn tags(&self) -> Option<&Tags> {
+        match self {
+            Record::Session(_) => None,
+            Record::Start { tags, .. }
+            | Record::Flip { tags, .. }
+            | Record::Heartbeat { tags, .. }
+            | Record::MemoryErrors { tags, .. }
+            | Record::Dropped { tags, .. }
+            | Record::Disagreement { tags, .. }
+            | Record::Panic { tags, .. }
+            | Record::Shutdown { tags, .. } => Some(tags),
+        }
+    }
+
+    pub fn set_tags(&mut self, new_tags: Tags) {
+        match self {
+            Record::Session(_) => {}
+            Record::Start { tags, .. }
+            | Record::Flip { tags, .. }
+            | Record::Heartbeat { tags, .. }
+            | Record::MemoryErrors { tags, .. }
+            | Record::Dropped { tags, .. }
+            | Record::Disagreement { tags, .. }
+            | Record::Panic { tags, .. }
+            | Record::Shutdown { tags, .. } => *tags = new_tags,
+        }
+    }
+
+    /// The energy, paging, residency, cadence and SMIs of a heartbeat, none for other records.
+    pub fn heartbeat_fields(&self) -> Option<HeartbeatFields> {
+        match self {
+            Record::Heartbeat { energy, paging, residency, cadence, smi, .. } => Some(HeartbeatFields {
+                energy: energy.clone(),
+                paging: paging.clone(),
+                residency: residency.clone(),
+                cadence: cadence.clone(),
+                smi: smi.clone(),
+            }),
+            _ => None,
+        }
+    }
+
+    /// The uncertainty and diffs of a flip, none for other records.
+    pub fn flip_fields(&self) -> Option<FlipFields> {
+        match self {
+            Record::Flip { uncertainty, changed_bytes, diffs, warmup, .. } => Some(FlipFields {
+                uncertainty: uncertainty.clone(),
+                changed_bytes: *changed_bytes,
+                diffs: diffs.clone(),
+                warmup: *warmup,
+            }),
+            _ => None,
+        }
+    }
+
+    /// Formats the record as a line of the CSV log, including the newline. The tags follow the record's own columns
+    /// as `tag.<key>=<value>` columns, and the sequence number goes last as a `seq=<number>` column.
+    pub fn to_csv(&self) -> String {
+        let mut line = self.csv_columns().trim_end_matches('\n').to_string();
+        for (key, value) in self.tags().into_iter().flatten() {
+            line += &format!(",{}{}={}", TAG_PREFIX, key, value);
+        }
+        if let Some(sequence) = self.sequence() {
+            line += &format!(",seq={}", sequence);
+        }
+        line + "\n"
+    }
+
+    fn csv_columns(&self) -> String {
+        match self {
+            Record::Session(session) => session.to_csv(),
+            Record::Start { started_ms, delay_ms, pid, location, .. } => {
+                let pid = pid.map(|pid| format!(",pid={}", pid)).unwrap_or_default();
+                format!("{},{},,{}{}\n", started_ms, delay_ms, location_columns(*location), pid)
+            }
+            Record::Flip { started_ms, delay_ms, checks, vanished, detected_ms, location, .. } => {
+                let extra = self.flip_fields().unwrap_or_default();
+                let columns: String =
+                    extra.fields().into_iter().map(|(key, value)| format!(",{}={}", key, value)).collect();
+                format!(
+                    "{},{},{},{},{}{}{}\n",
+                    started_ms,
+                    delay_ms,
+                    checks,
+                    *vanished as u8,
+                    detected_ms,
+                    location_columns(*location),
+                    columns
+                )
+            }
+            Record::Heartbeat { timestamp_ms, total_checks, .. } => {
+                let extra = self.heartbeat_fields().unwrap_or_default();
+                let columns: String =
+                    extra.fields().into_iter().map(|(key, value)| format!(",{}={}", key, value)).collect();
+                format!("#heartbeat,{},{}{}\n", timestamp_ms, total_checks, columns)
+            }
+            Record::MemoryErrors { timestamp_ms, corrected, uncorrected, .. } => {
+                format!("#memory_errors,{},{},{}\n", timestamp_ms, corrected, uncorrected)
+            }
+            Record::Dropped { timestamp_ms, records, .. } => format!("#dropped,{},{}\n", timestamp_ms, records),
+            Record::Disagreement { timestamp_ms, total_checks, crc_intact, scan_intact, .. } => format!(
+                "#disagreement,{},{},{},{}\n",
+                timestamp_ms, total_checks, *crc_intact as u8, *scan_intact as u8
+            ),
+            Record::Panic { timestamp_ms, total_checks, message, .. } => {
+                format!("#panic,{},{},{}\n", timestamp_ms, total_checks, message)
+            }
+            Record::Shutdown { timestamp_ms, total_checks, flips, uptime_ms, .. } => {
+                format!("#shutdown,{},{},{},{}\n", timestamp_ms, total_checks, flips, uptime_ms)
+            }
+        }
+    }
+
+    /// A copy of the record without anything that points at a specific machine or place:
+    /// the location is snapped to the given grid and the process ID is dropped.
+    pub fn anonymized(&self, grid_degrees: f64) -> Record {
+        let coarsen = |location: &Option<Location>| location.map(|location| location.coarsened(grid_degrees));
+        match self.clone() {
+            Record::Start { started_ms, delay_ms, location, tags, sequence, .. } => Record::Start {
+                started_ms,
+                delay_ms,
+                pid: None,
+                location: coarsen(&location),
+                tags,
+                sequence,
+            },
+            Record::Flip {
+                started_ms,
+                delay_ms,
+                checks,
+                vanished,
+                detected_ms,
+                location,
+                uncertainty,
+                changed_bytes,
+                diffs,
+                warmup,
+                tags,
+                sequence,
+            } => Record::Flip {
+                started_ms,
+                delay_ms,
+                checks,
+                vanished,
+                detected_ms,
+                location: coarsen(&location),
+                uncertainty,
+                changed_bytes,
+                diffs,
+                warmup,
+                tags,
+                sequence,
+            },
+            record => record,
+        }
+    }
+
+    /// Parses a single line of a text log, either CSV or JSON Lines. A log can hold both when the format was changed
+    /// between runs.
+    pub fn from_line(line: &str) -> Result<Self, String> {
+        match line.trim_start().starts_with('{') {
+            true => serde_json::from_str(line).map_err(|e| e.to_string()),
+            false => Record::from_csv(line),
+        }
+    }
+
+    /// Parses a single line of the CSV log.
+    pub fn from_csv(line: &str) -> Result<Self, String> {
+        let line = line.trim_end();
+        let (columns, sequence) = match line.rsplit_once(",seq=") {
+            Some((columns, sequence)) if !line.starts_with("#session") => {
+                (columns, Some(sequence.parse().map_err(|e: ParseIntError| e.to_string())?))
+            }
+            _ => (line, None),
+        };
+        let mut columns = columns;
+        let mut tags = Tags::new();
+        if !line.starts_with("#session") {
+            while let Some((rest, tag)) = columns.rsplit_once(',').filter(|(_, tag)| tag.starts_with(TAG_PREFIX)) {
+                let (key, value) = tag[TAG_PREFIX.len()..]
+                    .split_once('=')
+                    .ok_or_else(|| format!("Malformed tag '{}'", tag))?;
+                tags.insert(key.to_string(), value.to_string());
+                columns = rest;
+            }
+        }
+        let mut record = Self::from_csv_columns(columns)?;
+        record.set_sequence(sequence);
+        record.set_tags(tags);
+        Ok(record)
+    }
+
+    fn from_csv_columns(line: &str) -> Result<Self, String> {
+        if let Some(fields) = line.trim_end().strip_prefix("#session") {
+            return Ok(Record::Session(SessionInfo::from_csv(fields)?));
+        }
+        if let Some(fields) = line.trim_end().strip_prefix("#heartbeat,") {
+            let fields: Vec<&str> = fields.split(',').collect();
+            let [timestamp_ms, total_checks, extra_fields @ ..] = &fields[..] else {
+                return Err(format!("Malformed heartbeat '{}'", line));
+            };
+            // The energy, the paging, the residency, the cadence and the SMIs follow as key=value columns.
+            let extra = HeartbeatFields::from_fields(extra_fields.iter().filter_map(|field| field.split_once('=')))?;
+            return Ok(Record::Heartbeat {
+                timestamp_ms: timestamp_ms.parse().map_err(|e: ParseIntError| e.to_string())?,
+                total_checks: total_checks.parse().map_err(|e: ParseIntError| e.to_string())?,
+                energy: extra.energy,
+                paging: extra.paging,
+                residency: extra.residency,
+                cadence: extra.cadence,
+                smi: extra.smi,
+                tags: Tags::new(),
+                sequence: None,
+            });
+        }
+        if let Some(fields) = line.trim_end().strip_prefix("#memory_errors,") {
+            let fields: Vec<&str> = fields.split(',').collect();
+            let [timestamp_ms, corrected, uncorrected] = fields[..] else {
+                return Err(format!("Malformed memory error counts '{}'", line));
+            };
+            return Ok(Record::MemoryErrors {
+                timestamp_ms: timestamp_ms.parse().map_err(|e: ParseIntError| e.to_string())?,
+                corrected: corrected.parse().map_err(|e: ParseIntError| e.to_string())?,
+                uncorrected: uncorrected.parse().map_err(|e: ParseIntError| e.to_string())?,
+                tags: Tags::new(),
+                sequence: None,
+            });
+        }
+
+        if let Some(fields) = line.trim_end().strip_prefix("#dropped,") {
+            let (timestamp_ms, records) = fields
+                .split_once(',')
+                .ok_or_else(|| format!("Malformed dropped record count '{}'", line))?;
+            return Ok(Record::Dropped {
+                timestamp_ms: timestamp_ms.parse().map_err(|e: ParseIntError| e.to_string())?,
+                records: records.parse().map_err(|e: ParseIntError| e.to_string())?,
+                tags: Tags::new(),
+                sequence: None,
+            });
+        }
+
+        if let Some(fields) = line.trim_end().strip_prefix("#disagreement,") {
+            let fields: Vec<&str> = fields.split(',').collect();
+            let [timestamp_ms, total_checks, crc_intact, scan_intact] = fields[..] else {
+                return Err(format!("Malformed disagreement '{}'", line));
+            };
+            return Ok(Record::Disagreement {
+                timestamp_ms: timestamp_ms.parse().map_err(|e: ParseIntError| e.to_string())?,
+                total_checks: total_checks.parse().map_err(|e: ParseIntError| e.to_string())?,
+                crc_intact: crc_intact == "1",
+                scan_intact: scan_intact == "1",
+                tags: Tags::new(),
+                sequence: None,
+            });
+        }
+
+        if let Some(fields) = line.trim_end().strip_prefix("#panic,") {
+            let [timestamp_ms, total_checks, message] = fields.splitn(3, ',').collect::<Vec<&str>>()[..] else {
+                return Err(format!("Malformed panic '{}'", line));
+            };
+            return Ok(Record::Panic {
+                timestamp_ms: timestamp_ms.parse().map_err(|e: ParseIntError| e.to_string())?,
+                total_checks: total_checks.parse().map_err(|e: ParseIntError| e.to_string())?,
+                message: message.to_string(),
+                tags: Tags::new(),
+                sequence: None,
+            });
+        }
+
+        if let Some(fields) = line.trim_end().strip_prefix("#shutdown,") {
+            let fields: Vec<&str> = fields.split(',').collect();
+            let [timestamp_ms, total_checks, flips, uptime_ms] = fields[..] else {
+                return Err(format!("Malformed shutdown '{}'", line));
+            };
+            return Ok(Record::Shutdown {
+                timestamp_ms: timestamp_ms.parse().map_err(|e: ParseIntError| e.to_string())?,
+                total_checks: total_checks.parse().map_err(|e: ParseIntError| e.to_string())?,
+                flips: flips.parse().map_err(|e: ParseIntError| e.to_string())?,
+                uptime_ms: uptime_ms.parse().map_err(|e: ParseIntError| e.to_string())?,
+                tags: Tags::new(),
+                sequence: None,
+            });
+        }
+
+        let fields: Vec<&str> = line.trim_end().split(',').collect();
+        if fields.len() < 4 {
+            return Err(format!("Too few columns in log entry '{}'", line));
+        }
+
+        let started_ms: u128 = fields[0].parse().map_err(|e: ParseIntError| e.to_string())?;
+        let delay_ms: u64 = fields[1].parse().map_err(|e: ParseIntError| e.to_string())?;
+
+        if fields[2].is_empty() && fields[3].is_empty() {
+            // The PID follows the location as a key=value column, keys this version doesn't know are ignored.
+            let (extra_fields, location_fields): (Vec<&str>, Vec<&str>) =
+                fields[4..].iter().partition(|field| field.contains('='));
+            let location = parse_location(&location_fields)?;
+            let pid = extra_fields.iter().filter_map(|field| field.split_once('=')).find(|(key, _)| *key == "pid");
+            let pid = match pid {
+                Some((_, pid)) => Some(pid.parse().map_err(|e: ParseIntError| e.to_string())?),
+                None => None,
+            };
+            return Ok(Record::Start { started_ms, delay_ms, pid, location, tags: Tags::new(), sequence: None });
+        }
+
+        if fields.len() < 5 {
+            return Err(format!("Too few columns in log entry '{}'", line));
+        }
+        let checks: u64 = fields[2].parse().map_err(|e: ParseIntError| e.to_string())?;
+        let vanished = match fields[3] {
+            "0" => false,
+            "1" => true,
+            other => return Err(format!("Unknown detection type {}", other)),
+        };
+        let detected_ms: u128 = fields[4].parse().map_err(|e: ParseIntError| e.to_string())?;
+        // The uncertainty and the diffs follow the location as key=value columns.
+        let (extra_fields, location_fields): (Vec<&str>, Vec<&str>) =
+            fields[5..].iter().partition(|field| field.contains('='));
+        let location = parse_location(&location_fields)?;
+        let extra = FlipFields::from_fields(extra_fields.iter().filter_map(|field| field.split_once('=')))?;
+
+        Ok(Record::Flip {
+            started_ms,
+            delay_ms,
+            checks,
+            vanished,
+            detected_ms,
+            location,
+            uncertainty: extra.uncertainty,
+            changed_bytes: extra.changed_bytes,
+            diffs: extra.diffs,
+            warmup: extra.warmup,
+            tags: Tags::new(),
+            sequence: None,
+        })
+    }
+}
+
+/// Timestamps are u128 because that's what `Duration::as_millis` returns, but serde can't read u128 inside
+/// internally tagged enums. Milliseconds since 1970 fit in a u64 for the next few hundred million years.
+fn deserialize_ms<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u128, D::Error> {
+    u64::deserialize(deserializer).map(u128::from)
+}
+
+fn parse_location(fields: &[&str]) -> Result<Option<Location>, String> {
+    match fields {
+        [] => Ok(None),
+        [latitude, longitude] => Ok(Some(Location {
+            latitude: latitude.parse().map_err(|e: ParseFloatError| e.to_string())?,
+            longitude: longitude.parse().map_err(|e: ParseFloatError| e.to_string())?,
+        })),
+        _ => Err("Unexpected number of location columns".into()),
+    }
+}
+
+/// Reads every record of a log file, in the CSV, JSON Lines or binary format.
+pub fn read_log(path: &Path) -> Result<Vec<Record>, String> {
+    let bytes = fs::read(path).map_err(|e| format!("Unable to read {}: {}", path.display(), e))?;
+    if bytes.starts_with(binlog::MAGIC) {
+        return binlog::decode_log(&bytes);
+    }
+    if bytes.starts_with(SQLITE_MAGIC) {
+        #[cfg(feature = "sqlite")]
+        return crate::sqlite_log::read(path);
+        #[cfg(not(feature = "sqlite"))]
+        return Err(format!("{} is a SQLite log, which this build can't read, rebuild with --features sqlite", path.display()));
+    }
+
+    let contents = String::from_utf8(bytes).map_err(|e| format!("{} is not a text file: {}", path.display(), e))?;
+    contents
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(number, line)| Record::from_line(line).map_err(|e| format!("Line {}: {}", number + 1, e)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::detector::{Detector, DetectorArray};
+    use crate::binlog::{self, BinaryEncoder};
+
+    #[test]
+    fn shutdowns_are_logged_in_every_format() {
+        let shutdown = Record::Shutdown {
+            timestamp_ms: 1_700_000_000_000,
+            total_checks: 12,
+            flips: 2,
+            uptime_ms: 360_000,
+            tags: Tags::from_iter([("room".to_string(), "basement".to_string())]),
+            sequence: Some(3),
+        };
+        let mut binary = binlog::header();
+        binary.extend(BinaryEncoder::default().encode(&shutdown));
+        let json = serde_json::to_string(&shutdown).unwrap();
+        assert!(json.starts_with(r#"{"type":"shutdown","#));
+        for decoded in [
+            Record::from_csv(&shutdown.to_csv()).unwrap(),
+            binlog::decode_log(&binary).unwrap().remove(0),
+            Record::from_line(&json).unwrap(),
+        ] {
+            let Record::Shutdown { timestamp_ms, total_checks, flips, uptime_ms, tags, sequence } = decoded else {
+                panic!("Not a shutdown: {:?}", decoded);
+            };
+            assert_eq!((timestamp_ms, total_checks, flips, uptime_ms), (1_700_000_000_000, 12, 2, 360_000));
+            assert_eq!(sequence, Some(3));
+            assert_eq!(tags.get("room").map(String::as_str), Some("basement"));
+        }
+    }
+
+    #[test]
+    fn all_changed_elements_are_found_and_logged_together() {
+        let mut detector = Detector::new(0, 4096);
+        detector.flip_bit(5, 0);
+        detector.flip_bit(2048, 7);
+        detector.flip_bit(2048, 1);
+        let changed = detector.find_all_changed_elements();
+        assert_eq!(changed, vec![5, 2048]);
+
+        let flip = Record::Flip {
+            started_ms: 1_700_000_000_000,
+            delay_ms: 0,
+            checks: 3,
+            vanished: false,
+            detected_ms: 1_700_000_001_000,
+            location: None,
+            uncertainty: None,
+            changed_bytes: Some(changed.len() as u64),
+            diffs: changed.iter().filter_map(|&index| detector.diff_at(index)).map(FlipDiff::from).collect(),
+            warmup: true,
+            tags: Tags::new(),
+            sequence: None,
+        };
+        let mut binary = binlog::header();
+        binary.extend(BinaryEncoder::default().encode(&flip));
+        for decoded in [Record::from_csv(&flip.to_csv()).unwrap(), binlog::decode_log(&binary).unwrap().remove(0)] {
+            let Record::Flip { changed_bytes, diffs, warmup, .. } = decoded else {
+                panic!("Not a flip: {:?}", decoded);
+            };
+            assert_eq!(changed_bytes, Some(2));
+            assert!(warmup);
+            let bits: Vec<(u64, Vec<u8>)> = diffs.iter().map(|diff| (diff.index, diff.bits.clone())).collect();
+            assert_eq!(bits, vec![(5, vec![0]), (2048, vec![1, 7])]);
+        }
+    }
+
+    #[test]
+    fn physical_addresses_are_logged_in_hex() {
+        let mut detector = Detector::new(0, 64);
+        detector.flip_bit(3, 1);
+        let diff = FlipDiff { physical_address: Some(0x1_2345_6789), ..FlipDiff::from(detector.diff_at(3).unwrap()) };
+        let fields = diff.fields();
+        assert!(fields.contains(&("physical_address", "0x123456789".to_string())));
+        let parsed = FlipDiff::from_fields(fields.iter().map(|(key, value)| (*key, value.as_str()))).unwrap();
+        assert_eq!(parsed, diff);
+    }
+
+    #[test]
+    fn numa_nodes_are_logged_with_the_flips() {
+        let mut detector = Detector::new(0, 64);
+        detector.flip_bit(3, 1);
+        let diff = FlipDiff { numa_node: Some(1), ..FlipDiff::from(detector.diff_at(3).unwrap()) };
+        let fields = diff.fields();
+        assert!(fields.contains(&("numa_node", "1".to_string())));
+        let parsed = FlipDiff::from_fields(fields.iter().map(|(key, value)| (*key, value.as_str()))).unwrap();
+        assert_eq!(parsed, diff);
+    }
+
+    #[test]
+    fn chunks_and_offsets_are_logged_in_every_format() {
+        let mut detector = DetectorArray::with_chunk_bytes(0, 512, 128).unwrap();
+        detector.flip_bit(300, 1);
+        let (chunk, offset) = detector.chunk_of(300).unwrap();
+        let diff = FlipDiff {
+            chunk: Some(chunk as u64),
+            offset: Some(offset as u64),
+            ..FlipDiff::from(detector.diff_at(300).unwrap())
+        };
+        assert_eq!((diff.chunk, diff.offset), (Some(2), Some(44)));
+        let flip = Record::Flip {
+            started_ms: 1_700_000_000_000,
+            delay_ms: 10,
+            checks: 5,
+            vanished: false,
+            detected_ms: 1_700_000_000_010,
+            location: None,
+            uncertainty: None,
+            changed_bytes: Some(1),
+            diffs: vec![diff.clone()],
+            warmup: false,
+            tags: Tags::new(),
+            sequence: None,
+        };
+        let json = serde_json::to_string(&flip).unwrap();
+        let mut binary = binlog::header();
+        binary.extend(BinaryEncoder::default().encode(&flip));
+        for decoded in [
+            Record::from_csv(&flip.to_csv()).unwrap(),
+            serde_json::from_str(&json).unwrap(),
+            binlog::decode_log(&binary).unwrap().remove(0),
+        ] {
+            let Record::Flip { diffs, .. } = decoded else {
+                panic!("Not a flip: {:?}", decoded);
+            };
+            assert_eq!(diffs, vec![diff.clone()]);
+            assert_eq!(detector.index_of(chunk, offset), Some(diffs[0].index as usize));
+        }
+    }
+
+    #[test]
+    fn the_pid_follows_the_location_of_start_entries() {
+        let location = Some(Location { latitude: 10.5, longitude: -11.25 });
+        let start = Record::Start {
+            started_ms: 1_700_000_000_000,
+            delay_ms: 30_000,
+            pid: Some(1234),
+            location,
+            tags: Tags::new(),
+            sequence: Some(7),
+        };
+        assert_eq!(start.to_csv(), "1700000000000,30000,,,10.5,-11.25,pid=1234,seq=7\n");
+        let parsed = Record::from_csv(&start.to_csv()).unwrap();
+        let Record::Start { pid, location: parsed_location, sequence, .. } = parsed else {
+            panic!("Not a start entry");
+        };
+        let coordinates = |location: Option<Location>| location.map(|location| (location.latitude, location.longitude));
+        assert_eq!((pid, coordinates(parsed_location), sequence), (Some(1234), Some((10.5, -11.25)), Some(7)));
+
+        // Entries of older versions have neither.
+        let old_lines = [("1700000000000,30000,,", None), ("1700000000000,30000,,,10.5,-11.25", Some((10.5, -11.25)))];
+        for (line, expected) in old_lines {
+            let Record::Start { pid, location, .. } = Record::from_csv(line).unwrap() else {
+                panic!("Not a start entry: {}", line);
+            };
+            assert_eq!((pid, coordinates(location)), (None, expected));
+        }
+    }
+
+    #[test]
+    fn panics_are_logged_in_every_format() {
+        let panic = Record::Panic {
+            timestamp_ms: 1_700_000_000_000,
+            total_checks: 12,
+            message: "index out of bounds: the len is 4 but the index is 7".into(),
+            tags: Tags::from_iter([("room".to_string(), "basement".to_string())]),
+            sequence: Some(3),
+        };
+        let mut binary = binlog::header();
+        binary.extend(BinaryEncoder::default().encode(&panic));
+        for decoded in [Record::from_csv(&panic.to_csv()).unwrap(), binlog::decode_log(&binary).unwrap().remove(0)] {
+            let Record::Panic { timestamp_ms, total_checks, message, tags, sequence } = decoded else {
+                panic!("Not a panic: {:?}", decoded);
+            };
+            assert_eq!((timestamp_ms, total_checks, sequence), (1_700_000_000_000, 12, Some(3)));
+            assert_eq!(message, "index out of bounds: the len is 4 but the index is 7");
+            assert_eq!(tags.get("room").map(String::as_str), Some("basement"));
+        }
+    }
+}
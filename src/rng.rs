@@ -0,0 +1,40 @@
+use std::process;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A SplitMix64 generator. It is nowhere near good enough for anything secret,
+/// but it is fast and gives the same numbers for the same seed on every platform, which is what reproducible runs need.
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Rng { state: seed }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// A number from zero up to and including `max`.
+    /// The modulo bias is far below anything that matters for scheduling.
+    pub fn up_to(&mut self, max: u64) -> u64 {
+        match max.checked_add(1) {
+            Some(bound) => self.next_u64() % bound,
+            None => self.next_u64(),
+        }
+    }
+}
+
+/// A seed for runs that weren't given one, it is logged so that the run can still be repeated.
+pub fn random_seed() -> u64 {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Time went backwards")
+        .as_nanos() as u64;
+    Rng::new(nanos ^ ((process::id() as u64) << 32)).next_u64()
+}
@@ -0,0 +1,1435 @@
+use std::any::Any;
+use std::error::Error;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::thread;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use sysinfo::{RefreshKind, System, SystemExt};
+
+use crate::{
+    anomaly::{AlertWebhook, AnomalyDetector},
+    cadence::CadenceScheduler,
+    capabilities,
+    cgroup::SelfCgroup,
+    classroom::Classroom,
+    clock::{self, TimeSource},
+    budget::{self, CpuBudget},
+    config::{GlobalArgs, LogBackend, LogFormat, RunArgs, SizeSpec, SyncPolicy},
+    console::{Console, Style},
+    crash::CrashReporter,
+    detector::DetectorArray,
+    disk::{DiskSpace, DiskWatch},
+    edac::EdacSampler,
+    exposure::{gb_hours, Ledger},
+    fill::{fill_memory, fits_in_memory, ResizeReason, Resizer, Thresholds},
+    gpio::GpioPulser,
+    hardware,
+    i18n::tr,
+    healthcheck::HealthcheckPinger,
+    interval::IntervalAdvice,
+    influx::InfluxWriter,
+    lan::advertise,
+    instance::{send_command, start_control_server, InstanceLock, InstanceState},
+    lock::open_locked_log,
+    metrics::start_metrics_server,
+    update::check_for_update,
+    upload::Uploader,
+    mem_size,
+    log_writer::LogWriter,
+    numa,
+    pagemap::Pagemap,
+    paging::{PagingSampler, ResidencyTally},
+    pipeline::{Event, Pipeline, Sinks},
+    pps::{PpsClock, PpsSource},
+    preflight,
+    rapl::{EnergyTally, Rapl},
+    rate::RateEstimate,
+    record::{FlipDiff, Record, SessionInfo, Tags, Uncertainty, MAX_LOGGED_DIFFS},
+    rng::{random_seed, Rng},
+    s3::S3Exporter,
+    serial::SerialOutput,
+    share::{ShareConsent, Sharer},
+    sandbox::{directory_of, restrict_filesystem},
+    signing::StationKey,
+    smi::{SmiCounter, SmiMonitor, WindowSmi},
+    softdirty::SoftDirty,
+    stall::{self, StallMonitor},
+    summary::Summary,
+    state::StateFile,
+    station::StationConfig,
+};
+
+/// How many checks in a row may panic before the session gives up, a check that keeps panicking won't stop.
+const MAX_CONSECUTIVE_PANICS: u32 = 3;
+const LEDGER_SAVE_INTERVAL: Duration = Duration::from_secs(60);
+const CPU_BUDGET_RETUNE_INTERVAL: Duration = Duration::from_secs(60);
+const LAN_SYNC_ADDR: &str = "0.0.0.0:0";
+const TAKEOVER_TIMEOUT: Duration = Duration::from_secs(120);
+/// How often --resize looks at the memory.
+const RESIZE_POLL_INTERVAL: Duration = Duration::from_secs(10);
+/// No memory bus gets anywhere close to this, a check that claims to be faster was optimized away.
+const MAX_PLAUSIBLE_BANDWIDTH: f64 = 1e12; // 1TB/s
+/// Smaller detectors can fit in the CPU caches, which are fast enough to make the bandwidth check meaningless.
+const MIN_BANDWIDTH_CHECK_SIZE: usize = 256_000_000; // 256MB
+
+/// Fills memory with a detector and checks it for bitflips forever, logging every one that is found.
+pub fn run(global: &GlobalArgs, conf: RunArgs) -> Result<(), Box<dyn Error>> {
+    conf.check_log_backend()?;
+    // The classroom gets a simpler picture instead of the settings and the technical details.
+    let verbose: bool = global.verbose && !conf.classroom;
+    let mut check_delay: u64 = conf.delay_between_checks;
+
+    let mut sleep_duration: Duration = Duration::from_millis(check_delay);
+
+    // When events are streamed to stdout everything meant for humans goes to stderr instead.
+    let mut out = match conf.events_to_stdout {
+        true => Console::stderr(global.plain),
+        false => Console::stdout(global.plain),
+    };
+    let crash_dir = conf.crash_dir.clone().unwrap_or_else(|| directory_of(Path::new(&conf.file_path)));
+    CrashReporter::new(crash_dir.clone(), redacted_settings(&conf), PathBuf::from(&conf.file_path), out.clone())
+        .install();
+
+    let station = StationConfig::load(&global.station_config)?;
+    let location = station.location(conf.latitude, conf.longitude)?;
+    let station_id = station.station_id(global.station_id.as_deref())?;
+    // Refusing to start without consent beats finding out after filling the memory.
+    let share_consent = match conf.share {
+        true => Some(ShareConsent::require(&conf.share_consent)?),
+        false => None,
+    };
+    let station_key = match conf.upload_url.is_some() || conf.share {
+        true => Some(StationKey::load_or_create(&conf.station_key)?),
+        false => None,
+    };
+
+    if conf.check_update {
+        check_for_update(&mut out)?;
+    }
+
+    // The cgroup's memory limit is set once the detector size is known, the sandbox doesn't let it be opened later.
+    let mut self_cgroup = match conf.self_cgroup {
+        true => match SelfCgroup::enter(&station_id) {
+            Ok(cgroup) => {
+                if verbose {
+                    writeln!(out, "{}", tr!("Running in the memory cgroup {}", cgroup.path().display()))?;
+                }
+                Some(cgroup)
+            }
+            Err(err) => {
+                let message = tr!("Not running in a memory cgroup of its own: {}", err);
+                writeln!(out, "{}", out.paint(Style::Warning, &message))?;
+                None
+            }
+        },
+        false => None,
+    };
+
+    // Devices are opened before the sandbox, which doesn't let them be.
+    let mut gpio_pulser = match conf.gpio_pulse {
+        Some(pin) => Some(GpioPulser::open(pin, Duration::from_micros(conf.gpio_pulse_width))?),
+        None => None,
+    };
+    let serial = conf.serial.as_ref().map(SerialOutput::open).transpose()?;
+    let pps_source = conf.pps.as_deref().map(PpsSource::open).transpose()?;
+    let smi_counter = SmiCounter::open();
+    // So are the firmware tables the memory's speed comes from.
+    let hardware = hardware::detect();
+    if let (Some(true), Some(rated), Some(configured)) =
+        (hardware.memory_xmp, hardware.memory_rated_mts, hardware.memory_configured_mts)
+    {
+        let message = tr!(
+            "The memory runs at {} MT/s, faster than the {} MT/s it is rated for, which makes bitflips more likely",
+            configured,
+            rated
+        );
+        writeln!(out, "{}", out.paint(Style::Warning, &message))?;
+    }
+    // A single node, or none known, is the same as no NUMA at all.
+    let numa_nodes = numa::online_nodes().unwrap_or_default();
+
+    // No threads have been started yet, so the sandbox covers all of them.
+    if conf.sandbox {
+        let mut writable = vec![directory_of(Path::new(&conf.file_path))];
+        writable.extend(conf.ledger_path.as_deref().map(directory_of));
+        writable.extend(conf.summary_path.as_deref().map(directory_of));
+        writable.push(crash_dir);
+        writeln!(out, "{}", restrict_filesystem(&writable)?)?;
+    }
+    let pps_clock = pps_source.map(PpsClock::start);
+
+    let instance = match InstanceLock::try_acquire(&station_id)? {
+        Some(instance) => instance,
+        None if conf.attach => {
+            write!(out, "{}", send_command(&station_id, "status")?)?;
+            return Ok(());
+        }
+        None if conf.takeover => {
+            writeln!(out, "{}", tr!("Asking the running instance of station {} to shut down", station_id))?;
+            send_command(&station_id, "shutdown")?;
+            InstanceLock::wait_for(&station_id, TAKEOVER_TIMEOUT)?
+        }
+        None => {
+            return Err(format!(
+                "An instance is already running for station {}, use --attach or --takeover",
+                station_id
+            )
+            .into())
+        }
+    };
+    let seed = conf.seed.unwrap_or_else(random_seed);
+    let mut rng = Rng::new(seed);
+    let session = SessionInfo {
+        seed: Some(seed),
+        altitude_m: station.altitude,
+        hardware,
+        ..SessionInfo::current()
+    };
+    let state = Arc::new(InstanceState {
+        station_id: station_id.clone(),
+        session_id: session.session_id.clone().unwrap_or_default(),
+        ..Default::default()
+    });
+    start_control_server(&instance, state.clone())?;
+    let metrics_port = match &conf.metrics_addr {
+        Some(metrics_addr) => Some(start_metrics_server(metrics_addr, state.clone())?),
+        // LAN sync needs the server for its summaries, any free port will do.
+        None if conf.lan_sync => Some(start_metrics_server(LAN_SYNC_ADDR, state.clone())?),
+        None => None,
+    };
+    // Keep advertising for as long as we run.
+    let _lan_advertisement = match metrics_port {
+        Some(port) if conf.lan_sync => Some(advertise(&station_id, port)?),
+        _ => None,
+    };
+
+    // Take the lock on the log file before allocating anything, there is no point in filling memory if we can't log.
+    let (file, file_path) = open_locked_log(&conf.file_path, conf.on_locked_log)?;
+    let exporter = match conf.s3_url.clone() {
+        Some(url) => Some(S3Exporter::new(
+            url,
+            conf.s3_region.clone(),
+            conf.s3_tag.clone(),
+            station_id.clone(),
+            Path::new(&file_path),
+            Duration::from_millis(conf.s3_interval),
+        )?),
+        None => None,
+    };
+    let mut log = match conf.log_backend {
+        LogBackend::File => LogWriter::new(
+            file,
+            conf.log_format.unwrap_or(LogFormat::Csv),
+            conf.sync_policy.unwrap_or(SyncPolicy::Always),
+            Duration::from_millis(conf.sync_interval),
+        )?,
+        #[cfg(feature = "sqlite")]
+        LogBackend::Sqlite => LogWriter::sqlite(file, Path::new(&file_path))?,
+        #[cfg(not(feature = "sqlite"))]
+        LogBackend::Sqlite => return Err(tr!("This build has no SQLite support, rebuild it with --features sqlite").into()),
+    };
+    // On SD cards the heartbeats and the ledger are only written every few hours.
+    let sd_card_flush_interval = Duration::from_secs(conf.sd_card_flush_hours * 3600);
+    let ledger_save_interval = match conf.sd_card {
+        true => {
+            log.set_heartbeat_batching(Some(sd_card_flush_interval));
+            sd_card_flush_interval
+        }
+        false => LEDGER_SAVE_INTERVAL,
+    };
+    let ledger_path = conf
+        .ledger_path
+        .clone()
+        .unwrap_or_else(|| PathBuf::from(format!("{}.ledger", file_path)));
+    let preflight_checks = preflight::run(
+        &conf,
+        &preflight::Context {
+            log_path: Path::new(&file_path),
+            ledger_path: &ledger_path,
+            station_id: &station_id,
+            session: &session,
+            station_key: station_key.as_ref(),
+            exporter: exporter.as_ref(),
+        },
+    );
+    if verbose || preflight::failed(&preflight_checks) {
+        writeln!(out, "{}", tr!("Pre-flight checks:"))?;
+        preflight::print(&mut out, &preflight_checks)?;
+    }
+    if conf.strict_preflight && preflight::failed(&preflight_checks) {
+        return Err("Pre-flight checks failed, not starting with --strict-preflight".into());
+    }
+    let mut ledger = Ledger::open(ledger_path)?;
+    let summary_path = conf
+        .summary_path
+        .clone()
+        .unwrap_or_else(|| PathBuf::from(format!("{}.summary.json", file_path)));
+    if verbose && file_path != conf.file_path {
+        writeln!(out, "{}", tr!("Logging bitflips to {}", file_path))?;
+    }
+
+    let mut sys_info = System::new_with_specifics(RefreshKind::new().with_memory());
+    let defaults = Thresholds::default();
+    let min_free = match conf.memory_to_occupy {
+        SizeSpec::Auto { min_free } => min_free,
+        _ => None,
+    };
+    let thresholds = Thresholds {
+        swap_tolerance: conf.swap_tolerance.or(station.swap_tolerance).map_or(defaults.swap_tolerance, |bytes| bytes as u64),
+        free_headroom: min_free
+            .or(conf.free_headroom.or(station.free_headroom).map(|bytes| bytes as u64))
+            .unwrap_or(defaults.free_headroom),
+    };
+    if verbose {
+        writeln!(out, "\n{}", out.paint(Style::Heading, &tr!("------------ Runtime settings ------------")))?;
+    }
+    let mut state_file = StateFile::open(PathBuf::from(format!("{}.state", file_path)))?;
+    let total_memory = sys_info.total_memory();
+    let mut size = match conf.memory_to_occupy {
+        SizeSpec::Auto { .. } => {
+            if verbose {
+                writeln!(out, "{}", tr!("Using all available RAM as detector"))?;
+            }
+            // Finding the size takes a while, so it is remembered for the next run on the same machine.
+            match state_file.detector_size(total_memory) {
+                Some(cached) if fits_in_memory(&mut sys_info, thresholds, cached) => {
+                    if verbose {
+                        writeln!(out, "{}", tr!("Starting from the size that filled the memory last time"))?;
+                    }
+                    cached
+                }
+                _ => {
+                    let size = fill_memory(&mut out, &mut sys_info, thresholds, verbose)?;
+                    state_file.set_detector_size(total_memory, size)?;
+                    size
+                }
+            }
+        }
+        size_spec => size_spec.bytes(total_memory)?,
+    };
+    if verbose {
+        writeln!(out, "{}", tr!("Using {} bits ({}) of RAM as detector", size, mem_size(size as u64)))?;
+
+        if check_delay == 0 {
+            writeln!(out, "{}", tr!("Will do continuous integrity checks"))?;
+        } else {
+            writeln!(out, "{}", tr!("Waiting {} between integrity checks", format!("{:?}", sleep_duration)))?;
+        }
+        if conf.jitter > 0 {
+            writeln!(out, "{}", tr!("Adding up to {} ms of jitter to every wait, with seed {}", conf.jitter, seed))?;
+        }
+        writeln!(out, "{}", tr!("Checking memory integrity in parallel"))?;
+        writeln!(out, "\n{}", tr!("Optional features:"))?;
+        capabilities::print(&mut out, &capabilities::detect())?;
+        writeln!(out, "------------------------------------------\n")?;
+
+        write!(out, "{}", tr!("Allocating detector memory..."))?;
+        out.flush()?;
+    }
+
+
+
+    if let Some(cgroup) = &mut self_cgroup {
+        cgroup.limit(size)?;
+    }
+
+    // Instead of building a detector out of scintillators and photo multiplier tubes,
+    // we just allocate some memory on this here computer.
+    let mut detector = DetectorArray::new(0, size);
+    // Less exciting, much less accurate and sensitive, but much cheaper
+    if detector.size() < size {
+        let warning = tr!(
+            "Only {} of the {} asked for could be allocated, running with a smaller detector",
+            mem_size(detector.size() as u64),
+            mem_size(size as u64)
+        );
+        writeln!(out, "{}", out.paint(Style::Warning, &warning))?;
+        size = detector.size();
+        limit_memory(self_cgroup.as_mut(), size, &mut out)?;
+    }
+    detector.set_inverted_halves(conf.inverted_halves);
+    detector.set_scan_mode(conf.scan_mode);
+
+    // Huge pages are only used for memory that isn't in use yet, so they are asked for before anything is written.
+    let huge_pages = conf.huge_pages && use_huge_pages(&detector, &mut out)?;
+
+    // Placement only holds for pages that aren't in memory yet, so the chunks are bound before locking faults them in.
+    if conf.numa {
+        spread_over_nodes(&mut detector, &numa_nodes, &mut out)?;
+    }
+
+    // Memory that is swapped out can't be hit, so it is locked where the limits allow.
+    let memory_locked = !conf.no_mlock && lock_memory(&detector, &mut out)?;
+
+    // Avoid the pitfalls of virtual memory by writing nonzero values to the allocated memory.
+    detector.write(42);
+
+    if conf.paranoid_reads {
+        detector.set_paranoid_reads(true);
+        if size >= MIN_BANDWIDTH_CHECK_SIZE {
+            let bandwidth = detector.measure_check_bandwidth();
+            if bandwidth > MAX_PLAUSIBLE_BANDWIDTH {
+                return Err(format!(
+                    "Checking the detector ran at {}/s, which is faster than memory can be read. The checks are not reaching the memory",
+                    mem_size(bandwidth as u64)
+                )
+                .into());
+            }
+            if verbose {
+                write!(out, "{}", tr!("checks read {}/s... ", mem_size(bandwidth as u64)))?;
+            }
+        } else if verbose {
+            write!(out, "{}", tr!("too small to verify that checks reach the memory... "))?;
+        }
+    }
+
+    if verbose {
+        writeln!(out, "{}", tr!("done"))?;
+    }
+
+    let mut cpu_budget = conf.cpu_budget.map(CpuBudget::new);
+    if let Some(budget) = &mut cpu_budget {
+        detector.set_threads(Some(budget.threads()))?;
+        // Time a first check so that the first wait already fits the budget.
+        let (_, cpu, time) = budget::measure(budget.threads(), || detector.is_intact());
+        budget.record_check(cpu, time);
+        sleep_duration = budget.delay();
+        check_delay = sleep_duration.as_millis() as u64;
+        if verbose {
+            let message = tr!(
+                "CPU budget of {}% of {} cores: checking with {} threads, a check takes {} using {} of CPU time, waiting {} ms between checks",
+                100.0 * budget.fraction(),
+                budget.cores(),
+                budget.threads(),
+                format!("{:?}", time),
+                format!("{:?}", cpu),
+                check_delay
+            );
+            writeln!(out, "{}", message)?;
+        }
+    }
+    let mut last_retune = Instant::now();
+
+    // Where the processor's energy counters can be read, the heartbeats tell what the checks cost.
+    let rapl = match Rapl::open() {
+        Ok(rapl) => Some(rapl),
+        Err(err) => {
+            if verbose {
+                writeln!(out, "{}", tr!("Not measuring the energy of checks: {}", err))?;
+            }
+            None
+        }
+    };
+    let mut energy = EnergyTally::default();
+    // Firmware that keeps interrupting the checks can touch memory, heavy SMI activity is marked in the log.
+    let smi_counter = match smi_counter {
+        Ok(counter) => Some(counter),
+        Err(err) => {
+            if verbose {
+                writeln!(out, "{}", tr!("Not counting SMIs, only scans that take far too long are marked: {}", err))?;
+            }
+            None
+        }
+    };
+    let mut smi = SmiMonitor::new(smi_counter, conf.heavy_smi_rate);
+    // Heartbeats also tell how much the machine paged, while swapping some of the detector may not have been in memory.
+    let mut paging = PagingSampler::new();
+    // Pages of the detector that are swapped out can't be hit, checks tell when too few of them are in memory.
+    let mut min_residency = conf.min_residency;
+    if min_residency > 0.0 {
+        if let Err(err) = detector.resident_fraction() {
+            if verbose {
+                writeln!(out, "{}", tr!("Not checking that the detector is in memory: {}", err))?;
+            }
+            min_residency = 0.0;
+        }
+    }
+    let mut residency = ResidencyTally::default();
+    let mut stall_monitor = match conf.stall_factor > 0.0 {
+        true => Some(StallMonitor::start(conf.stall_factor, conf.cancel_stalled_scans, Box::new(out.clone()))?),
+        false => None,
+    };
+
+    let interval_advice = IntervalAdvice::new(
+        size,
+        station.altitude,
+        conf.expected_rate,
+        check_delay,
+        conf.jitter,
+        conf.interval_fraction,
+    );
+    if verbose {
+        interval_advice.print(&mut out)?;
+    }
+    if interval_advice.is_too_long() {
+        let suggested_delay = interval_advice.suggested_delay_ms(conf.jitter);
+        if conf.auto_interval {
+            writeln!(out, "{}", tr!("Lowering the delay between checks to {} ms to fit --interval-fraction", suggested_delay))?;
+            check_delay = suggested_delay;
+            sleep_duration = Duration::from_millis(check_delay);
+        } else {
+            let warning = tr!(
+                "Warning: the time between checks is over {}% of the expected time between flips, so the timestamps \
+                 are mostly down to when the checks happened. A delay of {} ms or less would be better, or pass --auto-interval",
+                100.0 * conf.interval_fraction,
+                suggested_delay
+            );
+            writeln!(out, "{}", out.paint(Style::Warning, &warning))?;
+        }
+    }
+
+    let mut cadence = match conf.fixed_cadence {
+        true => Some(CadenceScheduler::new(sleep_duration)?),
+        false => None,
+    };
+
+    let soft_dirty = match conf.soft_dirty {
+        true => match SoftDirty::open() {
+            Ok(soft_dirty) => Some(soft_dirty),
+            Err(err) => {
+                writeln!(out, "{}", tr!("Not tracking soft-dirty pages: {}", err))?;
+                None
+            }
+        },
+        false => None,
+    };
+
+    // Physical addresses tie flips to DIMMs and rows, but the kernel only shows them to CAP_SYS_ADMIN.
+    let pagemap = match detector.address_of(0).ok_or_else(|| "The detector is empty".to_string()).and_then(Pagemap::open) {
+        Ok(pagemap) => Some(pagemap),
+        Err(err) => {
+            if verbose {
+                writeln!(out, "{}", tr!("Not logging the physical addresses of flips: {}", err))?;
+            }
+            None
+        }
+    };
+
+    if verbose {
+        writeln!(out, "{}", tr!("Adding start entry to log file"))?;
+    }
+
+    let start = SystemTime::now();
+    // Nothing after this point may panic, a detector that stops at night loses the whole night's exposure.
+    // Times from a clock set before 1970 are logged as 1970.
+    let unix_timestamp = match start.duration_since(UNIX_EPOCH) {
+        Ok(unix_timestamp) => unix_timestamp,
+        Err(_) => {
+            writeln!(out, "{}", tr!("The system clock is set before 1970, times are logged as 1970 until it is fixed"))?;
+            Duration::ZERO
+        }
+    };
+
+    let session = SessionInfo {
+        detector_bytes: Some(size as u64),
+        memory_locked: Some(memory_locked),
+        warmup_ms: (!conf.warmup.is_zero()).then_some(conf.warmup.as_millis() as u64),
+        ..session
+    };
+    let session_entry = Record::Session(session.clone());
+    let start_entry = Record::Start {
+        started_ms: unix_timestamp.as_millis(),
+        delay_ms: check_delay,
+        pid: Some(process::id()),
+        location,
+        tags: Tags::new(),
+        sequence: None,
+    };
+    // Everything that can block on a disk or the network happens in the pipeline's worker thread,
+    // so that it never delays the checks or distorts their timing.
+    let sharer = match (&share_consent, &station_key) {
+        (Some(consent), Some(station_key)) => {
+            let record_path = PathBuf::from(format!("{}.shared", file_path));
+            let sharer = Sharer::new(consent, &session, station_key.clone(), &record_path)?;
+            if verbose {
+                writeln!(out, "{}", tr!("Sharing anonymized bitflips with {}, see {}", consent.url, record_path.display()))?;
+            }
+            Some(sharer)
+        }
+        _ => None,
+    };
+    let mut pipeline = Pipeline::start(Sinks {
+        log,
+        state: Some(state_file),
+        tags: conf.tag.iter().cloned().collect(),
+        uploader: match (conf.upload_url.clone(), station_key) {
+            (Some(url), Some(station_key)) => Some(Uploader::new(url, station_id.clone(), session, station_key)),
+            _ => None,
+        },
+        sharer,
+        influx: conf
+            .influx_url
+            .clone()
+            .map(|url| InfluxWriter::new(url, conf.influx_token.clone(), station_id.clone())),
+        healthcheck: conf.healthcheck_url.clone().map(HealthcheckPinger::new),
+        exporter,
+        alert_webhook: conf.alert_webhook.clone().map(|url| AlertWebhook::new(url, station_id.clone())),
+        serial,
+        events_to_stdout: conf.events_to_stdout,
+        heartbeats_to_stdout: conf.heartbeats_to_stdout,
+        out: Box::new(out.clone()),
+    });
+    pipeline.send(Event::Log(vec![session_entry, start_entry]));
+
+    if verbose {
+        writeln!(out, "\n{}", tr!("Beginning detection loop"))?;
+    }
+    let mut classroom = match conf.classroom {
+        true => Some(Classroom::open(PathBuf::from(format!("{}.classroom", file_path)))?),
+        false => None,
+    };
+    if let Some(classroom) = &mut classroom {
+        writeln!(out, "{}", tr!("Watching {} of the memory of this computer for cosmic rays", mem_size(size as u64)))?;
+        writeln!(out, "{}", classroom.status())?;
+    }
+
+    let mut summary = Summary {
+        station_id: station_id.clone(),
+        session_id: state.session_id.clone(),
+        started_ms: unix_timestamp.as_millis(),
+        ..Default::default()
+    };
+    let mut total_checks: u64 = 1;
+    let mut checks_since_last_bitflip: u64 = 1;
+    let mut everything_is_fine: bool;
+    let start: Instant = Instant::now();
+    let heartbeat_interval = match conf.heartbeat_interval {
+        0 => None,
+        interval => Some(Duration::from_millis(interval)),
+    };
+    let mut last_heartbeat: Instant = Instant::now();
+    let mut edac_sampler = match conf.edac_interval {
+        0 => None,
+        interval => EdacSampler::new(Duration::from_millis(interval))?,
+    };
+    if conf.edac_interval > 0 && edac_sampler.is_none() {
+        writeln!(out, "{}", tr!("This machine reports no memory error counts through EDAC, not sampling them"))?;
+    }
+    let mut disk_watch = match conf.disk_warning.max(conf.disk_pause_heartbeats) {
+        0 => None,
+        _ => match DiskWatch::new(
+            directory_of(Path::new(&file_path)),
+            conf.disk_warning as u64,
+            conf.disk_pause_heartbeats as u64,
+        ) {
+            Ok(disk_watch) => Some(disk_watch),
+            Err(err) => {
+                if verbose {
+                    writeln!(out, "{}", tr!("Not watching the free disk space: {}", err))?;
+                }
+                None
+            }
+        },
+    };
+    let mut resizer = match conf.resize {
+        true if matches!(conf.memory_to_occupy, SizeSpec::Auto { .. }) => Some(Resizer::new(
+            &mut sys_info,
+            thresholds,
+            Duration::from_secs_f64(conf.resize_grow_after.max(0.0) * 60.0),
+            Duration::from_secs_f64(conf.resize_cooldown.max(0.0) * 60.0),
+        )),
+        true => {
+            writeln!(out, "{}", tr!("--resize only resizes detectors that fill all available memory, not resizing"))?;
+            None
+        }
+        false => None,
+    };
+    let mut last_resize_poll = Instant::now();
+    let influx_interval = Duration::from_millis(conf.influx_interval);
+    let mut last_influx_report: Instant = Instant::now();
+    let mut checks_at_last_influx_report: u64 = total_checks;
+    let mut last_ledger_save: Instant = Instant::now();
+    let mut rate = RateEstimate::new(Duration::from_secs_f64(conf.rate_window.max(0.001) * 3600.0));
+    let mut anomalies = match conf.anomaly_sigma > 0.0 {
+        true => Some(AnomalyDetector::new(
+            Duration::from_secs_f64(conf.anomaly_baseline_window.max(0.001) * 3600.0),
+            interval_advice.sea_level_rate * interval_advice.altitude_factor * 24.0,
+            conf.anomaly_sigma,
+        )),
+        false => None,
+    };
+    state.detector_size.store(size, Ordering::Relaxed);
+    // Until the machine has settled after starting, checks run but count no exposure and flips no rate.
+    let warmup_until = Instant::now() + conf.warmup;
+    let mut warming_up = !conf.warmup.is_zero();
+    if warming_up && verbose {
+        let message = tr!(
+            "Warming up for {}, bitflips found until then are left out of the rates",
+            format!("{:?}", conf.warmup)
+        );
+        writeln!(out, "{}", message)?;
+    }
+    let mut scrub_cycles: usize = 0;
+    let mut last_scrub = Instant::now();
+    // The bytes the last bitflip changed, which are all that needs writing back until the next scrub is due.
+    let mut to_repair: Option<Vec<usize>> = None;
+    let mut consecutive_panics: u32 = 0;
+    'detection: loop {
+        // TODO have a thread watching to see if the free memory buffer begins to decrease (in which case, shrink the detector) instead of relying on swap.
+
+        // Reset detector!
+        let scrub = to_repair.is_none() || last_scrub.elapsed() >= conf.scrub_interval;
+        if scrub {
+            detector.set_default(conf.pattern.value(scrub_cycles, &mut rng));
+            scrub_cycles += 1;
+        }
+        if verbose {
+            let message = match &to_repair {
+                Some(changed) if !scrub => tr!("Writing back {} changed bytes... ", changed.len()),
+                _ => tr!("Writing {} to the detector memory... ", format!("{:#04x}", detector.default_value())),
+            };
+            write!(out, "{}", message)?;
+            out.flush()?;
+        }
+        let reset_start = Instant::now();
+        match to_repair.take() {
+            Some(changed) if !scrub => detector.repair(&changed),
+            _ => {
+                detector.reset();
+                last_scrub = Instant::now();
+            }
+        }
+        if let Some(soft_dirty) = &soft_dirty {
+            if let Err(err) = soft_dirty.clear() {
+                writeln!(out, "{}", err)?;
+            }
+        }
+        ledger.add_dead_time(reset_start.elapsed());
+        summary.add_dead_time(reset_start.elapsed());
+        let mut exposure_mark = Instant::now();
+        if let Some(cadence) = &mut cadence {
+            cadence.restart();
+        }
+        smi.restart();
+        // A flip found by a check happened after the previous check started reading.
+        let mut window_start = exposure_mark;
+        let mut check_start = exposure_mark;
+        // The resident fraction found before the previous and before the latest check, when it was below the minimum.
+        let mut reduced_previous_check: Option<f64> = None;
+        let mut reduced_latest_check: Option<f64> = None;
+        let mut window_smi = WindowSmi { count: None, heavy: false };
+        everything_is_fine = true;
+
+        // Some feedback for the user that the program is still running
+        if verbose {
+            writeln!(out, "{}", tr!("done"))?;
+            out.status(&tr!("Waiting for first check"))?;
+        }
+
+        while everything_is_fine {
+            // We're not gonna miss any events by being too slow
+            let wait = match &cadence {
+                Some(cadence) => cadence.until_next(),
+                None => sleep_duration + Duration::from_millis(rng.up_to(conf.jitter)),
+            };
+            if state.sleep(wait) {
+                break 'detection;
+            }
+            if let Some(cadence) = &mut cadence {
+                cadence.check_started();
+            }
+            reduced_previous_check = reduced_latest_check.take();
+            if min_residency > 0.0 {
+                match detector.resident_fraction() {
+                    Ok(fraction) => {
+                        let reduced = fraction < min_residency;
+                        residency.add(fraction, reduced);
+                        if reduced {
+                            summary.reduced_coverage_checks += 1;
+                            // Faulting the pages in first keeps the time it takes out of the scan.
+                            detector.fault_in();
+                            reduced_latest_check = Some(fraction);
+                            if verbose {
+                                let message = tr!(
+                                    "Only {}% of the detector was in memory, this check counts as reduced coverage",
+                                    format!("{:.1}", fraction * 100.0)
+                                );
+                                writeln!(out, "{}", message)?;
+                            }
+                        }
+                    }
+                    Err(err) => writeln!(out, "{}", out.paint(Style::Warning, &err))?,
+                }
+            }
+            // Check if all the bytes are still zero
+            window_start = check_start;
+            smi.check_started();
+            check_start = Instant::now();
+            let energy_start = rapl.as_ref().and_then(|rapl| rapl.read().ok());
+            let full_scan = conf.full_scan_every == 0 || total_checks.is_multiple_of(conf.full_scan_every);
+            let checked = match &mut cpu_budget {
+                Some(budget) => {
+                    let (checked, cpu, time) = budget::measure(budget.threads(), || {
+                        supervised_scan(&detector, conf.full_scan_every > 0, full_scan, stall_monitor.as_mut(), &state, &mut out)
+                    });
+                    let checked = checked?;
+                    budget.record_check(cpu, time);
+                    sleep_duration = budget.delay();
+                    check_delay = sleep_duration.as_millis() as u64;
+                    if last_retune.elapsed() >= CPU_BUDGET_RETUNE_INTERVAL {
+                        if let Some(threads) = budget.retune_threads(sys_info.load_average().one) {
+                            match detector.set_threads(Some(threads)) {
+                                Ok(()) if verbose => {
+                                    writeln!(out, "{}", tr!("The load changed, checking with {} threads now", threads))?
+                                }
+                                Ok(()) => {}
+                                // The old threads keep checking.
+                                Err(err) => writeln!(out, "{}", out.paint(Style::Warning, &err))?,
+                            }
+                        }
+                        last_retune = Instant::now();
+                    }
+                    checked
+                }
+                None => {
+                    supervised_scan(&detector, conf.full_scan_every > 0, full_scan, stall_monitor.as_mut(), &state, &mut out)?
+                }
+            };
+            let (intact, disagreed) = match checked {
+                Ok(checked) => {
+                    consecutive_panics = 0;
+                    checked
+                }
+                Err(message) => {
+                    consecutive_panics += 1;
+                    if consecutive_panics > MAX_CONSECUTIVE_PANICS {
+                        return Err(format!("The check panicked {} times in a row: {}", consecutive_panics, message).into());
+                    }
+                    let warning = tr!("The check panicked, writing the detector again and carrying on: {}", message);
+                    writeln!(out, "{}", out.paint(Style::Warning, &warning))?;
+                    summary.errors.panics += 1;
+                    if let Some(monitor) = &stall_monitor {
+                        monitor.abandon_scan();
+                    }
+                    pipeline.send(Event::Log(vec![Record::Panic {
+                        timestamp_ms: SystemTime::now()
+                            .duration_since(UNIX_EPOCH)
+                            .unwrap_or_default()
+                            .as_millis(),
+                        total_checks,
+                        // Commas and line breaks would end the column of the CSV log.
+                        message: message.replace(',', ";").replace('\n', " "),
+                        tags: Tags::new(),
+                        sequence: None,
+                    }]));
+                    // Nothing can be said about flips during the check, and writing the whole detector again starts
+                    // over from a known state.
+                    ledger.add_dead_time(exposure_mark.elapsed());
+                    summary.add_dead_time(exposure_mark.elapsed());
+                    to_repair = None;
+                    continue 'detection;
+                }
+            };
+            everything_is_fine = intact;
+            window_smi = smi.check_finished(check_start.elapsed(), window_start.elapsed());
+            if disagreed {
+                let message = tr!("The checksums and the full scan of the detector disagree, going by the full scan");
+                writeln!(out, "{}", out.paint(Style::Warning, &message))?;
+                summary.errors.disagreements += 1;
+                pipeline.send(Event::Log(vec![Record::Disagreement {
+                    timestamp_ms: SystemTime::now()
+                        .duration_since(UNIX_EPOCH)
+                        .unwrap_or_default()
+                        .as_millis(),
+                    total_checks,
+                    crc_intact: !intact,
+                    scan_intact: intact,
+                    tags: Tags::new(),
+                    sequence: None,
+                }]));
+            }
+            if let (Some(rapl), Some(energy_start)) = (&rapl, energy_start) {
+                if let Ok(energy_end) = rapl.read() {
+                    energy.add(rapl.joules_between(&energy_start, &energy_end));
+                }
+            }
+            let exposed = exposure_mark.elapsed();
+            if warming_up && Instant::now() >= warmup_until {
+                warming_up = false;
+                if verbose {
+                    writeln!(out, "{}", tr!("The warm-up is over, counting the exposure from now on"))?;
+                }
+            }
+            if warming_up {
+                ledger.add_dead_time(exposed);
+                summary.add_dead_time(exposed);
+            } else {
+                ledger.add_exposure(size, exposed, 1);
+                summary.add_exposure(size, exposed);
+                rate.add_exposure(size, exposed);
+                if let Some(anomalies) = &mut anomalies {
+                    anomalies.add_exposure(size, exposed);
+                }
+            }
+            state.set_rate_per_gb_day(rate.per_gb_day().unwrap_or_default());
+            exposure_mark = Instant::now();
+            if let Some(resizer) = resizer.as_mut().filter(|_| everything_is_fine) {
+                if last_resize_poll.elapsed() >= RESIZE_POLL_INTERVAL {
+                    if let Some(resize) = resizer.poll(&mut sys_info, size) {
+                        let (from, to) = (mem_size(size as u64), mem_size(resize.size as u64));
+                        let message = match resize.reason {
+                            ResizeReason::SwapGrew(bytes) => {
+                                tr!("Shrinking the detector from {} to {}, {} more swap is in use", from, to, mem_size(bytes))
+                            }
+                            ResizeReason::LowMemory(bytes) => {
+                                tr!("Shrinking the detector from {} to {}, only {} of memory is available", from, to, mem_size(bytes))
+                            }
+                            ResizeReason::FreeMemory(bytes) => tr!(
+                                "Growing the detector from {} to {}, {} of memory has been free for {} minutes",
+                                from,
+                                to,
+                                mem_size(bytes),
+                                conf.resize_grow_after
+                            ),
+                        };
+                        writeln!(out, "{}", message)?;
+                        let timestamp_ms = SystemTime::now()
+                            .duration_since(UNIX_EPOCH)
+                            .unwrap_or_default()
+                            .as_millis();
+                        summary.add_resize(timestamp_ms, size, resize.size, resize.reason);
+                        // The limit goes up before the detector grows and down after it shrank.
+                        let grows = resize.size > size;
+                        if grows {
+                            limit_memory(self_cgroup.as_mut(), resize.size, &mut out)?;
+                        }
+                        let resized = detector.resize(resize.size);
+                        if resized < resize.size {
+                            let warning = tr!("Only {} of detector memory could be allocated", mem_size(resized as u64));
+                            writeln!(out, "{}", out.paint(Style::Warning, &warning))?;
+                        }
+                        if huge_pages {
+                            use_huge_pages(&detector, &mut out)?;
+                        }
+                        if memory_locked {
+                            lock_memory(&detector, &mut out)?;
+                        }
+                        if !grows || resized < resize.size {
+                            limit_memory(self_cgroup.as_mut(), resized, &mut out)?;
+                        }
+                        // Resizing writes to the detector's pages, which says nothing about flips in them.
+                        if let Some(soft_dirty) = &soft_dirty {
+                            if let Err(err) = soft_dirty.clear() {
+                                writeln!(out, "{}", err)?;
+                            }
+                        }
+                        size = resized;
+                        state.detector_size.store(size, Ordering::Relaxed);
+                    }
+                    last_resize_poll = Instant::now();
+                }
+            }
+            pipeline.check()?;
+            if last_ledger_save.elapsed() >= ledger_save_interval {
+                pipeline.send(Event::Ledger(ledger.clone()));
+                last_ledger_save = Instant::now();
+            }
+            if let Some(classroom) = &mut classroom {
+                out.status(&classroom.status())?;
+            } else if verbose {
+                out.status(&tr!(
+                    "Integrity checks passed: {}   Bitflips: {}   Rate: {} per GB-day",
+                    format!("{:>12}", total_checks),
+                    format!("{:>4}", state.flips.load(Ordering::Relaxed)),
+                    format!("{:>9.3}", state.rate_per_gb_day())
+                ))?;
+            }
+            if let Some(disk_watch) = &mut disk_watch {
+                match disk_watch.poll() {
+                    Ok(Some((level, free_bytes))) => {
+                        let free = mem_size(free_bytes);
+                        let text = match level {
+                            DiskSpace::Enough => tr!("The filesystem holding the log has {} free again", free),
+                            DiskSpace::Low => tr!(
+                                "Only {} is free on the filesystem holding the log, nothing can be logged once it is full",
+                                free
+                            ),
+                            DiskSpace::Critical => tr!(
+                                "Only {} is free on the filesystem holding the log, leaving heartbeats out of the log until there is more",
+                                free
+                            ),
+                        };
+                        match level {
+                            DiskSpace::Enough => writeln!(out, "{}", text)?,
+                            _ => writeln!(out, "{}", out.paint(Style::Warning, &text))?,
+                        }
+                        pipeline.send(Event::DiskSpace { free_bytes, text });
+                    }
+                    Ok(None) => {}
+                    Err(err) => writeln!(out, "{}", out.paint(Style::Warning, &err))?,
+                }
+            }
+            // Heartbeats left out while the disk is nearly full go into the next one that is logged.
+            let heartbeats_paused = disk_watch.as_ref().is_some_and(DiskWatch::heartbeats_paused);
+            let heartbeat_due =
+                !heartbeats_paused && heartbeat_interval.map_or(false, |interval| last_heartbeat.elapsed() >= interval);
+            if everything_is_fine && (conf.heartbeats_to_stdout || heartbeat_due) {
+                let heartbeat = Record::Heartbeat {
+                    timestamp_ms: SystemTime::now()
+                        .duration_since(UNIX_EPOCH)
+                        .unwrap_or_default()
+                        .as_millis(),
+                    total_checks,
+                    energy: match heartbeat_due {
+                        true => energy.take(size),
+                        false => None,
+                    },
+                    paging: match heartbeat_due {
+                        true => paging.as_mut().and_then(PagingSampler::take),
+                        false => None,
+                    },
+                    residency: match heartbeat_due {
+                        true => residency.take(),
+                        false => None,
+                    },
+                    cadence: match heartbeat_due {
+                        true => cadence.as_mut().and_then(CadenceScheduler::take),
+                        false => None,
+                    },
+                    smi: match heartbeat_due {
+                        true => smi.take(),
+                        false => None,
+                    },
+                    tags: Tags::new(),
+                    sequence: None,
+                };
+                if heartbeat_due {
+                    pipeline.send(Event::Log(vec![heartbeat]));
+                    last_heartbeat = Instant::now();
+                } else {
+                    pipeline.send(Event::Stream(Box::new(heartbeat)));
+                }
+            }
+            if let Some(edac_sampler) = &mut edac_sampler {
+                match edac_sampler.poll() {
+                    Ok(Some(counts)) => {
+                        summary.set_memory_errors(counts.corrected, counts.uncorrected);
+                        let memory_errors = Record::MemoryErrors {
+                            timestamp_ms: SystemTime::now()
+                                .duration_since(UNIX_EPOCH)
+                                .unwrap_or_default()
+                                .as_millis(),
+                            corrected: counts.corrected,
+                            uncorrected: counts.uncorrected,
+                            tags: Tags::new(),
+                            sequence: None,
+                        };
+                        pipeline.send(Event::Log(vec![memory_errors]));
+                    }
+                    Ok(None) => {}
+                    Err(err) => writeln!(out, "{}", out.paint(Style::Warning, &err))?,
+                }
+            }
+            if everything_is_fine && conf.healthcheck_url.is_some() {
+                pipeline.send(Event::Healthy);
+            }
+            total_checks += 1;
+            checks_since_last_bitflip += 1;
+            state.total_checks.store(total_checks, Ordering::Relaxed);
+
+            if conf.influx_url.is_some() {
+                let since_last_report = last_influx_report.elapsed();
+                if since_last_report >= influx_interval {
+                    pipeline.send(Event::Status {
+                        checks_per_second: (total_checks - checks_at_last_influx_report) as f64
+                            / since_last_report.as_secs_f64(),
+                        total_checks,
+                        detector_bytes: size,
+                        gb_hours: gb_hours(size, start.elapsed()),
+                    });
+                    last_influx_report = Instant::now();
+                    checks_at_last_influx_report = total_checks;
+                }
+            }
+        }
+
+        let check_end = Instant::now();
+        let (end_check_time, time_source) = match &pps_clock {
+            Some(pps_clock) => pps_clock.now(),
+            None => (SystemTime::now(), TimeSource::System),
+        };
+        if let Some(gpio_pulser) = &mut gpio_pulser {
+            gpio_pulser.pulse();
+        }
+        let (clock_sync, clock_max_error_ms) = clock::sync_status();
+        let end_check_time_unix_timestamp = end_check_time
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+
+        let detected = tr!(
+            "Detected a bitflip after {} on integrity check number {}",
+            format!("{:?}", start.elapsed()),
+            total_checks
+        );
+        match &mut classroom {
+            Some(classroom) => {
+                if let Err(err) = classroom.add_event() {
+                    writeln!(out, "{}", tr!("Unable to save the classroom counter: {}", err))?;
+                }
+                writeln!(out, "{}", out.paint(Style::Flip, &classroom.banner()))?;
+            }
+            None => writeln!(out, "{}", out.paint(Style::Flip, &detected))?,
+        }
+        if warming_up {
+            writeln!(out, "{}", tr!("This bitflip was found during the warm-up, it is left out of the rates"))?;
+        }
+        if window_smi.heavy {
+            let warning = tr!("Firmware was busy with system management interrupts around this bitflip, it may be an artifact");
+            writeln!(out, "{}", out.paint(Style::Warning, &warning))?;
+        }
+
+        let changed = detector.find_all_changed_elements();
+        let vanished = changed.is_empty();
+        let physical_address = |index: usize| {
+            let pagemap = pagemap.as_ref()?;
+            pagemap.physical_address(detector.address_of(index)?).ok().flatten()
+        };
+        let numa_node = |index: usize| {
+            (numa_nodes.len() > 1).then_some(())?;
+            numa::node_of(detector.address_of(index)?).ok().flatten()
+        };
+        let diffs: Vec<FlipDiff> = changed
+            .iter()
+            .take(MAX_LOGGED_DIFFS)
+            .filter_map(|&index| detector.diff_at(index))
+            .map(|info| {
+                let (chunk, offset) = detector.chunk_of(info.index).unzip();
+                FlipDiff {
+                    physical_address: physical_address(info.index),
+                    numa_node: numa_node(info.index),
+                    chunk: chunk.map(|chunk| chunk as u64),
+                    offset: offset.map(|offset| offset as u64),
+                    ..FlipDiff::from(info)
+                }
+            })
+            .collect();
+        if classroom.is_none() {
+            if vanished {
+                writeln!(
+                    out,
+                    "{}",
+                    tr!("The same bit flipped back before we could find which one it was! Incredible!")
+                )?;
+            }
+            if changed.len() > 1 {
+                writeln!(out, "{}", tr!("{} bytes changed between the same two checks", changed.len()))?;
+            }
+            for &index in changed.iter().take(MAX_LOGGED_DIFFS) {
+                let diff = diffs.iter().find(|diff| diff.index == index as u64);
+                report_changed_byte(&detector, soft_dirty.as_ref(), index, diff, &mut out)?;
+            }
+            if changed.len() > MAX_LOGGED_DIFFS {
+                writeln!(
+                    out,
+                    "{}",
+                    tr!("The other {} changed bytes are left out of the log", changed.len() - MAX_LOGGED_DIFFS)
+                )?;
+            }
+        }
+
+        let log_entry = Record::Flip {
+            started_ms: unix_timestamp.as_millis(),
+            delay_ms: check_delay,
+            checks: checks_since_last_bitflip,
+            vanished,
+            detected_ms: end_check_time_unix_timestamp.as_millis(),
+            location,
+            uncertainty: Some(Uncertainty {
+                window_ms: (check_end - window_start).as_millis() as u64,
+                scan_ms: (check_end - check_start).as_millis() as u64,
+                clock: clock_sync,
+                clock_max_error_ms,
+                pattern_coverage: detector.pattern_coverage(),
+                resident_fraction: reduced_previous_check.into_iter().chain(reduced_latest_check).reduce(f64::min),
+                time_source,
+                detected_ns: (!time_source.is_system()).then_some(end_check_time_unix_timestamp.as_nanos()),
+                smi_count: window_smi.count,
+                heavy_smi: window_smi.heavy,
+            }),
+            changed_bytes: Some(changed.len() as u64),
+            diffs,
+            warmup: warming_up,
+            tags: Tags::new(),
+            sequence: None,
+        };
+        pipeline.send(Event::Log(vec![log_entry.clone()]));
+
+        checks_since_last_bitflip = 0;
+        state.flips.fetch_add(1, Ordering::Relaxed);
+        // Flips during the warm-up are logged, but don't count towards the rates.
+        if !warming_up {
+            summary.flips += 1;
+            summary.vanished_flips += vanished as u64;
+            rate.add_event();
+            state.set_rate_per_gb_day(rate.per_gb_day().unwrap_or_default());
+            if let Some(anomalies) = &mut anomalies {
+                anomalies.add_event();
+                if let Some(anomaly) = anomalies.check(&rate) {
+                    let text = tr!(
+                        "Alert: {} bitflips per GB-day lately, {} standard deviations above the usual {}. A burst like this almost always means a hardware problem",
+                        format!("{:.3}", anomaly.recent_per_gb_day),
+                        format!("{:.1}", anomaly.sigma),
+                        format!("{:.3}", anomaly.baseline_per_gb_day)
+                    );
+                    writeln!(out, "{}", out.paint(Style::Warning, &text))?;
+                    pipeline.send(Event::Alert { anomaly, text });
+                }
+            }
+        } else {
+            summary.warmup_flips += 1;
+        }
+        to_repair = Some(changed);
+        state.push_event(log_entry);
+    }
+
+    writeln!(out, "{}", tr!("Shutting down after {} integrity checks", total_checks))?;
+    pipeline.send(Event::Ledger(ledger));
+    // Logged last, so that a session without one in the log didn't stop cleanly.
+    pipeline.send(Event::Log(vec![Record::Shutdown {
+        timestamp_ms: SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis(),
+        total_checks,
+        flips: state.flips.load(Ordering::Relaxed),
+        uptime_ms: start.elapsed().as_millis() as u64,
+        tags: Tags::new(),
+        sequence: None,
+    }]));
+    summary.errors.dropped_records = pipeline.dropped_total();
+    pipeline.finish()?;
+    summary.stopped_ms = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis();
+    summary.uptime_seconds = start.elapsed().as_secs_f64();
+    summary.total_checks = total_checks;
+    summary.detector_bytes = size;
+    summary.errors.stalled_scans = state.stalls.load(Ordering::Relaxed);
+    if let Err(err) = summary.save(&summary_path) {
+        writeln!(out, "{}", out.paint(Style::Warning, &err))?;
+    }
+    Ok(())
+}
+
+/// Tells which bits of a changed byte flipped, which chunk it is in, where it is in physical memory and on which NUMA
+/// node when the logged diff knows, and whether software wrote to its page when that is tracked.
+fn report_changed_byte(
+    detector: &DetectorArray,
+    soft_dirty: Option<&SoftDirty>,
+    index: usize,
+    diff: Option<&FlipDiff>,
+    out: &mut Console,
+) -> io::Result<()> {
+    match detector.diff_at(index) {
+        Some(info) if info.is_multi_bit() => {
+            let bits: Vec<String> = info.changed_bits().iter().map(u8::to_string).collect();
+            writeln!(
+                out,
+                "{}",
+                tr!(
+                    "Bits {} flipped in the byte at index {}, it became {} instead of {}",
+                    bits.join(", "),
+                    index,
+                    info.observed,
+                    info.expected
+                )
+            )?
+        }
+        Some(info) => match info.changed_bits().first() {
+            Some(bit) => writeln!(
+                out,
+                "{}",
+                tr!(
+                    "Bit {} flipped in the byte at index {}, it became {} instead of {}",
+                    bit,
+                    index,
+                    info.observed,
+                    info.expected
+                )
+            )?,
+            // It flipped back between finding it and reading it.
+            None => writeln!(out, "{}", tr!("Bitflip in byte at index {}, it became {}", index, info.observed))?,
+        },
+        None => writeln!(out, "{}", tr!("Bitflip in byte at index {}, which is outside the detector", index))?,
+    }
+    if let Some((chunk, offset)) = diff.and_then(|diff| diff.chunk.zip(diff.offset)) {
+        writeln!(out, "{}", tr!("It is at offset {} of the detector's chunk {}", offset, chunk))?;
+    }
+    if let Some(physical_address) = diff.and_then(|diff| diff.physical_address) {
+        writeln!(out, "{}", tr!("Its physical address is {}", format!("{:#x}", physical_address)))?;
+    }
+    if let Some(numa_node) = diff.and_then(|diff| diff.numa_node) {
+        writeln!(out, "{}", tr!("It is on NUMA node {}", numa_node))?;
+    }
+    if let Some((soft_dirty, address)) = soft_dirty.zip(detector.address_of(index)) {
+        match soft_dirty.is_dirty(address) {
+            Ok(true) => writeln!(
+                out,
+                "{}",
+                tr!("The page holding it was written to by software since the last reset, this is probably not a cosmic ray")
+            )?,
+            Ok(false) => writeln!(out, "{}", tr!("Nothing wrote to the page holding it since the last reset"))?,
+            Err(err) => writeln!(out, "{}", err)?,
+        }
+    }
+    Ok(())
+}
+
+/// Limits the memory of the detector's own cgroup, if it has one, to a detector of the given size.
+/// A failure leaves the old limit and is only reported, the detector keeps running.
+fn limit_memory(cgroup: Option<&mut SelfCgroup>, detector_bytes: usize, out: &mut Console) -> io::Result<()> {
+    match cgroup.map(|cgroup| cgroup.limit(detector_bytes)) {
+        Some(Err(err)) => writeln!(out, "{}", out.paint(Style::Warning, &err)),
+        _ => Ok(()),
+    }
+}
+
+/// The settings as a crash report shows them, without the tokens and the URLs, which can carry credentials or
+/// presigned tokens.
+fn redacted_settings(conf: &RunArgs) -> String {
+    let redacted = |secret: &Option<String>| secret.as_ref().map(|_| "<redacted>".to_string());
+    let shown = RunArgs {
+        alert_webhook: redacted(&conf.alert_webhook),
+        healthcheck_url: redacted(&conf.healthcheck_url),
+        influx_url: redacted(&conf.influx_url),
+        influx_token: redacted(&conf.influx_token),
+        upload_url: redacted(&conf.upload_url),
+        s3_url: redacted(&conf.s3_url),
+        ..conf.clone()
+    };
+    format!("{:#?}", shown)
+}
+
+/// Asks for huge pages for the detector, returning whether that worked. A failure is only reported.
+fn use_huge_pages(detector: &DetectorArray, out: &mut Console) -> io::Result<bool> {
+    match detector.use_huge_pages() {
+        Ok(()) => Ok(true),
+        Err(err) => {
+            let warning = tr!("Not using huge pages for the detector: {}", err);
+            writeln!(out, "{}", out.paint(Style::Warning, &warning))?;
+            Ok(false)
+        }
+    }
+}
+
+/// Spreads the detector's chunks over the NUMA nodes for `--numa`, so that every node's memory is watched. Where there
+/// is only one node, or binding fails, it is only reported and the detector stays where the kernel put it.
+fn spread_over_nodes(detector: &mut DetectorArray, nodes: &[u32], out: &mut Console) -> io::Result<()> {
+    if nodes.len() < 2 {
+        let warning = tr!("Not spreading the detector over NUMA nodes, this machine only has one");
+        return writeln!(out, "{}", out.paint(Style::Warning, &warning));
+    }
+    match detector.spread_over_nodes(nodes.to_vec()) {
+        Ok(()) => writeln!(out, "{}", tr!("The detector is spread over {} NUMA nodes", nodes.len())),
+        Err(err) => {
+            let warning = tr!("Not spreading the detector over NUMA nodes: {}", err);
+            writeln!(out, "{}", out.paint(Style::Warning, &warning))
+        }
+    }
+}
+
+/// Locks the detector's memory so it can't be swapped out, returning whether it is locked.
+/// A failure, usually RLIMIT_MEMLOCK being lower than the detector, is only reported and the detector runs unlocked.
+fn lock_memory(detector: &DetectorArray, out: &mut Console) -> io::Result<bool> {
+    match detector.lock_memory() {
+        Ok(()) => Ok(true),
+        Err(err) => {
+            let warning = tr!("Not locking the detector's memory, it may be swapped out: {}", err);
+            writeln!(out, "{}", out.paint(Style::Warning, &warning))?;
+            Ok(false)
+        }
+    }
+}
+
+/// Checks the detector with its checksums when `checksums` is set, and scans it in full as well when `full_scan` is set
+/// or the checksums found a change. Returns whether it is intact, going by the full scan when there was one, and
+/// whether the checksums said otherwise.
+fn audited_scan(
+    detector: &DetectorArray,
+    checksums: bool,
+    full_scan: bool,
+    monitor: Option<&mut StallMonitor>,
+    state: &InstanceState,
+    out: &mut dyn Write,
+) -> io::Result<(bool, bool)> {
+    if !checksums {
+        return Ok((scan(detector, monitor, state, out)?, false));
+    }
+    let crc_intact = detector.checksums_intact();
+    if crc_intact && !full_scan {
+        return Ok((true, false));
+    }
+    let intact = scan(detector, monitor, state, out)?;
+    Ok((intact, intact != crc_intact))
+}
+
+/// Runs `audited_scan` on a thread of its own and waits for it, so that a panic in the check is caught instead of
+/// ending the session. What the check writes is passed on to `out` once it is done. The inner result is the panic's
+/// message when the check panicked.
+fn supervised_scan(
+    detector: &DetectorArray,
+    checksums: bool,
+    full_scan: bool,
+    monitor: Option<&mut StallMonitor>,
+    state: &InstanceState,
+    out: &mut Console,
+) -> io::Result<Result<(bool, bool), String>> {
+    let mut written = Vec::new();
+    let checked = thread::scope(|scope| {
+        scope.spawn(|| audited_scan(detector, checksums, full_scan, monitor, state, &mut written)).join()
+    });
+    out.write_all(&written)?;
+    match checked {
+        Ok(checked) => checked.map(Ok),
+        Err(payload) => Ok(Err(panic_message(payload.as_ref()))),
+    }
+}
+
+/// What a caught panic said, panics with a payload other than a message only say that they happened.
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    match (payload.downcast_ref::<&str>(), payload.downcast_ref::<String>()) {
+        (Some(message), _) => message.to_string(),
+        (_, Some(message)) => message.clone(),
+        _ => "a panic without a message".into(),
+    }
+}
+
+/// Checks the detector once. Scans that stall are counted, and cancelled and started over if the monitor is set to.
+fn scan(
+    detector: &DetectorArray,
+    monitor: Option<&mut StallMonitor>,
+    state: &InstanceState,
+    out: &mut dyn Write,
+) -> io::Result<bool> {
+    let Some(monitor) = monitor else {
+        return Ok(detector.is_intact());
+    };
+    let mut retries = 0;
+    loop {
+        monitor.begin_scan();
+        let scan_start = Instant::now();
+        let intact = match monitor.cancels_stalled_scans() && retries < stall::MAX_RETRIES {
+            true => detector.is_intact_unless_cancelled(monitor.cancel_flag()),
+            false => Some(detector.is_intact()),
+        };
+        let duration = scan_start.elapsed();
+        if monitor.end_scan(duration) {
+            state.stalls.fetch_add(1, Ordering::Relaxed);
+            let message = tr!(
+                "A scan stalled, it took {} where {} is typical",
+                format!("{:?}", duration),
+                format!("{:?}", monitor.typical().unwrap_or_default())
+            );
+            writeln!(out, "{}", message)?;
+        }
+        match intact {
+            Some(intact) => return Ok(intact),
+            None => {
+                retries += 1;
+                writeln!(out, "{}", tr!("Starting the cancelled scan over"))?;
+            }
+        }
+    }
+}
@@ -0,0 +1,222 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::{
+    config::Pattern,
+    detector::{Detector, DetectorMemory},
+    rng::Rng,
+};
+
+/// A change found in the detector memory.
+#[derive(Debug, Clone)]
+pub struct FlipEvent {
+    /// When the check that found the change finished.
+    pub detected_at: SystemTime,
+    /// The index of the changed byte, none if it had changed back by the time it was looked for.
+    pub index: Option<usize>,
+    /// The value the changed byte held.
+    pub value: Option<u8>,
+    /// The number of checks that passed since the previous event.
+    pub checks: u64,
+    /// The change happened at most this long before it was detected.
+    pub window: Duration,
+}
+
+/// Sent regularly while nothing changes, to show that the detector is checking.
+#[derive(Debug, Clone)]
+pub struct HeartbeatEvent {
+    pub timestamp: SystemTime,
+    pub total_checks: u64,
+}
+
+type Callback<T> = Box<dyn FnMut(&T) + Send>;
+
+/// Takes the events of a runner, like the callbacks but as a single value that keeps whatever state it needs, e.g. a
+/// log or a queue to another thread. Any `FnMut(&FlipEvent)` is a sink that ignores heartbeats.
+pub trait Sink: Send {
+    fn flip(&mut self, event: &FlipEvent);
+
+    fn heartbeat(&mut self, _event: &HeartbeatEvent) {}
+}
+
+impl<F: FnMut(&FlipEvent) + Send> Sink for F {
+    fn flip(&mut self, event: &FlipEvent) {
+        self(event)
+    }
+}
+
+impl Sink for Box<dyn Sink> {
+    fn flip(&mut self, event: &FlipEvent) {
+        (**self).flip(event)
+    }
+
+    fn heartbeat(&mut self, event: &HeartbeatEvent) {
+        (**self).heartbeat(event)
+    }
+}
+
+/// Checks a detector at a fixed delay and calls back on every flip and heartbeat, for embedding the detector.
+///
+/// Callbacks always run on the thread that checks, one at a time in the order they were registered, after the
+/// check that caused them and before the next one starts. They can keep state without locking, but a slow callback
+/// delays the next check, so hand anything slow over to another thread.
+pub struct Runner<D: DetectorMemory = Detector> {
+    detector: D,
+    delay: Duration,
+    heartbeat_interval: Option<Duration>,
+    flip_callbacks: Vec<Callback<FlipEvent>>,
+    heartbeat_callbacks: Vec<Callback<HeartbeatEvent>>,
+    /// What the detector is written with instead of its default value, and where random bytes for it come from.
+    pattern: Option<(Pattern, Rng)>,
+}
+
+impl<D: DetectorMemory + Send + 'static> Runner<D> {
+    pub fn new(detector: D, delay: Duration) -> Self {
+        Runner {
+            detector,
+            delay,
+            heartbeat_interval: None,
+            flip_callbacks: Vec::new(),
+            heartbeat_callbacks: Vec::new(),
+            pattern: None,
+        }
+    }
+
+    /// Writes the detector with the pattern every time it is reset, at the start and after every flip, instead of with
+    /// its default value. Random bytes are drawn from `rng`.
+    pub fn pattern(mut self, pattern: Pattern, rng: Rng) -> Self {
+        self.pattern = Some((pattern, rng));
+        self
+    }
+
+    /// Sends heartbeats every `interval` while nothing changes, to the heartbeat callbacks and the sinks.
+    pub fn heartbeats(mut self, interval: Duration) -> Self {
+        self.heartbeat_interval = Some(interval);
+        self
+    }
+
+    /// Hands every flip, and every heartbeat when they are on, to `sink`.
+    pub fn sink(mut self, sink: impl Sink + 'static) -> Self {
+        let flip_sink = Arc::new(Mutex::new(sink));
+        let heartbeat_sink = flip_sink.clone();
+        // The callbacks run one at a time on the checking thread, the lock is never contended.
+        self.flip_callbacks
+            .push(Box::new(move |event| flip_sink.lock().unwrap_or_else(PoisonError::into_inner).flip(event)));
+        self.heartbeat_callbacks.push(Box::new(move |event| {
+            heartbeat_sink.lock().unwrap_or_else(PoisonError::into_inner).heartbeat(event)
+        }));
+        self
+    }
+
+    /// Calls `callback` with every change found.
+    pub fn on_flip(mut self, callback: impl FnMut(&FlipEvent) + Send + 'static) -> Self {
+        self.flip_callbacks.push(Box::new(callback));
+        self
+    }
+
+    /// Calls `callback` after the first passing check once `interval` has gone by since the previous heartbeat.
+    /// All heartbeat callbacks share the interval given last.
+    pub fn on_heartbeat(mut self, interval: Duration, callback: impl FnMut(&HeartbeatEvent) + Send + 'static) -> Self {
+        self.heartbeat_interval = Some(interval);
+        self.heartbeat_callbacks.push(Box::new(callback));
+        self
+    }
+
+    /// Checks on the calling thread, where the callbacks then run too, until `stop` is set.
+    /// Setting it takes effect after the current wait or check.
+    pub fn run(mut self, stop: &AtomicBool) {
+        let mut resets: usize = 0;
+        self.reset(&mut resets);
+        let mut checks: u64 = 0;
+        let mut total_checks: u64 = 0;
+        let mut check_start = Instant::now();
+        let mut last_heartbeat = Instant::now();
+        loop {
+            let deadline = Instant::now() + self.delay;
+            while !stop.load(Ordering::Relaxed) && Instant::now() < deadline {
+                thread::park_timeout(deadline.saturating_duration_since(Instant::now()));
+            }
+            if stop.load(Ordering::Relaxed) {
+                return;
+            }
+            let window_start = check_start;
+            check_start = Instant::now();
+            let intact = self.detector.is_intact();
+            total_checks += 1;
+            if intact {
+                checks += 1;
+                if self.heartbeat_interval.is_some_and(|interval| last_heartbeat.elapsed() >= interval) {
+                    let heartbeat = HeartbeatEvent { timestamp: SystemTime::now(), total_checks };
+                    for callback in &mut self.heartbeat_callbacks {
+                        callback(&heartbeat);
+                    }
+                    last_heartbeat = Instant::now();
+                }
+                continue;
+            }
+            let index = self.detector.find_index_of_changed_element();
+            let event = FlipEvent {
+                detected_at: SystemTime::now(),
+                index,
+                value: index.and_then(|index| self.detector.get(index)),
+                checks,
+                window: window_start.elapsed(),
+            };
+            for callback in &mut self.flip_callbacks {
+                callback(&event);
+            }
+            checks = 0;
+            self.reset(&mut resets);
+            check_start = Instant::now();
+        }
+    }
+
+    /// Writes the detector again, with the pattern's next value when there is a pattern.
+    fn reset(&mut self, resets: &mut usize) {
+        if let Some((pattern, rng)) = &mut self.pattern {
+            self.detector.set_default(pattern.value(*resets, rng));
+        }
+        self.detector.reset();
+        *resets += 1;
+    }
+
+    /// Checks on a thread of its own named `detector`, where the callbacks then run too.
+    pub fn spawn(self) -> Result<RunnerHandle, String> {
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = stop.clone();
+        let thread = thread::Builder::new()
+            .name("detector".into())
+            .spawn(move || self.run(&thread_stop))
+            .map_err(|e| format!("Unable to start the detector thread: {}", e))?;
+        Ok(RunnerHandle { stop, thread: Some(thread) })
+    }
+}
+
+/// A runner checking on its own thread, which is stopped when the handle is dropped.
+pub struct RunnerHandle {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl RunnerHandle {
+    /// Stops the runner and waits for its current check and callbacks to finish.
+    pub fn stop(mut self) {
+        self.stop_thread();
+    }
+
+    fn stop_thread(&mut self) {
+        if let Some(thread) = self.thread.take() {
+            self.stop.store(true, Ordering::Relaxed);
+            thread.thread().unpark();
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for RunnerHandle {
+    fn drop(&mut self) {
+        self.stop_thread();
+    }
+}
@@ -0,0 +1,255 @@
+use std::fs::{self, File};
+use std::io::{ErrorKind, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use sha2::{Digest, Sha256};
+
+use crate::exposure::civil_from_days;
+
+const EXPORT_TIMEOUT: Duration = Duration::from_secs(300);
+/// Longer stretches of log are split over several segments.
+const MAX_SEGMENT_SIZE: u64 = 64 * 1024 * 1024;
+const INITIAL_BACKOFF: Duration = Duration::from_secs(60);
+const MAX_BACKOFF: Duration = Duration::from_secs(6 * 3600);
+
+/// Keys to sign the requests with, from the usual AWS environment variables.
+struct Credentials {
+    access_key_id: String,
+    secret_access_key: String,
+    session_token: Option<String>,
+}
+
+/// Copies what was added to the log since the last export to an S3 compatible bucket every so often, as gzipped
+/// segments at `<station ID>/<log file name>/<offset>.gz`. The offset of a segment is where it starts in the log,
+/// so concatenating the segments in order of their names gives back the log. How far the log has been exported is
+/// kept in `<log>.s3-export`, so a failed export is simply retried later, with an increasing delay, and nothing
+/// is exported twice after a restart. Segments are tagged so that lifecycle rules of the bucket can match them.
+pub struct S3Exporter {
+    /// The bucket in path style, e.g. https://s3.eu-central-1.amazonaws.com/my-bucket
+    bucket_url: String,
+    region: String,
+    credentials: Credentials,
+    /// `key=value` pairs.
+    tags: Vec<String>,
+    station_id: String,
+    log_path: PathBuf,
+    state_path: PathBuf,
+    interval: Duration,
+    backoff: Duration,
+    next_attempt: Instant,
+}
+
+impl S3Exporter {
+    pub fn new(
+        bucket_url: String,
+        region: String,
+        tags: Vec<String>,
+        station_id: String,
+        log_path: &Path,
+        interval: Duration,
+    ) -> Result<Self, String> {
+        let variable = |name: &str| std::env::var(name).map_err(|_| format!("{} has to be set for --s3-url", name));
+        let credentials = Credentials {
+            access_key_id: variable("AWS_ACCESS_KEY_ID")?,
+            secret_access_key: variable("AWS_SECRET_ACCESS_KEY")?,
+            session_token: std::env::var("AWS_SESSION_TOKEN").ok(),
+        };
+        if !bucket_url.starts_with("https://") && !bucket_url.starts_with("http://") {
+            return Err(format!("The bucket URL {} has to start with https://", bucket_url));
+        }
+        Ok(S3Exporter {
+            bucket_url: bucket_url.trim_end_matches('/').to_string(),
+            region,
+            credentials,
+            tags,
+            station_id,
+            log_path: log_path.to_path_buf(),
+            state_path: PathBuf::from(format!("{}.s3-export", log_path.display())),
+            interval,
+            backoff: INITIAL_BACKOFF,
+            next_attempt: Instant::now() + interval,
+        })
+    }
+
+    /// Exports the new part of the log if the interval has passed, or the retry delay after a failure.
+    /// Returns an error describing the failure if the export was attempted and failed.
+    pub fn export_if_due(&mut self) -> Result<(), String> {
+        if Instant::now() < self.next_attempt {
+            return Ok(());
+        }
+        match self.export() {
+            Ok(()) => {
+                self.backoff = INITIAL_BACKOFF;
+                self.next_attempt = Instant::now() + self.interval;
+                Ok(())
+            }
+            Err(err) => {
+                self.next_attempt = Instant::now() + self.backoff;
+                let retry_in = self.backoff;
+                self.backoff = (self.backoff * 2).min(MAX_BACKOFF);
+                Err(format!("Export to S3 failed, retrying in {:?}: {}", retry_in, err))
+            }
+        }
+    }
+
+    /// Exports everything that was added to the log since the last export.
+    pub fn export(&mut self) -> Result<(), String> {
+        let mut log = File::open(&self.log_path).map_err(|e| format!("Unable to read {}: {}", self.log_path.display(), e))?;
+        let length = log.metadata().map_err(|e| e.to_string())?.len();
+        let mut offset = self.exported_offset()?;
+        if offset > length {
+            // The log was replaced by a shorter one, which starts over.
+            offset = 0;
+        }
+        while offset < length {
+            let size = (length - offset).min(MAX_SEGMENT_SIZE);
+            let mut segment = vec![0; size as usize];
+            log.seek(SeekFrom::Start(offset))
+                .and_then(|_| log.read_exact(&mut segment))
+                .map_err(|e| format!("Unable to read {}: {}", self.log_path.display(), e))?;
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::best());
+            encoder.write_all(&segment).map_err(|e| e.to_string())?;
+            let body = encoder.finish().map_err(|e| e.to_string())?;
+
+            self.put(&self.segment_key(offset), "application/gzip", &body)?;
+            offset += size;
+            self.save_exported_offset(offset)?;
+        }
+        Ok(())
+    }
+
+    /// Uploads a small `<station ID>/preflight` object, to find out whether the bucket can be written at all.
+    pub fn write_test(&self) -> Result<(), String> {
+        self.put(&format!("{}/preflight", self.station_id), "text/plain", b"ok\n")
+    }
+
+    fn segment_key(&self, offset: u64) -> String {
+        let log_name = self.log_path.file_name().map(|name| name.to_string_lossy()).unwrap_or_default();
+        // Zero padded so that the names sort in the order of the log.
+        format!("{}/{}/{:020}.gz", self.station_id, log_name, offset)
+    }
+
+    fn exported_offset(&self) -> Result<u64, String> {
+        match fs::read_to_string(&self.state_path) {
+            Ok(contents) => contents
+                .trim()
+                .parse()
+                .map_err(|e| format!("Unable to parse {}: {}", self.state_path.display(), e)),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(0),
+            Err(err) => Err(format!("Unable to read {}: {}", self.state_path.display(), err)),
+        }
+    }
+
+    fn save_exported_offset(&self, offset: u64) -> Result<(), String> {
+        let temporary_path = PathBuf::from(format!("{}.tmp", self.state_path.display()));
+        fs::write(&temporary_path, offset.to_string())
+            .and_then(|_| fs::rename(&temporary_path, &self.state_path))
+            .map_err(|e| format!("Unable to save {}: {}", self.state_path.display(), e))
+    }
+
+    /// Uploads an object with a request signed with AWS Signature Version 4.
+    fn put(&self, key: &str, content_type: &str, body: &[u8]) -> Result<(), String> {
+        let url = format!("{}/{}", self.bucket_url, key.split('/').map(uri_encode).collect::<Vec<_>>().join("/"));
+        let (host, path) = match url.split_once("://").map(|(_, rest)| rest).and_then(|rest| rest.split_once('/')) {
+            Some((host, path)) => (host, format!("/{}", path)),
+            None => return Err(format!("Malformed bucket URL {}", self.bucket_url)),
+        };
+        let timestamp = amz_timestamp();
+        let date = &timestamp[..8];
+        let payload_hash = hex(&Sha256::digest(body));
+        let tagging = self.tags.iter().map(|tag| match tag.split_once('=') {
+            Some((key, value)) => format!("{}={}", uri_encode(key), uri_encode(value)),
+            None => uri_encode(tag),
+        });
+        let tagging = tagging.collect::<Vec<_>>().join("&");
+
+        // Every header that is sent is signed, in order of name.
+        let mut headers = vec![
+            ("content-type", content_type.to_string()),
+            ("host", host.to_string()),
+            ("x-amz-content-sha256", payload_hash.clone()),
+            ("x-amz-date", timestamp.clone()),
+        ];
+        if let Some(token) = &self.credentials.session_token {
+            headers.push(("x-amz-security-token", token.clone()));
+        }
+        if !tagging.is_empty() {
+            headers.push(("x-amz-tagging", tagging));
+        }
+        let signed_headers = headers.iter().map(|(name, _)| *name).collect::<Vec<_>>().join(";");
+        let canonical_headers: String = headers.iter().map(|(name, value)| format!("{}:{}\n", name, value.trim())).collect();
+        let canonical_request = format!("PUT\n{}\n\n{}\n{}\n{}", path, canonical_headers, signed_headers, payload_hash);
+
+        let scope = format!("{}/{}/s3/aws4_request", date, self.region);
+        let string_to_sign = format!("AWS4-HMAC-SHA256\n{}\n{}\n{}", timestamp, scope, hex(&Sha256::digest(canonical_request)));
+        let mut signing_key = hmac_sha256(format!("AWS4{}", self.credentials.secret_access_key).as_bytes(), date.as_bytes());
+        for part in [self.region.as_str(), "s3", "aws4_request"] {
+            signing_key = hmac_sha256(&signing_key, part.as_bytes());
+        }
+        let signature = hex(&hmac_sha256(&signing_key, string_to_sign.as_bytes()));
+        let authorization = format!(
+            "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+            self.credentials.access_key_id, scope, signed_headers, signature
+        );
+
+        let mut request = ureq::put(&url).timeout(EXPORT_TIMEOUT).set("Authorization", &authorization);
+        for (name, value) in headers.iter().filter(|(name, _)| *name != "host") {
+            request = request.set(name, value);
+        }
+        request.send_bytes(body).map(|_| ()).map_err(|e| format!("Unable to upload {}: {}", key, e))
+    }
+}
+
+/// Percent encodes everything but the characters S3 leaves alone.
+fn uri_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => (byte as char).to_string(),
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+fn hmac_sha256(key: &[u8], message: &[u8]) -> Vec<u8> {
+    const BLOCK_SIZE: usize = 64;
+    let mut block = [0u8; BLOCK_SIZE];
+    if key.len() > BLOCK_SIZE {
+        block[..32].copy_from_slice(&Sha256::digest(key));
+    } else {
+        block[..key.len()].copy_from_slice(key);
+    }
+    let mut inner = Sha256::new();
+    inner.update(block.map(|byte| byte ^ 0x36));
+    inner.update(message);
+    let mut outer = Sha256::new();
+    outer.update(block.map(|byte| byte ^ 0x5c));
+    outer.update(inner.finalize());
+    outer.finalize().to_vec()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// The current time as e.g. `20240131T120000Z`.
+fn amz_timestamp() -> String {
+    let seconds = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let (year, month, day) = civil_from_days((seconds / 86400) as i64);
+    let seconds_of_day = seconds % 86400;
+    format!(
+        "{:04}{:02}{:02}T{:02}{:02}{:02}Z",
+        year,
+        month,
+        day,
+        seconds_of_day / 3600,
+        seconds_of_day % 3600 / 60,
+        seconds_of_day % 60
+    )
+}
@@ -0,0 +1,55 @@
+use std::path::{Path, PathBuf};
+
+/// Directories the process may keep reading from once sandboxed. Resolving host names needs /etc and the
+/// system libraries, and the optional features read their statistics from /proc and /sys.
+#[cfg(target_os = "linux")]
+const READABLE_PATHS: [&str; 6] = ["/etc", "/usr", "/lib", "/lib64", "/proc", "/sys"];
+/// Soft-dirty tracking is reset by writing to this file.
+#[cfg(target_os = "linux")]
+const CLEAR_REFS_PATH: &str = "/proc/self/clear_refs";
+
+/// Restricts the filesystem access of the process with Landlock. Afterwards files can only be read below a few
+/// system directories, and only be created or written in the given directories, the temporary directory and
+/// /proc/self/clear_refs.
+/// Landlock only applies to the calling thread and the threads it starts later, so this has to be called
+/// before any other threads are started. Returns a description of how well it worked.
+#[cfg(target_os = "linux")]
+pub fn restrict_filesystem(writable_directories: &[PathBuf]) -> Result<String, String> {
+    use landlock::{
+        path_beneath_rules, Access, AccessFs, Ruleset, RulesetAttr, RulesetCreatedAttr, RulesetStatus, ABI,
+    };
+
+    let abi = ABI::V2;
+    let read = AccessFs::ReadFile | AccessFs::ReadDir;
+    let write = AccessFs::from_all(abi) & !AccessFs::Execute;
+    let mut writable = writable_directories.to_vec();
+    writable.push(std::env::temp_dir());
+    writable.push(PathBuf::from(CLEAR_REFS_PATH));
+
+    let status = Ruleset::default()
+        .handle_access(AccessFs::from_all(abi))
+        .and_then(|ruleset| ruleset.create())
+        .and_then(|ruleset| ruleset.add_rules(path_beneath_rules(READABLE_PATHS, read)))
+        .and_then(|ruleset| ruleset.add_rules(path_beneath_rules(&writable, write)))
+        .and_then(|ruleset| ruleset.restrict_self())
+        .map_err(|e| format!("Unable to sandbox the process: {}", e))?;
+
+    Ok(match status.ruleset {
+        RulesetStatus::FullyEnforced => "Sandboxed the filesystem access of the process".into(),
+        RulesetStatus::PartiallyEnforced => "Partially sandboxed the filesystem access, the kernel lacks some Landlock features".into(),
+        _ => "Not sandboxed, the kernel doesn't support Landlock".into(),
+    })
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn restrict_filesystem(_writable_directories: &[PathBuf]) -> Result<String, String> {
+    Ok("Not sandboxed, sandboxing is only supported on Linux".into())
+}
+
+/// The directory a file is in, which for a bare file name is the current directory.
+pub fn directory_of(path: &Path) -> PathBuf {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+        _ => PathBuf::from("."),
+    }
+}
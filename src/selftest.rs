@@ -0,0 +1,44 @@
+use std::error::Error;
+
+use crate::{
+    config::{GlobalArgs, SelftestArgs},
+    detector::DetectorArray,
+    mem_size,
+};
+
+/// Flips a bit in a detector on purpose and makes sure that the integrity check finds it.
+pub fn selftest(global: &GlobalArgs, args: SelftestArgs) -> Result<(), Box<dyn Error>> {
+    let size = args.memory_to_occupy;
+    if size == 0 {
+        return Err("The selftest needs a detector of at least one byte".into());
+    }
+
+    if global.verbose {
+        println!("Running selftest with a detector of {}", mem_size(size as u64));
+    }
+
+    let mut detector = DetectorArray::new(0, size);
+    if detector.size() < size {
+        return Err(format!("Only {} of the detector could be allocated", mem_size(detector.size() as u64)).into());
+    }
+    detector.reset();
+    if !detector.is_intact() {
+        return Err("The detector was not intact right after being reset".into());
+    }
+
+    let index = size / 2;
+    detector.flip_bit(index, 3).ok_or_else(|| format!("Byte {} is past the end of the detector", index))?;
+    match detector.find_index_of_changed_element() {
+        Some(found) if found == index => {}
+        Some(found) => return Err(format!("Flipped byte {} but byte {} was reported", index, found).into()),
+        None => return Err(format!("Flipped byte {} but it was not detected", index).into()),
+    }
+
+    detector.reset();
+    if !detector.is_intact() {
+        return Err("The detector was not intact after being reset following the flip".into());
+    }
+
+    println!("Selftest passed");
+    Ok(())
+}
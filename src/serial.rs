@@ -0,0 +1,55 @@
+use std::io::Write;
+use std::time::Duration;
+
+use serialport::SerialPort;
+
+use crate::{config::SerialSpec, record::Record};
+
+/// A DAQ that stopped reading must not hold up the rest of the sinks for long.
+const SERIAL_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Writes a compact frame for every bitflip to a serial port, so that lab data acquisition systems can timestamp
+/// them alongside their own channels, such as scintillators. Frames are NMEA style ASCII lines,
+/// `$CRD,<sequence>,<detected_ms>,<window_ms>,<checks>,<vanished>*<checksum>\r\n`, where the checksum is the XOR
+/// of the bytes between `$` and `*` in two hex digits. A sequence number or window the record hasn't got is left empty.
+pub struct SerialOutput {
+    port: Box<dyn SerialPort>,
+}
+
+impl SerialOutput {
+    pub fn open(spec: &SerialSpec) -> Result<Self, String> {
+        let port = serialport::new(&spec.path, spec.baud_rate)
+            .timeout(SERIAL_TIMEOUT)
+            .open()
+            .map_err(|e| format!("Unable to open the serial port {}: {}", spec.path, e))?;
+        Ok(SerialOutput { port })
+    }
+
+    /// Writes the frame of a bitflip, other records have none.
+    pub fn write(&mut self, record: &Record) -> Result<(), String> {
+        let Some(frame) = frame(record) else {
+            return Ok(());
+        };
+        self.port
+            .write_all(frame.as_bytes())
+            .and_then(|_| self.port.flush())
+            .map_err(|e| format!("Unable to write to the serial port: {}", e))
+    }
+}
+
+fn frame(record: &Record) -> Option<String> {
+    let Record::Flip { detected_ms, checks, vanished, uncertainty, sequence, .. } = record else {
+        return None;
+    };
+    let optional = |value: Option<u64>| value.map(|value| value.to_string()).unwrap_or_default();
+    let body = format!(
+        "CRD,{},{},{},{},{}",
+        optional(*sequence),
+        detected_ms,
+        optional(uncertainty.as_ref().map(|uncertainty| uncertainty.window_ms)),
+        checks,
+        *vanished as u8
+    );
+    let checksum = body.bytes().fold(0, |checksum, byte| checksum ^ byte);
+    Some(format!("${}*{:02X}\r\n", body, checksum))
+}
@@ -0,0 +1,272 @@
+use std::collections::{BTreeMap, HashSet};
+use std::error::Error;
+use std::io;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::Deserialize;
+use serde_json::json;
+use tiny_http::{Header, Method, Request, Response, Server};
+
+use crate::{
+    config::{GlobalArgs, ServeArgs},
+    leaderboard::Leaderboard,
+    record::{Record, SessionInfo},
+    signing::verify,
+    station::StationConfig,
+    storage::{self, StationEntry, Storage, StoredRecord},
+    upload::UploadRecord,
+};
+
+const UPLOAD_PATH: &str = "/upload";
+const LEADERBOARD_PATH: &str = "/leaderboard";
+/// Where `fetch-community` downloads the aggregate rates from.
+pub const COMMUNITY_PATH: &str = "/community";
+
+/// A batch as sent by `upload::Uploader`.
+#[derive(Deserialize)]
+struct ReceivedBatch {
+    station_id: String,
+    session: SessionInfo,
+    records: Vec<UploadRecord>,
+    /// The station's estimate of how far its clock is off from ours.
+    #[serde(default)]
+    clock_offset_ms: Option<i64>,
+}
+
+/// Receives uploads from stations, keeping a registry of the stations and every record they sent exactly once.
+struct Collector {
+    storage: Box<dyn Storage>,
+    registry: BTreeMap<String, StationEntry>,
+    /// The station and record ID of every stored record.
+    seen: HashSet<(String, String)>,
+    leaderboard: Leaderboard,
+    open_registration: bool,
+}
+
+/// Why an upload was turned down, with the status code to answer with.
+struct Rejection(u16, String);
+
+impl Collector {
+    fn open(mut storage: Box<dyn Storage>, open_registration: bool) -> Result<Self, String> {
+        let registry = storage.stations()?;
+        let mut seen = HashSet::new();
+        let mut leaderboard = Leaderboard::default();
+        for stored in storage.records()? {
+            leaderboard.add(&stored.station_id, &stored.id, &stored.record);
+            seen.insert((stored.station_id, stored.id));
+        }
+        Ok(Collector { storage, registry, seen, leaderboard, open_registration })
+    }
+
+    /// Verifies and stores an upload that arrived at `now`, returning the IDs of the records that are now stored.
+    fn receive(&mut self, body: &[u8], public_key: &str, signature: &str, now: u64) -> Result<Vec<String>, Rejection> {
+        verify(public_key, body, signature).map_err(|e| Rejection(401, e))?;
+        let batch: ReceivedBatch =
+            serde_json::from_slice(body).map_err(|e| Rejection(400, format!("Malformed batch: {}", e)))?;
+        StationConfig::default()
+            .station_id(Some(&batch.station_id))
+            .map_err(|e| Rejection(400, e))?;
+        let session_id = batch.session.session_id.as_deref().unwrap_or_default();
+
+        match self.registry.get(&batch.station_id) {
+            Some(station) if station.public_key != public_key => {
+                return Err(Rejection(403, format!("Station {} is registered with a different key", batch.station_id)))
+            }
+            Some(_) => {}
+            None if self.open_registration => {
+                self.registry.insert(
+                    batch.station_id.clone(),
+                    StationEntry {
+                        public_key: public_key.to_string(),
+                        first_seen_ms: now,
+                        last_seen_ms: now,
+                        version: String::new(),
+                        location: None,
+                    },
+                );
+            }
+            None => return Err(Rejection(403, format!("Station {} is not registered", batch.station_id))),
+        }
+
+        let mut acked = Vec::new();
+        let mut new_records: Vec<StoredRecord> = Vec::new();
+        let mut location = None;
+        for upload in batch.records {
+            // The ID is derived from the session and sequence number, anything else would break deduplication.
+            if upload.id != format!("{}:{}", session_id, upload.sequence) {
+                return Err(Rejection(400, format!("Record ID {} does not match its session and sequence", upload.id)));
+            }
+            if let Record::Start { location: Some(start_location), .. } = &upload.record {
+                location = Some(*start_location);
+            }
+            let key = (batch.station_id.clone(), upload.id.clone());
+            if !self.seen.contains(&key) && !new_records.iter().any(|stored| stored.id == upload.id) {
+                new_records.push(StoredRecord {
+                    station_id: batch.station_id.clone(),
+                    received_ms: now,
+                    id: upload.id.clone(),
+                    record: upload.record,
+                    clock_offset_ms: batch.clock_offset_ms,
+                });
+            }
+            acked.push(upload.id);
+        }
+
+        // Records are only acknowledged once they are stored.
+        self.storage.append(&new_records).map_err(|e| Rejection(500, e))?;
+        for stored in new_records {
+            self.leaderboard.add(&stored.station_id, &stored.id, &stored.record);
+            self.seen.insert((stored.station_id, stored.id));
+        }
+
+        let station = self.registry.get_mut(&batch.station_id).expect("The station was registered above");
+        station.last_seen_ms = now;
+        station.version = batch.session.version;
+        if location.is_some() {
+            station.location = location;
+        }
+        self.storage
+            .save_station(&batch.station_id, station)
+            .map_err(|e| Rejection(500, e))?;
+        Ok(acked)
+    }
+}
+
+/// Runs a collector that stations can send their results to, see docs/upload_protocol.md.
+pub fn serve(global: &GlobalArgs, args: ServeArgs) -> Result<(), Box<dyn Error>> {
+    if args.migrate_dry_run {
+        for line in storage::pending_migrations(&args.storage, &args.data_dir)? {
+            println!("{}", line);
+        }
+        return Ok(());
+    }
+    let mut collector = Collector::open(storage::open(&args.storage, &args.data_dir)?, args.open_registration)?;
+    let server = Server::http(&args.listen).map_err(|e| format!("Unable to listen on {}: {}", args.listen, e))?;
+    if global.verbose {
+        println!(
+            "Collecting uploads on http://{}{} into {} storage, {} stations registered",
+            args.listen,
+            UPLOAD_PATH,
+            args.storage,
+            collector.registry.len()
+        );
+    }
+
+    for request in server.incoming_requests() {
+        if let Err(err) = handle_request(request, &mut collector, global.verbose) {
+            eprintln!("Unable to answer a request: {}", err);
+        }
+    }
+    Ok(())
+}
+
+fn handle_request(mut request: Request, collector: &mut Collector, verbose: bool) -> io::Result<()> {
+    let received_ms = unix_ms();
+    match (request.method(), request.url()) {
+        (Method::Post, UPLOAD_PATH) => {}
+        (Method::Get, LEADERBOARD_PATH) => {
+            let summary = serde_json::to_string(&collector.leaderboard.summary())?;
+            return request.respond(Response::from_string(summary).with_header(json_header()));
+        }
+        (Method::Get, COMMUNITY_PATH) => {
+            let rates = json!({ "generated_ms": received_ms, "rates": collector.leaderboard.community_rates() });
+            return request.respond(Response::from_string(rates.to_string()).with_header(json_header()));
+        }
+        _ => return request.respond(Response::from_string("Not found").with_status_code(404)),
+    }
+
+    let header = |name: &'static str| {
+        request
+            .headers()
+            .iter()
+            .find(|header| header.field.equiv(name))
+            .map(|header| header.value.as_str().to_string())
+            .unwrap_or_default()
+    };
+    let public_key = header("X-Station-Key");
+    let signature = header("X-Signature");
+    let mut body = Vec::new();
+    request.as_reader().read_to_end(&mut body)?;
+
+    match collector.receive(&body, &public_key, &signature, received_ms) {
+        Ok(acked) => {
+            // The times let the station estimate how far its clock is off from ours.
+            let response = json!({ "acked": acked, "received_ms": received_ms, "sent_ms": unix_ms() }).to_string();
+            request.respond(Response::from_string(response).with_header(json_header()))
+        }
+        Err(Rejection(status, reason)) => {
+            if verbose {
+                println!("Rejected an upload from {}: {}", request.remote_addr().map_or("unknown".into(), |a| a.to_string()), reason);
+            }
+            request.respond(Response::from_string(reason).with_status_code(status))
+        }
+    }
+}
+
+fn json_header() -> Header {
+    Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..]).expect("Static header is valid")
+}
+
+fn unix_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Time went backwards")
+        .as_millis() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use serde_json::Value;
+
+    use super::*;
+    use crate::signing::StationKey;
+
+    fn flip(sequence: u64) -> Value {
+        json!({
+            "id": format!("session-1:{}", sequence),
+            "sequence": sequence,
+            "record": {
+                "type": "flip",
+                "started_ms": 1_700_000_000_000u64,
+                "delay_ms": 30_000,
+                "checks": 3,
+                "vanished": false,
+                "detected_ms": 1_700_000_000_100u64,
+                "location": null,
+                "sequence": sequence,
+            },
+        })
+    }
+
+    fn batch(records: Vec<Value>) -> Vec<u8> {
+        let session = json!({ "session_id": "session-1", "version": "1.2.0", "schema": 6 });
+        serde_json::to_vec(&json!({ "station_id": "alpha", "session": session, "records": records })).unwrap()
+    }
+
+    #[test]
+    fn resent_records_are_stored_once_and_distinct_ones_with_the_same_time_are_not_merged() {
+        let dir = std::env::temp_dir().join(format!("cosmic_ray_detector-collector-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let key = StationKey::load_or_create(&dir.join("station.key")).unwrap();
+        let send = |collector: &mut Collector, body: Vec<u8>, now: u64| {
+            let signature = key.sign(&body);
+            collector.receive(&body, &key.public_key(), &signature, now).map_err(|Rejection(_, e)| e).unwrap()
+        };
+
+        let mut collector = Collector::open(storage::open("files", &dir).unwrap(), true).unwrap();
+        assert_eq!(send(&mut collector, batch(vec![flip(1)]), 1), vec!["session-1:1"]);
+        // The first record again, its acknowledgement got lost, together with a new one detected at the same time.
+        let acked = send(&mut collector, batch(vec![flip(1), flip(2)]), 2);
+        assert_eq!(acked, vec!["session-1:1", "session-1:2"]);
+        assert_eq!(collector.storage.records().unwrap().len(), 2);
+
+        // The records already stored are still recognized after a restart of the collector.
+        let mut collector = Collector::open(storage::open("files", &dir).unwrap(), true).unwrap();
+        send(&mut collector, batch(vec![flip(2), flip(2)]), 3);
+        let ids: Vec<String> = collector.storage.records().unwrap().into_iter().map(|stored| stored.id).collect();
+        assert_eq!(ids, vec!["session-1:1", "session-1:2"]);
+    }
+}
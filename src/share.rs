@@ -0,0 +1,200 @@
+use std::error::Error;
+use std::fs::{self, File, OpenOptions};
+use std::io::{stdin, stdout, ErrorKind, Write};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use crate::{
+    config::{GlobalArgs, ShareArgs},
+    record::{Hardware, Record, SessionInfo, Tags},
+    signing::{to_hex, StationKey},
+    upload::Uploader,
+};
+
+/// The version of what is shared, consent given to an older one has to be given again.
+pub const SHARE_TERMS_VERSION: u32 = 1;
+/// The size of the grid shared locations are snapped to, roughly 110 km.
+const SHARE_GRID_DEGREES: f64 = 1.0;
+/// Shared altitudes are rounded to this many meters.
+const SHARE_ALTITUDE_STEP_M: f64 = 100.0;
+/// What is shared, shown before asking for consent. Keep it in step with `anonymized`.
+const SHARE_TERMS: &str = "\
+Sharing sends the following to the community collector, signed with the station key:
+- every bitflip, with the bytes that changed but not their physical addresses
+- every heartbeat and the start of every session, for working out the exposure
+- the software version, the detector size and the processor and memory speeds
+- the location snapped to a 1 degree grid (roughly 110 km) and the altitude rounded to 100 m
+It does not send the station ID, which is replaced by one made from the station key, the process ID, the random
+seed, the memory part numbers, the tags or anything else in the log. Everything sent is also appended to
+<log>.shared, so that what left the station can be checked at any time.";
+
+/// The consent to sharing, kept in a file so that `run --share` can't share anything without it.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ShareConsent {
+    /// The community collector consented to.
+    pub url: String,
+    /// When consent was given, in milliseconds since the Unix epoch.
+    pub consented_ms: u64,
+    /// The version of the terms consented to.
+    pub terms: u32,
+}
+
+impl ShareConsent {
+    /// Reads the consent, none if it was never given or was withdrawn.
+    pub fn load(path: &Path) -> Result<Option<Self>, String> {
+        match fs::read_to_string(path) {
+            Ok(contents) => serde_json::from_str(&contents)
+                .map(Some)
+                .map_err(|e| format!("Unable to parse the consent to sharing in {}: {}", path.display(), e)),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(format!("Unable to read the consent to sharing in {}: {}", path.display(), err)),
+        }
+    }
+
+    /// Reads the consent for `run --share`, which refuses to start without consent to the current terms.
+    pub fn require(path: &Path) -> Result<Self, String> {
+        match ShareConsent::load(path)? {
+            Some(consent) if consent.terms >= SHARE_TERMS_VERSION => Ok(consent),
+            Some(_) => Err("What is shared has changed since consent was given, run the share subcommand again".into()),
+            None => Err(format!(
+                "Sharing needs consent first, see what is shared with the share subcommand and consent with share --url <collector> --consent {}",
+                path.display()
+            )),
+        }
+    }
+
+    fn save(&self, path: &Path) -> Result<(), String> {
+        let contents = serde_json::to_string_pretty(self).map_err(|e| e.to_string())?;
+        fs::write(path, contents + "\n")
+            .map_err(|e| format!("Unable to save the consent to sharing in {}: {}", path.display(), e))
+    }
+}
+
+/// Shares records with a community collector, anonymized and signed. Everything shared is appended to a local record
+/// before it is sent, so nothing leaves the station without being in it.
+pub struct Sharer {
+    uploader: Uploader,
+    record: File,
+}
+
+impl Sharer {
+    pub fn new(consent: &ShareConsent, session: &SessionInfo, key: StationKey, record_path: &Path) -> Result<Self, String> {
+        let record = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(record_path)
+            .map_err(|e| format!("Unable to open the record of what is shared {}: {}", record_path.display(), e))?;
+        let station_id = anonymous_station_id(&key);
+        let mut sharer = Sharer {
+            uploader: Uploader::new(consent.url.clone(), station_id, anonymized_session(session), key),
+            record,
+        };
+        sharer.write_record(&Record::Session(anonymized_session(session)))?;
+        Ok(sharer)
+    }
+
+    /// Queues the record for sharing if it is one that is shared, after writing it to the local record.
+    pub fn push(&mut self, record: &Record) -> Result<(), String> {
+        if !matches!(record, Record::Start { .. } | Record::Flip { .. } | Record::Heartbeat { .. }) {
+            return Ok(());
+        }
+        let record = anonymized(record);
+        self.write_record(&record)?;
+        self.uploader.push(&record);
+        Ok(())
+    }
+
+    fn write_record(&mut self, record: &Record) -> Result<(), String> {
+        let line = serde_json::to_string(record).map_err(|e| e.to_string())?;
+        writeln!(self.record, "{}", line).map_err(|e| format!("Unable to write the record of what is shared: {}", e))
+    }
+
+    /// Sends the queued records unless the last attempt failed too recently.
+    pub fn flush(&mut self) -> Result<(), String> {
+        self.uploader.flush().map_err(|e| format!("Sharing: {}", e))
+    }
+
+    /// The number of records the community collector has not acknowledged yet.
+    pub fn pending(&self) -> usize {
+        self.uploader.pending()
+    }
+}
+
+/// The station as the community knows it, the same for as long as it keeps its key but telling nothing about it.
+fn anonymous_station_id(key: &StationKey) -> String {
+    let digest = Sha256::digest(key.public_key().as_bytes());
+    format!("anonymous-{}", to_hex(&digest[..8]))
+}
+
+fn anonymized_session(session: &SessionInfo) -> SessionInfo {
+    SessionInfo {
+        // A session ID of its own keeps the shared records from being matched with ones sent elsewhere.
+        session_id: Some(Uuid::new_v4().to_string()),
+        seed: None,
+        altitude_m: session
+            .altitude_m
+            .map(|altitude_m| (altitude_m / SHARE_ALTITUDE_STEP_M).round() * SHARE_ALTITUDE_STEP_M),
+        hardware: Hardware { memory_parts: None, ..session.hardware.clone() },
+        ..session.clone()
+    }
+}
+
+/// The record as it is shared: anonymized, without tags, sequence numbers or physical addresses.
+fn anonymized(record: &Record) -> Record {
+    let mut record = record.anonymized(SHARE_GRID_DEGREES);
+    record.set_tags(Tags::new());
+    record.set_sequence(None);
+    if let Record::Flip { diffs, .. } = &mut record {
+        for diff in diffs {
+            diff.physical_address = None;
+        }
+    }
+    record
+}
+
+/// Shows what sharing involves and the consent given, or gives or withdraws consent.
+pub fn share(_global: &GlobalArgs, args: ShareArgs) -> Result<(), Box<dyn Error>> {
+    if args.revoke {
+        match fs::remove_file(&args.consent) {
+            Ok(()) => println!("Consent withdrawn, stations stop sharing when they next start"),
+            Err(err) if err.kind() == ErrorKind::NotFound => println!("No consent to sharing was given"),
+            Err(err) => return Err(format!("Unable to remove {}: {}", args.consent.display(), err).into()),
+        }
+        return Ok(());
+    }
+
+    println!("{}\n", SHARE_TERMS);
+    let Some(url) = args.url else {
+        match ShareConsent::load(&args.consent)? {
+            Some(consent) if consent.terms >= SHARE_TERMS_VERSION => {
+                println!("Consent was given to share with {}, run with --share to share", consent.url)
+            }
+            Some(consent) => println!("Consent to share with {} was given to older terms, give it again", consent.url),
+            None => println!("No consent to sharing was given, give it with --url <collector>"),
+        }
+        return Ok(());
+    };
+
+    if !args.yes {
+        print!("Type yes to share with {}: ", url);
+        stdout().flush()?;
+        let mut answer = String::new();
+        stdin().read_line(&mut answer)?;
+        if answer.trim() != "yes" {
+            println!("Nothing will be shared");
+            return Ok(());
+        }
+    }
+    let consent = ShareConsent {
+        url,
+        consented_ms: SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64,
+        terms: SHARE_TERMS_VERSION,
+    };
+    consent.save(&args.consent)?;
+    println!("Consent saved in {}, run with --share to share", args.consent.display());
+    Ok(())
+}
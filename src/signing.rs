@@ -0,0 +1,113 @@
+use std::fs::{self, OpenOptions};
+use std::io::{ErrorKind, Write};
+#[cfg(unix)]
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::Path;
+
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey, SECRET_KEY_LENGTH};
+
+/// The Ed25519 key pair of a station, used to sign everything it uploads so that the collector can tell
+/// which station sent it and that nobody changed it on the way.
+#[derive(Clone)]
+pub struct StationKey {
+    signing_key: SigningKey,
+}
+
+impl StationKey {
+    /// Reads the secret key from the given file, or generates a new key pair and stores it there if the file doesn't exist.
+    /// The file holds the secret key as hex and must be kept private, the collector only ever sees the public key.
+    pub fn load_or_create(path: &Path) -> Result<Self, String> {
+        match fs::read_to_string(path) {
+            Ok(contents) => {
+                let secret = from_hex(contents.trim())
+                    .and_then(|bytes| <[u8; SECRET_KEY_LENGTH]>::try_from(bytes).ok())
+                    .ok_or_else(|| format!("{} does not contain a valid station key", path.display()))?;
+                Ok(StationKey { signing_key: SigningKey::from_bytes(&secret) })
+            }
+            Err(err) if err.kind() == ErrorKind::NotFound => {
+                let mut secret = [0; SECRET_KEY_LENGTH];
+                getrandom::getrandom(&mut secret).map_err(|e| format!("Unable to generate a station key: {}", e))?;
+                let key = StationKey { signing_key: SigningKey::from_bytes(&secret) };
+
+                let mut options = OpenOptions::new();
+                options.write(true).create_new(true);
+                #[cfg(unix)]
+                options.mode(0o600);
+                options
+                    .open(path)
+                    .and_then(|mut file| writeln!(file, "{}", to_hex(&secret)))
+                    .map_err(|e| format!("Unable to write the station key to {}: {}", path.display(), e))?;
+                Ok(key)
+            }
+            Err(err) => Err(format!("Unable to read the station key {}: {}", path.display(), err)),
+        }
+    }
+
+    /// The public key as hex, which is what the collector identifies the station by.
+    pub fn public_key(&self) -> String {
+        to_hex(self.signing_key.verifying_key().as_bytes())
+    }
+
+    /// Signs the given bytes and returns the signature as hex.
+    pub fn sign(&self, message: &[u8]) -> String {
+        to_hex(&self.signing_key.sign(message).to_bytes())
+    }
+}
+
+/// Checks a signature made by `StationKey::sign`, with the public key and signature as hex.
+pub fn verify(public_key: &str, message: &[u8], signature: &str) -> Result<(), String> {
+    let public_key = from_hex(public_key)
+        .and_then(|bytes| <[u8; 32]>::try_from(bytes).ok())
+        .ok_or("Malformed public key")?;
+    let public_key = VerifyingKey::from_bytes(&public_key).map_err(|e| format!("Invalid public key: {}", e))?;
+    let signature = from_hex(signature)
+        .and_then(|bytes| <[u8; 64]>::try_from(bytes).ok())
+        .ok_or("Malformed signature")?;
+    public_key
+        .verify(message, &Signature::from_bytes(&signature))
+        .map_err(|_| "The signature does not match".to_string())
+}
+
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+pub fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if hex.len() % 2 != 0 {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signatures_verify_only_for_the_signed_payload() {
+        let path = std::env::temp_dir().join(format!("cosmic_ray_detector-station-key-{}", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let key = StationKey::load_or_create(&path).unwrap();
+        let payload = br#"{"type":"flip","checks":3}"#;
+        let signature = key.sign(payload);
+        assert_eq!(verify(&key.public_key(), payload, &signature), Ok(()));
+
+        // The stored key signs the same way after a restart.
+        let reloaded = StationKey::load_or_create(&path).unwrap();
+        assert_eq!(reloaded.public_key(), key.public_key());
+        assert_eq!(verify(&reloaded.public_key(), payload, &signature), Ok(()));
+
+        assert!(verify(&key.public_key(), br#"{"type":"flip","checks":4}"#, &signature).is_err());
+        let other = StationKey::load_or_create(&path.with_extension("other")).unwrap();
+        assert!(verify(&other.public_key(), payload, &signature).is_err());
+        let mut tampered = from_hex(&signature).unwrap();
+        tampered[0] ^= 1;
+        assert!(verify(&key.public_key(), payload, &to_hex(&tampered)).is_err());
+        assert!(verify(&key.public_key(), payload, "not hex").is_err());
+        let _ = fs::remove_file(path.with_extension("other"));
+        let _ = fs::remove_file(&path);
+    }
+}
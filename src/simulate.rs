@@ -0,0 +1,176 @@
+use std::error::Error;
+use std::time::Instant;
+
+use crate::{
+    config::{GlobalArgs, SimulateArgs},
+    detector::Detector,
+    mem_size,
+    rng::{random_seed, Rng},
+};
+
+/// The size of the detector the scan and write rates are measured on when they aren't given.
+const MEASURE_SIZE: usize = 64 * 1024 * 1024;
+
+/// How the detector cycles, all times in seconds.
+struct Schedule {
+    delay: f64,
+    jitter: f64,
+    /// How long a full check takes.
+    scan: f64,
+    /// How long resetting the detector takes, events during it are overwritten.
+    reset: f64,
+    /// The chance that an event flips a bit in the direction the pattern can show.
+    coverage: f64,
+}
+
+/// What became of an injected event.
+#[derive(Default)]
+struct Tally {
+    /// The time from each detected event to its detection.
+    delays: Vec<f64>,
+    invisible: u64,
+    during_reset: u64,
+    piled_up: u64,
+}
+
+/// An event in the detector memory, at a position from 0 to 1 through the memory.
+struct Event {
+    time: f64,
+    position: f64,
+}
+
+/// Injects events at random times and positions into a model of the detection loop and reports how many of them
+/// would have been logged and how late, for the configured detector. The model follows `run`: a reset, then checks
+/// separated by the delay and jitter, each reading the memory from start to end and stopping at the first change,
+/// which is logged with the time of the check, after which the memory is reset again. Events during a reset are
+/// overwritten, events that flip a bit in the direction the pattern can't show are invisible, and all events that
+/// are in the memory when a change is found are logged as one.
+pub fn simulate(_global: &GlobalArgs, args: SimulateArgs) -> Result<(), Box<dyn Error>> {
+    if args.memory_to_occupy == 0 || args.events == 0 || args.rate <= 0.0 {
+        return Err("The detector size, the number of events and the rate have to be above zero".into());
+    }
+    let mut detector = Detector::new(0, MEASURE_SIZE);
+    let scan_rate = match args.scan_rate {
+        Some(scan_rate) => scan_rate as f64,
+        None => detector.measure_check_bandwidth(),
+    };
+    let write_rate = match args.write_rate {
+        Some(write_rate) => write_rate as f64,
+        None => {
+            let start = Instant::now();
+            detector.reset();
+            MEASURE_SIZE as f64 / start.elapsed().as_secs_f64().max(f64::EPSILON)
+        }
+    };
+    let schedule = Schedule {
+        delay: args.delay_between_checks as f64 / 1000.0,
+        jitter: args.jitter as f64 / 1000.0,
+        scan: args.memory_to_occupy as f64 / scan_rate,
+        reset: args.memory_to_occupy as f64 / write_rate,
+        coverage: detector.pattern_coverage(),
+    };
+    let seed = args.seed.unwrap_or_else(random_seed);
+    let rate_per_second = args.rate / 3600.0;
+    let mut tally = run_schedule(&schedule, args.events, rate_per_second, &mut Rng::new(seed));
+
+    println!(
+        "Simulated {} events in {:.1} days of a {} detector, checked every {} ms with checks reading {}/s, with seed {}",
+        args.events,
+        args.events as f64 / rate_per_second / 86400.0,
+        mem_size(args.memory_to_occupy as u64),
+        args.delay_between_checks,
+        mem_size(scan_rate as u64),
+        seed
+    );
+    let percent = |count: u64| 100.0 * count as f64 / args.events as f64;
+    println!("Detection efficiency: {:.2}%", percent(tally.delays.len() as u64));
+    println!("  Invisible with the bit pattern: {:.2}%", percent(tally.invisible));
+    println!("  Overwritten by a reset: {:.2}%", percent(tally.during_reset));
+    println!("  Logged together with an earlier event: {:.2}%", percent(tally.piled_up));
+    if !tally.delays.is_empty() {
+        tally.delays.sort_by(f64::total_cmp);
+        let quantile = |q: f64| tally.delays[((tally.delays.len() - 1) as f64 * q).round() as usize];
+        println!(
+            "Time from event to detection: mean {:.3} s, median {:.3} s, 90% {:.3} s, 99% {:.3} s, max {:.3} s",
+            tally.delays.iter().sum::<f64>() / tally.delays.len() as f64,
+            quantile(0.5),
+            quantile(0.9),
+            quantile(0.99),
+            quantile(1.0)
+        );
+    }
+    Ok(())
+}
+
+fn run_schedule(schedule: &Schedule, events: u64, rate_per_second: f64, rng: &mut Rng) -> Tally {
+    let mut tally = Tally::default();
+    let mut injected = 0;
+    let mut next_time = exponential(rng, rate_per_second);
+    // Visible events in the memory that no check has seen yet.
+    let mut pending: Vec<Event> = Vec::new();
+    let mut clean_from = schedule.reset;
+    let mut previous_end = clean_from;
+
+    // The average time from the end of one check to the end of the next.
+    let cycle = schedule.delay + schedule.jitter / 2.0 + schedule.scan;
+
+    while injected < events || !pending.is_empty() {
+        // Checks of an empty memory change nothing, so skip all but the last few before the next event.
+        if pending.is_empty() && cycle > 0.0 {
+            let idle_cycles = ((next_time - previous_end) / cycle).floor() - 2.0;
+            if idle_cycles > 0.0 {
+                previous_end += idle_cycles * cycle;
+            }
+        }
+        let check_start = previous_end + schedule.delay + schedule.jitter * uniform(rng);
+        let check_end = check_start + schedule.scan;
+        while injected < events && next_time < check_end {
+            let event = Event { time: next_time, position: uniform(rng) };
+            injected += 1;
+            next_time += exponential(rng, rate_per_second);
+            if event.time < clean_from {
+                tally.during_reset += 1;
+            } else if uniform(rng) >= schedule.coverage {
+                tally.invisible += 1;
+            } else {
+                pending.push(event);
+            }
+        }
+
+        // An event is seen if it happened before the check read its position.
+        let read_time = |event: &Event| check_start + schedule.scan * event.position;
+        let found = pending
+            .iter()
+            .filter(|event| event.time < read_time(event))
+            .min_by(|a, b| read_time(a).total_cmp(&read_time(b)));
+        let Some(found) = found else {
+            previous_end = check_end;
+            continue;
+        };
+        let detected = read_time(found);
+        tally.delays.push(detected - found.time);
+        // Finding the changed position takes another scan up to it, then the reset wipes everything in the memory.
+        clean_from = detected + schedule.scan * found.position + schedule.reset;
+        previous_end = clean_from;
+        let found_time = found.time;
+        let (wiped, later): (Vec<Event>, Vec<Event>) = pending.drain(..).partition(|event| event.time < clean_from);
+        for event in wiped.iter().filter(|event| event.time != found_time) {
+            match event.time < detected {
+                true => tally.piled_up += 1,
+                false => tally.during_reset += 1,
+            }
+        }
+        pending = later;
+    }
+    tally
+}
+
+/// A number from 0 up to but not including 1.
+fn uniform(rng: &mut Rng) -> f64 {
+    (rng.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// The time until the next event of a Poisson process.
+fn exponential(rng: &mut Rng, rate: f64) -> f64 {
+    -(1.0 - uniform(rng)).ln() / rate
+}
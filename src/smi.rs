@@ -0,0 +1,120 @@
+use std::fs::File;
+use std::time::Duration;
+
+use crate::record::Smi;
+
+/// The first processor's MSRs, the msr kernel module has to be loaded and reading them takes root.
+const MSR_PATH: &str = "/dev/cpu/0/msr";
+/// MSR_SMI_COUNT of Intel processors, counting the system management interrupts since the last reset in its lower
+/// 32 bits.
+const MSR_SMI_COUNT: u64 = 0x34;
+const SMI_COUNT_MASK: u64 = 0xFFFF_FFFF;
+/// A scan that takes this many times as long as the fastest one was held up, where the counter can't be read that is
+/// how SMIs show.
+const LATENCY_SPIKE_FACTOR: u32 = 3;
+/// Scans of small detectors vary by more than the factor for all kinds of reasons, only spikes longer than this count.
+const MIN_LATENCY_SPIKE: Duration = Duration::from_millis(10);
+
+/// Reads the SMI counter of the first processor, SMIs stop all of them at once.
+pub struct SmiCounter {
+    msr: File,
+}
+
+impl SmiCounter {
+    /// Fails where the MSR can't be read, on processors other than Intel's, without the msr module or without root,
+    /// and on systems other than Linux.
+    pub fn open() -> Result<Self, String> {
+        let msr = File::open(MSR_PATH).map_err(|e| format!("Unable to open {}: {}", MSR_PATH, e))?;
+        let counter = SmiCounter { msr };
+        counter.read()?;
+        Ok(counter)
+    }
+
+    #[cfg(target_os = "linux")]
+    pub fn read(&self) -> Result<u64, String> {
+        use std::os::unix::fs::FileExt;
+        let mut value = [0; 8];
+        self.msr
+            .read_exact_at(&mut value, MSR_SMI_COUNT)
+            .map_err(|e| format!("Unable to read the SMI count from {}: {}", MSR_PATH, e))?;
+        Ok(u64::from_le_bytes(value) & SMI_COUNT_MASK)
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn read(&self) -> Result<u64, String> {
+        Err("Reading the SMI count is only supported on Linux".into())
+    }
+}
+
+/// How much firmware got in the way of a check window.
+pub struct WindowSmi {
+    /// The SMIs in the window, where the counter can be read.
+    pub count: Option<u64>,
+    /// Whether they came at more than the heavy rate, or the scan took far longer than usual.
+    pub heavy: bool,
+}
+
+/// Watches the system management interrupts around the checks. SMIs take the processors away from the operating
+/// system and run firmware that can touch memory, so flips found in windows with a lot of them are suspect on some
+/// platforms. Where the counter can't be read, scans that take far longer than the fastest one stand in for them.
+pub struct SmiMonitor {
+    counter: Option<SmiCounter>,
+    heavy_rate: f64,
+    fastest_scan: Option<Duration>,
+    /// The counter at the start of the previous and of the latest check.
+    previous: Option<u64>,
+    latest: Option<u64>,
+    tally: Smi,
+}
+
+impl SmiMonitor {
+    /// Windows with more than `heavy_rate` SMIs per second count as heavy activity.
+    pub fn new(counter: Option<SmiCounter>, heavy_rate: f64) -> Self {
+        let tally = Smi { smi_count: counter.as_ref().map(|_| 0), slow_checks: 0, heavy_checks: 0 };
+        SmiMonitor { counter, heavy_rate, fastest_scan: None, previous: None, latest: None, tally }
+    }
+
+    fn read(&self) -> Option<u64> {
+        self.counter.as_ref().and_then(|counter| counter.read().ok())
+    }
+
+    /// Starts the windows over, after time the detector wasn't being checked, such as a reset.
+    pub fn restart(&mut self) {
+        self.latest = self.read();
+        self.previous = self.latest;
+    }
+
+    /// Reads the counter as a check starts, the window of a flip reaches back to the start of the check before.
+    pub fn check_started(&mut self) {
+        let reading = self.read();
+        if let (Some(reading), Some(latest), Some(count)) = (reading, self.latest, self.tally.smi_count.as_mut()) {
+            *count += reading.wrapping_sub(latest) & SMI_COUNT_MASK;
+        }
+        self.previous = self.latest.or(reading);
+        self.latest = reading;
+    }
+
+    /// Tells how much firmware got in the way of the window ending with a check that took `scan`.
+    pub fn check_finished(&mut self, scan: Duration, window: Duration) -> WindowSmi {
+        let fastest = *self.fastest_scan.get_or_insert(scan);
+        self.fastest_scan = Some(fastest.min(scan));
+        let slow = scan > fastest * LATENCY_SPIKE_FACTOR && scan - fastest > MIN_LATENCY_SPIKE;
+        let count = match (self.read(), self.previous) {
+            (Some(reading), Some(start)) => Some(reading.wrapping_sub(start) & SMI_COUNT_MASK),
+            _ => None,
+        };
+        let heavy =
+            slow || count.is_some_and(|count| count as f64 > self.heavy_rate * window.as_secs_f64().max(f64::EPSILON));
+        self.tally.slow_checks += slow as u64;
+        self.tally.heavy_checks += heavy as u64;
+        WindowSmi { count, heavy }
+    }
+
+    /// The activity since the last time, starting over afterwards. None when the counter can't be read and no check
+    /// was held up.
+    pub fn take(&mut self) -> Option<Smi> {
+        let empty = Smi { smi_count: self.tally.smi_count.map(|_| 0), slow_checks: 0, heavy_checks: 0 };
+        let tally = std::mem::replace(&mut self.tally, empty);
+        (tally.smi_count.is_some() || tally.heavy_checks > 0).then_some(tally)
+    }
+}
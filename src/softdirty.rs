@@ -0,0 +1,67 @@
+use std::fs::{self, File};
+use std::io::{Read, Seek, SeekFrom};
+use std::ptr::write_volatile;
+
+const PAGEMAP_PATH: &str = "/proc/self/pagemap";
+const CLEAR_REFS_PATH: &str = "/proc/self/clear_refs";
+const SMAPS_PATH: &str = "/proc/self/smaps";
+/// Writing this to clear_refs clears the soft-dirty bits of every page of the process.
+const CLEAR_SOFT_DIRTY: &str = "4";
+const SOFT_DIRTY_BIT: u64 = 1 << 55;
+
+/// Linux soft-dirty page tracking, see https://docs.kernel.org/admin-guide/mm/soft-dirty.html.
+/// The kernel marks a page soft-dirty when the process writes to it. A bitflip in the memory chips doesn't go
+/// through the CPU at all, so soft-dirty bits can't narrow down where to look for flips, every page still has to be checked.
+/// What they can tell is whether a changed byte was written by software, such as a stray pointer, instead of by radiation.
+pub struct SoftDirty {
+    pagemap: File,
+    page_size: usize,
+}
+
+impl SoftDirty {
+    /// Fails on systems other than Linux and on kernels built without soft-dirty support.
+    pub fn open() -> Result<Self, String> {
+        let pagemap = File::open(PAGEMAP_PATH).map_err(|e| format!("Unable to open {}: {}", PAGEMAP_PATH, e))?;
+        let soft_dirty = SoftDirty { pagemap, page_size: page_size()? };
+
+        // Kernels without soft-dirty support happily accept the clear request, they just never mark anything.
+        // Writing to a page of our own shows whether it works.
+        let mut probe = vec![0u8; soft_dirty.page_size];
+        soft_dirty.clear()?;
+        unsafe { write_volatile(probe.as_mut_ptr(), 1) };
+        if !soft_dirty.is_dirty(probe.as_ptr() as usize)? {
+            return Err("This kernel doesn't track soft-dirty pages".into());
+        }
+        soft_dirty.clear()?;
+        Ok(soft_dirty)
+    }
+
+    /// Forgets about all earlier writes, should be called right after the detector has been reset.
+    pub fn clear(&self) -> Result<(), String> {
+        fs::write(CLEAR_REFS_PATH, CLEAR_SOFT_DIRTY)
+            .map_err(|e| format!("This kernel doesn't support soft-dirty tracking: {}", e))
+    }
+
+    /// Whether the page holding the given address has been written to since the last `clear`.
+    pub fn is_dirty(&self, address: usize) -> Result<bool, String> {
+        let offset = (address / self.page_size * 8) as u64;
+        let mut entry = [0; 8];
+        (&self.pagemap)
+            .seek(SeekFrom::Start(offset))
+            .and_then(|_| (&self.pagemap).read_exact(&mut entry))
+            .map_err(|e| format!("Unable to read {}: {}", PAGEMAP_PATH, e))?;
+        Ok(u64::from_le_bytes(entry) & SOFT_DIRTY_BIT != 0)
+    }
+}
+
+/// The size of the pages the kernel maps normal memory with, as reported in smaps.
+pub fn page_size() -> Result<usize, String> {
+    let smaps = fs::read_to_string(SMAPS_PATH).map_err(|e| format!("Unable to read {}: {}", SMAPS_PATH, e))?;
+    smaps
+        .lines()
+        .find_map(|line| line.strip_prefix("KernelPageSize:"))
+        .and_then(|size| size.trim().strip_suffix("kB"))
+        .and_then(|size| size.trim().parse::<usize>().ok())
+        .map(|size| size * 1024)
+        .ok_or_else(|| format!("Unable to find the page size in {}", SMAPS_PATH))
+}
@@ -0,0 +1,131 @@
+use std::path::Path;
+
+use rusqlite::{params, Connection, OpenFlags};
+
+use crate::record::Record;
+
+/// The version of the schema, kept in the database's user_version.
+const SCHEMA_VERSION: i64 = 1;
+const SCHEMA: &str = "CREATE TABLE IF NOT EXISTS runs (
+                          run_id INTEGER PRIMARY KEY,
+                          session_id TEXT,
+                          version TEXT,
+                          started_ms INTEGER,
+                          session TEXT,
+                          start TEXT
+                      );
+                      CREATE TABLE IF NOT EXISTS events (
+                          event_id INTEGER PRIMARY KEY,
+                          run_id INTEGER NOT NULL REFERENCES runs (run_id),
+                          type TEXT NOT NULL,
+                          timestamp_ms INTEGER,
+                          sequence INTEGER,
+                          record TEXT NOT NULL
+                      );
+                      CREATE INDEX IF NOT EXISTS events_by_type ON events (type, timestamp_ms);";
+
+/// The log kept in a SQLite database rather than appended to a file: a row in `runs` for every time the program
+/// started, with its session and start records, and a row in `events` for every other record, with the run it belongs
+/// to. Every write is a transaction of its own, so a crash never leaves half a record behind. The records are kept
+/// whole as JSON next to the columns, so nothing is lost that the columns leave out.
+pub struct SqliteLog {
+    connection: Connection,
+    /// The run records are added to, none until the first session or start record.
+    run_id: Option<i64>,
+}
+
+impl SqliteLog {
+    /// Opens the database, creating its tables if it is new.
+    pub fn open(path: &Path) -> Result<Self, String> {
+        let connection = Connection::open(path).map_err(|e| format!("Unable to open {}: {}", path.display(), e))?;
+        connection
+            .execute_batch("PRAGMA journal_mode = WAL; PRAGMA synchronous = FULL;")
+            .and_then(|_| connection.query_row("PRAGMA user_version", [], |row| row.get::<_, i64>(0)))
+            .and_then(|version| match version {
+                0 => connection.execute_batch(&format!("{} PRAGMA user_version = {};", SCHEMA, SCHEMA_VERSION)),
+                _ => Ok(()),
+            })
+            .map_err(|e| format!("Unable to set up {}: {}", path.display(), e))?;
+        Ok(SqliteLog { connection, run_id: None })
+    }
+
+    /// Stores the records in a single transaction.
+    pub fn append(&mut self, records: &[&Record]) -> Result<(), String> {
+        let transaction = self.connection.transaction().map_err(|e| e.to_string())?;
+        let store = |e: rusqlite::Error| format!("Unable to store records: {}", e);
+        for record in records {
+            let json = serde_json::to_string(record).map_err(|e| e.to_string())?;
+            let timestamp_ms = record.timestamp_ms().map(|ms| ms as i64);
+            match record {
+                Record::Session(info) => {
+                    transaction
+                        .execute(
+                            "INSERT INTO runs (session_id, version, session) VALUES (?1, ?2, ?3)",
+                            params![info.session_id, info.version, json],
+                        )
+                        .map_err(store)?;
+                    self.run_id = Some(transaction.last_insert_rowid());
+                }
+                Record::Start { .. } => {
+                    // The start record completes the run its session began, or begins one of its own.
+                    let completed = match self.run_id {
+                        Some(run_id) => transaction
+                            .execute(
+                                "UPDATE runs SET started_ms = ?1, start = ?2 WHERE run_id = ?3 AND start IS NULL",
+                                params![timestamp_ms, json, run_id],
+                            )
+                            .map_err(store)?,
+                        None => 0,
+                    };
+                    if completed == 0 {
+                        transaction
+                            .execute("INSERT INTO runs (started_ms, start) VALUES (?1, ?2)", params![timestamp_ms, json])
+                            .map_err(store)?;
+                        self.run_id = Some(transaction.last_insert_rowid());
+                    }
+                }
+                _ => {
+                    let run_id = match self.run_id {
+                        Some(run_id) => run_id,
+                        // Events without a run before them still need one to belong to.
+                        None => {
+                            transaction.execute("INSERT INTO runs DEFAULT VALUES", []).map_err(store)?;
+                            *self.run_id.insert(transaction.last_insert_rowid())
+                        }
+                    };
+                    transaction
+                        .execute(
+                            "INSERT INTO events (run_id, type, timestamp_ms, sequence, record) VALUES (?1, ?2, ?3, ?4, ?5)",
+                            params![run_id, record.kind(), timestamp_ms, record.sequence().map(|n| n as i64), json],
+                        )
+                        .map_err(store)?;
+                }
+            }
+        }
+        transaction.commit().map_err(store)
+    }
+}
+
+/// Reads every record of a database log, each run's session and start record followed by its events.
+pub fn read(path: &Path) -> Result<Vec<Record>, String> {
+    let connection = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .map_err(|e| format!("Unable to open {}: {}", path.display(), e))?;
+    let read = || -> rusqlite::Result<Vec<String>> {
+        let mut runs = connection.prepare("SELECT run_id, session, start FROM runs ORDER BY run_id")?;
+        let mut events = connection.prepare("SELECT record FROM events WHERE run_id = ?1 ORDER BY event_id")?;
+        let mut records = Vec::new();
+        let runs = runs
+            .query_map([], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, Option<String>>(1)?, row.get::<_, Option<String>>(2)?)))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        for (run_id, session, start) in runs {
+            records.extend(session);
+            records.extend(start);
+            for event in events.query_map([run_id], |row| row.get::<_, String>(0))? {
+                records.push(event?);
+            }
+        }
+        Ok(records)
+    };
+    let records = read().map_err(|e| format!("Unable to read {}: {}", path.display(), e))?;
+    records.iter().map(|record| serde_json::from_str(record).map_err(|e| e.to_string())).collect()
+}
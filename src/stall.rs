@@ -0,0 +1,119 @@
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, PoisonError, Weak};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::i18n::tr;
+
+/// How often the watchdog looks at the running scan.
+const WATCH_INTERVAL: Duration = Duration::from_millis(100);
+/// Scans of detectors that fit in the caches take microseconds and vary wildly, they never count as stalled
+/// before this.
+const MIN_STALL: Duration = Duration::from_secs(1);
+/// How much a new scan moves the typical duration.
+const SMOOTHING: f64 = 0.1;
+/// How often in a row a stalled scan is cancelled before one is left to finish, so that a machine that got
+/// slower for good doesn't keep cancelling every scan.
+pub const MAX_RETRIES: u32 = 3;
+
+/// Notices scans that take far longer than usual, which points at a stuck network filesystem, a thrashing machine
+/// or a livelock. A watchdog thread warns about a stalled scan while it is still running, and cancels it if asked to.
+pub struct StallMonitor {
+    factor: f64,
+    /// The average duration of recent scans that didn't stall.
+    typical: Option<Duration>,
+    watch: Arc<Watch>,
+}
+
+/// What the watchdog thread needs to know about the running scan.
+struct Watch {
+    /// When the running scan started and how long it may take, none between scans.
+    scan: Mutex<Option<(Instant, Duration)>>,
+    cancel_stalled: bool,
+    /// Set by the watchdog to make a stalled scan give up.
+    cancel: AtomicBool,
+    /// Whether the running scan was found to be stalled.
+    stalled: AtomicBool,
+}
+
+impl StallMonitor {
+    /// Scans taking more than `factor` times the typical duration count as stalled. The watchdog warns on `out`.
+    pub fn start(factor: f64, cancel_stalled: bool, out: Box<dyn Write + Send>) -> Result<Self, String> {
+        let watch = Arc::new(Watch {
+            scan: Mutex::new(None),
+            cancel_stalled,
+            cancel: AtomicBool::new(false),
+            stalled: AtomicBool::new(false),
+        });
+        let weak = Arc::downgrade(&watch);
+        thread::Builder::new()
+            .name("stall watchdog".into())
+            .spawn(move || watch_scans(weak, out))
+            .map_err(|e| format!("Unable to start the stall watchdog: {}", e))?;
+        Ok(StallMonitor { factor, typical: None, watch })
+    }
+
+    pub fn cancels_stalled_scans(&self) -> bool {
+        self.watch.cancel_stalled
+    }
+
+    /// The flag that tells a cancellable scan to give up.
+    pub fn cancel_flag(&self) -> &AtomicBool {
+        &self.watch.cancel
+    }
+
+    pub fn typical(&self) -> Option<Duration> {
+        self.typical
+    }
+
+    /// Starts watching a scan. Until a few scans were timed nothing counts as stalled.
+    pub fn begin_scan(&self) {
+        self.watch.cancel.store(false, Ordering::Relaxed);
+        self.watch.stalled.store(false, Ordering::Relaxed);
+        let limit = self.typical.map(|typical| typical.mul_f64(self.factor).max(MIN_STALL));
+        *self.watch.scan.lock().unwrap_or_else(PoisonError::into_inner) =
+            limit.map(|limit| (Instant::now(), limit));
+    }
+
+    /// Stops watching the scan and returns whether it stalled. Stalled scans don't change the typical duration.
+    pub fn end_scan(&mut self, duration: Duration) -> bool {
+        let limit = self.watch.scan.lock().unwrap_or_else(PoisonError::into_inner).take();
+        let stalled = self.watch.stalled.load(Ordering::Relaxed) || limit.is_some_and(|(_, limit)| duration > limit);
+        if !stalled {
+            self.typical = Some(match self.typical {
+                Some(typical) => typical.mul_f64(1.0 - SMOOTHING) + duration.mul_f64(SMOOTHING),
+                None => duration,
+            });
+        }
+        stalled
+    }
+
+    /// Stops watching a scan that never finished, because it panicked, without timing it.
+    pub fn abandon_scan(&self) {
+        self.watch.scan.lock().unwrap_or_else(PoisonError::into_inner).take();
+    }
+}
+
+/// Runs until the monitor is dropped. Only the watchdog can speak up while the detection loop is stuck in a scan,
+/// so it warns itself.
+fn watch_scans(watch: Weak<Watch>, mut out: Box<dyn Write + Send>) {
+    while let Some(watch) = watch.upgrade() {
+        let scan = *watch.scan.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some((start, limit)) = scan {
+            if start.elapsed() > limit && !watch.stalled.swap(true, Ordering::Relaxed) {
+                let limit = format!("{:?}", limit);
+                let message = match watch.cancel_stalled {
+                    true => tr!("The running scan has taken more than {}, it seems to be stalled, cancelling it", limit),
+                    false => tr!("The running scan has taken more than {}, it seems to be stalled", limit),
+                };
+                let _ = writeln!(out, "{}", message);
+                if watch.cancel_stalled {
+                    watch.cancel.store(true, Ordering::Relaxed);
+                }
+            }
+        }
+        drop(watch);
+        thread::sleep(WATCH_INTERVAL);
+    }
+}
@@ -0,0 +1,134 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+/// How many sequence numbers are reserved in the state file at once, so that it isn't written for every record.
+/// A crash loses what is left of the reservation, which shows up as a gap in the numbers but never as a duplicate.
+const SEQUENCE_RESERVATION: u64 = 1000;
+
+/// What a station remembers between runs, kept as JSON next to the log.
+pub struct StateFile {
+    path: PathBuf,
+    state: State,
+    /// The sequence number to hand out next.
+    next_sequence: u64,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct State {
+    /// The detector size filling all memory converged to, by the total memory of the machine in bytes.
+    /// A log that moves to another machine, or a machine that gets more memory, starts over.
+    #[serde(default)]
+    detector_sizes: BTreeMap<u64, usize>,
+    /// Every sequence number below this may have been handed out already.
+    #[serde(default)]
+    next_sequence: u64,
+}
+
+impl StateFile {
+    pub fn open(path: PathBuf) -> Result<Self, String> {
+        let state = match fs::read_to_string(&path) {
+            Ok(contents) => serde_json::from_str(&contents)
+                .map_err(|e| format!("Unable to parse the state file {}: {}", path.display(), e))?,
+            Err(err) if err.kind() == ErrorKind::NotFound => State::default(),
+            Err(err) => return Err(format!("Unable to read the state file {}: {}", path.display(), err)),
+        };
+        let next_sequence = state.next_sequence;
+        Ok(StateFile { path, state, next_sequence })
+    }
+
+    /// The detector size that filled the memory last time on a machine with this much memory.
+    pub fn detector_size(&self, total_memory: u64) -> Option<usize> {
+        self.state.detector_sizes.get(&total_memory).copied()
+    }
+
+    pub fn set_detector_size(&mut self, total_memory: u64, size: usize) -> Result<(), String> {
+        self.state.detector_sizes.insert(total_memory, size);
+        self.save()
+    }
+
+    /// The station's next sequence number, each is handed out only once even across restarts.
+    pub fn take_sequence(&mut self) -> Result<u64, String> {
+        if self.next_sequence >= self.state.next_sequence {
+            let reserved = self.state.next_sequence;
+            self.state.next_sequence = self.next_sequence + SEQUENCE_RESERVATION;
+            if let Err(err) = self.save() {
+                self.state.next_sequence = reserved;
+                return Err(err);
+            }
+        }
+        let sequence = self.next_sequence;
+        self.next_sequence += 1;
+        Ok(sequence)
+    }
+
+    /// Gives back the reserved sequence numbers that weren't handed out, so that a clean shutdown leaves no gap.
+    pub fn release_sequences(&mut self) -> Result<(), String> {
+        self.state.next_sequence = self.next_sequence;
+        self.save()
+    }
+
+    fn save(&self) -> Result<(), String> {
+        let temporary_path = PathBuf::from(format!("{}.tmp", self.path.display()));
+        serde_json::to_string(&self.state)
+            .map_err(|e| e.to_string())
+            .and_then(|contents| fs::write(&temporary_path, contents).map_err(|e| e.to_string()))
+            .and_then(|_| fs::rename(&temporary_path, &self.path).map_err(|e| e.to_string()))
+            .map_err(|e| format!("Unable to save the state file {}: {}", self.path.display(), e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state_path(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("cosmic_ray_detector-state-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir.join("results.state")
+    }
+
+    fn take(state: &mut StateFile, count: usize) -> Vec<u64> {
+        (0..count).map(|_| state.take_sequence().unwrap()).collect()
+    }
+
+    #[test]
+    fn a_crash_leaves_a_gap_but_no_duplicate() {
+        let path = state_path("crash");
+        let mut state = StateFile::open(path.clone()).unwrap();
+        assert_eq!(take(&mut state, 3), vec![0, 1, 2]);
+        // Dropped without releasing, like a process that crashed.
+        drop(state);
+        let mut state = StateFile::open(path).unwrap();
+        assert_eq!(take(&mut state, 2), vec![SEQUENCE_RESERVATION, SEQUENCE_RESERVATION + 1]);
+    }
+
+    #[test]
+    fn a_clean_shutdown_leaves_no_gap() {
+        let path = state_path("release");
+        let mut state = StateFile::open(path.clone()).unwrap();
+        assert_eq!(take(&mut state, 3), vec![0, 1, 2]);
+        state.release_sequences().unwrap();
+        let mut state = StateFile::open(path).unwrap();
+        assert_eq!(take(&mut state, 2), vec![3, 4]);
+    }
+
+    #[test]
+    fn a_reservation_that_cant_be_saved_is_rolled_back() {
+        let path = state_path("unsaved");
+        let mut state = StateFile::open(path.clone()).unwrap();
+        let dir = path.parent().unwrap();
+        fs::remove_dir_all(dir).unwrap();
+        assert!(state.take_sequence().is_err());
+
+        // Nothing was handed out, so once the state can be saved again the numbers start where they would have.
+        fs::create_dir_all(dir).unwrap();
+        assert_eq!(take(&mut state, 2), vec![0, 1]);
+        let saved: State = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(saved.next_sequence, SEQUENCE_RESERVATION);
+    }
+}
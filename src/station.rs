@@ -0,0 +1,119 @@
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
+
+use serde::{de, Deserialize, Deserializer, Serialize};
+
+use crate::config::{parse_size_string, validate_latitude, validate_longitude};
+
+const DEFAULT_STATION_ID: &str = "default";
+
+/// Settings describing the station, read from a TOML file so they don't have to be repeated on every start.
+/// Everything in it is optional and values given on the command line take precedence.
+#[derive(Deserialize, Debug, Default)]
+pub struct StationConfig {
+    pub station_id: Option<String>,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    /// Meters above sea level.
+    pub altitude: Option<f64>,
+    /// Defaults for `run --swap-tolerance` and `--free-headroom`, which depend on the machine. Given in bytes or
+    /// as a string such as "10MB".
+    #[serde(default, deserialize_with = "deserialize_size")]
+    pub swap_tolerance: Option<usize>,
+    #[serde(default, deserialize_with = "deserialize_size")]
+    pub free_headroom: Option<usize>,
+}
+
+impl StationConfig {
+    /// Reads the station file at the given path. A missing file just gives an empty config.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(StationConfig::default()),
+            Err(err) => return Err(format!("Unable to read station file {}: {}", path.display(), err)),
+        };
+
+        let station: StationConfig = toml::from_str(&contents)
+            .map_err(|e| format!("Unable to parse station file {}: {}", path.display(), e))?;
+
+        if let Some(latitude) = station.latitude {
+            validate_latitude(latitude)?;
+        }
+        if let Some(longitude) = station.longitude {
+            validate_longitude(longitude)?;
+        }
+
+        Ok(station)
+    }
+
+    /// The ID of the station, preferring the one given on the command line.
+    /// It ends up in file names so it is limited to letters, digits, dashes and underscores.
+    pub fn station_id(&self, station_id: Option<&str>) -> Result<String, String> {
+        let station_id = station_id
+            .or(self.station_id.as_deref())
+            .unwrap_or(DEFAULT_STATION_ID);
+        if station_id.is_empty()
+            || !station_id
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            return Err(format!(
+                "Station ID '{}' may only contain letters, digits, dashes and underscores",
+                station_id
+            ));
+        }
+        Ok(station_id.to_string())
+    }
+
+    /// Combines the coordinates given on the command line with the ones in the station file.
+    /// Latitude and longitude have to be known together or not at all.
+    pub fn location(&self, latitude: Option<f64>, longitude: Option<f64>) -> Result<Option<Location>, String> {
+        match (latitude.or(self.latitude), longitude.or(self.longitude)) {
+            (Some(latitude), Some(longitude)) => Ok(Some(Location { latitude, longitude })),
+            (None, None) => Ok(None),
+            _ => Err("Latitude and longitude must be given together".into()),
+        }
+    }
+}
+
+fn deserialize_size<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<usize>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Size {
+        Bytes(usize),
+        Text(String),
+    }
+    match Option::<Size>::deserialize(deserializer)? {
+        Some(Size::Bytes(bytes)) => Ok(Some(bytes)),
+        Some(Size::Text(text)) => parse_size_string(&text).map(Some).map_err(de::Error::custom),
+        None => Ok(None),
+    }
+}
+
+/// Where on earth the detector is.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub struct Location {
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+impl Location {
+    /// The location snapped to a grid of the given size in degrees, so that it no longer points at a building.
+    pub fn coarsened(&self, grid_degrees: f64) -> Location {
+        // Dividing by the number of cells per degree keeps 0.1 degree steps printable without rounding noise.
+        let cells_per_degree = 1.0 / grid_degrees;
+        Location {
+            latitude: (self.latitude * cells_per_degree).round() / cells_per_degree,
+            longitude: (self.longitude * cells_per_degree).round() / cells_per_degree,
+        }
+    }
+}
+
+/// The trailing location columns of a log entry, or nothing at all if the location is unknown.
+pub fn location_columns(location: Option<Location>) -> String {
+    match location {
+        Some(location) => format!(",{},{}", location.latitude, location.longitude),
+        None => String::new(),
+    }
+}
@@ -0,0 +1,185 @@
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{record::Record, station::Location};
+
+#[cfg(any(feature = "sqlite", feature = "postgres"))]
+mod migrations;
+#[cfg(feature = "parquet")]
+mod parquet;
+#[cfg(feature = "postgres")]
+mod postgres;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+
+const REGISTRY_FILE: &str = "stations.json";
+const RECORDS_FILE: &str = "records.jsonl";
+
+/// What the collector knows about a station that sent it data.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct StationEntry {
+    /// The key the station signs its uploads with, uploads signed with any other key are rejected.
+    pub public_key: String,
+    pub first_seen_ms: u64,
+    pub last_seen_ms: u64,
+    /// The software version of the station's latest session.
+    #[serde(default)]
+    pub version: String,
+    #[serde(default)]
+    pub location: Option<Location>,
+}
+
+/// A record as stored by the collector.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct StoredRecord {
+    pub station_id: String,
+    pub received_ms: u64,
+    pub id: String,
+    pub record: Record,
+    /// Add this to the record's timestamps to get the collector's clock, as estimated by the station.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub clock_offset_ms: Option<i64>,
+}
+
+/// Where the collector keeps the station registry and the records, so that small and large deployments can
+/// pick what suits them. The collector reads everything once at startup and then only writes.
+pub trait Storage {
+    /// Every station in the registry.
+    fn stations(&mut self) -> Result<BTreeMap<String, StationEntry>, String>;
+
+    /// Adds the station to the registry or updates it.
+    fn save_station(&mut self, station_id: &str, station: &StationEntry) -> Result<(), String>;
+
+    /// Every stored record, in the order they were stored.
+    fn records(&mut self) -> Result<Vec<StoredRecord>, String>;
+
+    /// Stores the records durably, they are acknowledged to the station as soon as this returns.
+    fn append(&mut self, records: &[StoredRecord]) -> Result<(), String>;
+}
+
+/// Opens the storage described by `spec`: `files` for JSON files in the data directory, `sqlite` for a
+/// database file in the data directory, `parquet` for Parquet files in the data directory,
+/// or a `postgres://` connection URL. Everything but `files` has to be enabled when building.
+pub fn open(spec: &str, data_dir: &Path) -> Result<Box<dyn Storage>, String> {
+    fs::create_dir_all(data_dir).map_err(|e| format!("Unable to create {}: {}", data_dir.display(), e))?;
+    match spec {
+        "files" => Ok(Box::new(FileStorage::open(data_dir)?)),
+        #[cfg(feature = "sqlite")]
+        "sqlite" => Ok(Box::new(sqlite::SqliteStorage::open(data_dir)?)),
+        #[cfg(feature = "parquet")]
+        "parquet" => Ok(Box::new(parquet::ParquetStorage::open(data_dir)?)),
+        #[cfg(feature = "postgres")]
+        url if url.starts_with("postgres://") || url.starts_with("postgresql://") => {
+            Ok(Box::new(postgres::PostgresStorage::open(url)?))
+        }
+        #[cfg(not(feature = "sqlite"))]
+        "sqlite" => Err("This build has no SQLite support, rebuild with --features sqlite".into()),
+        #[cfg(not(feature = "parquet"))]
+        "parquet" => Err("This build has no Parquet support, rebuild with --features parquet".into()),
+        #[cfg(not(feature = "postgres"))]
+        url if url.starts_with("postgres://") || url.starts_with("postgresql://") => {
+            Err("This build has no PostgreSQL support, rebuild with --features postgres".into())
+        }
+        other => Err(format!("Unknown storage '{}'", other)),
+    }
+}
+
+/// Describes the schema migrations opening the storage would apply to its database, without changing anything.
+/// Storages without a database have nothing to migrate.
+pub fn pending_migrations(spec: &str, data_dir: &Path) -> Result<Vec<String>, String> {
+    match spec {
+        #[cfg(feature = "sqlite")]
+        "sqlite" => sqlite::SqliteStorage::pending_migrations(data_dir),
+        #[cfg(feature = "postgres")]
+        url if url.starts_with("postgres://") || url.starts_with("postgresql://") => {
+            postgres::PostgresStorage::pending_migrations(url)
+        }
+        "files" | "parquet" => Ok(vec![format!("The {} storage has no schema to migrate", spec)]),
+        // Opening reports what is missing from the build.
+        other => open(other, data_dir).map(|_| Vec::new()),
+    }
+}
+
+/// The registry as a JSON file and the records as JSON lines, for small deployments that want no database.
+pub struct FileStorage {
+    registry: JsonRegistry,
+    records_path: PathBuf,
+    records: File,
+}
+
+impl FileStorage {
+    pub fn open(data_dir: &Path) -> Result<Self, String> {
+        let records_path = data_dir.join(RECORDS_FILE);
+        let records = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&records_path)
+            .map_err(|e| format!("Unable to open {}: {}", records_path.display(), e))?;
+        Ok(FileStorage { registry: JsonRegistry::open(data_dir)?, records_path, records })
+    }
+}
+
+impl Storage for FileStorage {
+    fn stations(&mut self) -> Result<BTreeMap<String, StationEntry>, String> {
+        Ok(self.registry.stations.clone())
+    }
+
+    fn save_station(&mut self, station_id: &str, station: &StationEntry) -> Result<(), String> {
+        self.registry.save_station(station_id, station)
+    }
+
+    fn records(&mut self) -> Result<Vec<StoredRecord>, String> {
+        let file = File::open(&self.records_path).map_err(|e| format!("Unable to read {}: {}", self.records_path.display(), e))?;
+        BufReader::new(file)
+            .lines()
+            .map(|line| {
+                let line = line.map_err(|e| format!("Unable to read {}: {}", self.records_path.display(), e))?;
+                serde_json::from_str(&line).map_err(|e| format!("Unable to parse {}: {}", self.records_path.display(), e))
+            })
+            .collect()
+    }
+
+    fn append(&mut self, records: &[StoredRecord]) -> Result<(), String> {
+        let mut lines = String::new();
+        for record in records {
+            lines += &(serde_json::to_string(record).map_err(|e| e.to_string())? + "\n");
+        }
+        self.records
+            .write_all(lines.as_bytes())
+            .and_then(|_| self.records.sync_data())
+            .map_err(|e| format!("Unable to store records: {}", e))
+    }
+}
+
+/// The station registry as a JSON file, shared by the storages that don't keep it in a database.
+struct JsonRegistry {
+    path: PathBuf,
+    stations: BTreeMap<String, StationEntry>,
+}
+
+impl JsonRegistry {
+    fn open(data_dir: &Path) -> Result<Self, String> {
+        let path = data_dir.join(REGISTRY_FILE);
+        let stations = match fs::read_to_string(&path) {
+            Ok(contents) => {
+                serde_json::from_str(&contents).map_err(|e| format!("Unable to parse {}: {}", path.display(), e))?
+            }
+            Err(err) if err.kind() == ErrorKind::NotFound => BTreeMap::new(),
+            Err(err) => return Err(format!("Unable to read {}: {}", path.display(), err)),
+        };
+        Ok(JsonRegistry { path, stations })
+    }
+
+    fn save_station(&mut self, station_id: &str, station: &StationEntry) -> Result<(), String> {
+        self.stations.insert(station_id.to_string(), station.clone());
+        let contents = serde_json::to_string_pretty(&self.stations).map_err(|e| e.to_string())?;
+        let temporary_path = self.path.with_extension("tmp");
+        fs::write(&temporary_path, contents)
+            .and_then(|_| fs::rename(&temporary_path, &self.path))
+            .map_err(|e| format!("Unable to save {}: {}", self.path.display(), e))
+    }
+}
@@ -0,0 +1,33 @@
+/// One step in the history of a database schema. Migrations are applied in order of version, each one exactly
+/// once, and are never changed after a release: a schema change is always a new migration.
+pub struct Migration {
+    pub version: u32,
+    pub description: &'static str,
+    pub sql: &'static str,
+}
+
+/// The migrations a database at the given version still needs. A database migrated by a newer release is an
+/// error rather than something to guess about.
+pub fn pending(migrations: &'static [Migration], current: u32) -> Result<&'static [Migration], String> {
+    let latest = migrations.last().map_or(0, |migration| migration.version);
+    if current > latest {
+        return Err(format!(
+            "The database is at schema version {} but this version only knows up to {}, upgrade the collector",
+            current, latest
+        ));
+    }
+    Ok(&migrations[migrations.iter().take_while(|migration| migration.version <= current).count()..])
+}
+
+/// What a dry run prints about the migrations.
+pub fn describe(current: u32, pending: &[Migration]) -> Vec<String> {
+    if pending.is_empty() {
+        return vec![format!("The database is at schema version {}, nothing to migrate", current)];
+    }
+    let mut lines = vec![format!("The database is at schema version {}, these migrations would be applied:", current)];
+    for migration in pending {
+        lines.push(format!("\n-- {}: {}", migration.version, migration.description));
+        lines.extend(migration.sql.lines().map(|line| line.trim().to_string()).filter(|line| !line.is_empty()));
+    }
+    lines
+}
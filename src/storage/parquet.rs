@@ -0,0 +1,143 @@
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use parquet::data_type::{ByteArray, ByteArrayType, Int64Type};
+use parquet::file::properties::WriterProperties;
+use parquet::file::reader::{FileReader, SerializedFileReader};
+use parquet::file::writer::SerializedFileWriter;
+use parquet::record::RowAccessor;
+use parquet::schema::parser::parse_message_type;
+
+use super::{JsonRegistry, StationEntry, Storage, StoredRecord};
+
+const RECORDS_DIRECTORY: &str = "records";
+const SCHEMA: &str = "message record {
+    REQUIRED BYTE_ARRAY station_id (UTF8);
+    REQUIRED BYTE_ARRAY id (UTF8);
+    REQUIRED INT64 received_ms;
+    REQUIRED BYTE_ARRAY record (UTF8);
+    OPTIONAL INT64 clock_offset_ms;
+}";
+
+/// The records as append-only Parquet files, one per received batch, for loading into analysis tools directly.
+/// The registry is kept as a JSON file like with the plain file storage.
+/// Files are never rewritten, merging the many small files into larger ones is left to the tools reading them.
+pub struct ParquetStorage {
+    registry: JsonRegistry,
+    records_directory: PathBuf,
+    next_file: u64,
+}
+
+impl ParquetStorage {
+    pub fn open(data_dir: &Path) -> Result<Self, String> {
+        let records_directory = data_dir.join(RECORDS_DIRECTORY);
+        fs::create_dir_all(&records_directory)
+            .map_err(|e| format!("Unable to create {}: {}", records_directory.display(), e))?;
+        let next_file = record_files(&records_directory)?.len() as u64;
+        Ok(ParquetStorage { registry: JsonRegistry::open(data_dir)?, records_directory, next_file })
+    }
+}
+
+impl Storage for ParquetStorage {
+    fn stations(&mut self) -> Result<BTreeMap<String, StationEntry>, String> {
+        Ok(self.registry.stations.clone())
+    }
+
+    fn save_station(&mut self, station_id: &str, station: &StationEntry) -> Result<(), String> {
+        self.registry.save_station(station_id, station)
+    }
+
+    fn records(&mut self) -> Result<Vec<StoredRecord>, String> {
+        let mut records = Vec::new();
+        for path in record_files(&self.records_directory)? {
+            let parse_error = |e: &dyn std::fmt::Display| format!("Unable to read {}: {}", path.display(), e);
+            let file = File::open(&path).map_err(|e| parse_error(&e))?;
+            let reader = SerializedFileReader::new(file).map_err(|e| parse_error(&e))?;
+            for row in reader.get_row_iter(None).map_err(|e| parse_error(&e))? {
+                let row = row.map_err(|e| parse_error(&e))?;
+                records.push(StoredRecord {
+                    station_id: row.get_string(0).map_err(|e| parse_error(&e))?.clone(),
+                    id: row.get_string(1).map_err(|e| parse_error(&e))?.clone(),
+                    received_ms: row.get_long(2).map_err(|e| parse_error(&e))? as u64,
+                    record: serde_json::from_str(row.get_string(3).map_err(|e| parse_error(&e))?)
+                        .map_err(|e| parse_error(&e))?,
+                    // Missing offsets are nulls, which can't be read as a long.
+                    clock_offset_ms: row.get_long(4).ok(),
+                });
+            }
+        }
+        Ok(records)
+    }
+
+    fn append(&mut self, records: &[StoredRecord]) -> Result<(), String> {
+        if records.is_empty() {
+            return Ok(());
+        }
+        // The file only gets its final name once it is complete, so readers never see half a file.
+        let path = self.records_directory.join(format!("{:010}.parquet", self.next_file));
+        let temporary_path = path.with_extension("tmp");
+        write_records(&temporary_path, records)
+            .and_then(|_| fs::rename(&temporary_path, &path).map_err(|e| e.to_string()))
+            .map_err(|e| format!("Unable to store records in {}: {}", path.display(), e))?;
+        self.next_file += 1;
+        Ok(())
+    }
+}
+
+fn write_records(path: &Path, records: &[StoredRecord]) -> Result<(), String> {
+    let schema = Arc::new(parse_message_type(SCHEMA).expect("The record schema is valid"));
+    let file = File::create(path).map_err(|e| e.to_string())?;
+    let mut writer = SerializedFileWriter::new(file, schema, Arc::new(WriterProperties::builder().build()))
+        .map_err(|e| e.to_string())?;
+
+    let strings = |field: fn(&StoredRecord) -> String| -> Vec<ByteArray> {
+        records.iter().map(|record| ByteArray::from(field(record).into_bytes())).collect()
+    };
+    let station_ids = strings(|record| record.station_id.clone());
+    let ids = strings(|record| record.id.clone());
+    let received = records.iter().map(|record| record.received_ms as i64).collect::<Vec<_>>();
+    let contents = records
+        .iter()
+        .map(|record| serde_json::to_string(&record.record).map(|json| ByteArray::from(json.into_bytes())))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+    let clock_offsets = records.iter().filter_map(|record| record.clock_offset_ms).collect::<Vec<_>>();
+    let clock_offset_levels = records
+        .iter()
+        .map(|record| record.clock_offset_ms.is_some() as i16)
+        .collect::<Vec<_>>();
+
+    let mut row_group = writer.next_row_group().map_err(|e| e.to_string())?;
+    let mut index = 0;
+    while let Some(mut column) = row_group.next_column().map_err(|e| e.to_string())? {
+        match index {
+            0 => column.typed::<ByteArrayType>().write_batch(&station_ids, None, None),
+            1 => column.typed::<ByteArrayType>().write_batch(&ids, None, None),
+            2 => column.typed::<Int64Type>().write_batch(&received, None, None),
+            3 => column.typed::<ByteArrayType>().write_batch(&contents, None, None),
+            _ => column
+                .typed::<Int64Type>()
+                .write_batch(&clock_offsets, Some(&clock_offset_levels), None),
+        }
+        .map_err(|e| e.to_string())?;
+        column.close().map_err(|e| e.to_string())?;
+        index += 1;
+    }
+    row_group.close().map_err(|e| e.to_string())?;
+    let file = writer.into_inner().map_err(|e| e.to_string())?;
+    file.sync_all().map_err(|e| e.to_string())
+}
+
+/// The record files in the order they were written.
+fn record_files(records_directory: &Path) -> Result<Vec<PathBuf>, String> {
+    let mut files = fs::read_dir(records_directory)
+        .map_err(|e| format!("Unable to read {}: {}", records_directory.display(), e))?
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().map_or(false, |extension| extension == "parquet"))
+        .collect::<Vec<_>>();
+    files.sort();
+    Ok(files)
+}
@@ -0,0 +1,205 @@
+use std::collections::BTreeMap;
+
+use postgres::{Client, GenericClient, NoTls};
+
+use super::migrations::{self, Migration};
+use super::{StationEntry, Storage, StoredRecord};
+use crate::station::Location;
+
+/// The schema, one migration per change. Databases from before migrations existed are recognised by their tables.
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "stations and records",
+        sql: "CREATE TABLE stations (
+                  station_id TEXT PRIMARY KEY,
+                  public_key TEXT NOT NULL,
+                  first_seen_ms BIGINT NOT NULL,
+                  last_seen_ms BIGINT NOT NULL,
+                  version TEXT NOT NULL,
+                  latitude DOUBLE PRECISION,
+                  longitude DOUBLE PRECISION
+              );
+              CREATE TABLE records (
+                  sequence BIGSERIAL,
+                  station_id TEXT NOT NULL,
+                  id TEXT NOT NULL,
+                  received_ms BIGINT NOT NULL,
+                  record JSONB NOT NULL,
+                  PRIMARY KEY (station_id, id)
+              );",
+    },
+    Migration {
+        version: 2,
+        description: "clock offsets estimated by the stations",
+        sql: "ALTER TABLE records ADD COLUMN clock_offset_ms BIGINT;",
+    },
+];
+
+/// Everything in a PostgreSQL database, for collectors with many stations.
+/// The records are kept as JSONB so that they can be queried directly.
+pub struct PostgresStorage {
+    client: Client,
+}
+
+impl PostgresStorage {
+    /// Connects to the database, creating the tables or migrating them to the latest schema if needed.
+    /// Migrations run in a single transaction, but take a `pg_dump` before upgrading a collector all the same.
+    pub fn open(url: &str) -> Result<Self, String> {
+        let mut client = Client::connect(url, NoTls).map_err(|e| format!("Unable to connect to PostgreSQL: {}", e))?;
+        migrate(&mut client).map_err(|e| format!("Unable to migrate the PostgreSQL database: {}", e))?;
+        Ok(PostgresStorage { client })
+    }
+
+    /// Describes the migrations opening the database would apply, without changing anything.
+    pub fn pending_migrations(url: &str) -> Result<Vec<String>, String> {
+        let mut client = Client::connect(url, NoTls).map_err(|e| format!("Unable to connect to PostgreSQL: {}", e))?;
+        let (current, _) = schema_version(&mut client)?;
+        Ok(migrations::describe(current, migrations::pending(MIGRATIONS, current)?))
+    }
+}
+
+/// The schema version of the database, and whether it is recorded or was recognised from the tables.
+fn schema_version(client: &mut impl GenericClient) -> Result<(u32, bool), String> {
+    if exists(client, "SELECT to_regclass('schema_migrations') IS NOT NULL")? {
+        let row = client
+            .query_one("SELECT COALESCE(MAX(version), 0) FROM schema_migrations", &[])
+            .map_err(|e| e.to_string())?;
+        return Ok((row.get::<_, i32>(0) as u32, true));
+    }
+    if !exists(client, "SELECT to_regclass('records') IS NOT NULL")? {
+        return Ok((0, false));
+    }
+    let has_clock_offset = exists(
+        client,
+        "SELECT EXISTS (SELECT 1 FROM information_schema.columns
+                        WHERE table_name = 'records' AND column_name = 'clock_offset_ms')",
+    )?;
+    Ok((if has_clock_offset { 2 } else { 1 }, false))
+}
+
+fn exists(client: &mut impl GenericClient, sql: &str) -> Result<bool, String> {
+    client.query_one(sql, &[]).map(|row| row.get(0)).map_err(|e| e.to_string())
+}
+
+/// Applies the pending migrations in a single transaction, so that a failed migration leaves the database as it was.
+fn migrate(client: &mut Client) -> Result<(), String> {
+    let mut transaction = client.transaction().map_err(|e| e.to_string())?;
+    // Keeps two collectors starting at the same time from both migrating.
+    transaction
+        .batch_execute("SELECT pg_advisory_xact_lock(7036947)")
+        .map_err(|e| e.to_string())?;
+    let (current, recorded) = schema_version(&mut transaction)?;
+    let pending = migrations::pending(MIGRATIONS, current)?;
+    if recorded && pending.is_empty() {
+        return Ok(());
+    }
+    transaction
+        .batch_execute("CREATE TABLE IF NOT EXISTS schema_migrations (version INTEGER PRIMARY KEY, description TEXT NOT NULL)")
+        .map_err(|e| e.to_string())?;
+    for migration in MIGRATIONS.iter().take_while(|migration| migration.version <= current) {
+        transaction
+            .execute(
+                "INSERT INTO schema_migrations VALUES ($1, $2) ON CONFLICT DO NOTHING",
+                &[&(migration.version as i32), &migration.description],
+            )
+            .map_err(|e| e.to_string())?;
+    }
+    for migration in pending {
+        transaction
+            .batch_execute(migration.sql)
+            .and_then(|_| {
+                transaction.execute(
+                    "INSERT INTO schema_migrations VALUES ($1, $2)",
+                    &[&(migration.version as i32), &migration.description],
+                )
+            })
+            .map_err(|e| format!("migration {} failed: {}", migration.version, e))?;
+    }
+    transaction.commit().map_err(|e| e.to_string())
+}
+
+impl Storage for PostgresStorage {
+    fn stations(&mut self) -> Result<BTreeMap<String, StationEntry>, String> {
+        let rows = self
+            .client
+            .query(
+                "SELECT station_id, public_key, first_seen_ms, last_seen_ms, version, latitude, longitude FROM stations",
+                &[],
+            )
+            .map_err(|e| e.to_string())?;
+        Ok(rows
+            .iter()
+            .map(|row| {
+                let location = match (row.get(5), row.get(6)) {
+                    (Some(latitude), Some(longitude)) => Some(Location { latitude, longitude }),
+                    _ => None,
+                };
+                (
+                    row.get(0),
+                    StationEntry {
+                        public_key: row.get(1),
+                        first_seen_ms: row.get::<_, i64>(2) as u64,
+                        last_seen_ms: row.get::<_, i64>(3) as u64,
+                        version: row.get(4),
+                        location,
+                    },
+                )
+            })
+            .collect())
+    }
+
+    fn save_station(&mut self, station_id: &str, station: &StationEntry) -> Result<(), String> {
+        self.client
+            .execute(
+                "INSERT INTO stations VALUES ($1, $2, $3, $4, $5, $6, $7)
+                 ON CONFLICT (station_id) DO UPDATE SET public_key = $2, first_seen_ms = $3, last_seen_ms = $4,
+                     version = $5, latitude = $6, longitude = $7",
+                &[
+                    &station_id,
+                    &station.public_key,
+                    &(station.first_seen_ms as i64),
+                    &(station.last_seen_ms as i64),
+                    &station.version,
+                    &station.location.map(|location| location.latitude),
+                    &station.location.map(|location| location.longitude),
+                ],
+            )
+            .map(|_| ())
+            .map_err(|e| format!("Unable to save station {}: {}", station_id, e))
+    }
+
+    fn records(&mut self) -> Result<Vec<StoredRecord>, String> {
+        let rows = self
+            .client
+            .query("SELECT station_id, id, received_ms, record::TEXT, clock_offset_ms FROM records ORDER BY sequence", &[])
+            .map_err(|e| e.to_string())?;
+        rows.iter()
+            .map(|row| {
+                Ok(StoredRecord {
+                    station_id: row.get(0),
+                    id: row.get(1),
+                    received_ms: row.get::<_, i64>(2) as u64,
+                    record: serde_json::from_str(row.get(3)).map_err(|e| e.to_string())?,
+                    clock_offset_ms: row.get(4),
+                })
+            })
+            .collect()
+    }
+
+    fn append(&mut self, records: &[StoredRecord]) -> Result<(), String> {
+        let mut transaction = self.client.transaction().map_err(|e| e.to_string())?;
+        for record in records {
+            let json = serde_json::to_string(&record.record).map_err(|e| e.to_string())?;
+            transaction
+                .execute(
+                    "INSERT INTO records (station_id, id, received_ms, record, clock_offset_ms)
+                     VALUES ($1, $2, $3, CAST($4::TEXT AS JSONB), $5)
+                     ON CONFLICT DO NOTHING",
+                    &[&record.station_id, &record.id, &(record.received_ms as i64), &json, &record.clock_offset_ms],
+                )
+                .map_err(|e| format!("Unable to store records: {}", e))?;
+        }
+        transaction.commit().map_err(|e| format!("Unable to store records: {}", e))
+    }
+}
@@ -0,0 +1,281 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use rusqlite::{params, Connection, OpenFlags};
+
+use super::migrations::{self, Migration};
+use super::{StationEntry, Storage, StoredRecord};
+use crate::{backup, station::Location};
+
+const DATABASE_FILE: &str = "collector.sqlite3";
+
+/// The schema, one migration per change. Databases from before migrations existed are recognised by their tables.
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "stations and records",
+        sql: "CREATE TABLE stations (
+                  station_id TEXT PRIMARY KEY,
+                  public_key TEXT NOT NULL,
+                  first_seen_ms INTEGER NOT NULL,
+                  last_seen_ms INTEGER NOT NULL,
+                  version TEXT NOT NULL,
+                  latitude REAL,
+                  longitude REAL
+              );
+              CREATE TABLE records (
+                  station_id TEXT NOT NULL,
+                  id TEXT NOT NULL,
+                  received_ms INTEGER NOT NULL,
+                  record TEXT NOT NULL,
+                  PRIMARY KEY (station_id, id)
+              );",
+    },
+    Migration {
+        version: 2,
+        description: "clock offsets estimated by the stations",
+        sql: "ALTER TABLE records ADD COLUMN clock_offset_ms INTEGER;",
+    },
+];
+
+/// Everything in a single SQLite database file, for collectors that want to query their data with SQL.
+pub struct SqliteStorage {
+    connection: Connection,
+}
+
+impl SqliteStorage {
+    /// Opens the database, creating it or migrating it to the latest schema if needed. An existing database is
+    /// backed up before it is migrated.
+    pub fn open(data_dir: &Path) -> Result<Self, String> {
+        let path = data_dir.join(DATABASE_FILE);
+        let mut connection =
+            Connection::open(&path).map_err(|e| format!("Unable to open {}: {}", path.display(), e))?;
+        connection
+            .execute_batch("PRAGMA journal_mode = WAL; PRAGMA synchronous = FULL;")
+            .map_err(|e| format!("Unable to set up {}: {}", path.display(), e))?;
+        migrate(&mut connection, &path).map_err(|e| format!("Unable to migrate {}: {}", path.display(), e))?;
+        Ok(SqliteStorage { connection })
+    }
+
+    /// Describes the migrations opening the database would apply, without changing anything.
+    pub fn pending_migrations(data_dir: &Path) -> Result<Vec<String>, String> {
+        let path = data_dir.join(DATABASE_FILE);
+        if !path.exists() {
+            return Ok(migrations::describe(0, MIGRATIONS));
+        }
+        let connection = Connection::open_with_flags(&path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+            .map_err(|e| format!("Unable to open {}: {}", path.display(), e))?;
+        let (current, _) = schema_version(&connection).map_err(|e| format!("{}: {}", path.display(), e))?;
+        Ok(migrations::describe(current, migrations::pending(MIGRATIONS, current)?))
+    }
+}
+
+/// The schema version of the database, and whether it is recorded or was recognised from the tables.
+fn schema_version(connection: &Connection) -> Result<(u32, bool), String> {
+    let count = |sql: &str| connection.query_row(sql, [], |row| row.get::<_, i64>(0)).map_err(|e| e.to_string());
+    if count("SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name = 'schema_migrations'")? > 0 {
+        return Ok((count("SELECT COALESCE(MAX(version), 0) FROM schema_migrations")? as u32, true));
+    }
+    if count("SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name = 'records'")? == 0 {
+        return Ok((0, false));
+    }
+    let has_clock_offset = count("SELECT COUNT(*) FROM pragma_table_info('records') WHERE name = 'clock_offset_ms'")? > 0;
+    Ok((if has_clock_offset { 2 } else { 1 }, false))
+}
+
+/// Applies the pending migrations in a single transaction, so that a failed migration leaves the database as it was.
+fn migrate(connection: &mut Connection, path: &Path) -> Result<(), String> {
+    let (current, recorded) = schema_version(connection)?;
+    let pending = migrations::pending(MIGRATIONS, current)?;
+    if recorded && pending.is_empty() {
+        return Ok(());
+    }
+    if current > 0 && !pending.is_empty() {
+        // Move everything from the write-ahead log into the database file, so that the copy is complete.
+        connection.execute_batch("PRAGMA wal_checkpoint(TRUNCATE);").map_err(|e| e.to_string())?;
+        let backup_path = backup::backup(path)?;
+        println!("Backed up {} to {} before migrating it", path.display(), backup_path.display());
+    }
+
+    let transaction = connection.transaction().map_err(|e| e.to_string())?;
+    transaction
+        .execute_batch("CREATE TABLE IF NOT EXISTS schema_migrations (version INTEGER PRIMARY KEY, description TEXT NOT NULL);")
+        .map_err(|e| e.to_string())?;
+    let known = MIGRATIONS.iter().take_while(|migration| migration.version <= current);
+    for migration in known {
+        transaction
+            .execute("INSERT OR IGNORE INTO schema_migrations VALUES (?1, ?2)", params![migration.version, migration.description])
+            .map_err(|e| e.to_string())?;
+    }
+    for migration in pending {
+        transaction
+            .execute_batch(migration.sql)
+            .and_then(|_| {
+                transaction.execute(
+                    "INSERT INTO schema_migrations VALUES (?1, ?2)",
+                    params![migration.version, migration.description],
+                )
+            })
+            .map_err(|e| format!("migration {} failed: {}", migration.version, e))?;
+    }
+    transaction.commit().map_err(|e| e.to_string())
+}
+
+impl Storage for SqliteStorage {
+    fn stations(&mut self) -> Result<BTreeMap<String, StationEntry>, String> {
+        let mut statement = self
+            .connection
+            .prepare("SELECT station_id, public_key, first_seen_ms, last_seen_ms, version, latitude, longitude FROM stations")
+            .map_err(|e| e.to_string())?;
+        let rows = statement
+            .query_map([], |row| {
+                let location = match (row.get(5)?, row.get(6)?) {
+                    (Some(latitude), Some(longitude)) => Some(Location { latitude, longitude }),
+                    _ => None,
+                };
+                Ok((
+                    row.get::<_, String>(0)?,
+                    StationEntry {
+                        public_key: row.get(1)?,
+                        first_seen_ms: row.get::<_, i64>(2)? as u64,
+                        last_seen_ms: row.get::<_, i64>(3)? as u64,
+                        version: row.get(4)?,
+                        location,
+                    },
+                ))
+            })
+            .map_err(|e| e.to_string())?;
+        rows.collect::<Result<_, _>>().map_err(|e| e.to_string())
+    }
+
+    fn save_station(&mut self, station_id: &str, station: &StationEntry) -> Result<(), String> {
+        self.connection
+            .execute(
+                "INSERT OR REPLACE INTO stations VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                params![
+                    station_id,
+                    station.public_key,
+                    station.first_seen_ms as i64,
+                    station.last_seen_ms as i64,
+                    station.version,
+                    station.location.map(|location| location.latitude),
+                    station.location.map(|location| location.longitude),
+                ],
+            )
+            .map(|_| ())
+            .map_err(|e| format!("Unable to save station {}: {}", station_id, e))
+    }
+
+    fn records(&mut self) -> Result<Vec<StoredRecord>, String> {
+        let mut statement = self
+            .connection
+            .prepare("SELECT station_id, id, received_ms, record, clock_offset_ms FROM records ORDER BY rowid")
+            .map_err(|e| e.to_string())?;
+        let rows = statement
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, i64>(2)?,
+                    row.get::<_, String>(3)?,
+                    row.get::<_, Option<i64>>(4)?,
+                ))
+            })
+            .map_err(|e| e.to_string())?;
+        rows.map(|row| {
+            let (station_id, id, received_ms, record, clock_offset_ms) = row.map_err(|e| e.to_string())?;
+            Ok(StoredRecord {
+                station_id,
+                id,
+                received_ms: received_ms as u64,
+                record: serde_json::from_str(&record).map_err(|e| e.to_string())?,
+                clock_offset_ms,
+            })
+        })
+        .collect()
+    }
+
+    fn append(&mut self, records: &[StoredRecord]) -> Result<(), String> {
+        let transaction = self.connection.transaction().map_err(|e| e.to_string())?;
+        for record in records {
+            transaction
+                .execute(
+                    "INSERT OR IGNORE INTO records (station_id, id, received_ms, record, clock_offset_ms) VALUES (?1, ?2, ?3, ?4, ?5)",
+                    params![
+                        record.station_id,
+                        record.id,
+                        record.received_ms as i64,
+                        serde_json::to_string(&record.record).map_err(|e| e.to_string())?,
+                        record.clock_offset_ms,
+                    ],
+                )
+                .map_err(|e| format!("Unable to store records: {}", e))?;
+        }
+        transaction.commit().map_err(|e| format!("Unable to store records: {}", e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::record::Record;
+
+    /// A database as the first release of the SQLite storage left it, with a record a station of schema 5 sent,
+    /// before records had sequence numbers.
+    const SCHEMA_1_FIXTURE: &str = r#"
+        INSERT INTO stations VALUES ('alpha', 'ab12', 1700000000000, 1700000600000, '1.1.0', 10.5, -11.25);
+        INSERT INTO records VALUES ('alpha', 'session-1:0', 1700000600000,
+            '{"type":"flip","started_ms":1700000000000,"delay_ms":30000,"checks":3,"vanished":false,"detected_ms":1700000000100,"location":null}');
+    "#;
+
+    #[test]
+    fn a_schema_1_database_is_migrated_and_keeps_its_data() {
+        // Recorded in schema_migrations, and from before the collector recorded migrations at all.
+        for (name, recorded) in [("recorded", true), ("unrecorded", false)] {
+            let dir_name = format!("cosmic_ray_detector-migration-{}-{}", name, std::process::id());
+            let dir = std::env::temp_dir().join(dir_name);
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(&dir).unwrap();
+            let connection = Connection::open(dir.join(DATABASE_FILE)).unwrap();
+            connection.execute_batch(MIGRATIONS[0].sql).unwrap();
+            if recorded {
+                connection
+                    .execute_batch(
+                        "CREATE TABLE schema_migrations (version INTEGER PRIMARY KEY, description TEXT NOT NULL);
+                         INSERT INTO schema_migrations VALUES (1, 'stations and records');",
+                    )
+                    .unwrap();
+            }
+            connection.execute_batch(SCHEMA_1_FIXTURE).unwrap();
+            drop(connection);
+
+            let pending = SqliteStorage::pending_migrations(&dir).unwrap().join("\n");
+            assert!(pending.contains("at schema version 1") && pending.contains("-- 2: "));
+            let mut storage = SqliteStorage::open(&dir).unwrap();
+            let latest = MIGRATIONS.last().unwrap().version;
+            assert_eq!(schema_version(&storage.connection), Ok((latest, true)));
+            let backups = fs::read_dir(&dir)
+                .unwrap()
+                .filter(|entry| entry.as_ref().unwrap().file_name().to_string_lossy().contains(".backup-"))
+                .count();
+            assert!(backups >= 1);
+
+            let stations = storage.stations().unwrap();
+            assert_eq!(stations["alpha"].version, "1.1.0");
+            let records = storage.records().unwrap();
+            assert_eq!(records.len(), 1);
+            assert!(matches!(records[0].record, Record::Flip { checks: 3, sequence: None, .. }));
+            assert_eq!(records[0].clock_offset_ms, None);
+
+            // The new column takes the data the old schema had no room for.
+            let received = StoredRecord { id: "session-2:0".into(), clock_offset_ms: Some(-250), ..records[0].clone() };
+            storage.append(&[received]).unwrap();
+            drop(storage);
+            let mut storage = SqliteStorage::open(&dir).unwrap();
+            assert_eq!(storage.records().unwrap()[1].clock_offset_ms, Some(-250));
+            assert!(SqliteStorage::pending_migrations(&dir).unwrap()[0].contains("nothing to migrate"));
+        }
+    }
+}
@@ -0,0 +1,39 @@
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use futures_core::Stream;
+use tokio::sync::mpsc;
+
+use crate::detector::Detector;
+use crate::runner::{FlipEvent, Runner, RunnerHandle};
+
+/// The flips found by `Detector::run_stream`. Dropping it stops the checks.
+pub struct FlipStream {
+    receiver: mpsc::UnboundedReceiver<FlipEvent>,
+    _runner: RunnerHandle,
+}
+
+impl Stream for FlipStream {
+    type Item = FlipEvent;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<FlipEvent>> {
+        self.receiver.poll_recv(cx)
+    }
+}
+
+impl Detector {
+    /// Checks the detector every `delay` and yields every change it finds, resetting the memory after each one.
+    /// Checking blocks a core for as long as it takes to read the memory, so it happens on a thread of its own
+    /// rather than in the async runtime.
+    pub fn run_stream(self, delay: Duration) -> Result<FlipStream, String> {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let runner = Runner::new(self, delay)
+            .on_flip(move |event| {
+                // The stream is being dropped, which stops the runner.
+                let _ = sender.send(event.clone());
+            })
+            .spawn()?;
+        Ok(FlipStream { receiver, _runner: runner })
+    }
+}
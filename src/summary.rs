@@ -0,0 +1,98 @@
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+use serde::Serialize;
+
+use crate::{exposure::gb_hours, fill::ResizeReason};
+
+/// What one run of the detector did, written as JSON when it shuts down so that fleet tooling can collect it
+/// without parsing the log. Only a clean shutdown writes one, a summary with another session id is left from
+/// an earlier run.
+#[derive(Serialize, Default)]
+pub struct Summary {
+    pub station_id: String,
+    pub session_id: String,
+    pub started_ms: u128,
+    pub stopped_ms: u128,
+    pub uptime_seconds: f64,
+    pub total_checks: u64,
+    pub flips: u64,
+    /// Bitflips that flipped back before the changed byte was found.
+    pub vanished_flips: u64,
+    /// Bitflips found during `--warmup`, which `flips` leaves out.
+    pub warmup_flips: u64,
+    /// Checks of a detector that wasn't all in memory.
+    pub reduced_coverage_checks: u64,
+    pub gb_hours: f64,
+    /// Time spent not monitoring while running, e.g. rewriting the detector after a flip.
+    pub dead_seconds: f64,
+    /// The size of the detector when it shut down.
+    pub detector_bytes: usize,
+    pub resizes: Vec<ResizeEntry>,
+    pub errors: ErrorCounts,
+}
+
+#[derive(Serialize)]
+pub struct ResizeEntry {
+    pub timestamp_ms: u128,
+    pub from_bytes: usize,
+    pub to_bytes: usize,
+    /// swap_grew, low_memory or free_memory.
+    pub reason: &'static str,
+}
+
+#[derive(Serialize, Default)]
+pub struct ErrorCounts {
+    pub stalled_scans: u64,
+    /// Checks where the checksums and the full scan of the detector disagreed.
+    pub disagreements: u64,
+    /// Checks that panicked, were caught and started over.
+    pub panics: u64,
+    /// Records the log couldn't keep up with.
+    pub dropped_records: u64,
+    /// Memory errors of the whole machine while running, as far as EDAC counts them.
+    pub corrected_memory_errors: u64,
+    pub uncorrected_memory_errors: u64,
+    /// The first counts EDAC reported while running, which the ones while running are counted from.
+    #[serde(skip)]
+    memory_errors_at_start: Option<(u64, u64)>,
+}
+
+impl Summary {
+    pub fn add_exposure(&mut self, detector_bytes: usize, duration: Duration) {
+        self.gb_hours += gb_hours(detector_bytes, duration);
+    }
+
+    pub fn add_dead_time(&mut self, duration: Duration) {
+        self.dead_seconds += duration.as_secs_f64();
+    }
+
+    pub fn add_resize(&mut self, timestamp_ms: u128, from_bytes: usize, to_bytes: usize, reason: ResizeReason) {
+        let reason = match reason {
+            ResizeReason::SwapGrew(_) => "swap_grew",
+            ResizeReason::LowMemory(_) => "low_memory",
+            ResizeReason::FreeMemory(_) => "free_memory",
+        };
+        self.resizes.push(ResizeEntry { timestamp_ms, from_bytes, to_bytes, reason });
+    }
+
+    /// Counts the memory errors since the first counts seen. They start over when the machine reboots,
+    /// which it can't while running.
+    pub fn set_memory_errors(&mut self, corrected: u64, uncorrected: u64) {
+        let errors = &mut self.errors;
+        let (corrected_at_start, uncorrected_at_start) = *errors.memory_errors_at_start.get_or_insert((corrected, uncorrected));
+        errors.corrected_memory_errors = corrected.saturating_sub(corrected_at_start);
+        errors.uncorrected_memory_errors = uncorrected.saturating_sub(uncorrected_at_start);
+    }
+
+    /// Writes the summary to a temporary file and moves it into place, so it is never left half written.
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let temporary_path = format!("{}.tmp", path.display());
+        serde_json::to_string_pretty(self)
+            .map_err(|e| e.to_string())
+            .and_then(|contents| fs::write(&temporary_path, contents).map_err(|e| e.to_string()))
+            .and_then(|_| fs::rename(&temporary_path, path).map_err(|e| e.to_string()))
+            .map_err(|e| format!("Unable to save the shutdown summary {}: {}", path.display(), e))
+    }
+}
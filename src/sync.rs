@@ -0,0 +1,45 @@
+use std::error::Error;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::thread::sleep;
+use std::time::Duration;
+
+use crate::{
+    config::{GlobalArgs, SyncArgs},
+    lan::{discover, fetch_summary},
+};
+
+/// Collects summaries from every station on the local network into one file, for labs without an internet collector.
+pub fn sync(global: &GlobalArgs, args: SyncArgs) -> Result<(), Box<dyn Error>> {
+    let mut output = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&args.output)?;
+
+    loop {
+        let stations = discover(Duration::from_millis(args.discovery_time))?;
+        if global.verbose {
+            println!("Found {} stations", stations.len());
+        }
+        for (station_id, address) in stations {
+            match fetch_summary(address) {
+                Ok(synced) => {
+                    if global.verbose {
+                        println!(
+                            "{}: {} checks, {} bitflips, clock off by {} ms",
+                            station_id, synced.summary.total_checks, synced.summary.flips, synced.clock_offset_ms
+                        );
+                    }
+                    writeln!(output, "{}", serde_json::to_string(&synced)?)?;
+                    output.flush()?;
+                }
+                Err(err) => eprintln!("Unable to sync with {} at {}: {}", station_id, address, err),
+            }
+        }
+
+        if args.once {
+            return Ok(());
+        }
+        sleep(Duration::from_millis(args.interval));
+    }
+}
@@ -0,0 +1,306 @@
+use std::error::Error;
+use std::fs::File;
+use std::io::{self, IsTerminal, Read, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::{
+    binlog::{self, BinaryDecoder},
+    config::{GlobalArgs, TailArgs},
+    console::{self, Style},
+    exposure::civil_from_days,
+    record::{Record, SQLITE_MAGIC},
+    storage,
+};
+
+/// Follows a log file or the collector's storage and prints every record in a form meant for people.
+pub fn tail(global: &GlobalArgs, args: TailArgs) -> Result<(), Box<dyn Error>> {
+    let color = console::use_color(io::stdout().is_terminal(), global.plain);
+    let interval = Duration::from_millis(args.interval);
+    let mut out = io::stdout().lock();
+    match (&args.file_path, &args.storage) {
+        (Some(path), _) => {
+            let mut follower = LogFollower::new(path.clone());
+            let mut records = follower.poll()?;
+            skip_all_but(&mut records, args.lines);
+            loop {
+                for record in &records {
+                    writeln!(out, "{}", format_record(record, None, color))?;
+                }
+                out.flush()?;
+                thread::sleep(interval);
+                records = follower.poll()?;
+            }
+        }
+        (None, Some(spec)) => {
+            let mut seen = 0;
+            let mut first = true;
+            loop {
+                let mut records = storage::open(spec, &args.data_dir)?.records()?;
+                if records.len() < seen {
+                    seen = 0;
+                }
+                let mut new = records.split_off(seen);
+                seen += new.len();
+                if first {
+                    skip_all_but(&mut new, args.lines);
+                    first = false;
+                }
+                for stored in &new {
+                    writeln!(out, "{}", format_record(&stored.record, Some(&stored.station_id), color))?;
+                }
+                out.flush()?;
+                thread::sleep(interval);
+            }
+        }
+        (None, None) => Err("Give the log file to follow, or the collector storage with --storage".into()),
+    }
+}
+
+fn skip_all_but<T>(records: &mut Vec<T>, lines: usize) {
+    records.drain(..records.len().saturating_sub(lines));
+}
+
+/// Reads what was appended to a log file since it was last polled, in either format. A record that is still
+/// being written is left for the next poll, and a file that got shorter is read again from the start.
+struct LogFollower {
+    path: PathBuf,
+    offset: u64,
+    /// The bytes after the last complete record.
+    pending: Vec<u8>,
+    /// Set once the file turned out to be a binary log.
+    decoder: Option<BinaryDecoder>,
+    /// How many records of a --log-backend sqlite database were already returned, set once the file turned out to be
+    /// one. Databases are read through SQLite rather than byte by byte.
+    database_records: Option<usize>,
+}
+
+impl LogFollower {
+    fn new(path: PathBuf) -> Self {
+        LogFollower { path, offset: 0, pending: Vec::new(), decoder: None, database_records: None }
+    }
+
+    fn poll(&mut self) -> Result<Vec<Record>, String> {
+        if self.database_records.is_some() {
+            return self.poll_database();
+        }
+        let mut file = match File::open(&self.path) {
+            Ok(file) => file,
+            // Not created yet, or being rotated.
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(format!("Unable to open {}: {}", self.path.display(), e)),
+        };
+        let length = file.metadata().map_err(|e| format!("Unable to read {}: {}", self.path.display(), e))?.len();
+        if length < self.offset {
+            *self = LogFollower::new(self.path.clone());
+        }
+        let read = file
+            .seek(SeekFrom::Start(self.offset))
+            .and_then(|_| file.read_to_end(&mut self.pending))
+            .map_err(|e| format!("Unable to read {}: {}", self.path.display(), e))?;
+        self.offset += read as u64;
+
+        if self.decoder.is_none() && self.offset == self.pending.len() as u64 {
+            if self.pending.starts_with(SQLITE_MAGIC) {
+                self.pending.clear();
+                self.database_records = Some(0);
+                return self.poll_database();
+            }
+            let header = binlog::header();
+            if self.pending.len() < binlog::MAGIC.len() && binlog::MAGIC.starts_with(&self.pending) {
+                return Ok(Vec::new());
+            }
+            if self.pending.starts_with(binlog::MAGIC) {
+                if self.pending.len() < header.len() {
+                    return Ok(Vec::new());
+                }
+                if !self.pending.starts_with(&header) {
+                    return Err(format!("{} is a binary log of an unsupported version", self.path.display()));
+                }
+                self.pending.drain(..header.len());
+                self.decoder = Some(BinaryDecoder::default());
+            }
+        }
+
+        match &mut self.decoder {
+            Some(decoder) => {
+                let (records, consumed) = decoder.decode(&self.pending)?;
+                self.pending.drain(..consumed);
+                Ok(records)
+            }
+            None => {
+                let Some(end) = self.pending.iter().rposition(|&byte| byte == b'\n') else {
+                    return Ok(Vec::new());
+                };
+                let lines: Vec<u8> = self.pending.drain(..=end).collect();
+                String::from_utf8_lossy(&lines)
+                    .lines()
+                    .filter(|line| !line.trim().is_empty())
+                    .map(|line| Record::from_line(line).map_err(|e| format!("{}: {}", e, line)))
+                    .collect()
+            }
+        }
+    }
+
+    /// Reads the whole database and returns the records that weren't returned yet, which are always the last ones.
+    #[cfg(feature = "sqlite")]
+    fn poll_database(&mut self) -> Result<Vec<Record>, String> {
+        let mut records = crate::sqlite_log::read(&self.path)?;
+        let seen = self.database_records.get_or_insert(0);
+        if records.len() < *seen {
+            *seen = 0;
+        }
+        let new = records.split_off(*seen);
+        *seen += new.len();
+        Ok(new)
+    }
+
+    #[cfg(not(feature = "sqlite"))]
+    fn poll_database(&mut self) -> Result<Vec<Record>, String> {
+        Err(format!("{} is a SQLite log, which this build can't follow, rebuild with --features sqlite", self.path.display()))
+    }
+}
+
+fn format_record(record: &Record, station_id: Option<&str>, color: bool) -> String {
+    let (time, style, text) = match record {
+        Record::Session(info) => (
+            None,
+            None,
+            format!(
+                "session    version {}{}{}",
+                info.version,
+                info.session_id.as_ref().map(|id| format!(", session {}", id)).unwrap_or_default(),
+                info.detector_bytes.map(|bytes| format!(", detector {}", humanize_bytes(bytes))).unwrap_or_default()
+            ),
+        ),
+        Record::Start { started_ms, delay_ms, pid, location, .. } => (
+            Some(*started_ms),
+            None,
+            format!(
+                "start      checking every {}{}{}",
+                humanize_duration(*delay_ms),
+                pid.map(|pid| format!(", pid {}", pid)).unwrap_or_default(),
+                location
+                    .map(|location| format!(", at {:.4}, {:.4}", location.latitude, location.longitude))
+                    .unwrap_or_default()
+            ),
+        ),
+        Record::Flip { started_ms, detected_ms, checks, vanished, uncertainty, .. } => (
+            Some(*detected_ms),
+            Some(Style::Flip),
+            format!(
+                "BITFLIP    after {} passed checks, within {} before detection{}{}",
+                checks,
+                humanize_duration(detected_ms.saturating_sub(*started_ms) as u64),
+                if *vanished { ", changed back before it could be found" } else { "" },
+                uncertainty
+                    .as_ref()
+                    .and_then(|uncertainty| uncertainty.resident_fraction)
+                    .map(|fraction| format!(", reduced coverage with {:.1}% in memory", fraction * 100.0))
+                    .unwrap_or_default()
+            ),
+        ),
+        Record::Heartbeat { timestamp_ms, total_checks, energy, paging, residency, .. } => (
+            Some(*timestamp_ms),
+            Some(Style::Dim),
+            format!(
+                "heartbeat  {} checks{}{}{}",
+                total_checks,
+                energy.as_ref().map(|energy| format!(", {:.3} J per check", energy.joules_per_check)).unwrap_or_default(),
+                paging
+                    .as_ref()
+                    .filter(|paging| paging.swapped())
+                    .map(|paging| format!(
+                        ", {} pages swapped out and {} in",
+                        paging.swapped_out_pages, paging.swapped_in_pages
+                    ))
+                    .unwrap_or_default(),
+                residency
+                    .as_ref()
+                    .filter(|residency| residency.reduced_checks > 0)
+                    .map(|residency| format!(
+                        ", {} with reduced coverage, down to {:.1}% in memory",
+                        residency.reduced_checks,
+                        residency.min_resident_fraction * 100.0
+                    ))
+                    .unwrap_or_default()
+            ),
+        ),
+        Record::MemoryErrors { timestamp_ms, corrected, uncorrected, .. } => (
+            Some(*timestamp_ms),
+            Some(Style::Warning),
+            format!("ECC        {} corrected, {} uncorrected memory errors since boot", corrected, uncorrected),
+        ),
+        Record::Dropped { timestamp_ms, records, .. } => (
+            Some(*timestamp_ms),
+            Some(Style::Warning),
+            format!("dropped    {} heartbeats or status updates that couldn't be written in time", records),
+        ),
+        Record::Disagreement { timestamp_ms, total_checks, crc_intact, .. } => (
+            Some(*timestamp_ms),
+            Some(Style::Warning),
+            format!(
+                "disagree   on check {}, the checksums say the detector is {} but the full scan doesn't",
+                total_checks,
+                if *crc_intact { "intact" } else { "changed" }
+            ),
+        ),
+        Record::Panic { timestamp_ms, total_checks, message, .. } => (
+            Some(*timestamp_ms),
+            Some(Style::Warning),
+            format!("panic      on check {}, started over: {}", total_checks, message),
+        ),
+        Record::Shutdown { timestamp_ms, total_checks, flips, .. } => (
+            Some(*timestamp_ms),
+            None,
+            format!("shutdown   after {} checks and {} bitflips", total_checks, flips),
+        ),
+    };
+    let time = time.map(humanize_time).unwrap_or_default();
+    let station = station_id.map(|id| format!("[{}] ", id)).unwrap_or_default();
+    let line = format!("{:<36} {}{}", time, station, text);
+    match style {
+        Some(style) => console::paint(color, style, &line),
+        None => line,
+    }
+}
+
+/// Formats a timestamp in UTC, followed by how long ago it was.
+fn humanize_time(ms: u128) -> String {
+    let seconds = (ms / 1000) as u64;
+    let (year, month, day) = civil_from_days((seconds / 86400) as i64);
+    let ago = (unix_ms() as u128).saturating_sub(ms) as u64;
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}Z ({} ago)",
+        year,
+        month,
+        day,
+        seconds / 3600 % 24,
+        seconds / 60 % 60,
+        seconds % 60,
+        humanize_duration(ago)
+    )
+}
+
+fn humanize_duration(ms: u64) -> String {
+    match ms {
+        0..=999 => format!("{} ms", ms),
+        1000..=59_999 => format!("{} s", ms / 1000),
+        60_000..=3_599_999 => format!("{} min", ms / 60_000),
+        3_600_000..=86_399_999 => format!("{} h", ms / 3_600_000),
+        _ => format!("{} d", ms / 86_400_000),
+    }
+}
+
+fn humanize_bytes(bytes: u64) -> String {
+    match bytes {
+        0..=999_999 => format!("{} kB", bytes / 1000),
+        1_000_000..=999_999_999 => format!("{} MB", bytes / 1_000_000),
+        _ => format!("{:.1} GB", bytes as f64 / 1e9),
+    }
+}
+
+fn unix_ms() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64
+}
@@ -0,0 +1,44 @@
+use std::io::{self, Write};
+use std::time::Duration;
+
+use serde::Deserialize;
+
+const LATEST_RELEASE_URL: &str = "https://api.github.com/repos/dbchuck/cosmic_ray_detector/releases/latest";
+const UPDATE_CHECK_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Deserialize)]
+struct Release {
+    tag_name: String,
+}
+
+/// Warns if a newer release than this one has been published.
+/// Not being able to check is only worth a note, it should never keep the detector from starting.
+pub fn check_for_update(out: &mut dyn Write) -> io::Result<()> {
+    let current = env!("CARGO_PKG_VERSION");
+    match latest_release() {
+        Ok(latest) => match (parse_version(&latest), parse_version(current)) {
+            (Some(latest_version), Some(current_version)) if latest_version > current_version => {
+                writeln!(out, "A newer version ({}) is available, this is version {}", latest, current)
+            }
+            _ => Ok(()),
+        },
+        Err(err) => writeln!(out, "Unable to check for updates: {}", err),
+    }
+}
+
+fn latest_release() -> Result<String, String> {
+    let release: Release = ureq::get(LATEST_RELEASE_URL)
+        .set("User-Agent", concat!("cosmic_ray_detector/", env!("CARGO_PKG_VERSION")))
+        .timeout(UPDATE_CHECK_TIMEOUT)
+        .call()
+        .map_err(|e| e.to_string())?
+        .into_json()
+        .map_err(|e| e.to_string())?;
+    Ok(release.tag_name)
+}
+
+/// Turns e.g. `v1.2.0` into `(1, 2, 0)`.
+fn parse_version(version: &str) -> Option<(u64, u64, u64)> {
+    let mut parts = version.trim_start_matches('v').split('.').map(|part| part.parse().ok());
+    Some((parts.next()??, parts.next()??, parts.next()??))
+}
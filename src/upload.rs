@@ -0,0 +1,171 @@
+use std::collections::HashSet;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    record::{Record, SessionInfo},
+    signing::StationKey,
+};
+
+const UPLOAD_TIMEOUT: Duration = Duration::from_secs(30);
+const MAX_BATCH_SIZE: usize = 500;
+const INITIAL_BACKOFF: Duration = Duration::from_secs(10);
+const MAX_BACKOFF: Duration = Duration::from_secs(3600);
+
+/// A record together with the ID the collector uses to tell whether it has already seen it.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct UploadRecord {
+    /// `<session ID>:<sequence number>`, the same record always gets the same ID no matter how often it is sent.
+    pub id: String,
+    pub sequence: u64,
+    pub record: Record,
+}
+
+#[derive(Serialize)]
+struct Batch<'a> {
+    station_id: &'a str,
+    session: &'a SessionInfo,
+    records: &'a [UploadRecord],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    clock_offset_ms: Option<i64>,
+}
+
+#[derive(Deserialize)]
+struct Acknowledgement {
+    acked: Vec<String>,
+    /// The collector's clock when the request arrived and when it answered, collectors that don't send them
+    /// leave the station's clock offset unknown.
+    #[serde(default)]
+    received_ms: Option<u64>,
+    #[serde(default)]
+    sent_ms: Option<u64>,
+}
+
+/// Sends records to a collector as described in docs/upload_protocol.md.
+/// Records are kept until the collector has acknowledged them, and resent with an increasing delay after failures.
+/// Every batch is signed with the station key, and carries the offset of the station's clock from the collector's
+/// as estimated from the previous round trip.
+pub struct Uploader {
+    url: String,
+    station_id: String,
+    session: SessionInfo,
+    key: StationKey,
+    next_sequence: u64,
+    pending: Vec<UploadRecord>,
+    backoff: Duration,
+    next_attempt: Instant,
+    /// Add this to the station's timestamps to get the collector's.
+    clock_offset_ms: Option<i64>,
+}
+
+impl Uploader {
+    pub fn new(url: String, station_id: String, session: SessionInfo, key: StationKey) -> Self {
+        Uploader {
+            url,
+            station_id,
+            session,
+            key,
+            next_sequence: 0,
+            pending: Vec::new(),
+            backoff: INITIAL_BACKOFF,
+            next_attempt: Instant::now(),
+            clock_offset_ms: None,
+        }
+    }
+
+    /// Queues a record for upload, giving it the next sequence number of the session.
+    pub fn push(&mut self, record: &Record) {
+        let sequence = self.next_sequence;
+        self.next_sequence += 1;
+        self.pending.push(UploadRecord {
+            id: format!("{}:{}", self.session.session_id.as_deref().unwrap_or_default(), sequence),
+            sequence,
+            record: record.clone(),
+        });
+    }
+
+    /// The number of records that the collector has not acknowledged yet.
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Sends the queued records unless the last attempt failed too recently.
+    /// Returns an error describing the failure if the upload was attempted and failed.
+    pub fn flush(&mut self) -> Result<(), String> {
+        while !self.pending.is_empty() && Instant::now() >= self.next_attempt {
+            match self.send_batch() {
+                Ok(()) => self.backoff = INITIAL_BACKOFF,
+                Err(err) => {
+                    self.next_attempt = Instant::now() + self.backoff;
+                    let retry_in = self.backoff;
+                    self.backoff = (self.backoff * 2).min(MAX_BACKOFF);
+                    return Err(format!("Upload failed, retrying in {:?}: {}", retry_in, err));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn send_batch(&mut self) -> Result<(), String> {
+        let batch_size = self.pending.len().min(MAX_BATCH_SIZE);
+        let batch = Batch {
+            station_id: &self.station_id,
+            session: &self.session,
+            records: &self.pending[..batch_size],
+            clock_offset_ms: self.clock_offset_ms,
+        };
+        let body = serde_json::to_vec(&batch).map_err(|e| e.to_string())?;
+        let sent_ms = unix_ms();
+        let acknowledgement: Acknowledgement = ureq::post(&self.url)
+            .timeout(UPLOAD_TIMEOUT)
+            .set("Content-Type", "application/json")
+            .set("X-Station-Key", &self.key.public_key())
+            .set("X-Signature", &self.key.sign(&body))
+            .send_bytes(&body)
+            .map_err(|e| e.to_string())?
+            .into_json()
+            .map_err(|e| format!("Malformed acknowledgement: {}", e))?;
+        let received_ms = unix_ms();
+
+        // The NTP estimate, which assumes the request and the response took equally long.
+        if let (Some(collector_received_ms), Some(collector_sent_ms)) =
+            (acknowledgement.received_ms, acknowledgement.sent_ms)
+        {
+            let offset_ms = (collector_received_ms as i64 - sent_ms as i64 + collector_sent_ms as i64 - received_ms as i64) / 2;
+            self.clock_offset_ms = Some(offset_ms);
+        }
+
+        let acked: HashSet<String> = acknowledgement.acked.into_iter().collect();
+        let before = self.pending.len();
+        self.pending.retain(|record| !acked.contains(&record.id));
+        if self.pending.len() == before {
+            return Err("The collector did not acknowledge any records".into());
+        }
+        Ok(())
+    }
+}
+
+/// Sends the collector an empty batch, which it answers like any other once it has checked the signature and
+/// that the station is registered, without storing anything.
+pub fn check_collector(url: &str, station_id: &str, session: &SessionInfo, key: &StationKey) -> Result<(), String> {
+    let batch = Batch { station_id, session, records: &[], clock_offset_ms: None };
+    let body = serde_json::to_vec(&batch).map_err(|e| e.to_string())?;
+    let _: Acknowledgement = ureq::post(url)
+        .timeout(UPLOAD_TIMEOUT)
+        .set("Content-Type", "application/json")
+        .set("X-Station-Key", &key.public_key())
+        .set("X-Signature", &key.sign(&body))
+        .send_bytes(&body)
+        .map_err(|e| e.to_string())?
+        .into_json()
+        .map_err(|e| format!("Malformed acknowledgement: {}", e))?;
+    Ok(())
+}
+
+fn unix_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
@@ -0,0 +1,194 @@
+//! Uses the library the way a monitoring agent embedding the detector would, through its public API only.
+
+use std::fs::{self, OpenOptions};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use cosmic_ray_detector::{
+    builder::RunnerBuilder,
+    config::{parse_size_spec, LogFormat, Pattern, SizeSpec, SyncPolicy},
+    detector::{Detector, DetectorArray},
+    fill::Thresholds,
+    log_writer::LogWriter,
+    mem_size,
+    mock::{Corruption, MockDetector},
+    record::{read_log, FlipDiff, Record, Tags},
+    rng::Rng,
+    runner::{FlipEvent, HeartbeatEvent, Runner, Sink},
+};
+
+#[test]
+fn a_detector_finds_the_bit_that_flipped() {
+    let mut detector = Detector::new(0, 4096);
+    assert!(detector.is_intact());
+    detector.flip_bit(100, 3);
+    assert!(!detector.is_intact());
+    assert_eq!(detector.find_index_of_changed_element(), Some(100));
+    assert_eq!(detector.diff_at(100).unwrap().changed_bits(), vec![3]);
+    detector.reset();
+    assert!(detector.is_intact());
+}
+
+#[test]
+fn a_detector_array_can_be_resized() {
+    let mut detector = DetectorArray::with_chunk_bytes(0, 4096, 1024).unwrap();
+    assert_eq!(detector.resize(8192), 8192);
+    assert_eq!(detector.size(), 8192);
+    detector.flip_bit(5000, 0);
+    assert_eq!(detector.find_index_of_changed_element(), Some(5000));
+    assert_eq!(detector.chunk_of(5000), Some((4, 904)));
+    assert_eq!(detector.index_of(4, 904), Some(5000));
+    assert_eq!(detector.resize(2048), 2048);
+    assert!(detector.is_intact());
+    assert_eq!(detector.chunk_of(5000), None);
+    assert_eq!(detector.index_of(1, 1024), None);
+    assert!(DetectorArray::with_chunk_bytes(0, 4096, 100).is_err());
+}
+
+#[test]
+fn the_runner_calls_back_for_every_flip() {
+    let script = vec![(2, Corruption::At { index: 7, value: 1 }), (4, Corruption::Vanished)];
+    let stop = Arc::new(AtomicBool::new(false));
+    let flips = Arc::new(Mutex::new(Vec::new()));
+    let (callback_stop, callback_flips) = (stop.clone(), flips.clone());
+    Runner::new(MockDetector::new(script), Duration::ZERO)
+        .on_flip(move |event| {
+            let mut flips = callback_flips.lock().unwrap();
+            flips.push((event.index, event.value, event.checks));
+            callback_stop.store(flips.len() == 2, Ordering::Relaxed);
+        })
+        .run(&stop);
+    assert_eq!(*flips.lock().unwrap(), vec![(Some(7), Some(1), 1), (None, None, 1)]);
+}
+
+#[test]
+fn records_written_by_the_log_writer_read_back_in_both_formats() {
+    let dir = std::env::temp_dir().join(format!("cosmic_ray_detector-library-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let mut detector = Detector::new(0, 64);
+    detector.flip_bit(3, 1);
+    let flip = Record::Flip {
+        started_ms: 1_700_000_000_000,
+        delay_ms: 30_000,
+        checks: 4,
+        vanished: false,
+        detected_ms: 1_700_000_030_000,
+        location: None,
+        uncertainty: None,
+        changed_bytes: Some(1),
+        diffs: vec![FlipDiff::from(detector.diff_at(3).unwrap())],
+        warmup: false,
+        tags: Tags::new(),
+        sequence: Some(1),
+    };
+    for (name, format) in [("results.txt", LogFormat::Csv), ("results.bin", LogFormat::Binary)] {
+        let path = dir.join(name);
+        let _ = fs::remove_file(&path);
+        let file = OpenOptions::new().create(true).append(true).open(&path).unwrap();
+        let mut writer = LogWriter::new(file, format, SyncPolicy::Always, Duration::ZERO).unwrap();
+        writer.write(&[&flip]).unwrap();
+        writer.flush().unwrap();
+        let records = read_log(&path).unwrap();
+        let [Record::Flip { checks, diffs, sequence, .. }] = records.as_slice() else {
+            panic!("Not a single flip: {:?}", records);
+        };
+        assert_eq!((*checks, *sequence), (4, Some(1)));
+        assert_eq!(diffs[0].bits, vec![1]);
+    }
+}
+
+#[test]
+fn sizes_and_thresholds_have_usable_defaults() {
+    assert_eq!(mem_size(2048), "2 KiB");
+    let thresholds = Thresholds::default();
+    assert!(thresholds.swap_tolerance > 0 && thresholds.free_headroom > 0);
+}
+
+/// Counts the heartbeats of a runner.
+struct HeartbeatCounter(Arc<AtomicU64>);
+
+impl Sink for HeartbeatCounter {
+    fn flip(&mut self, _event: &FlipEvent) {}
+
+    fn heartbeat(&mut self, _event: &HeartbeatEvent) {
+        self.0.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+#[test]
+fn a_runner_is_built_from_typed_settings() {
+    let heartbeats = Arc::new(AtomicU64::new(0));
+    let runner = RunnerBuilder::new()
+        .size(SizeSpec::Bytes(1 << 20))
+        .pattern(Pattern::Checkerboard)
+        .interval(Duration::ZERO)
+        .heartbeats(Duration::ZERO)
+        .sink(HeartbeatCounter(heartbeats.clone()))
+        .build()
+        .unwrap();
+    let handle = runner.spawn().unwrap();
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while heartbeats.load(Ordering::Relaxed) == 0 && Instant::now() < deadline {
+        thread::sleep(Duration::from_millis(1));
+    }
+    handle.stop();
+    assert!(heartbeats.load(Ordering::Relaxed) > 0);
+}
+
+#[test]
+fn sizes_are_given_in_bytes_or_as_a_share_of_the_memory() {
+    assert_eq!(SizeSpec::Bytes(4096).bytes(1 << 30), Ok(4096));
+    assert_eq!(SizeSpec::Percent(50.0).bytes(1000), Ok(500));
+    assert!(SizeSpec::Percent(0.0).bytes(1000).is_err());
+    assert!(SizeSpec::Percent(150.0).bytes(1000).is_err());
+    assert!(SizeSpec::Bytes(0).bytes(1000).is_err());
+    assert!(SizeSpec::Auto { min_free: None }.bytes(1000).is_err());
+    assert_eq!(parse_size_spec("2kB"), Ok(SizeSpec::Bytes(2000)));
+    assert_eq!(parse_size_spec("25%"), Ok(SizeSpec::Percent(25.0)));
+    assert_eq!(parse_size_spec("auto"), Ok(SizeSpec::Auto { min_free: None }));
+    assert!(parse_size_spec("0").is_err_and(|err| err.contains("auto")));
+    assert_eq!(parse_size_spec("auto:1GB"), Ok(SizeSpec::Auto { min_free: Some(1_000_000_000) }));
+    assert!(parse_size_spec("120%").is_err());
+    assert!(parse_size_spec("0.1").is_err());
+    assert!(parse_size_spec("0kB").is_err());
+}
+
+#[test]
+fn a_checkerboard_alternates_between_two_bytes() {
+    let mut rng = Rng::new(1);
+    let values: Vec<u8> = (0..4).map(|cycle| Pattern::Checkerboard.value(cycle, &mut rng)).collect();
+    assert_eq!(values, [0x55, 0xAA, 0x55, 0xAA]);
+}
+
+#[cfg(feature = "sqlite")]
+#[test]
+fn runs_logged_into_sqlite_read_back_in_order() {
+    let dir = std::env::temp_dir().join(format!("cosmic_ray_detector-library-sqlite-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("results.db");
+    let _ = fs::remove_file(&path);
+    for started_ms in [1_700_000_000_000, 1_700_003_600_000] {
+        let start = Record::Start { started_ms, delay_ms: 30_000, pid: None, location: None, tags: Tags::new(), sequence: None };
+        let dropped = Record::Dropped { timestamp_ms: started_ms + 60_000, records: 3, tags: Tags::new(), sequence: Some(1) };
+        // Every run opens the database anew, as the program does.
+        let file = OpenOptions::new().create(true).append(true).open(&path).unwrap();
+        let mut writer = LogWriter::sqlite(file, &path).unwrap();
+        writer.write(&[&start]).unwrap();
+        writer.write(&[&dropped]).unwrap();
+    }
+    let kinds: Vec<_> = read_log(&path).unwrap().iter().map(|record| (record.kind(), record.timestamp_ms())).collect();
+    assert_eq!(
+        kinds,
+        vec![
+            ("start", Some(1_700_000_000_000)),
+            ("dropped", Some(1_700_000_060_000)),
+            ("start", Some(1_700_003_600_000)),
+            ("dropped", Some(1_700_003_660_000)),
+        ]
+    );
+    // Appending lines to the database would break it.
+    let file = OpenOptions::new().read(true).append(true).open(&path).unwrap();
+    assert!(LogWriter::new(file, LogFormat::Csv, SyncPolicy::Always, Duration::ZERO).is_err());
+}